@@ -0,0 +1,258 @@
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tracing::{Instrument, field, info, info_span};
+
+use crate::{
+    config::Config,
+    log_format::{LogEntry, LogFormat},
+    logging::ACCESS_LOG_TARGET,
+    privacy::Anonymizer,
+    probes::is_health_probe,
+    request_id::RequestId,
+};
+
+pub struct AccessLog {
+    exclude: GlobSet,
+    format: Option<LogFormat>,
+    anonymizer: Anonymizer,
+    sample_rate: f64,
+}
+
+impl AccessLog {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &config.log_exclude {
+            let glob = Glob::new(pattern)
+                .map_err(|e| anyhow!("Invalid log exclude pattern '{}': {}", pattern, e))?;
+            builder.add(glob);
+        }
+        let exclude = builder
+            .build()
+            .map_err(|e| anyhow!("Failed to build log exclude patterns: {}", e))?;
+        let format = config
+            .log_format
+            .as_deref()
+            .map(LogFormat::parse)
+            .transpose()?;
+        if !(0.0..=1.0).contains(&config.log_sample_rate) {
+            return Err(anyhow!(
+                "'log_sample_rate' must be between 0.0 and 1.0, got {}",
+                config.log_sample_rate
+            ));
+        }
+        Ok(Self {
+            exclude,
+            format,
+            anonymizer: Anonymizer::new(config),
+            sample_rate: config.log_sample_rate,
+        })
+    }
+
+    /// Whether the outcome of a request is written, which is always the case for errors.
+    fn samples(&self, status: StatusCode) -> bool {
+        self.sample_rate >= 1.0
+            || status.is_client_error()
+            || status.is_server_error()
+            || rand::random::<f64>() < self.sample_rate
+    }
+}
+
+/// Wraps every request in a span and logs its outcome once the response is ready.
+pub async fn log_requests(
+    State(access_log): State<Arc<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if access_log.exclude.is_match(request.uri().path()) || is_health_probe(&request) {
+        return next.run(request).await;
+    }
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_default();
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let span = info_span!(
+        "request",
+        id = %request_id,
+        method = %request.method(),
+        route = %route,
+        path = %request.uri().path(),
+        status = field::Empty,
+        duration_ms = field::Empty,
+        bytes = field::Empty,
+    );
+    // The template needs request details that are gone once the request is consumed.
+    let request_parts = access_log.format.as_ref().map(|_| {
+        let remote_addr = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0);
+        (
+            remote_addr,
+            request.method().clone(),
+            request.uri().clone(),
+            request.version(),
+            request.headers().clone(),
+        )
+    });
+    let start = Instant::now();
+
+    let response = next.run(request).instrument(span.clone()).await;
+
+    let duration = start.elapsed();
+    span.record("status", response.status().as_u16());
+    span.record(
+        "duration_ms",
+        field::display(format_args!("{:.3}", duration.as_secs_f64() * 1000.0)),
+    );
+    let bytes = response.body().size_hint().exact();
+    if let Some(bytes) = bytes {
+        span.record("bytes", bytes);
+    }
+    if !access_log.samples(response.status()) {
+        return response;
+    }
+
+    match (&access_log.format, request_parts) {
+        (Some(format), Some((remote_addr, method, uri, version, headers))) => {
+            let line = format.render(&LogEntry {
+                remote_addr,
+                method: &method,
+                uri: &uri,
+                version,
+                headers: &headers,
+                request_id: &request_id,
+                status: response.status(),
+                bytes,
+                duration,
+                anonymizer: &access_log.anonymizer,
+            });
+            info!(target: ACCESS_LOG_TARGET, "{}", line);
+        }
+        _ => span.in_scope(|| info!("Request completed")),
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+    use tracing::{
+        Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+    use tracing_subscriber::{Layer, layer::Context, prelude::*};
+
+    use super::*;
+
+    /// The fields of every `request` span, by name.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    struct Fields<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Spans {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            if attrs.metadata().name() == "request" {
+                let mut fields = HashMap::new();
+                attrs.record(&mut Fields(&mut fields));
+                self.0.lock().unwrap().push(fields);
+            }
+        }
+
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            if let Some(fields) = self.0.lock().unwrap().last_mut() {
+                values.record(&mut Fields(fields));
+            }
+        }
+    }
+
+    /// Sends a request for `uri` through the access log, returning the spans recorded.
+    async fn request_spans(config: &Config, uri: &str) -> Vec<HashMap<String, String>> {
+        let spans = Spans::default();
+        let _guard = tracing_subscriber::registry()
+            .with(spans.clone())
+            .set_default();
+        let access_log = Arc::new(AccessLog::new(config).unwrap());
+        let router = Router::new()
+            .route("/blog/{post}", get(|| async { "hello" }))
+            .layer(middleware::from_fn_with_state(access_log, log_requests));
+        let request = axum::http::Request::get(uri).body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap();
+        spans.0.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn records_requests_in_a_span() {
+        let spans = request_spans(&Config::default(), "/blog/first").await;
+        assert_eq!(spans.len(), 1);
+        let span = &spans[0];
+        assert_eq!(span["method"], "GET");
+        assert_eq!(span["route"], "/blog/{post}");
+        assert_eq!(span["path"], "/blog/first");
+        assert_eq!(span["status"], "200");
+        assert_eq!(span["bytes"], "5");
+        assert!(span["duration_ms"].parse::<f64>().is_ok());
+    }
+
+    #[test]
+    fn always_samples_errors() {
+        let config = Config {
+            log_sample_rate: 0.0,
+            ..Config::default()
+        };
+        let access_log = AccessLog::new(&config).unwrap();
+        assert!(!access_log.samples(StatusCode::OK));
+        assert!(access_log.samples(StatusCode::NOT_FOUND));
+        assert!(access_log.samples(StatusCode::BAD_GATEWAY));
+
+        let config = Config {
+            log_sample_rate: 1.5,
+            ..Config::default()
+        };
+        assert!(AccessLog::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn skips_excluded_paths() {
+        let config = Config {
+            log_exclude: vec!["/blog/draft-*".to_string()],
+            ..Config::default()
+        };
+        assert!(request_spans(&config, "/blog/draft-1").await.is_empty());
+        assert_eq!(request_spans(&config, "/blog/first").await.len(), 1);
+
+        let config = Config {
+            log_exclude: vec!["[".to_string()],
+            ..Config::default()
+        };
+        assert!(AccessLog::new(&config).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::{path::PathBuf, sync::Arc};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{any, get, post},
+};
+use tracing::warn;
+
+use crate::{
+    analytics::{Analytics, handle_analytics},
+    auth::{bearer_token, constant_time_eq},
+    config::Config,
+    content::handle_content,
+    csrf::reject_cross_site,
+    export::handle_export,
+    files::handle_files,
+    notify::Notifier,
+    purge::{Caches, handle_purge},
+    status::{ServerStatus, handle_status},
+    version::{enabled_features, handle_version},
+};
+
+/// URL prefix of all admin endpoints.
+pub const ADMIN_PREFIX: &str = "/__lime";
+
+pub struct AdminState {
+    pub token: String,
+    pub pages_dir: PathBuf,
+    pub static_dir: PathBuf,
+    pub content_dir: Option<PathBuf>,
+    pub max_file_size: Option<u64>,
+    pub caches: Caches,
+    pub status: Arc<ServerStatus>,
+    pub features: Vec<&'static str>,
+    pub workers: usize,
+    pub notifier: Option<Arc<Notifier>>,
+}
+
+/// Builds the admin endpoints, or `None` when no admin token is configured.
+pub fn admin_router<S: Clone + Send + Sync + 'static>(
+    config: &Config,
+    caches: Caches,
+    status: Arc<ServerStatus>,
+    notifier: Option<Arc<Notifier>>,
+    analytics: Option<Arc<Analytics>>,
+) -> Option<Router<S>> {
+    let token = config.admin.token.clone().filter(|t| !t.is_empty())?;
+    let state = Arc::new(AdminState {
+        token,
+        pages_dir: PathBuf::from(&config.pages_dir),
+        static_dir: PathBuf::from(&config.static_dir),
+        content_dir: config.admin.content_dir.as_ref().map(PathBuf::from),
+        max_file_size: config.max_file_size.map(|s| s.0),
+        caches,
+        status,
+        features: enabled_features(config),
+        workers: config.workers,
+        notifier,
+    });
+
+    let mut router = Router::new()
+        .route("/export.tar.gz", get(handle_export))
+        .route("/content/{*path}", any(handle_content))
+        .route("/files/{*path}", get(handle_files))
+        .route("/purge", post(handle_purge))
+        .route("/status", get(handle_status))
+        .route("/version", get(handle_version));
+    if let Some(analytics) = analytics {
+        router = router.route("/analytics", get(handle_analytics).with_state(analytics));
+    }
+    let router = router
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .layer(middleware::from_fn(reject_cross_site))
+        .with_state(state);
+    Some(router)
+}
+
+/// Rejects requests without `Authorization: Bearer <token>`.
+async fn require_token(
+    State(state): State<Arc<AdminState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized =
+        bearer_token(request.headers()).is_some_and(|token| constant_time_eq(token, &state.token));
+
+    if !authorized {
+        warn!(path = %request.uri().path(), "Unauthorized admin request");
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .body(Body::empty())
+            .unwrap();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{error, info};
+
+use crate::{config::Config, notify::Notifier, probes::is_health_probe};
+
+/// Response counts for one second.
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    second: u64,
+    total: u64,
+    client_errors: u64,
+    server_errors: u64,
+}
+
+#[derive(Clone, Copy)]
+enum ErrorClass {
+    Client,
+    Server,
+}
+
+impl ErrorClass {
+    fn describe(self) -> &'static str {
+        match self {
+            ErrorClass::Client => "client errors (4xx)",
+            ErrorClass::Server => "server errors (5xx)",
+        }
+    }
+}
+
+struct Rates {
+    buckets: VecDeque<Bucket>,
+    /// Whether the client and server error alerts are raised.
+    raised: [bool; 2],
+}
+
+/// Watches the share of `4xx` and `5xx` responses over a rolling window, and raises an
+/// alert when one exceeds its threshold: an error in the log, and the `errors` webhooks.
+/// Another message follows once the rate drops below the threshold again.
+pub struct ErrorAlerts {
+    window: u64,
+    min_requests: u64,
+    thresholds: [Option<f64>; 2],
+    started: Instant,
+    rates: Mutex<Rates>,
+    notifier: Option<Arc<Notifier>>,
+}
+
+impl ErrorAlerts {
+    /// Builds the alerts, or `None` when no threshold is configured.
+    pub fn new(config: &Config, notifier: Option<Arc<Notifier>>) -> Option<Self> {
+        let alerts = &config.alerts;
+        let thresholds = [alerts.client_error_rate, alerts.server_error_rate];
+        if thresholds.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(Self {
+            window: alerts.window.max(1),
+            min_requests: alerts.min_requests,
+            thresholds,
+            started: Instant::now(),
+            rates: Mutex::new(Rates {
+                buckets: VecDeque::new(),
+                raised: [false; 2],
+            }),
+            notifier,
+        })
+    }
+
+    fn record(&self, status: u16) {
+        let second = self.started.elapsed().as_secs();
+        let mut messages = Vec::new();
+        {
+            let mut rates = self.rates.lock().unwrap();
+            if rates.buckets.back().is_none_or(|b| b.second != second) {
+                rates.buckets.push_back(Bucket {
+                    second,
+                    ..Bucket::default()
+                });
+            }
+            while rates
+                .buckets
+                .front()
+                .is_some_and(|b| b.second + self.window <= second)
+            {
+                rates.buckets.pop_front();
+            }
+            if let Some(bucket) = rates.buckets.back_mut() {
+                bucket.total += 1;
+                match status {
+                    400..=499 => bucket.client_errors += 1,
+                    500..=599 => bucket.server_errors += 1,
+                    _ => {}
+                }
+            }
+
+            let total: u64 = rates.buckets.iter().map(|b| b.total).sum();
+            if total < self.min_requests {
+                return;
+            }
+            let errors = [
+                rates.buckets.iter().map(|b| b.client_errors).sum::<u64>(),
+                rates.buckets.iter().map(|b| b.server_errors).sum::<u64>(),
+            ];
+            for (index, class) in [ErrorClass::Client, ErrorClass::Server]
+                .into_iter()
+                .enumerate()
+            {
+                let Some(threshold) = self.thresholds[index] else {
+                    continue;
+                };
+                let rate = errors[index] as f64 / total as f64;
+                let exceeded = rate > threshold;
+                if exceeded == rates.raised[index] {
+                    continue;
+                }
+                rates.raised[index] = exceeded;
+                messages.push((exceeded, self.message(class, rate, threshold, exceeded)));
+            }
+        }
+
+        for (raised, message) in messages {
+            // Logged as an error, so it shows up with the default log level.
+            if raised {
+                error!("{}", message);
+            } else {
+                info!("{}", message);
+            }
+            if let Some(notifier) = &self.notifier {
+                notifier.alert(message);
+            }
+        }
+    }
+
+    fn message(&self, class: ErrorClass, rate: f64, threshold: f64, raised: bool) -> String {
+        if raised {
+            format!(
+                "ALERT: {:.0}% of responses in the last {} seconds were {}, above the {:.0}% threshold",
+                rate * 100.0,
+                self.window,
+                class.describe(),
+                threshold * 100.0
+            )
+        } else {
+            format!(
+                "Resolved: {} are back to {:.0}% of responses",
+                class.describe(),
+                rate * 100.0
+            )
+        }
+    }
+}
+
+pub async fn track_errors(
+    State(alerts): State<Arc<ErrorAlerts>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_health_probe(&request) {
+        return next.run(request).await;
+    }
+    let response = next.run(request).await;
+    alerts.record(response.status().as_u16());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertConfig;
+
+    #[test]
+    fn raises_and_resolves_alerts() {
+        let config = Config {
+            alerts: AlertConfig {
+                min_requests: 10,
+                client_error_rate: Some(0.5),
+                ..AlertConfig::default()
+            },
+            ..Config::default()
+        };
+        let alerts = ErrorAlerts::new(&config, None).unwrap();
+        for _ in 0..5 {
+            alerts.record(200);
+        }
+        // Too few responses to judge yet.
+        for _ in 0..4 {
+            alerts.record(404);
+        }
+        assert!(!alerts.rates.lock().unwrap().raised[0]);
+        for _ in 0..3 {
+            alerts.record(404);
+        }
+        assert!(alerts.rates.lock().unwrap().raised[0]);
+        for _ in 0..3 {
+            alerts.record(200);
+        }
+        assert!(!alerts.rates.lock().unwrap().raised[0]);
+        assert!(!alerts.rates.lock().unwrap().raised[1]);
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{Local, NaiveDate};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::{
+    admin::ADMIN_PREFIX, bots::Untracked, config::Config, privacy, probes::is_health_probe,
+};
+
+/// How often counts are written to disk.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Defaults of `GET /__lime/analytics`.
+const DEFAULT_DAYS: u64 = 7;
+const DEFAULT_TOP: usize = 10;
+const MAX_TOP: usize = 100;
+
+/// User agents of crawlers and monitors, which are not visitors.
+const BOT_MARKERS: [&str; 6] = ["bot", "crawler", "spider", "slurp", "curl", "monitor"];
+
+/// Referring sites tracked per day; views from any more are counted as `other`.
+const MAX_REFERRERS: usize = 500;
+
+/// Counts of one day.
+#[derive(Default, Clone, Deserialize, Serialize)]
+pub struct Day {
+    pub views: u64,
+    pub visitors: u64,
+    pub pages: BTreeMap<String, u64>,
+    /// Views by the host of the site linking to the page.
+    #[serde(default)]
+    pub referrers: BTreeMap<String, u64>,
+}
+
+/// Page views over a range of days, with the most viewed pages and referring sites.
+#[derive(Serialize)]
+pub struct Report {
+    pub from: String,
+    pub to: String,
+    pub views: u64,
+    /// Sum of the daily visitors, so a visitor returning on another day counts twice.
+    pub visitors: u64,
+    pub days: Vec<DayReport>,
+    pub pages: Vec<Ranked>,
+    pub referrers: Vec<Ranked>,
+}
+
+#[derive(Serialize)]
+pub struct DayReport {
+    pub date: String,
+    pub views: u64,
+    pub visitors: u64,
+}
+
+#[derive(Serialize)]
+pub struct Ranked {
+    pub name: String,
+    pub views: u64,
+}
+
+/// What is needed to recognise returning visitors today. Dropped at the end of the day,
+/// so visitors can't be followed from one day to the next.
+#[derive(Deserialize, Serialize)]
+struct Today {
+    date: String,
+    salt: String,
+    seen: HashSet<String>,
+}
+
+impl Today {
+    fn new(date: String) -> Self {
+        Self {
+            date,
+            salt: format!("{:032x}", rand::random::<u128>()),
+            seen: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Stats {
+    /// Counts by date, as `YYYY-MM-DD`.
+    days: BTreeMap<String, Day>,
+    today: Today,
+}
+
+impl Stats {
+    /// Reports the last `days` days up to today, listing the `top` pages and referrers.
+    fn report(&self, days: u64, top: usize) -> Report {
+        let today = Local::now().date_naive();
+        let from = date_key(today - chrono::Days::new(days.max(1) - 1));
+        let to = date_key(today);
+        let range: Vec<_> = self.days.range(from.clone()..=to.clone()).collect();
+
+        let mut pages = BTreeMap::new();
+        let mut referrers = BTreeMap::new();
+        for (_, day) in &range {
+            for (page, views) in &day.pages {
+                *pages.entry(page.as_str()).or_default() += views;
+            }
+            for (host, views) in &day.referrers {
+                *referrers.entry(host.as_str()).or_default() += views;
+            }
+        }
+        Report {
+            views: range.iter().map(|(_, day)| day.views).sum(),
+            visitors: range.iter().map(|(_, day)| day.visitors).sum(),
+            days: range
+                .iter()
+                .map(|(date, day)| DayReport {
+                    date: date.to_string(),
+                    views: day.views,
+                    visitors: day.visitors,
+                })
+                .collect(),
+            pages: rank(pages, top),
+            referrers: rank(referrers, top),
+            from,
+            to,
+        }
+    }
+}
+
+/// The `top` entries with the most views, most viewed first.
+fn rank(counts: BTreeMap<&str, u64>, top: usize) -> Vec<Ranked> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+        .into_iter()
+        .take(top)
+        .map(|(name, views)| Ranked {
+            name: name.to_string(),
+            views,
+        })
+        .collect()
+}
+
+/// Cookie-less page view and visitor counts, kept in a local file.
+pub struct Analytics {
+    path: PathBuf,
+    respect_privacy: bool,
+    stats: Mutex<Stats>,
+}
+
+impl Analytics {
+    /// Opens the counts stored at the configured path, or `None` when analytics are off.
+    pub fn open(config: &Config) -> Result<Option<Arc<Self>>> {
+        if !config.analytics.enabled {
+            return Ok(None);
+        }
+        let path = PathBuf::from(&config.analytics.file);
+        let stats = match load(&path)? {
+            Some(stats) => stats,
+            None => Stats {
+                days: BTreeMap::new(),
+                today: Today::new(today()),
+            },
+        };
+        Ok(Some(Arc::new(Self {
+            path,
+            respect_privacy: config.analytics.respect_privacy,
+            stats: Mutex::new(stats),
+        })))
+    }
+
+    /// Counts a view of `page`, linked to from `referrer` if that is another site. Visitors
+    /// are told apart by a hash of their address and user agent with today's salt.
+    fn record(&self, page: &str, ip: &str, user_agent: &str, referrer: Option<&str>) {
+        let date = today();
+        let mut stats = self.stats.lock().unwrap();
+        if stats.today.date != date {
+            stats.today = Today::new(date.clone());
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(&stats.today.salt);
+        hasher.update(ip);
+        hasher.update(user_agent);
+        let visitor: String = hasher.finalize()[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let new_visitor = stats.today.seen.insert(visitor);
+
+        let day = stats.days.entry(date).or_default();
+        day.views += 1;
+        if new_visitor {
+            day.visitors += 1;
+        }
+        *day.pages.entry(page.to_string()).or_default() += 1;
+        if let Some(referrer) = referrer {
+            let referrer =
+                if day.referrers.len() < MAX_REFERRERS || day.referrers.contains_key(referrer) {
+                    referrer
+                } else {
+                    "other"
+                };
+            *day.referrers.entry(referrer.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn report(&self, days: u64, top: usize) -> Report {
+        self.stats.lock().unwrap().report(days, top)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let content = serde_json::to_string(&*self.stats.lock().unwrap())
+            .map_err(|e| anyhow!("Failed to serialize page views: {}", e))?;
+        // Written next to the file first, so a crash never leaves half of it behind.
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, content)
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// Writes the counts to disk periodically.
+    pub fn autosave(self: &Arc<Self>) {
+        let analytics = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = analytics.save() {
+                    error!("{}", e);
+                }
+            }
+        });
+    }
+}
+
+fn today() -> String {
+    date_key(Local::now().date_naive())
+}
+
+fn date_key(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+fn load(path: &PathBuf) -> Result<Option<Stats>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let stats = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))?;
+    Ok(Some(stats))
+}
+
+/// Counts successful `GET` requests for HTML pages.
+pub async fn count_page_views(
+    State(analytics): State<Arc<Analytics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let opted_out = analytics.respect_privacy && privacy::opted_out(request.headers());
+    let countable = request.method() == Method::GET
+        && !path.starts_with(ADMIN_PREFIX)
+        && !opted_out
+        && request.extensions().get::<Untracked>().is_none()
+        && !is_health_probe(&request)
+        && !is_bot(&user_agent);
+    let referrer = referrer(request.headers());
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip().to_string())
+        .unwrap_or_default();
+
+    let response = next.run(request).await;
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if countable && is_html && response.status() == StatusCode::OK {
+        analytics.record(&path, &ip, &user_agent, referrer.as_deref());
+    }
+    response
+}
+
+/// The host of the site the visitor came from, if it's not this one.
+fn referrer(headers: &HeaderMap) -> Option<String> {
+    let referrer = headers.get(header::REFERER)?.to_str().ok()?;
+    let host = reqwest::Url::parse(referrer).ok()?.host_str()?.to_string();
+    let own_host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.rsplit_once(':').map_or(v, |(host, _)| host));
+    (own_host != Some(host.as_str())).then_some(host)
+}
+
+fn is_bot(user_agent: &str) -> bool {
+    let user_agent = user_agent.to_ascii_lowercase();
+    user_agent.is_empty() || BOT_MARKERS.iter().any(|m| user_agent.contains(m))
+}
+
+/// Prints page views and visitors of the last `days` days, and the `top` pages and
+/// referring sites.
+pub fn print_stats(config: &Config, days: u64, top: usize) -> Result<()> {
+    let path = PathBuf::from(&config.analytics.file);
+    let Some(stats) = load(&path)? else {
+        return Err(anyhow!(
+            "No page views recorded yet in {}; enable [analytics] and serve some pages first",
+            path.display()
+        ));
+    };
+    let report = stats.report(days, top);
+
+    println!(
+        "{:<12} {:>8} {:>9}",
+        "Date".bold(),
+        "Views".bold(),
+        "Visitors".bold()
+    );
+    for day in &report.days {
+        println!("{:<12} {:>8} {:>9}", day.date, day.views, day.visitors);
+    }
+    println!("{:<12} {:>8} {:>9}", "Total", report.views, report.visitors);
+    for (title, ranked) in [
+        ("Top pages", &report.pages),
+        ("Top referrers", &report.referrers),
+    ] {
+        println!("\n{}", title.bold());
+        if ranked.is_empty() {
+            println!("{}", "None".dimmed());
+        }
+        for entry in ranked {
+            println!("{:>8}  {}", entry.views, entry.name);
+        }
+    }
+    Ok(())
+}
+
+/// Answers `GET /__lime/analytics[?days=<n>&top=<n>]` with a JSON [`Report`].
+pub async fn handle_analytics(
+    State(analytics): State<Arc<Analytics>>,
+    Query(params): Query<ReportParams>,
+) -> Json<Report> {
+    Json(analytics.report(
+        params.days.unwrap_or(DEFAULT_DAYS),
+        params.top.unwrap_or(DEFAULT_TOP).min(MAX_TOP),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ReportParams {
+    days: Option<u64>,
+    top: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, body::to_bytes, routing::get};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::AnalyticsConfig;
+
+    #[test]
+    fn counts_unique_visitors() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("stats.json");
+        let config = Config {
+            analytics: AnalyticsConfig {
+                enabled: true,
+                file: file.to_string_lossy().into_owned(),
+                ..AnalyticsConfig::default()
+            },
+            ..Config::default()
+        };
+        let analytics = Analytics::open(&config).unwrap().unwrap();
+        analytics.record("/", "203.0.113.7", "Firefox", None);
+        analytics.record("/about", "203.0.113.7", "Firefox", None);
+        analytics.record("/", "198.51.100.1", "Safari", Some("news.example.com"));
+        let report = analytics.report(1, 10);
+        assert_eq!((report.views, report.visitors), (3, 2));
+
+        analytics.save().unwrap();
+        let reopened = Analytics::open(&config).unwrap().unwrap();
+        reopened.record("/", "203.0.113.7", "Firefox", None);
+
+        let report = reopened.report(7, 1);
+        assert_eq!((report.views, report.visitors), (4, 2));
+        assert_eq!(
+            (report.pages[0].name.as_str(), report.pages[0].views),
+            ("/", 3)
+        );
+        assert_eq!(report.referrers[0].name, "news.example.com");
+    }
+
+    /// Analytics with views of `pages` on each of the given days before today.
+    fn history(days: &[(u64, &[(&str, u64)])]) -> Arc<Analytics> {
+        let today = Local::now().date_naive();
+        let mut stats = Stats {
+            days: BTreeMap::new(),
+            today: Today::new(super::today()),
+        };
+        for (ago, pages) in days {
+            let views = pages.iter().map(|(_, views)| views).sum();
+            let day = Day {
+                views,
+                visitors: views / 2,
+                pages: pages
+                    .iter()
+                    .map(|(page, views)| (page.to_string(), *views))
+                    .collect(),
+                referrers: BTreeMap::from([("news.example.com".to_string(), views / 2)]),
+            };
+            stats
+                .days
+                .insert(date_key(today - chrono::Days::new(*ago)), day);
+        }
+        Arc::new(Analytics {
+            path: PathBuf::new(),
+            respect_privacy: false,
+            stats: Mutex::new(stats),
+        })
+    }
+
+    async fn report(analytics: &Arc<Analytics>, query: &str) -> Value {
+        let router = Router::new()
+            .route("/analytics", get(handle_analytics))
+            .with_state(analytics.clone());
+        let request = Request::get(format!("/analytics{}", query))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_the_requested_days() {
+        let analytics = history(&[
+            (0, &[("/", 4)]),
+            (6, &[("/", 2)]),
+            (7, &[("/", 8)]),
+            (30, &[("/", 16)]),
+        ]);
+        let today = Local::now().date_naive();
+        let ago = |days| date_key(today - chrono::Days::new(days));
+
+        let week = report(&analytics, "").await;
+        assert_eq!(week["from"], ago(6));
+        assert_eq!(week["to"], ago(0));
+        assert_eq!(week["views"], 6);
+        assert_eq!(week["visitors"], 3);
+        assert_eq!(
+            week["days"],
+            json!([
+                {"date": ago(6), "views": 2, "visitors": 1},
+                {"date": ago(0), "views": 4, "visitors": 2},
+            ])
+        );
+
+        let today_only = report(&analytics, "?days=1").await;
+        assert_eq!(today_only["from"], ago(0));
+        assert_eq!(today_only["views"], 4);
+        // Zero days still reports today.
+        assert_eq!(report(&analytics, "?days=0").await["views"], 4);
+        assert_eq!(report(&analytics, "?days=8").await["views"], 14);
+        assert_eq!(report(&analytics, "?days=31").await["views"], 30);
+    }
+
+    #[tokio::test]
+    async fn ranks_the_most_viewed_pages() {
+        let analytics = history(&[
+            (0, &[("/", 3), ("/about", 5), ("/blog/", 1)]),
+            (1, &[("/", 4), ("/docs/", 5), ("/blog/", 1)]),
+            (10, &[("/blog/", 50)]),
+        ]);
+        let top = report(&analytics, "?top=3").await;
+        // Views add up over the days, and ties are ordered by name.
+        assert_eq!(
+            top["pages"],
+            json!([
+                {"name": "/", "views": 7},
+                {"name": "/about", "views": 5},
+                {"name": "/docs/", "views": 5},
+            ])
+        );
+        assert_eq!(
+            top["referrers"],
+            json!([{"name": "news.example.com", "views": 9}])
+        );
+        assert_eq!(
+            report(&analytics, "?top=1").await["pages"],
+            json!([{"name": "/", "views": 7}])
+        );
+        assert_eq!(
+            report(&analytics, "").await["pages"]
+                .as_array()
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(report(&analytics, "?top=0").await["pages"], json!([]));
+    }
+}
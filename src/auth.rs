@@ -0,0 +1,404 @@
+use std::{env, fs, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use globset::{GlobBuilder, GlobMatcher};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{AuthMethod, AuthRule, SessionConfig},
+    error::LimeError,
+    jwt::JwtValidator,
+    oidc::Oidc,
+    paths::{PathError, normalize_request_path},
+    proxy::ClaimHeaders,
+    session::Sessions,
+};
+
+struct CompiledRule {
+    pattern: String,
+    matcher: GlobMatcher,
+    method: AuthMethod,
+    realm: String,
+    credentials: Vec<String>,
+    jwt: Option<JwtValidator>,
+    oidc: Option<Oidc>,
+}
+
+/// Per-route authentication. The first rule whose pattern matches the request path applies.
+pub struct Auth {
+    rules: Vec<CompiledRule>,
+    sessions: Sessions,
+    case_insensitive: bool,
+}
+
+impl Auth {
+    pub fn new(
+        rules: &[AuthRule],
+        session: &SessionConfig,
+        case_insensitive: bool,
+    ) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                if rule.method == AuthMethod::Mtls {
+                    return Err(LimeError::Tls(format!(
+                        "Auth rule '{}': mTLS requires TLS, which Lime does not support yet",
+                        rule.pattern
+                    ))
+                    .into());
+                }
+                let matcher = GlobBuilder::new(&rule.pattern)
+                    .case_insensitive(case_insensitive)
+                    .build()
+                    .map_err(|e| anyhow!("Invalid auth pattern '{}': {}", rule.pattern, e))?
+                    .compile_matcher();
+                let credentials = load_credentials(rule)?;
+                let jwt = match (rule.method, &rule.jwt) {
+                    (AuthMethod::Jwt, Some(jwt)) => Some(
+                        JwtValidator::new(jwt)
+                            .map_err(|e| anyhow!("Auth rule '{}': {}", rule.pattern, e))?,
+                    ),
+                    (AuthMethod::Jwt, None) => {
+                        return Err(anyhow!("Auth rule '{}' has no [auth.jwt]", rule.pattern));
+                    }
+                    _ => None,
+                };
+                let oidc = match (rule.method, &rule.oidc) {
+                    (AuthMethod::Oidc, Some(oidc)) => Some(
+                        Oidc::new(oidc)
+                            .map_err(|e| anyhow!("Auth rule '{}': {}", rule.pattern, e))?,
+                    ),
+                    (AuthMethod::Oidc, None) => {
+                        return Err(anyhow!("Auth rule '{}' has no [auth.oidc]", rule.pattern));
+                    }
+                    _ => None,
+                };
+                let needs_credentials =
+                    matches!(rule.method, AuthMethod::Basic | AuthMethod::Bearer);
+                if needs_credentials && credentials.is_empty() {
+                    return Err(anyhow!("Auth rule '{}' has no credentials", rule.pattern));
+                }
+                Ok(CompiledRule {
+                    pattern: rule.pattern.clone(),
+                    matcher,
+                    method: rule.method,
+                    realm: rule.realm.clone().unwrap_or_else(|| "Lime".to_string()),
+                    credentials,
+                    jwt,
+                    oidc,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            rules,
+            sessions: Sessions::new(session),
+            case_insensitive,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether requests for `path` have to authenticate. Paths that can't be decoded are
+    /// treated as protected.
+    pub fn is_protected(&self, path: &str) -> bool {
+        let Ok(path) = self.rule_path(path) else {
+            return true;
+        };
+        self.rule(&path)
+            .is_some_and(|r| r.method != AuthMethod::None)
+    }
+
+    /// The path rules are matched against: decoded and normalized the way files are
+    /// resolved, so `/%69nternal` or `//internal` can't slip past a rule for `/internal/**`.
+    fn rule_path(&self, path: &str) -> Result<String, PathError> {
+        let path = format!("/{}", normalize_request_path(path)?);
+        Ok(if self.case_insensitive {
+            path.to_lowercase()
+        } else {
+            path
+        })
+    }
+
+    /// Headers JWT claims are forwarded in, which clients must not set themselves.
+    pub fn claim_header_names(&self) -> Vec<HeaderName> {
+        self.rules
+            .iter()
+            .filter_map(|r| r.jwt.as_ref())
+            .flat_map(|jwt| jwt.claim_header_names().cloned())
+            .collect()
+    }
+
+    fn rule(&self, path: &str) -> Option<&CompiledRule> {
+        self.rules.iter().find(|r| r.matcher.is_match(path))
+    }
+}
+
+/// Collects credentials from the inline list, the credentials file, and the environment.
+fn load_credentials(rule: &AuthRule) -> Result<Vec<String>> {
+    let mut credentials = rule.credentials.clone();
+    if let Some(path) = &rule.credentials_file {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read credentials file {}: {}", path, e))?;
+        credentials.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_owned),
+        );
+    }
+    if let Some(name) = &rule.credentials_env {
+        let value =
+            env::var(name).map_err(|_| anyhow!("Environment variable {} is not set", name))?;
+        credentials.extend(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(str::to_owned),
+        );
+    }
+    Ok(credentials)
+}
+
+pub async fn authenticate(
+    State(auth): State<Arc<Auth>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // The OIDC callback must be reachable before the user has a session.
+    if let Some(oidc) = auth
+        .rules
+        .iter()
+        .filter_map(|r| r.oidc.as_ref())
+        .find(|o| o.callback_path() == request.uri().path())
+    {
+        return oidc
+            .callback(&auth.sessions, request.uri(), request.headers())
+            .await;
+    }
+
+    let path = match auth.rule_path(request.uri().path()) {
+        Ok(path) => path,
+        // Nothing can be served from a path that doesn't decode, and letting it through
+        // unmatched would skip the rules.
+        Err(_) => {
+            debug!("Malformed request path: {}", request.uri().path());
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+    let Some(rule) = auth.rule(&path) else {
+        return next.run(request).await;
+    };
+
+    let source = format!("basic:{}", rule.realm);
+    let mut new_session = None;
+    let mut claim_headers = None;
+    let authorized = match rule.method {
+        AuthMethod::None => true,
+        AuthMethod::Basic => {
+            // A session from an earlier request spares re-checking the password.
+            let session = auth.sessions.get(request.headers(), &source);
+            if session.is_some_and(|s| rule.credentials.iter().any(|c| user(c) == s.sub)) {
+                true
+            } else {
+                let credentials = basic_credentials(request.headers())
+                    .filter(|c| rule.credentials.iter().any(|k| constant_time_eq(k, c)));
+                new_session = credentials.map(|c| user(&c).to_string());
+                new_session.is_some()
+            }
+        }
+        AuthMethod::Bearer => bearer_token(request.headers())
+            .is_some_and(|t| rule.credentials.iter().any(|k| constant_time_eq(k, t))),
+        AuthMethod::Jwt => match (bearer_token(request.headers()), &rule.jwt) {
+            (Some(token), Some(jwt)) => match jwt.validate(token).await {
+                Ok(claims) => {
+                    claim_headers = Some(jwt.claim_headers(&claims));
+                    true
+                }
+                Err(e) => {
+                    debug!(path = %path, "Invalid token: {}", e);
+                    false
+                }
+            },
+            _ => false,
+        },
+        AuthMethod::Oidc => rule
+            .oidc
+            .as_ref()
+            .is_some_and(|o| auth.sessions.get(request.headers(), o.source()).is_some()),
+        // Rejected when the configuration is loaded.
+        AuthMethod::Mtls => false,
+    };
+    if authorized {
+        debug!(path = %path, pattern = %rule.pattern, "Request authorized");
+        if let Some(headers) = claim_headers {
+            request.extensions_mut().insert(ClaimHeaders(headers));
+        }
+        let mut response = next.run(request).await;
+        if let Some(sub) = new_session {
+            let cookie = auth.sessions.start(&sub, None, &source, false);
+            if let Ok(value) = HeaderValue::from_str(&cookie) {
+                response.headers_mut().append(header::SET_COOKIE, value);
+            }
+        }
+        return response;
+    }
+
+    if let Some(oidc) = &rule.oidc {
+        return oidc.login(&auth.sessions, request.uri()).await;
+    }
+
+    warn!(path = %path, pattern = %rule.pattern, "Unauthorized request");
+    let challenge = match rule.method {
+        AuthMethod::Basic => format!("Basic realm=\"{}\", charset=\"UTF-8\"", rule.realm),
+        _ => format!("Bearer realm=\"{}\"", rule.realm),
+    };
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::WWW_AUTHENTICATE, challenge)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Returns the user name of a `user:password` credential.
+fn user(credential: &str) -> &str {
+    credential
+        .split_once(':')
+        .map_or(credential, |(user, _)| user)
+}
+
+/// Returns the `user:password` pair from a Basic `Authorization` header.
+fn basic_credentials(headers: &HeaderMap) -> Option<String> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router(method: AuthMethod, credential: &str) -> Router {
+        let rules = [AuthRule {
+            pattern: "/private/**".to_string(),
+            method,
+            realm: None,
+            credentials: vec![credential.to_string()],
+            credentials_file: None,
+            credentials_env: None,
+            jwt: None,
+            oidc: None,
+        }];
+        let auth = Auth::new(&rules, &SessionConfig::default(), false).unwrap();
+        Router::new()
+            .route("/{*path}", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(Arc::new(auth), authenticate))
+    }
+
+    async fn get_with(router: &Router, uri: &str, headers: &[(&str, &str)]) -> Response {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[tokio::test]
+    async fn checks_basic_credentials() {
+        let router = router(AuthMethod::Basic, "ada:lovelace");
+        let response = get_with(&router, "/public", &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get_with(&router, "/private/page", &[]).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Basic realm=\"Lime\", charset=\"UTF-8\""
+        );
+        for wrong in ["ada:babbage", "bob:lovelace", "ada"] {
+            let response = get_with(
+                &router,
+                "/private/page",
+                &[("authorization", &basic(wrong))],
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", wrong);
+        }
+
+        let response = get_with(
+            &router,
+            "/private/page",
+            &[("authorization", &basic("ada:lovelace"))],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The session cookie stands in for the password on later requests.
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap();
+        let response = get_with(&router, "/private/other", &[("cookie", cookie)]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn checks_bearer_tokens() {
+        let router = router(AuthMethod::Bearer, "s3cret");
+        let response = get_with(
+            &router,
+            "/private/api",
+            &[("authorization", "Bearer s3cret")],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        for wrong in [
+            "Bearer s3cre",
+            "Bearer s3cret2",
+            "bearer s3cret",
+            "Basic s3cret",
+        ] {
+            let response = get_with(&router, "/private/api", &[("authorization", wrong)]).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", wrong);
+            assert_eq!(
+                response.headers()[header::WWW_AUTHENTICATE],
+                "Bearer realm=\"Lime\""
+            );
+        }
+    }
+}
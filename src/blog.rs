@@ -0,0 +1,587 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{Path as UrlPath, State},
+    http::{StatusCode, header},
+    response::Response,
+    routing::get,
+};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use tracing::warn;
+
+use crate::{
+    catalog::{Catalog, Page},
+    config::BlogConfig,
+    html, listing,
+    server::not_found,
+};
+
+/// Number of posts in the feed.
+const FEED_SIZE: usize = 20;
+
+/// Posts in a directory of the pages, with listings, permalinks and a feed.
+pub struct Blog {
+    config: BlogConfig,
+    /// URL of the first listing page, e.g. `/blog/`.
+    route: String,
+    catalog: Arc<Catalog>,
+    pages_dir: PathBuf,
+    /// Posts already warned about sharing a permalink with another post.
+    collisions: Mutex<HashSet<PathBuf>>,
+}
+
+/// A post with its date and URL resolved.
+pub struct Post {
+    pub path: PathBuf,
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub date: Option<DateTime<Utc>>,
+    /// Whether `url` is a date-based permalink rather than the file URL.
+    pub permalink: bool,
+}
+
+impl Blog {
+    pub fn new(config: &BlogConfig, catalog: Arc<Catalog>, pages_dir: PathBuf) -> Arc<Self> {
+        let dir = config.dir.trim_matches('/');
+        Arc::new(Self {
+            config: BlogConfig {
+                dir: dir.to_string(),
+                per_page: config.per_page.max(1),
+                ..config.clone()
+            },
+            route: format!("/{}/", dir),
+            catalog,
+            pages_dir,
+            collisions: Mutex::default(),
+        })
+    }
+
+    /// Pages in the blog directory except index pages, newest first.
+    /// Posts with the same date, and undated posts at the end, are ordered by title.
+    /// When posts of the same month share a slug, only the newest gets the permalink and
+    /// the others keep their file URL.
+    pub fn posts(&self) -> Vec<Post> {
+        let dir = Path::new(&self.config.dir);
+        let pages = self.catalog.pages();
+        let mut posts: Vec<(Post, &Page)> = pages
+            .iter()
+            .filter(|page| page.path.starts_with(dir) && !page.url.ends_with('/'))
+            .map(|page| (self.post(page), page))
+            .collect();
+        posts.sort_by(|(a, _), (b, _)| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+
+        let mut permalinks: HashMap<String, PathBuf> = HashMap::new();
+        for (post, page) in &mut posts {
+            if !post.permalink {
+                continue;
+            }
+            let Some(taken_by) = permalinks.get(&post.url) else {
+                permalinks.insert(post.url.clone(), post.path.clone());
+                continue;
+            };
+            if self.collisions.lock().unwrap().insert(post.path.clone()) {
+                warn!(
+                    "Posts {} and {} share the permalink {}, so {} is served at {}",
+                    taken_by.display(),
+                    post.path.display(),
+                    post.url,
+                    post.path.display(),
+                    page.url
+                );
+            }
+            post.url = page.url.clone();
+            post.permalink = false;
+        }
+        posts.into_iter().map(|(post, _)| post).collect()
+    }
+
+    /// Finds the page file of a permalink such as `/2024/05/hello`.
+    pub fn resolve_permalink(&self, request_path: &str) -> Option<PathBuf> {
+        if !self.config.permalinks {
+            return None;
+        }
+        self.posts()
+            .into_iter()
+            .find(|post| post.permalink && post.url == request_path)
+            .map(|post| post.path)
+    }
+
+    /// Returns the permalink of the post in `relative`, if it has one.
+    pub fn permalink(&self, relative: &Path) -> Option<String> {
+        if !self.config.permalinks || !relative.starts_with(&self.config.dir) {
+            return None;
+        }
+        self.posts()
+            .into_iter()
+            .find(|post| post.path == relative)
+            .filter(|post| post.permalink)
+            .map(|post| post.url)
+    }
+
+    /// Fills in `{{prev_url}}`, `{{prev_title}}`, `{{next_url}}` and `{{next_title}}` in a post.
+    /// "Previous" is the older post and "next" the newer one; missing ones become empty.
+    pub fn fill_navigation(&self, relative: &Path, html: String) -> String {
+        if !relative.starts_with(&self.config.dir) || !html.contains("{{") {
+            return html;
+        }
+        let posts = self.posts();
+        let Some(index) = posts.iter().position(|post| post.path == relative) else {
+            return html;
+        };
+        let link = |post: Option<&Post>| {
+            post.map(|p| (html::escape(&p.url), html::escape(&p.title)))
+                .unwrap_or_default()
+        };
+        let (next_url, next_title) = link(index.checked_sub(1).and_then(|i| posts.get(i)));
+        let (prev_url, prev_title) = link(posts.get(index + 1));
+        html.replace("{{prev_url}}", &prev_url)
+            .replace("{{prev_title}}", &prev_title)
+            .replace("{{next_url}}", &next_url)
+            .replace("{{next_title}}", &next_title)
+    }
+
+    fn post(&self, page: &Page) -> Post {
+        let stem = page
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // Files named like `2024-05-01-hello.html` carry their date and slug.
+        let (file_date, slug) = match stem.get(..10).and_then(parse_file_date) {
+            Some(date) => (Some(date), stem[10..].trim_start_matches('-').to_string()),
+            None => (None, stem.clone()),
+        };
+        let date = page.front_matter.date.or(file_date);
+        let permalink = date.filter(|_| self.config.permalinks).map(|date| {
+            let date = date.with_timezone(&Local);
+            format!("/{}/{:02}/{}", date.year(), date.month(), slug)
+        });
+        Post {
+            path: page.path.clone(),
+            permalink: permalink.is_some(),
+            url: permalink.unwrap_or_else(|| page.url.clone()),
+            title: page.title.clone(),
+            description: page.front_matter.description.clone(),
+            date,
+        }
+    }
+}
+
+fn parse_file_date(text: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    Local
+        .from_local_datetime(&date.and_time(Default::default()))
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Builds the paginated post listing: `/<dir>/`, `/<dir>/page/2/`, and the feed.
+pub fn blog_router<S: Clone + Send + Sync + 'static>(blog: Arc<Blog>) -> Router<S> {
+    let route = blog.route.clone();
+    Router::new()
+        .route(&route, get(handle_first_page))
+        .route(&format!("{}page/{{number}}/", route), get(handle_page))
+        .route(&format!("{}feed.xml", route), get(handle_feed))
+        .with_state(blog)
+}
+
+async fn handle_first_page(State(blog): State<Arc<Blog>>) -> Response {
+    render_page(&blog, 1).await
+}
+
+async fn handle_page(State(blog): State<Arc<Blog>>, UrlPath(number): UrlPath<String>) -> Response {
+    match number.parse::<usize>() {
+        // The first page only lives at the listing root.
+        Ok(1) => Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header(header::LOCATION, &blog.route)
+            .body(Body::empty())
+            .unwrap(),
+        Ok(number) if number > 1 => render_page(&blog, number).await,
+        _ => not_found(&blog.pages_dir).await,
+    }
+}
+
+async fn render_page(blog: &Blog, number: usize) -> Response {
+    let posts = blog.posts();
+    let per_page = blog.config.per_page;
+    let page_count = posts.len().div_ceil(per_page).max(1);
+    if number > page_count {
+        return not_found(&blog.pages_dir).await;
+    }
+
+    let mut content = String::from("<ul>\n");
+    for post in posts.iter().skip((number - 1) * per_page).take(per_page) {
+        content.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            html::escape(&post.url),
+            html::escape(&post.title)
+        ));
+        if let Some(date) = post.date {
+            content.push_str(&format!(
+                " <time datetime=\"{}\">{}</time>",
+                date.to_rfc3339(),
+                date.with_timezone(&Local).format("%Y-%m-%d")
+            ));
+        }
+        content.push_str("</li>\n");
+    }
+    content.push_str("</ul>\n");
+    content.push_str(&pagination(&blog.route, number, page_count));
+
+    let title = if number == 1 {
+        blog.config.title.clone()
+    } else {
+        format!("{} – page {}", blog.config.title, number)
+    };
+    listing::render(&blog.pages_dir, &title, &content).await
+}
+
+fn pagination(route: &str, number: usize, page_count: usize) -> String {
+    let url = |n: usize| match n {
+        1 => route.to_string(),
+        n => format!("{}page/{}/", route, n),
+    };
+    let mut nav = String::from("<nav>");
+    if number > 1 {
+        nav.push_str(&format!(
+            "<a href=\"{}\" rel=\"prev\">Newer posts</a>",
+            url(number - 1)
+        ));
+    }
+    if number < page_count {
+        nav.push_str(&format!(
+            "<a href=\"{}\" rel=\"next\">Older posts</a>",
+            url(number + 1)
+        ));
+    }
+    nav.push_str("</nav>");
+    nav
+}
+
+/// Serves an RSS 2.0 feed of the latest dated posts.
+async fn handle_feed(State(blog): State<Arc<Blog>>) -> Response {
+    let base_url = blog
+        .config
+        .base_url
+        .as_deref()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    feed.push_str("<rss version=\"2.0\">\n<channel>\n");
+    feed.push_str(&format!(
+        "<title>{}</title>\n<link>{}{}</link>\n<description>{}</description>\n",
+        html::escape(&blog.config.title),
+        html::escape(base_url),
+        html::escape(&blog.route),
+        html::escape(&blog.config.title)
+    ));
+    for post in blog
+        .posts()
+        .iter()
+        .filter(|post| post.date.is_some())
+        .take(FEED_SIZE)
+    {
+        let link = html::escape(&format!("{}{}", base_url, post.url));
+        feed.push_str("<item>\n");
+        feed.push_str(&format!("<title>{}</title>\n", html::escape(&post.title)));
+        feed.push_str(&format!("<link>{}</link>\n<guid>{}</guid>\n", link, link));
+        if let Some(date) = post.date {
+            feed.push_str(&format!("<pubDate>{}</pubDate>\n", date.to_rfc2822()));
+        }
+        if let Some(description) = &post.description {
+            feed.push_str(&format!(
+                "<description>{}</description>\n",
+                html::escape(description)
+            ));
+        }
+        feed.push_str("</item>\n");
+    }
+    feed.push_str("</channel>\n</rss>\n");
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/rss+xml")
+        .body(Body::from(feed))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{body::to_bytes, http::Request};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{config::Config, test_router};
+
+    /// Builds a site with `posts` in `pages/blog` and `blog` as its `[blog]` table.
+    fn site(blog: &str, posts: &[(&str, &str)]) -> (TempDir, Router) {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        fs::create_dir_all(pages.join("blog")).unwrap();
+        for (name, content) in posts {
+            fs::write(pages.join("blog").join(name), content).unwrap();
+        }
+        let mut config: Config =
+            toml::from_str(&format!("[blog]\ndir = \"blog\"\n{}", blog)).unwrap();
+        config.pages_dir = pages.to_string_lossy().into_owned();
+        let router = test_router(&config).unwrap();
+        (dir, router)
+    }
+
+    async fn get(router: &Router, uri: &str) -> (StatusCode, Option<String>, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            location,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    /// Titles of the posts linked from a listing, in order.
+    fn titles(listing: &str) -> Vec<&str> {
+        listing
+            .split("<li><a href=\"")
+            .skip(1)
+            .filter_map(|item| item.split_once("\">")?.1.split_once("</a>"))
+            .map(|(title, _)| title)
+            .collect()
+    }
+
+    fn dated(title: &str, date: &str) -> String {
+        format!(
+            "+++\ntitle = \"{}\"\ndate = {}\n+++\n{}",
+            title, date, title
+        )
+    }
+
+    #[tokio::test]
+    async fn lists_posts_newest_first_across_pages() {
+        let (_dir, router) = site(
+            "per_page = 2",
+            &[
+                ("a.html", &dated("Spring", "2024-04-01")),
+                ("b.html", &dated("Winter", "2024-01-15")),
+                ("c.html", &dated("Summer", "2024-07-01")),
+                ("d.html", "<title>Zebra</title>"),
+                ("e.html", "<title>About this blog</title>"),
+            ],
+        );
+        let (status, _, first) = get(&router, "/blog/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(titles(&first), ["Summer", "Spring"]);
+        let (status, _, second) = get(&router, "/blog/page/2/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(titles(&second), ["Winter", "About this blog"]);
+        let (_, _, third) = get(&router, "/blog/page/3/").await;
+        assert_eq!(titles(&third), ["Zebra"]);
+    }
+
+    #[tokio::test]
+    async fn orders_posts_of_the_same_date_by_title() {
+        let (_dir, router) = site(
+            "",
+            &[
+                ("b.html", &dated("Beta", "2024-04-01")),
+                ("a.html", &dated("Gamma", "2024-04-01")),
+                ("c.html", &dated("Alpha", "2024-04-01")),
+            ],
+        );
+        let (_, _, listing) = get(&router, "/blog/").await;
+        assert_eq!(titles(&listing), ["Alpha", "Beta", "Gamma"]);
+    }
+
+    #[tokio::test]
+    async fn paginates_listings() {
+        let posts: Vec<_> = (1..=5)
+            .map(|day| {
+                (
+                    format!("{}.html", day),
+                    dated(&format!("Day {}", day), &format!("2024-05-0{}", day)),
+                )
+            })
+            .collect();
+        let posts: Vec<_> = posts
+            .iter()
+            .map(|(n, c)| (n.as_str(), c.as_str()))
+            .collect();
+        let (_dir, router) = site("per_page = 2", &posts);
+
+        assert_eq!(
+            get(&router, "/blog/page/1/").await,
+            (
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/blog/".into()),
+                String::new()
+            )
+        );
+        for uri in ["/blog/page/4/", "/blog/page/0/", "/blog/page/two/"] {
+            assert_eq!(get(&router, uri).await.0, StatusCode::NOT_FOUND, "{}", uri);
+        }
+
+        let (_, _, first) = get(&router, "/blog/").await;
+        assert!(
+            first.contains("<nav><a href=\"/blog/page/2/\" rel=\"next\">Older posts</a></nav>"),
+            "{}",
+            first
+        );
+        let (_, _, second) = get(&router, "/blog/page/2/").await;
+        assert!(
+            second.contains(concat!(
+                "<nav><a href=\"/blog/\" rel=\"prev\">Newer posts</a>",
+                "<a href=\"/blog/page/3/\" rel=\"next\">Older posts</a></nav>"
+            )),
+            "{}",
+            second
+        );
+        let (_, _, last) = get(&router, "/blog/page/3/").await;
+        assert!(
+            last.contains("<nav><a href=\"/blog/page/2/\" rel=\"prev\">Newer posts</a></nav>"),
+            "{}",
+            last
+        );
+        assert_eq!(titles(&last), ["Day 1"]);
+    }
+
+    #[tokio::test]
+    async fn serves_posts_at_their_permalinks() {
+        let (_dir, router) = site(
+            "permalinks = true",
+            &[
+                ("2024-05-01-hello.html", "hello"),
+                ("notes.html", "+++\ndate = 2023-11-20T12:00:00Z\n+++\nnotes"),
+                ("about.html", "about"),
+            ],
+        );
+        assert_eq!(get(&router, "/2024/05/hello").await.2, "hello");
+        assert_eq!(get(&router, "/2023/11/notes").await.2, "notes");
+        assert_eq!(
+            get(&router, "/2024/05/notes").await.0,
+            StatusCode::NOT_FOUND
+        );
+
+        for (file, permalink) in [
+            ("/blog/2024-05-01-hello", "/2024/05/hello"),
+            ("/blog/2024-05-01-hello.html", "/2024/05/hello"),
+            ("/blog/notes", "/2023/11/notes"),
+        ] {
+            let (status, location, _) = get(&router, file).await;
+            assert_eq!(status, StatusCode::MOVED_PERMANENTLY, "{}", file);
+            assert_eq!(location.as_deref(), Some(permalink), "{}", file);
+        }
+        // Undated posts have no permalink.
+        assert_eq!(get(&router, "/blog/about").await.2, "about");
+    }
+
+    #[tokio::test]
+    async fn keeps_file_urls_for_colliding_slugs() {
+        let (_dir, router) = site(
+            "permalinks = true",
+            &[
+                ("2024-05-01-hello.html", "first"),
+                ("2024-05-20-hello.html", "second"),
+            ],
+        );
+        assert_eq!(get(&router, "/2024/05/hello").await.2, "second");
+        assert_eq!(
+            get(&router, "/blog/2024-05-01-hello").await,
+            (StatusCode::OK, None, "first".into())
+        );
+        let (_, _, listing) = get(&router, "/blog/").await;
+        assert!(listing.contains("href=\"/2024/05/hello\""), "{}", listing);
+        assert!(
+            listing.contains("href=\"/blog/2024-05-01-hello\""),
+            "{}",
+            listing
+        );
+    }
+
+    #[tokio::test]
+    async fn links_posts_to_their_neighbours() {
+        let navigation = "{{prev_url}}|{{prev_title}}|{{next_url}}|{{next_title}}";
+        let post = |title: &str, date: &str| format!("{}{}", dated(title, date), navigation);
+        let (_dir, router) = site(
+            "permalinks = true",
+            &[
+                ("old.html", &post("Old", "2024-01-10T12:00:00Z")),
+                (
+                    "middle.html",
+                    &post("Middle & more", "2024-02-10T12:00:00Z"),
+                ),
+                ("new.html", &post("New", "2024-03-10T12:00:00Z")),
+            ],
+        );
+        assert_eq!(
+            get(&router, "/2024/02/middle").await.2,
+            "Middle & more/2024/01/old|Old|/2024/03/new|New"
+        );
+        assert_eq!(
+            get(&router, "/2024/01/old").await.2,
+            "Old||/2024/02/middle|Middle &amp; more"
+        );
+        assert_eq!(
+            get(&router, "/2024/03/new").await.2,
+            "New/2024/02/middle|Middle &amp; more||"
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_a_feed_of_dated_posts() {
+        let (_dir, router) = site(
+            "permalinks = true\nbase_url = \"https://example.com/\"\ntitle = \"News\"",
+            &[
+                (
+                    "notes.html",
+                    "+++\ntitle = \"Notes\"\ndescription = \"Tea & cake\"\ndate = 2023-11-20T12:00:00Z\n+++\n",
+                ),
+                ("launch.html", &dated("Launch", "2024-03-01T08:30:00Z")),
+                ("about.html", "<title>About</title>"),
+            ],
+        );
+        let (status, _, feed) = get(&router, "/blog/feed.xml").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            feed.contains("<title>News</title>\n<link>https://example.com/blog/</link>"),
+            "{}",
+            feed
+        );
+        let items: Vec<_> = feed.split("<item>\n").skip(1).collect();
+        assert_eq!(
+            items,
+            [
+                concat!(
+                    "<title>Launch</title>\n",
+                    "<link>https://example.com/2024/03/launch</link>\n",
+                    "<guid>https://example.com/2024/03/launch</guid>\n",
+                    "<pubDate>Fri, 1 Mar 2024 08:30:00 +0000</pubDate>\n",
+                    "</item>\n",
+                ),
+                concat!(
+                    "<title>Notes</title>\n",
+                    "<link>https://example.com/2023/11/notes</link>\n",
+                    "<guid>https://example.com/2023/11/notes</guid>\n",
+                    "<pubDate>Mon, 20 Nov 2023 12:00:00 +0000</pubDate>\n",
+                    "<description>Tea &amp; cake</description>\n",
+                    "</item>\n</channel>\n</rss>\n",
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use tracing::debug;
+
+use crate::{
+    config::{BotAction, BotConfig},
+    probes::is_health_probe,
+};
+
+/// Applied after the configured rules when `default_rules` is on.
+const DEFAULT_RULES: [(&str, BotAction); 24] = [
+    // AI scrapers collecting training data.
+    ("gptbot", BotAction::Block),
+    ("chatgpt-user", BotAction::Block),
+    ("ccbot", BotAction::Block),
+    ("claudebot", BotAction::Block),
+    ("anthropic-ai", BotAction::Block),
+    ("bytespider", BotAction::Block),
+    ("perplexitybot", BotAction::Block),
+    ("meta-externalagent", BotAction::Block),
+    ("cohere-ai", BotAction::Block),
+    ("diffbot", BotAction::Block),
+    ("omgilibot", BotAction::Block),
+    ("imagesiftbot", BotAction::Block),
+    // Vulnerability scanners.
+    ("sqlmap", BotAction::Block),
+    ("nikto", BotAction::Block),
+    ("masscan", BotAction::Block),
+    ("zgrab", BotAction::Block),
+    ("nuclei", BotAction::Block),
+    // SEO crawlers, which are harmless but can be very busy.
+    ("ahrefsbot", BotAction::Limit),
+    ("semrushbot", BotAction::Limit),
+    ("mj12bot", BotAction::Limit),
+    ("dotbot", BotAction::Limit),
+    ("petalbot", BotAction::Limit),
+    ("blexbot", BotAction::Limit),
+    ("dataforseobot", BotAction::Limit),
+];
+
+/// Marks requests that are left out of the page view counts.
+#[derive(Clone, Copy)]
+pub struct Untracked;
+
+/// Blocks, rate limits or stops counting requests by user agent.
+pub struct BotFilter {
+    /// Lowercase user agent fragments, in the order they are tried.
+    rules: Vec<(String, BotAction)>,
+    rate_limit: u32,
+    started: Instant,
+    /// Requests in the current minute by address.
+    requests: Mutex<HashMap<IpAddr, (u64, u32)>>,
+}
+
+impl BotFilter {
+    /// Builds the filter, or `None` when there are no rules.
+    pub fn new(config: &BotConfig) -> Option<Self> {
+        let mut rules: Vec<(String, BotAction)> = config
+            .rules
+            .iter()
+            .map(|rule| (rule.user_agent.to_lowercase(), rule.action))
+            .collect();
+        if config.default_rules {
+            rules.extend(
+                DEFAULT_RULES
+                    .iter()
+                    .map(|(user_agent, action)| (user_agent.to_string(), *action)),
+            );
+        }
+        if rules.is_empty() {
+            return None;
+        }
+        Some(Self {
+            rules,
+            rate_limit: config.rate_limit,
+            started: Instant::now(),
+            requests: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn action(&self, user_agent: &str) -> BotAction {
+        let user_agent = user_agent.to_lowercase();
+        self.rules
+            .iter()
+            .find(|(fragment, _)| user_agent.contains(fragment.as_str()))
+            .map_or(BotAction::Allow, |(_, action)| *action)
+    }
+
+    /// Counts a request from `ip`, returning the seconds to wait if it's over the limit.
+    fn limit(&self, ip: IpAddr) -> Option<u64> {
+        let elapsed = self.started.elapsed().as_secs();
+        let minute = elapsed / 60;
+        let mut requests = self.requests.lock().unwrap();
+        if requests.len() > 10_000 {
+            requests.retain(|_, (m, _)| *m == minute);
+        }
+        let entry = requests.entry(ip).or_insert((minute, 0));
+        if entry.0 != minute {
+            *entry = (minute, 0);
+        }
+        if entry.1 >= self.rate_limit {
+            return Some(60 - elapsed % 60);
+        }
+        entry.1 += 1;
+        None
+    }
+}
+
+pub async fn filter_bots(
+    State(filter): State<Arc<BotFilter>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if is_health_probe(&request) {
+        return next.run(request).await;
+    }
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    match filter.action(user_agent) {
+        BotAction::Allow => {}
+        BotAction::Ignore => {
+            request.extensions_mut().insert(Untracked);
+        }
+        BotAction::Limit => {
+            let ip = request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|c| c.0.ip());
+            if let Some(retry_after) = ip.and_then(|ip| filter.limit(ip)) {
+                return Response::builder()
+                    .status(StatusCode::TOO_MANY_REQUESTS)
+                    .header(header::RETRY_AFTER, retry_after)
+                    .body(Body::empty())
+                    .unwrap();
+            }
+            request.extensions_mut().insert(Untracked);
+        }
+        BotAction::Block => {
+            debug!(user_agent, "Blocked bot");
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .unwrap();
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BotRule;
+
+    #[test]
+    fn configured_rules_come_first() {
+        let config = BotConfig {
+            default_rules: true,
+            rules: vec![BotRule {
+                user_agent: "AhrefsBot".to_string(),
+                action: BotAction::Allow,
+            }],
+            rate_limit: 2,
+        };
+        let filter = BotFilter::new(&config).unwrap();
+        assert_eq!(
+            filter.action("Mozilla/5.0 (compatible; AhrefsBot/7.0)"),
+            BotAction::Allow
+        );
+        assert_eq!(filter.action("Mozilla/5.0 GPTBot/1.2"), BotAction::Block);
+        assert_eq!(filter.action("Mozilla/5.0 Firefox/130.0"), BotAction::Allow);
+
+        let ip = IpAddr::from([203, 0, 113, 7]);
+        assert!(filter.limit(ip).is_none());
+        assert!(filter.limit(ip).is_none());
+        assert!(filter.limit(ip).is_some());
+    }
+}
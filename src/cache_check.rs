@@ -0,0 +1,253 @@
+use std::{fs, path::Path};
+
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use mime_guess::mime::Mime;
+use reqwest::{Client, header};
+
+use crate::{
+    compress::{SIDECAR_EXTENSIONS, SkipList},
+    config::Config,
+    paths,
+};
+
+/// Caching problems found in a single response.
+#[derive(Debug, PartialEq)]
+struct Finding {
+    route: String,
+    status: u16,
+    missing: Vec<&'static str>,
+}
+
+/// Requests every page and static file from the instance at `base_url` and reports the
+/// responses that lack validators, caching rules or compression.
+pub async fn check_cache(config: &Config, base_url: &str) -> Result<()> {
+    let routes = routes(config);
+    let findings = findings(config, base_url, &routes).await?;
+    for finding in &findings {
+        let status = if finding.status < 400 {
+            finding.status.to_string().normal()
+        } else {
+            finding.status.to_string().red()
+        };
+        if finding.missing.is_empty() {
+            println!("{} {}", status, finding.route);
+        } else {
+            println!(
+                "{} {} {} {}",
+                status,
+                finding.route,
+                "missing".yellow(),
+                finding.missing.join(", ")
+            );
+        }
+    }
+    if findings.is_empty() {
+        println!(
+            "{} All {} responses are cacheable",
+            "".green(),
+            routes.len()
+        );
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} of {} responses need attention",
+            findings.len(),
+            routes.len()
+        ))
+    }
+}
+
+/// Requests `routes` from the instance at `base_url` and returns the responses that failed
+/// or lack validators, caching rules or compression.
+async fn findings(config: &Config, base_url: &str, routes: &[String]) -> Result<Vec<Finding>> {
+    let base_url = base_url.trim_end_matches('/');
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+
+    let skip = SkipList::new(&config.compress_skip);
+    let mut findings = Vec::new();
+    for route in routes {
+        let response = client
+            .get(format!("{}{}", base_url, route))
+            .header(header::ACCEPT_ENCODING, "br, zstd, gzip")
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to request {}: {}", route, e))?;
+        let headers = response.headers();
+        let mut missing = Vec::new();
+        if !headers.contains_key(header::ETAG) && !headers.contains_key(header::LAST_MODIFIED) {
+            missing.push("ETag/Last-Modified");
+        }
+        if !headers.contains_key(header::CACHE_CONTROL) {
+            missing.push("Cache-Control");
+        }
+        let compressible = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<Mime>().ok())
+            .is_some_and(|mime| !skip.skips_type(&mime));
+        if compressible && !headers.contains_key(header::CONTENT_ENCODING) {
+            missing.push("compression");
+        }
+        if !response.status().is_success() || !missing.is_empty() {
+            findings.push(Finding {
+                route: route.clone(),
+                status: response.status().as_u16(),
+                missing,
+            });
+        }
+    }
+    Ok(findings)
+}
+
+/// Lists the URLs of every content page and static file.
+fn routes(config: &Config) -> Vec<String> {
+    let mut routes: Vec<String> = paths::content_pages(Path::new(&config.pages_dir))
+        .iter()
+        .map(|page| paths::page_url(page, &config.index_files))
+        .collect();
+    let static_dir = Path::new(&config.static_dir);
+    let mut files = Vec::new();
+    static_files(static_dir, &mut files);
+    routes.extend(files.iter().filter_map(|file| {
+        let relative = file.strip_prefix(static_dir).ok()?;
+        Some(format!(
+            "/{}",
+            relative.to_string_lossy().replace('\\', "/")
+        ))
+    }));
+    routes.sort();
+    routes
+}
+
+fn static_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            static_files(&path, files);
+        } else if path.is_file()
+            && !path
+                .extension()
+                .is_some_and(|e| SIDECAR_EXTENSIONS.iter().any(|s| e == *s))
+        {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, extract::Path as UrlPath, http::StatusCode, response::IntoResponse};
+
+    use super::*;
+
+    /// Answers like a server that forgets a different header on some routes.
+    async fn respond(path: Option<UrlPath<String>>) -> impl IntoResponse {
+        let path = path.map(|UrlPath(path)| path).unwrap_or_default();
+        let mut headers = vec![
+            (header::ETAG, "\"v1\""),
+            (header::CACHE_CONTROL, "max-age=60"),
+            (header::CONTENT_ENCODING, "gzip"),
+            (header::CONTENT_TYPE, "text/html"),
+        ];
+        let status = match path.as_str() {
+            "no-etag" => {
+                headers.retain(|(name, _)| name != header::ETAG);
+                StatusCode::OK
+            }
+            "last-modified" => {
+                headers.retain(|(name, _)| name != header::ETAG);
+                headers.push((header::LAST_MODIFIED, "Tue, 01 Oct 2024 00:00:00 GMT"));
+                StatusCode::OK
+            }
+            "no-cache-control" => {
+                headers.retain(|(name, _)| name != header::CACHE_CONTROL);
+                StatusCode::OK
+            }
+            "uncompressed" => {
+                headers.retain(|(name, _)| name != header::CONTENT_ENCODING);
+                StatusCode::OK
+            }
+            // Images are already compressed, so they are fine without an encoding.
+            "logo.png" => {
+                headers.retain(|(name, _)| {
+                    name != header::CONTENT_ENCODING && name != header::CONTENT_TYPE
+                });
+                headers.push((header::CONTENT_TYPE, "image/png"));
+                StatusCode::OK
+            }
+            "gone.css" => StatusCode::NOT_FOUND,
+            _ => StatusCode::OK,
+        };
+        let mut response = status.into_response();
+        for (name, value) in headers {
+            response
+                .headers_mut()
+                .insert(name, header::HeaderValue::from_static(value));
+        }
+        response
+    }
+
+    #[tokio::test]
+    async fn reports_routes_missing_caching_headers() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route("/", axum::routing::get(respond))
+            .route("/{*path}", axum::routing::get(respond));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let dir = tempfile::tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        let assets = dir.path().join("static");
+        fs::create_dir_all(&pages).unwrap();
+        fs::create_dir_all(&assets).unwrap();
+        for page in [
+            "index",
+            "compliant",
+            "no-etag",
+            "last-modified",
+            "no-cache-control",
+            "uncompressed",
+        ] {
+            fs::write(pages.join(format!("{}.html", page)), "").unwrap();
+        }
+        fs::write(assets.join("logo.png"), "").unwrap();
+        fs::write(assets.join("gone.css"), "").unwrap();
+        let config = Config {
+            pages_dir: pages.to_string_lossy().into_owned(),
+            static_dir: assets.to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+
+        let routes = routes(&config);
+        let findings = findings(&config, &format!("http://{}/", address), &routes)
+            .await
+            .unwrap();
+        let finding = |route: &str, status, missing: &[&'static str]| Finding {
+            route: route.to_string(),
+            status,
+            missing: missing.to_vec(),
+        };
+        assert_eq!(
+            findings,
+            [
+                finding("/gone.css", 404, &[]),
+                finding("/no-cache-control", 200, &["Cache-Control"]),
+                finding("/no-etag", 200, &["ETag/Last-Modified"]),
+                finding("/uncompressed", 200, &["compression"]),
+            ]
+        );
+        assert!(
+            check_cache(&config, &format!("http://{}", address))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    front_matter::{self, FrontMatter},
+    html,
+    paths::{content_pages, page_url, walk},
+};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A content page and its front matter.
+pub struct Page {
+    /// Path relative to the pages directory.
+    pub path: PathBuf,
+    pub url: String,
+    pub title: String,
+    pub front_matter: FrontMatter,
+}
+
+/// Front matter of every page, reloaded when the pages directory changes.
+pub struct Catalog {
+    pages_dir: PathBuf,
+    index_files: Vec<String>,
+    drafts: bool,
+    include: Box<dyn Fn(&str) -> bool + Send + Sync>,
+    pages: RwLock<Arc<Vec<Page>>>,
+    fingerprint: RwLock<Fingerprint>,
+    /// When the next scheduled page is due, which forces a reload.
+    next_publish: RwLock<Option<DateTime<Utc>>>,
+}
+
+/// Number of entries and the latest modification time, enough to notice additions,
+/// removals, and edits without reading every page.
+#[derive(PartialEq, Clone, Copy, Default)]
+pub struct Fingerprint(usize, Option<SystemTime>);
+
+impl Catalog {
+    /// Loads the pages whose URL passes `include`.
+    pub fn load(
+        config: &Config,
+        include: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        let catalog = Self {
+            pages_dir: PathBuf::from(&config.pages_dir),
+            index_files: config.index_files.clone(),
+            drafts: config.drafts,
+            include: Box::new(include),
+            pages: RwLock::new(Arc::default()),
+            fingerprint: RwLock::new(Fingerprint(0, None)),
+            next_publish: RwLock::new(None),
+        };
+        catalog.reload_if_changed();
+        Arc::new(catalog)
+    }
+
+    pub fn pages(&self) -> Arc<Vec<Page>> {
+        self.pages.read().unwrap().clone()
+    }
+
+    pub fn watch(self: &Arc<Self>) {
+        let catalog = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            loop {
+                interval.tick().await;
+                let catalog = Arc::clone(&catalog);
+                let _ = tokio::task::spawn_blocking(move || catalog.reload_if_changed()).await;
+            }
+        });
+    }
+
+    fn reload_if_changed(&self) {
+        let fingerprint = fingerprint(&self.pages_dir);
+        let due = self
+            .next_publish
+            .read()
+            .unwrap()
+            .is_some_and(|at| at <= Utc::now());
+        if fingerprint == *self.fingerprint.read().unwrap() && !due {
+            return;
+        }
+        *self.fingerprint.write().unwrap() = fingerprint;
+
+        let mut pages = Vec::new();
+        let mut next_publish = None;
+        for relative in content_pages(&self.pages_dir) {
+            let url = page_url(&relative, &self.index_files);
+            if !(self.include)(&url) {
+                continue;
+            }
+            let path = self.pages_dir.join(&relative);
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("failed to read {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let (front_matter, content) = front_matter::split(&content);
+            if let Some(at) = front_matter.publish_at.filter(|at| *at > Utc::now()) {
+                next_publish = Some(next_publish.map_or(at, |next: DateTime<Utc>| next.min(at)));
+            }
+            if !self.drafts && front_matter.is_hidden(&relative) {
+                continue;
+            }
+            let title = front_matter
+                .title
+                .clone()
+                .or_else(|| html::title(content))
+                .unwrap_or_else(|| url.clone());
+            pages.push(Page {
+                path: relative,
+                url,
+                title,
+                front_matter,
+            });
+        }
+        pages.sort_by(|a, b| a.title.cmp(&b.title));
+        info!(count = pages.len(), "Loaded page catalog");
+        *self.pages.write().unwrap() = Arc::new(pages);
+        *self.next_publish.write().unwrap() = next_publish;
+    }
+}
+
+pub fn fingerprint(dir: &Path) -> Fingerprint {
+    let mut total = Fingerprint(0, fs::metadata(dir).and_then(|m| m.modified()).ok());
+    walk(dir, &mut |_, metadata| {
+        if !metadata.is_dir() {
+            total.0 += 1;
+        }
+        total.1 = total.1.max(metadata.modified().ok());
+    });
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_files_once() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("index.html"), "").unwrap();
+        fs::write(dir.path().join("docs/guide.html"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("docs/up")).unwrap();
+
+        let before = fingerprint(dir.path());
+        assert_eq!(before.0, 2);
+        fs::write(dir.path().join("docs/new.html"), "").unwrap();
+        assert_eq!(fingerprint(dir.path()).0, 3);
+    }
+}
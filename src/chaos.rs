@@ -0,0 +1,113 @@
+use std::{io, sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use globset::{Glob, GlobMatcher};
+use tracing::debug;
+
+use crate::config::{ChaosRule, Config};
+
+/// Slows down, fails and drops responses on purpose, to test how an app copes.
+pub struct Chaos {
+    rules: Vec<(GlobMatcher, ChaosRule)>,
+}
+
+impl Chaos {
+    /// Returns `None` unless `lime serve --chaos` turned the `[[chaos]]` rules on.
+    pub fn new(config: &Config) -> Result<Option<Self>> {
+        if !config.chaos_enabled || config.chaos_rules.is_empty() {
+            return Ok(None);
+        }
+        let rules = config
+            .chaos_rules
+            .iter()
+            .map(|rule| {
+                for (name, rate) in [
+                    ("error_rate", rule.error_rate),
+                    ("drop_rate", rule.drop_rate),
+                ] {
+                    if !(0.0..=1.0).contains(&rate) {
+                        return Err(anyhow!(
+                            "'{}' must be between 0.0 and 1.0, got {}",
+                            name,
+                            rate
+                        ));
+                    }
+                }
+                let matcher = Glob::new(&rule.pattern)
+                    .map_err(|e| anyhow!("Invalid chaos pattern '{}': {}", rule.pattern, e))?
+                    .compile_matcher();
+                Ok((matcher, rule.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Self { rules }))
+    }
+
+    fn rule(&self, path: &str) -> Option<&ChaosRule> {
+        self.rules
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(path))
+            .map(|(_, rule)| rule)
+    }
+}
+
+pub async fn inject_chaos(
+    State(chaos): State<Arc<Chaos>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(rule) = chaos.rule(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let latency = rule.latency + rand::random_range(0..=rule.jitter);
+    if latency > 0 {
+        tokio::time::sleep(Duration::from_millis(latency)).await;
+    }
+    if rand::random::<f64>() < rule.drop_rate {
+        debug!(path = %request.uri().path(), "Dropping the connection on purpose");
+        // A body that fails makes the server close the connection mid-response.
+        let failing = tokio_stream::once(Err::<Bytes, _>(io::Error::other("chaos")));
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from_stream(failing))
+            .unwrap();
+    }
+    if rand::random::<f64>() < rule.error_rate {
+        debug!(path = %request.uri().path(), "Failing the request on purpose");
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_applies_when_turned_on() {
+        let mut config = Config {
+            chaos_rules: vec![ChaosRule {
+                pattern: "/api/**".to_string(),
+                error_rate: 1.5,
+                ..ChaosRule::default()
+            }],
+            ..Config::default()
+        };
+        assert!(Chaos::new(&config).unwrap().is_none());
+        config.chaos_enabled = true;
+        assert!(Chaos::new(&config).is_err());
+        config.chaos_rules[0].error_rate = 0.5;
+        let chaos = Chaos::new(&config).unwrap().unwrap();
+        assert!(chaos.rule("/api/users").is_some());
+        assert!(chaos.rule("/index.html").is_none());
+    }
+}
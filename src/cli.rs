@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand};
 
+use crate::config::Config;
+
 #[derive(Parser)]
 #[command(
     name = "lime",
@@ -13,10 +15,184 @@ pub struct Cli {
     /// Path to the configuration file.
     #[arg(short, long, default_value = "lime.toml")]
     pub config: Option<String>,
+
+    /// Do not print anything to the console on startup and only log errors.
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Increase log verbosity (-v for info, -vv for debug, -vvv for trace).
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Do not print the startup banner.
+    #[arg(long, global = true)]
+    pub no_banner: bool,
+
+    /// Disable colored output.
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Serve draft pages.
+    #[arg(long, global = true)]
+    pub drafts: bool,
+}
+
+impl Cli {
+    /// Applies the global flags to `config`. They take precedence over the configuration
+    /// file.
+    pub fn apply(&self, config: &mut Config) {
+        config.quiet |= self.quiet;
+        config.banner &= !self.no_banner;
+        config.color &= !self.no_color;
+        config.drafts |= self.drafts;
+        if config.quiet {
+            config.log_level = "error".to_string();
+        }
+        match self.verbose {
+            0 => {}
+            1 => config.log_level = "info".to_string(),
+            2 => config.log_level = "debug".to_string(),
+            _ => config.log_level = "trace".to_string(),
+        }
+    }
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Start an HTML server.
-    Serve,
+    Serve {
+        /// Show a live dashboard of requests instead of the log.
+        #[arg(long)]
+        tui: bool,
+        /// Record a timeline of request handling to this file: a Chrome trace, or folded
+        /// stacks for flame graphs when the name ends in `.folded`.
+        #[arg(long, value_name = "FILE")]
+        trace: Option<String>,
+        /// Seconds to record the timeline for.
+        #[arg(long, default_value_t = 30, value_name = "SECONDS")]
+        trace_duration: u64,
+        /// Delay, fail and drop responses as the `[[chaos]]` rules say, for testing.
+        #[arg(long)]
+        chaos: bool,
+    },
+    /// Build the search index from scratch.
+    Index,
+    /// Write .gz, .br and .zst copies of compressible files.
+    Compress {
+        /// Directories to compress (defaults to the static and pages directories).
+        dirs: Vec<String>,
+    },
+    /// Check pages for unclosed tags, missing titles and alt texts, and broken links.
+    #[command(alias = "doctor")]
+    Lint,
+    /// List the URL of every page and the file it's served from.
+    Routes,
+    /// Report links to pages and files that don't exist.
+    Links {
+        /// Also check links to other sites.
+        #[arg(long)]
+        external: bool,
+        /// How many external links to check at once.
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+    },
+    /// Report responses of a running server that lack caching headers or compression.
+    CheckCache {
+        /// Address of the server (defaults to the configured host and port).
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Bundle this executable, the site and its configuration into a single executable
+    /// that serves the site when run without arguments.
+    Pack {
+        /// Where to write the executable.
+        #[arg(short, long, default_value = "lime-site")]
+        output: String,
+    },
+    /// Print the page views and visitors counted by `[analytics]`.
+    Stats {
+        /// How many days up to today to show.
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+        /// How many pages and referring sites to list.
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Inspect the configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Replace this executable with the latest release.
+    SelfUpdate {
+        /// Only report whether a newer release is available.
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective configuration and where each value comes from.
+    Print,
+    /// Print the effective value of a key, e.g. `search.enabled`.
+    Get { key: String },
+    /// Set a key in the config file, keeping its comments and formatting.
+    Set { key: String, value: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(args: &[&str], config: &mut Config) {
+        Cli::parse_from(["lime"].iter().chain(args)).apply(config);
+    }
+
+    #[test]
+    fn flags_override_output_settings() {
+        let mut config = Config::default();
+        apply(&["serve"], &mut config);
+        assert!(!config.quiet && config.banner && config.color);
+
+        apply(&["--no-banner", "serve", "--no-color"], &mut config);
+        assert!(!config.quiet && !config.banner && !config.color);
+
+        // Flags only ever turn output down, never back on.
+        let mut config = Config {
+            quiet: true,
+            banner: false,
+            ..Config::default()
+        };
+        apply(&["serve"], &mut config);
+        assert!(config.quiet && !config.banner);
+
+        let mut config = Config::default();
+        apply(&["lint", "-q"], &mut config);
+        assert!(config.quiet);
+    }
+
+    #[test]
+    fn verbosity_sets_log_level() {
+        for (args, level) in [
+            (&["-v", "serve"][..], "info"),
+            (&["serve", "-vv"], "debug"),
+            (&["-vvvv", "serve"], "trace"),
+            (&["-q", "serve"], "error"),
+        ] {
+            let mut config = Config {
+                log_level: "warn".to_string(),
+                ..Config::default()
+            };
+            apply(args, &mut config);
+            assert_eq!(config.log_level, level, "{:?}", args);
+        }
+        // The configured level stands without flags.
+        let mut config = Config {
+            log_level: "lime=debug".to_string(),
+            ..Config::default()
+        };
+        apply(&["serve"], &mut config);
+        assert_eq!(config.log_level, "lime=debug");
+    }
 }
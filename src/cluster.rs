@@ -0,0 +1,218 @@
+use std::{env, ffi::OsString, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use tokio::{process::Command, sync::watch, task::JoinSet};
+use tracing::error;
+
+use crate::{
+    config::Config,
+    handoff::{PidFile, shutdown_signal},
+    logging::init_logging,
+    notify::Notifier,
+    server::{announce, notify_started},
+};
+
+/// Set for worker processes started by the supervisor, to their index.
+const WORKER_ENV: &str = "LIME_WORKER";
+
+/// Delay before a worker that exited is started again.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Time the workers get to start listening before an older server is asked to hand over.
+const STARTUP_GRACE: Duration = Duration::from_secs(1);
+
+/// Whether this process is a worker started by [`supervise`].
+pub fn is_worker() -> bool {
+    env::var_os(WORKER_ENV).is_some()
+}
+
+/// Adjusts the configuration of a worker: workers share the port, and the supervisor
+/// prints the banner and owns the pid file.
+pub fn configure_worker(config: &mut Config) {
+    config.reuse_port = true;
+    config.quiet = true;
+    config.pid_file = None;
+}
+
+/// Runs `config.workers` copies of this process serving the same port, and starts any
+/// worker that exits again until the supervisor is asked to stop.
+pub async fn supervise(config: &Config) -> Result<()> {
+    if cfg!(not(unix)) {
+        return Err(anyhow!("'workers' is only supported on Unix"));
+    }
+    init_logging(config)?;
+    let notifier = Notifier::new(config)?;
+    let exe = env::current_exe()
+        .map_err(|e| anyhow!("Failed to locate the current executable: {}", e))?;
+    let args = Arc::new(env::args_os().skip(1).collect::<Vec<_>>());
+    let exe = Arc::new(exe);
+
+    let (stop, stopping) = watch::channel(false);
+    let mut workers = JoinSet::new();
+    for index in 0..config.workers {
+        workers.spawn(run_worker(
+            index,
+            exe.clone(),
+            args.clone(),
+            stopping.clone(),
+        ));
+    }
+
+    tokio::time::sleep(STARTUP_GRACE).await;
+    let _pid_file = config
+        .pid_file
+        .as_deref()
+        .map(|path| PidFile::create(path, config.reuse_port))
+        .transpose()?;
+    announce(config, &format!(" ({} workers)", config.workers));
+    notify_started(notifier.clone());
+
+    shutdown_signal().await;
+    let _ = stop.send(true);
+    while workers.join_next().await.is_some() {}
+    if let Some(notifier) = notifier {
+        notifier.stopped().await;
+    }
+    Ok(())
+}
+
+/// Keeps worker `index` running until `stopping` is set, then asks it to drain and exit.
+async fn run_worker(
+    index: usize,
+    exe: Arc<PathBuf>,
+    args: Arc<Vec<OsString>>,
+    mut stopping: watch::Receiver<bool>,
+) {
+    while !*stopping.borrow() {
+        let mut child = match Command::new(exe.as_path())
+            .args(args.iter())
+            .env(WORKER_ENV, index.to_string())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start worker {}: {}", index, e);
+                tokio::time::sleep(RESTART_DELAY).await;
+                continue;
+            }
+        };
+        tokio::select! {
+            status = child.wait() => {
+                // Workers get Ctrl+C too, so give the supervisor time to notice first.
+                let stopped = tokio::time::timeout(RESTART_DELAY, stopping.wait_for(|s| *s))
+                    .await
+                    .is_ok();
+                if stopped {
+                    return;
+                }
+                match status {
+                    Ok(status) => error!("Worker {} exited with {}, restarting", index, status),
+                    Err(e) => error!("Failed to wait for worker {}: {}", index, e),
+                }
+            }
+            _ = stopping.changed() => {
+                terminate(&mut child).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Sends `SIGTERM`, so the worker finishes its requests before exiting.
+async fn terminate(child: &mut tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id().and_then(|pid| i32::try_from(pid).ok()) {
+        use nix::{
+            sys::signal::{Signal, kill},
+            unistd::Pid,
+        };
+        let _ = kill(Pid::from_raw(pid), Signal::SIGTERM);
+    }
+    #[cfg(not(unix))]
+    let _ = child.start_kill();
+    let _ = child.wait().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    #[test]
+    fn configures_workers() {
+        let mut config = Config {
+            reuse_port: false,
+            quiet: false,
+            pid_file: Some("lime.pid".to_string()),
+            ..Config::default()
+        };
+        configure_worker(&mut config);
+        assert!(config.reuse_port);
+        assert!(config.quiet);
+        assert_eq!(config.pid_file, None);
+    }
+
+    /// Starts a worker that runs `script` in `sh`, after appending a line to `starts`.
+    fn start(starts: &Path, script: &str) -> (watch::Sender<bool>, tokio::task::JoinHandle<()>) {
+        let script = format!("echo >> '{}'; {}", starts.display(), script);
+        let args = vec![OsString::from("-c"), OsString::from(script)];
+        let (stop, stopping) = watch::channel(false);
+        let worker = tokio::spawn(run_worker(
+            0,
+            Arc::new(PathBuf::from("/bin/sh")),
+            Arc::new(args),
+            stopping,
+        ));
+        (stop, worker)
+    }
+
+    fn count(starts: &Path) -> usize {
+        fs::read_to_string(starts).map_or(0, |s| s.lines().count())
+    }
+
+    #[tokio::test]
+    async fn restarts_workers_that_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let starts = dir.path().join("starts");
+        let (stop, worker) = start(&starts, "exit 1");
+        tokio::time::sleep(RESTART_DELAY * 2 + RESTART_DELAY / 2).await;
+        assert!(count(&starts) >= 2, "{}", count(&starts));
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(RESTART_DELAY * 3, worker)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_running_workers() {
+        let dir = tempfile::tempdir().unwrap();
+        let starts = dir.path().join("starts");
+        let (stop, worker) = start(&starts, "exec sleep 30");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(count(&starts), 1);
+    }
+
+    #[tokio::test]
+    async fn doesnt_restart_workers_that_exit_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let starts = dir.path().join("starts");
+        // The worker exits right away, as it would on Ctrl+C, and the stop follows shortly.
+        let (stop, worker) = start(&starts, "exit 0");
+        tokio::time::sleep(RESTART_DELAY / 4).await;
+        stop.send(true).unwrap();
+        tokio::time::timeout(RESTART_DELAY * 2, worker)
+            .await
+            .unwrap()
+            .unwrap();
+        tokio::time::sleep(RESTART_DELAY / 2).await;
+        assert_eq!(count(&starts), 1);
+    }
+}
@@ -1,8 +1,181 @@
-use anyhow::Result;
+use std::{path::Path, sync::Arc};
 
-use crate::{config::Config, server::start_server};
+use anyhow::{Result, anyhow};
 
-pub async fn handle_serve(config: &Config) -> Result<()> {
-    start_server(config).await?;
+use crate::{
+    analytics::print_stats,
+    auth::Auth,
+    cache_check::check_cache,
+    cli::ConfigCommands,
+    cluster,
+    compress::{SkipList, compress_dir},
+    config::{AuthMethod, Config},
+    config_edit, effective_config,
+    links::check_links,
+    lint::lint,
+    pack::pack,
+    routes::print_routes,
+    search::Search,
+    server::start_server,
+    storage::mirror_buckets,
+    update::self_update,
+};
+
+pub async fn handle_serve(config: &mut Config, tui: bool) -> Result<()> {
+    mirror_buckets(config).await?;
+    if config.workers > 1 && !cluster::is_worker() {
+        if tui {
+            return Err(anyhow!("--tui can't be used with more than one worker"));
+        }
+        if config.trace_file.is_some() {
+            return Err(anyhow!("--trace can't be used with more than one worker"));
+        }
+        // Every worker would keep its own counts and overwrite the others' in the file.
+        if config.analytics.enabled {
+            return Err(anyhow!("analytics can't be used with more than one worker"));
+        }
+        // Every worker would sign sessions with its own random key and reject the others'.
+        let uses_sessions = config
+            .auth_rules
+            .iter()
+            .any(|rule| matches!(rule.method, AuthMethod::Basic | AuthMethod::Oidc));
+        if uses_sessions && config.session.secret.is_none() {
+            return Err(anyhow!(
+                "'secret' must be set in [session] to use basic or oidc auth with more than one worker"
+            ));
+        }
+        // Only one process can hold the writer lock of an index on disk.
+        if config.search.enabled && config.search.index_dir.is_some() {
+            return Err(anyhow!(
+                "a search 'index_dir' can't be used with more than one worker, leave it unset to keep the index in memory"
+            ));
+        }
+        return cluster::supervise(config).await;
+    }
+    start_server(config, tui).await?;
+    Ok(())
+}
+
+pub async fn handle_index(config: &Config) -> Result<()> {
+    let Some(index_dir) = &config.search.index_dir else {
+        return Err(anyhow!(
+            "'index_dir' must be set in [search] to build the index ahead of time"
+        ));
+    };
+    let auth = Arc::new(Auth::new(
+        &config.auth_rules,
+        &config.session,
+        config.case_insensitive,
+    )?);
+    let search = Search::open(config, move |url| !auth.is_protected(url))?;
+    let count = search.rebuild()?;
+    if !config.quiet {
+        println!("Indexed {} pages into {}", count, index_dir);
+    }
+    Ok(())
+}
+
+pub async fn handle_compress(config: &Config, dirs: &[String]) -> Result<()> {
+    let dirs = if dirs.is_empty() {
+        vec![config.static_dir.clone(), config.pages_dir.clone()]
+    } else {
+        dirs.to_vec()
+    };
+    let skip = SkipList::new(&config.compress_skip);
+    for dir in &dirs {
+        if !Path::new(dir).is_dir() {
+            continue;
+        }
+        let summary = compress_dir(Path::new(dir), &skip)?;
+        if !config.quiet {
+            println!(
+                "Compressed {} files in {} ({} sidecars written, {} bytes saved)",
+                summary.files, dir, summary.written, summary.saved
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_check_cache(config: &Config, url: Option<String>) -> Result<()> {
+    let url = url.unwrap_or_else(|| {
+        // A server listening on every interface is reachable locally.
+        let host = match config.host.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            host => host,
+        };
+        format!("http://{}:{}", host, config.port)
+    });
+    check_cache(config, &url).await
+}
+
+pub async fn handle_lint(config: &Config) -> Result<()> {
+    lint(config)
+}
+
+pub async fn handle_routes(config: &Config) -> Result<()> {
+    print_routes(config)
+}
+
+pub async fn handle_links(config: &Config, external: bool, concurrency: usize) -> Result<()> {
+    check_links(config, external, concurrency).await
+}
+
+pub async fn handle_pack(config: &Config, config_path: &str, output: &str) -> Result<()> {
+    pack(config, config_path, output)
+}
+
+pub async fn handle_stats(config: &Config, days: u64, top: usize) -> Result<()> {
+    print_stats(config, days, top)
+}
+
+pub async fn handle_self_update(check: bool) -> Result<()> {
+    self_update(check).await
+}
+
+pub async fn handle_config(config: &Config, path: &str, command: ConfigCommands) -> Result<()> {
+    match command {
+        ConfigCommands::Print => print!("{}", effective_config::render(config, path)?),
+        ConfigCommands::Get { key } => println!("{}", config_edit::get(config, &key)?),
+        ConfigCommands::Set { key, value } => config_edit::set(path, &key, &value)?,
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn workers_need_a_session_secret() {
+        let mut config: Config = toml::from_str(
+            r#"
+            workers = 2
+
+            [[auth]]
+            pattern = "/internal/**"
+            method = "basic"
+            credentials = ["ada:lovelace"]
+            "#,
+        )
+        .unwrap();
+        let error = handle_serve(&mut config, false).await.unwrap_err();
+        assert!(error.to_string().contains("[session]"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn workers_cant_share_a_search_index() {
+        let mut config: Config = toml::from_str(
+            r#"
+            workers = 2
+
+            [search]
+            enabled = true
+            index_dir = "/var/lib/lime/index"
+            "#,
+        )
+        .unwrap();
+        let error = handle_serve(&mut config, false).await.unwrap_err();
+        assert!(error.to_string().contains("index_dir"), "{}", error);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use flate2::{Compression, write::GzEncoder};
+use mime_guess::mime::Mime;
+
+/// Extensions of the sidecar files, which are never compressed again.
+pub const SIDECAR_EXTENSIONS: [&str; 3] = ["gz", "br", "zst"];
+
+/// Files smaller than this gain nothing from compression.
+const MIN_SIZE: u64 = 256;
+
+/// Extensions of formats that are compressed already.
+const COMPRESSED_EXTENSIONS: [&str; 22] = [
+    "gz", "br", "zst", "zip", "tgz", "bz2", "xz", "7z", "rar", "svgz", "jpg", "jpeg", "png", "gif",
+    "webp", "avif", "mp3", "mp4", "webm", "ogg", "woff", "woff2",
+];
+
+/// Leading bytes of compressed formats, to catch files whose extension hides them.
+const COMPRESSED_SIGNATURES: [&[u8]; 10] = [
+    b"\x1f\x8b",           // gzip
+    b"PK\x03\x04",         // zip
+    b"\x28\xb5\x2f\xfd",   // zstd
+    b"BZh",                // bzip2
+    b"\xfd7zXZ\x00",       // xz
+    b"7z\xbc\xaf\x27\x1c", // 7-Zip
+    b"\x89PNG",            // PNG
+    b"\xff\xd8\xff",       // JPEG
+    b"wOFF",               // WOFF
+    b"wOF2",               // WOFF2
+];
+
+#[derive(Default)]
+pub struct Summary {
+    pub files: usize,
+    pub written: usize,
+    pub saved: u64,
+}
+
+/// Decides which files are never compressed: formats that are compressed already, and the
+/// MIME types and extensions listed in `compress_skip`.
+pub struct SkipList {
+    extensions: Vec<String>,
+    types: Vec<String>,
+}
+
+impl SkipList {
+    /// Reads entries like `.dat` (an extension) or `video/*` (MIME types).
+    pub fn new(entries: &[String]) -> Self {
+        let (types, extensions): (Vec<String>, Vec<String>) = entries
+            .iter()
+            .map(|entry| entry.trim().to_ascii_lowercase())
+            .partition(|entry| entry.contains('/'));
+        Self {
+            extensions: extensions
+                .into_iter()
+                .map(|e| e.trim_start_matches('.').to_string())
+                .collect(),
+            types,
+        }
+    }
+
+    /// Whether content of this type is sent and stored as it is.
+    pub fn skips_type(&self, mime: &Mime) -> bool {
+        !is_compressible_type(mime)
+            || self
+                .types
+                .iter()
+                .any(|skipped| match skipped.strip_suffix("/*") {
+                    Some(type_) => mime.type_() == type_,
+                    None => mime.essence_str() == skipped,
+                })
+    }
+
+    fn skips_path(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        COMPRESSED_EXTENSIONS.contains(&extension.as_str())
+            || self.extensions.contains(&extension)
+            || self.skips_type(&mime_guess::from_path(path).first_or_octet_stream())
+    }
+}
+
+/// Whether `data` starts like a compressed format.
+pub fn is_compressed(data: &[u8]) -> bool {
+    COMPRESSED_SIGNATURES
+        .iter()
+        .any(|signature| data.starts_with(signature))
+}
+
+/// Writes `.gz`, `.br` and `.zst` sidecars next to every compressible file under `dir`.
+/// Sidecars newer than their source are left alone, and ones that would not be smaller are skipped.
+pub fn compress_dir(dir: &Path, skip: &SkipList) -> Result<Summary> {
+    let mut summary = Summary::default();
+    for path in compressible_files(dir, skip)? {
+        let source =
+            fs::read(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        if is_compressed(&source) {
+            continue;
+        }
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        summary.files += 1;
+
+        for extension in SIDECAR_EXTENSIONS {
+            let sidecar = sidecar_path(&path, extension);
+            let sidecar_modified = fs::metadata(&sidecar).and_then(|m| m.modified()).ok();
+            if sidecar_modified.is_some() && sidecar_modified >= modified {
+                continue;
+            }
+            let compressed = match extension {
+                "gz" => gzip(&source),
+                "br" => brotli(&source),
+                _ => zstd(&source),
+            }
+            .map_err(|e| anyhow!("Failed to compress {}: {}", path.display(), e))?;
+            if compressed.len() >= source.len() {
+                continue;
+            }
+            fs::write(&sidecar, &compressed)
+                .map_err(|e| anyhow!("Failed to write {}: {}", sidecar.display(), e))?;
+            summary.written += 1;
+            summary.saved += (source.len() - compressed.len()) as u64;
+        }
+    }
+    Ok(summary)
+}
+
+fn compressible_files(dir: &Path, skip: &SkipList) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let entries =
+        fs::read_dir(dir).map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            files.extend(compressible_files(&path, skip)?);
+        } else if metadata.is_file() && metadata.len() >= MIN_SIZE && !skip.skips_path(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Whether content of this type shrinks noticeably when compressed.
+fn is_compressible_type(mime: &Mime) -> bool {
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("text", _) => true,
+        ("image", "svg") => true,
+        ("application", subtype) => matches!(
+            subtype,
+            "javascript" | "json" | "xml" | "wasm" | "manifest+json" | "rss+xml" | "atom+xml"
+        ),
+        ("font", subtype) => matches!(subtype, "ttf" | "otf"),
+        _ => false,
+    }
+}
+
+/// Where the copy of `path` compressed with the format of `extension` is written.
+pub fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn brotli(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    {
+        let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, 11, 22);
+        encoder.write_all(data)?;
+    }
+    Ok(output)
+}
+
+fn zstd(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::encode_all(data, *zstd::compression_level_range().end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_sidecars_for_compressible_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let css = "body { color: black; }\n".repeat(64);
+        fs::write(dir.path().join("style.css"), &css).unwrap();
+        fs::write(dir.path().join("tiny.js"), "let a = 1;").unwrap();
+        fs::write(dir.path().join("photo.png"), vec![0u8; 4096]).unwrap();
+        fs::write(dir.path().join("table.csv"), "a,b\n".repeat(256)).unwrap();
+        let mut gzipped = gzip(css.as_bytes()).unwrap();
+        gzipped.resize(4096, 0);
+        fs::write(dir.path().join("bundle.js"), gzipped).unwrap();
+        let skip = SkipList::new(&["text/csv".to_string()]);
+
+        let summary = compress_dir(dir.path(), &skip).unwrap();
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.written, 3);
+        for extension in SIDECAR_EXTENSIONS {
+            assert!(dir.path().join(format!("style.css.{}", extension)).exists());
+        }
+        assert!(!dir.path().join("tiny.js.gz").exists());
+        assert!(!dir.path().join("photo.png.gz").exists());
+        assert!(!dir.path().join("table.csv.gz").exists());
+        assert!(!dir.path().join("bundle.js.gz").exists());
+
+        // Fresh sidecars are not written again.
+        assert_eq!(compress_dir(dir.path(), &skip).unwrap().written, 0);
+    }
+}
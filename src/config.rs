@@ -1,23 +1,1001 @@
-use std::fs;
+use std::{collections::BTreeMap, fmt, fs};
 
 use anyhow::Result;
-use serde::Deserialize;
+use axum::Router;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 
-#[derive(Debug, Deserialize)]
+use crate::error::LimeError;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub host: String,
     pub port: i32,
-    pub working_dir: String,
+    /// Bind with `SO_REUSEPORT`, so a new process can take over the port (Unix only).
+    pub reuse_port: bool,
+    /// File holding the server's process ID. With `reuse_port`, a new process asks the one
+    /// recorded here to drain and exit once it is listening.
+    pub pid_file: Option<String>,
+    /// Number of worker processes sharing the port; more than one needs Unix.
+    pub workers: usize,
+    /// Directory with HTML pages.
+    pub pages_dir: String,
+    /// Directory with static assets (CSS, JavaScript, images, etc.).
+    pub static_dir: String,
+    /// File names tried, in order, when a directory is requested.
+    pub index_files: Vec<String>,
+    pub trailing_slash: TrailingSlash,
+    /// Files larger than this are refused instead of being served.
+    pub max_file_size: Option<ByteSize>,
+    /// Charset of pages that aren't UTF-8 and don't declare one, e.g. `Shift_JIS`.
+    pub legacy_charset: Option<String>,
+    /// Bytes per second responses are sent at on each connection, at most.
+    pub max_rate_per_connection: Option<ByteRate>,
+    /// Bytes per second all responses together are sent at, at most.
+    pub max_rate: Option<ByteRate>,
+    /// Directories outside of the served ones that symlinks may point into.
+    pub symlink_targets: Vec<String>,
+    /// TOML or CSV file mapping old URLs to new ones.
+    pub redirects_file: Option<String>,
+    /// Match request paths against files ignoring case.
+    pub case_insensitive: bool,
+    /// How the `ETag` of served files is computed.
+    pub etag: EtagMode,
+    /// Additional directories exposed under URL prefixes.
+    #[serde(rename = "mount")]
+    pub mounts: Vec<MountConfig>,
+    /// Treat each folder in `pages_dir` as a site of its own, served under `/<folder>/`,
+    /// with a page linking to them all at `/`.
+    pub multi_site: bool,
+    pub quiet: bool,
+    pub banner: bool,
+    /// Print how many pages and static assets the site has on startup.
+    pub route_summary: bool,
+    pub color: bool,
+    /// Log filter used when `RUST_LOG` is not set (e.g. `info` or `lime=debug`).
+    pub log_level: String,
+    pub log_output: LogOutput,
+    /// Path to the log file when `log_output` is `file`.
+    pub log_file: Option<String>,
+    /// Glob patterns of request paths that are not written to the access log.
+    pub log_exclude: Vec<String>,
+    /// nginx-style access log template, e.g. `$remote_addr "$request" $status`.
+    pub log_format: Option<String>,
+    /// How client IPs appear in the access log.
+    pub log_ip: LogIp,
+    /// Write `User-Agent` headers to the access log.
+    pub log_user_agent: bool,
+    /// Share of successful requests written to the access log, from `0.0` to `1.0`.
+    /// Requests that failed are always logged.
+    pub log_sample_rate: f64,
+    /// Serve a built-in icon when `/favicon.ico` does not exist.
+    pub favicon: bool,
+    /// Serve files and directories whose name starts with a dot, other than `.well-known`.
+    pub serve_dotfiles: bool,
+    /// Answer requests that are denied with `404 Not Found` instead of `403 Forbidden`,
+    /// so it isn't revealed what exists.
+    pub mask_forbidden: bool,
+    /// Check for a newer release in the background on startup.
+    pub update_check: bool,
+    #[serde(rename = "inject")]
+    pub inject_rules: Vec<InjectRule>,
+    #[serde(rename = "headers")]
+    pub header_rules: Vec<HeaderRule>,
+    #[serde(rename = "throttle")]
+    pub throttle_rules: Vec<ThrottleRule>,
+    #[serde(rename = "chaos")]
+    pub chaos_rules: Vec<ChaosRule>,
+    /// MIME types (`video/*`) and extensions (`.dat`) that are never compressed, besides
+    /// formats that are compressed already.
+    pub compress_skip: Vec<String>,
+    /// Paths (`/photos/**`) served from the copies `lime compress` writes, when the client
+    /// accepts them. Lime never compresses responses itself.
+    pub static_gzip_only: Vec<String>,
+    /// Add Subresource Integrity hashes to local scripts and stylesheets in pages.
+    pub sri: bool,
+    /// `Content-Security-Policy` for HTML responses; `{{nonce}}` is replaced per response.
+    pub csp: Option<String>,
+    /// Send `Link: rel=preload` headers for the stylesheets, scripts and fonts pages load.
+    pub preload: bool,
+    #[serde(rename = "cors")]
+    pub cors_rules: Vec<CorsRule>,
+    #[serde(rename = "proxy")]
+    pub proxy_rules: Vec<ProxyRule>,
+    #[serde(rename = "notify")]
+    pub notify_rules: Vec<NotifyRule>,
+    pub admin: AdminConfig,
+    #[serde(rename = "auth")]
+    pub auth_rules: Vec<AuthRule>,
+    pub session: SessionConfig,
+    pub search: SearchConfig,
+    pub cache: CacheConfig,
+    pub metrics: MetricsConfig,
+    pub alerts: AlertConfig,
+    pub analytics: AnalyticsConfig,
+    pub bots: BotConfig,
+    pub honeypot: HoneypotConfig,
+    pub health_probes: HealthProbeConfig,
+    pub priority: PriorityConfig,
+    pub storage: StorageConfig,
+    /// Generate listing pages for the tags and collections declared in front matter.
+    pub taxonomies: bool,
+    /// Serve and list draft pages.
+    pub drafts: bool,
+    pub blog: Option<BlogConfig>,
+    pub mock_api: Option<MockApiConfig>,
+    pub forms: Vec<FormConfig>,
+    /// Stream content changes at `/__lime/events` as server-sent events.
+    pub events: bool,
+    /// File to record a timeline of request handling to, set by `lime serve --trace`.
+    #[serde(skip)]
+    pub trace_file: Option<String>,
+    /// Seconds to record the timeline for.
+    #[serde(skip)]
+    pub trace_duration: u64,
+    /// Apply the `[[chaos]]` rules, set by `lime serve --chaos`.
+    #[serde(skip)]
+    pub chaos_enabled: bool,
+    /// Routes of an application embedding Lime, answered before any of Lime's own routes.
+    #[serde(skip)]
+    pub handlers: Option<Router>,
     #[serde(skip)]
     pub default: bool,
 }
 
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Bearer token required by the admin endpoints under `/__lime`.
+    /// The endpoints are disabled when it is not set.
+    pub token: Option<String>,
+    /// Directory that `PUT`, `MKCOL` and `DELETE` under `/__lime/content/` write to.
+    /// The content API is disabled when it is not set.
+    pub content_dir: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Secret used to sign session cookies. A random one is generated at startup
+    /// when it is not set, which signs everyone out on restart.
+    pub secret: Option<String>,
+    /// How long a session stays valid, in seconds.
+    pub lifetime: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            lifetime: 8 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Index the pages directory and answer search queries.
+    pub enabled: bool,
+    /// Route of the search endpoint.
+    pub route: String,
+    /// Directory to keep the index in, so it survives restarts. Kept in memory when not set.
+    pub index_dir: Option<String>,
+    /// Reindex pages as they change.
+    pub watch: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            route: "/search".to_string(),
+            index_dir: None,
+            watch: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Keep the files that are served in memory.
+    pub enabled: bool,
+    /// Memory the cache may use; files larger than a quarter of it are never cached.
+    pub max_size: ByteSize,
+    /// Seconds a changed file may still be served from the cache while it is read again.
+    /// Also sent to browsers as `Cache-Control: stale-while-revalidate`.
+    pub stale_while_revalidate: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size: ByteSize(64 << 20),
+            stale_while_revalidate: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Serve Prometheus metrics at `route`.
+    pub enabled: bool,
+    pub route: String,
+    /// Routes tracked separately within each group; the rest are counted as `other`.
+    pub max_routes: usize,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            route: "/metrics".to_string(),
+            max_routes: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// Count page views and daily visitors, without cookies.
+    pub enabled: bool,
+    /// Where the counts are kept.
+    pub file: String,
+    /// Leave out visitors sending `DNT: 1` or `Sec-GPC: 1`.
+    pub respect_privacy: bool,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: "lime-stats.json".to_string(),
+            respect_privacy: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    /// Seconds over which error rates are measured.
+    pub window: u64,
+    /// Responses needed within the window before its error rates are judged.
+    pub min_requests: u64,
+    /// Share of `4xx` responses that raises an alert, e.g. `0.3`.
+    pub client_error_rate: Option<f64>,
+    /// Share of `5xx` responses that raises an alert, e.g. `0.05`.
+    pub server_error_rate: Option<f64>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            window: 60,
+            min_requests: 20,
+            client_error_rate: None,
+            server_error_rate: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BotConfig {
+    /// Apply the built-in rules for AI scrapers and known bad bots after `rules`.
+    pub default_rules: bool,
+    pub rules: Vec<BotRule>,
+    /// Requests per minute allowed from one address for `limit` rules.
+    pub rate_limit: u32,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            default_rules: false,
+            rules: Vec::new(),
+            rate_limit: 30,
+        }
+    }
+}
+
+/// What to do with requests whose user agent contains `user_agent`, ignoring case.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BotRule {
+    pub user_agent: String,
+    pub action: BotAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotAction {
+    /// Serve as usual, e.g. to exempt a bot from a default rule.
+    Allow,
+    /// Serve, but leave out of the page view counts.
+    Ignore,
+    /// Answer with `429 Too Many Requests` beyond `rate_limit`.
+    Limit,
+    /// Answer with `403 Forbidden`.
+    Block,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HoneypotConfig {
+    /// Glob patterns of paths only scanners request, e.g. `/wp-login.php`.
+    pub paths: Vec<String>,
+    /// What happens to addresses that requested one of `paths`.
+    pub action: HoneypotAction,
+    /// Seconds the action lasts.
+    pub duration: u64,
+    /// Seconds each response is held back for with `tarpit`.
+    pub tarpit_delay: u64,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            action: HoneypotAction::Ban,
+            duration: 3600,
+            tarpit_delay: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoneypotAction {
+    /// Answer every request with `403 Forbidden`.
+    Ban,
+    /// Answer every request with `403 Forbidden` after `tarpit_delay`, keeping the
+    /// scanner busy.
+    Tarpit,
+}
+
+/// Requests from load balancers and orchestrators checking that the server is up.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthProbeConfig {
+    /// Fragments of their user agents, ignoring case.
+    pub user_agents: Vec<String>,
+    /// Glob patterns of the paths they request.
+    pub paths: Vec<String>,
+}
+
+impl Default for HealthProbeConfig {
+    fn default() -> Self {
+        Self {
+            user_agents: [
+                "ELB-HealthChecker",
+                "kube-probe",
+                "GoogleHC",
+                "Consul Health Check",
+                "UptimeRobot",
+            ]
+            .map(String::from)
+            .to_vec(),
+            paths: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PriorityConfig {
+    /// Files at least this big are large.
+    pub large_response: ByteSize,
+    /// Large files sent at once; more wait for their turn. Not limited when not set.
+    pub max_large_responses: Option<usize>,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            large_response: ByteSize(1 << 20),
+            max_large_responses: None,
+        }
+    }
+}
+
+/// Where directories given as `s3://bucket/prefix` are fetched from.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Endpoint of an S3-compatible service, e.g. `https://<account>.r2.cloudflarestorage.com`.
+    pub endpoint: String,
+    pub region: String,
+    /// Directory holding the local copies of buckets.
+    pub cache_dir: String,
+    /// Seconds between checks for changes in the bucket; `0` only syncs on startup.
+    pub refresh: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://s3.amazonaws.com".to_string(),
+            region: "us-east-1".to_string(),
+            cache_dir: ".lime-cache".to_string(),
+            refresh: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BlogConfig {
+    /// Directory of posts inside `pages_dir`, also the URL of the post listing.
+    pub dir: String,
+    /// Heading of the listing pages.
+    #[serde(default = "default_blog_title")]
+    pub title: String,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+    /// Serve dated posts at `/<year>/<month>/<slug>`.
+    #[serde(default)]
+    pub permalinks: bool,
+    /// Public URL of the site, used for absolute links in the feed.
+    pub base_url: Option<String>,
+}
+
+fn default_blog_title() -> String {
+    "Blog".to_string()
+}
+
+fn default_per_page() -> usize {
+    10
+}
+
+/// A form whose submissions to `POST /__lime/form/<name>` are forwarded to a webhook, by
+/// email, or both.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FormConfig {
+    pub name: String,
+    /// URL the fields are posted to as JSON.
+    pub webhook: Option<String>,
+    pub email: Option<FormEmailConfig>,
+    /// Page to redirect to once a submission is sent, e.g. `/thanks`.
+    pub redirect: Option<String>,
+    /// Field that must be left empty. It's hidden from people, so submissions that fill it
+    /// in come from bots and are dropped.
+    #[serde(default = "default_form_honeypot")]
+    pub honeypot: String,
+    /// Submissions accepted from one address per hour.
+    #[serde(default = "default_form_rate_limit")]
+    pub rate_limit: u32,
+}
+
+fn default_form_honeypot() -> String {
+    "_gotcha".to_string()
+}
+
+fn default_form_rate_limit() -> u32 {
+    5
+}
+
+/// Where form submissions are mailed, through an SMTP server that accepts mail without
+/// authentication, such as a relay on the same machine.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FormEmailConfig {
+    #[serde(default = "default_smtp_server")]
+    pub smtp: String,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default = "default_form_subject")]
+    pub subject: String,
+}
+
+fn default_smtp_server() -> String {
+    "localhost:25".to_string()
+}
+
+fn default_form_subject() -> String {
+    "New form submission".to_string()
+}
+
+/// JSON files served as a read-only REST API, for prototyping front-ends.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MockApiConfig {
+    /// Directory of JSON files, e.g. `users.json` in it is served at `<route>/users`.
+    pub dir: String,
+    #[serde(default = "default_mock_api_route")]
+    pub route: String,
+    /// Milliseconds every response is delayed by.
+    #[serde(default)]
+    pub latency: u64,
+}
+
+fn default_mock_api_route() -> String {
+    "/api".to_string()
+}
+
+/// A directory served under a URL prefix, e.g. `/media` → `/mnt/photos`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MountConfig {
+    pub route: String,
+    pub dir: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// Never redirect.
+    Off,
+    /// Redirect directory URLs without a trailing slash to `/dir/`.
+    Add,
+    /// Like `add`, and also redirect file URLs with a trailing slash to `/file`.
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EtagMode {
+    /// From the size and modification time of the file, which is cheap.
+    Weak,
+    /// From a hash of the content, which stays the same across servers and deploys.
+    Strong,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    Stdout,
+    File,
+    Syslog,
+    Journald,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogIp {
+    Full,
+    /// Zero the last octet of IPv4 addresses and the last 80 bits of IPv6 addresses.
+    Truncate,
+    /// Replace addresses with a hash salted anew on every start.
+    Hash,
+    Omit,
+}
+
+/// A snippet of HTML inserted into served pages matching `pattern`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InjectRule {
+    /// Glob pattern matched against the request path (e.g. `/blog/*`).
+    #[serde(default = "default_inject_pattern")]
+    pub pattern: String,
+    pub position: InjectPosition,
+    pub html: String,
+    /// Leave the snippet out for visitors who send `DNT: 1` or `Sec-GPC: 1`.
+    #[serde(default)]
+    pub respect_privacy: bool,
+}
+
+/// Rate each response to requests matching `pattern` is sent at, at most.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThrottleRule {
+    /// Glob pattern matched against the request path (e.g. `/mirror/**`).
+    pub pattern: String,
+    pub rate: ByteRate,
+}
+
+/// Trouble caused on purpose for requests matching `pattern`, with `lime serve --chaos`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChaosRule {
+    /// Glob pattern matched against the request path (e.g. `/api/**`).
+    pub pattern: String,
+    /// Milliseconds every response is delayed by.
+    pub latency: u64,
+    /// Up to this many more milliseconds, at random.
+    pub jitter: u64,
+    /// Share of requests answered with `500 Internal Server Error`, from 0.0 to 1.0.
+    pub error_rate: f64,
+    /// Share of requests whose connection is closed without a complete response.
+    pub drop_rate: f64,
+}
+
+/// Response headers set on requests matching `pattern` or one of `extensions`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HeaderRule {
+    /// Glob pattern matched against the request path (e.g. `/assets/**`).
+    pub pattern: Option<String>,
+    /// File extensions without the dot (e.g. `woff2`), matched case-insensitively.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Headers to set, by name.
+    pub set: BTreeMap<String, String>,
+}
+
+/// Cross-origin policy for request paths under `prefix`.
+/// The rule with the longest matching prefix applies.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CorsRule {
+    #[serde(default = "default_cors_prefix")]
+    pub prefix: String,
+    /// Allowed origins, or `*` for any.
+    pub allow_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allow_methods: Vec<String>,
+    #[serde(default)]
+    pub allow_headers: Vec<String>,
+    #[serde(default)]
+    pub expose_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// Seconds browsers may cache a preflight response.
+    pub max_age: Option<u64>,
+}
+
+fn default_cors_prefix() -> String {
+    "/".to_string()
+}
+
+fn default_cors_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+/// Requests under `route` forwarded to other servers, with their paths unchanged.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProxyRule {
+    pub route: String,
+    /// Base URLs of the servers, e.g. `http://127.0.0.1:8080`, tried in order.
+    pub upstreams: Vec<String>,
+    /// Seconds to wait for an upstream's response.
+    #[serde(default = "default_proxy_timeout")]
+    pub timeout: u64,
+    /// Consecutive failures after which an upstream gets no requests for `cooldown` seconds.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+    /// Times an idempotent request is sent again, to the next upstream, when it fails to
+    /// connect or times out.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubling for each one after it.
+    #[serde(default = "default_retry_backoff")]
+    pub retry_backoff: u64,
+    /// HTML page served with `503 Service Unavailable` while no upstream is available.
+    pub error_page: Option<String>,
+    /// Keep `GET` and `HEAD` responses in memory for as long as their `Cache-Control` allows.
+    #[serde(default)]
+    pub cache: bool,
+    /// Seconds to keep responses instead, even ones without `Cache-Control`.
+    pub cache_ttl: Option<u64>,
+    #[serde(default = "default_proxy_cache_size")]
+    pub cache_max_size: ByteSize,
+    /// Query parameters that tell cached responses apart. Others, like `utm_source`, are
+    /// left out of the cache key. Every parameter counts when unset.
+    #[serde(default)]
+    pub cache_query_params: Option<Vec<String>>,
+}
+
+/// A webhook called when something happens to the server.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NotifyRule {
+    pub url: String,
+    #[serde(default = "default_notify_events")]
+    pub events: Vec<NotifyEvent>,
+    /// JSON body sent to the webhook; `{{event}}`, `{{message}}`, `{{server}}` and `{{time}}`
+    /// are replaced. Defaults to a Slack message.
+    #[serde(default = "default_notify_template")]
+    pub template: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyEvent {
+    Startup,
+    Shutdown,
+    /// An error rate in `[alerts]` was exceeded, or is back below its threshold.
+    Errors,
+    /// Files changed through the content API.
+    Deploy,
+    /// Reserved for when TLS support lands.
+    Certificate,
+}
+
+fn default_notify_events() -> Vec<NotifyEvent> {
+    vec![
+        NotifyEvent::Startup,
+        NotifyEvent::Shutdown,
+        NotifyEvent::Errors,
+        NotifyEvent::Deploy,
+    ]
+}
+
+fn default_notify_template() -> String {
+    r#"{"text": "{{message}}"}"#.to_string()
+}
+
+fn default_proxy_timeout() -> u64 {
+    30
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_cooldown() -> u64 {
+    30
+}
+
+fn default_proxy_cache_size() -> ByteSize {
+    ByteSize(32 << 20)
+}
+
+fn default_retries() -> u32 {
+    1
+}
+
+fn default_retry_backoff() -> u64 {
+    100
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InjectPosition {
+    /// Right before `</head>`.
+    Head,
+    /// Right before `</body>`.
+    Body,
+}
+
+fn default_inject_pattern() -> String {
+    "**".to_string()
+}
+
+/// Authentication required for request paths matching `pattern`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthRule {
+    pub pattern: String,
+    pub method: AuthMethod,
+    pub realm: Option<String>,
+    /// `user:password` pairs for `basic`, tokens for `bearer`.
+    #[serde(default)]
+    pub credentials: Vec<String>,
+    /// File with one credential per line.
+    pub credentials_file: Option<String>,
+    /// Environment variable with comma-separated credentials.
+    pub credentials_env: Option<String>,
+    /// Token validation settings for `jwt`.
+    pub jwt: Option<JwtConfig>,
+    /// Identity provider settings for `oidc`.
+    pub oidc: Option<OidcConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OidcConfig {
+    /// Issuer URL, used to discover the provider's endpoints.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Absolute callback URL registered at the provider, e.g. `https://docs.example.com/auth/callback`.
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// Allowed email addresses, or whole domains written as `@example.com`.
+    #[serde(default)]
+    pub allowed_emails: Vec<String>,
+    #[serde(default)]
+    pub allowed_groups: Vec<String>,
+    /// Name of the claim listing the user's groups.
+    #[serde(default = "default_groups_claim")]
+    pub groups_claim: String,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
+}
+
+fn default_groups_claim() -> String {
+    "groups".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JwtConfig {
+    /// Shared secret for HS256 tokens.
+    pub secret: Option<String>,
+    /// PEM file with the RSA public key for RS256 tokens.
+    pub public_key_file: Option<String>,
+    /// URL of a JWKS document with RS256 keys.
+    pub jwks_url: Option<String>,
+    /// Required `iss` claim.
+    pub issuer: Option<String>,
+    /// Required `aud` claim.
+    pub audience: Option<String>,
+    /// Claims forwarded to proxied upstreams, by claim name, e.g. `sub = "X-User"`.
+    #[serde(default)]
+    pub claim_headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    None,
+    Basic,
+    Bearer,
+    Jwt,
+    Oidc,
+    Mtls,
+}
+
+/// A size in bytes, written either as a number or as a string like `"10MB"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" | "kib" => 1 << 10,
+            "m" | "mb" | "mib" => 1 << 20,
+            "g" | "gb" | "gib" => 1 << 30,
+            _ => return None,
+        };
+        number
+            .parse::<u64>()
+            .ok()?
+            .checked_mul(multiplier)
+            .map(Self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = ByteSize;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a size in bytes or a string like \"10MB\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ByteSize, E> {
+                u64::try_from(v)
+                    .map(ByteSize)
+                    .map_err(|_| E::custom("size must not be negative"))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ByteSize, E> {
+                ByteSize::parse(v).ok_or_else(|| E::custom(format!("invalid size '{}'", v)))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+/// Bytes per second, written like a [`ByteSize`] followed by `/s`, e.g. `"5MB/s"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRate(pub u64);
+
+impl<'de> Deserialize<'de> for ByteRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = ByteRate;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("bytes per second or a string like \"5MB/s\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ByteRate, E> {
+                u64::try_from(v)
+                    .map(ByteRate)
+                    .map_err(|_| E::custom("rate must not be negative"))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ByteRate, E> {
+                let size = v.trim();
+                ByteSize::parse(size.strip_suffix("/s").unwrap_or(size))
+                    .map(|size| ByteRate(size.0))
+                    .ok_or_else(|| E::custom(format!("invalid rate '{}'", v)))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Serialize for ByteRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             host: "127.0.0.1".to_string(),
             port: 3000,
-            working_dir: "./".to_string(),
+            reuse_port: false,
+            pid_file: None,
+            workers: 1,
+            pages_dir: "./pages".to_string(),
+            static_dir: "./static".to_string(),
+            index_files: vec!["index.html".to_string()],
+            trailing_slash: TrailingSlash::Add,
+            case_insensitive: false,
+            etag: EtagMode::Weak,
+            redirects_file: None,
+            symlink_targets: Vec::new(),
+            max_file_size: None,
+            legacy_charset: None,
+            max_rate_per_connection: None,
+            max_rate: None,
+            mounts: Vec::new(),
+            multi_site: false,
+            quiet: false,
+            banner: true,
+            route_summary: false,
+            color: true,
+            log_level: "error".to_string(),
+            log_output: LogOutput::Stdout,
+            log_file: None,
+            log_exclude: Vec::new(),
+            log_format: None,
+            log_ip: LogIp::Full,
+            log_user_agent: true,
+            log_sample_rate: 1.0,
+            favicon: true,
+            serve_dotfiles: false,
+            mask_forbidden: false,
+            update_check: true,
+            inject_rules: Vec::new(),
+            header_rules: Vec::new(),
+            throttle_rules: Vec::new(),
+            chaos_rules: Vec::new(),
+            compress_skip: Vec::new(),
+            static_gzip_only: Vec::new(),
+            sri: false,
+            csp: None,
+            preload: false,
+            cors_rules: Vec::new(),
+            proxy_rules: Vec::new(),
+            notify_rules: Vec::new(),
+            admin: AdminConfig::default(),
+            auth_rules: Vec::new(),
+            session: SessionConfig::default(),
+            search: SearchConfig::default(),
+            cache: CacheConfig::default(),
+            metrics: MetricsConfig::default(),
+            alerts: AlertConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            bots: BotConfig::default(),
+            honeypot: HoneypotConfig::default(),
+            health_probes: HealthProbeConfig::default(),
+            priority: PriorityConfig::default(),
+            storage: StorageConfig::default(),
+            taxonomies: false,
+            drafts: false,
+            blog: None,
+            mock_api: None,
+            forms: Vec::new(),
+            events: false,
+            trace_file: None,
+            trace_duration: 0,
+            chaos_enabled: false,
+            handlers: None,
             default: true,
         }
     }
@@ -25,9 +1003,9 @@ impl Default for Config {
 
 pub fn load_config(path: &str) -> Result<Config> {
     let content = fs::read_to_string(path)
-        .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {}", path, e))?;
+        .map_err(|e| LimeError::Config(format!("Failed to read config file {}: {}", path, e)))?;
     let mut config = toml::from_str::<Config>(&content)
-        .map_err(|e| anyhow::anyhow!("Failed to parse config file {}: {}", path, e))?;
+        .map_err(|e| LimeError::Config(format!("Failed to parse config file {}: {}", path, e)))?;
     config.default = false;
     Ok(config)
 }
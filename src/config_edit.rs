@@ -0,0 +1,93 @@
+use std::{fs, path::Path};
+
+use anyhow::{Result, anyhow};
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::config::Config;
+
+/// Returns the effective value of the dotted `key`, e.g. `search.enabled`.
+/// Strings are returned without quotes, so scripts can use them as they are.
+pub fn get(config: &Config, key: &str) -> Result<String> {
+    let table = toml::Table::try_from(config)
+        .map_err(|e| anyhow!("Failed to serialize configuration: {}", e))?;
+    let mut value = None;
+    let mut current = Some(&table);
+    for part in key.split('.') {
+        value = current.and_then(|table| table.get(part));
+        current = value.and_then(toml::Value::as_table);
+    }
+    match value {
+        Some(toml::Value::String(s)) => Ok(s.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => Err(anyhow!("'{}' is not set", key)),
+    }
+}
+
+/// Sets the dotted `key` to `value` in the config file at `path`, keeping its comments and
+/// formatting. `value` is read as TOML, or taken as a string when it isn't valid TOML.
+/// The file is only written when the result is a valid configuration.
+pub fn set(path: &str, key: &str, value: &str) -> Result<()> {
+    let content = if Path::new(path).exists() {
+        fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read config file {}: {}", path, e))?
+    } else {
+        String::new()
+    };
+    let mut document = content
+        .parse::<DocumentMut>()
+        .map_err(|e| anyhow!("Failed to parse config file {}: {}", path, e))?;
+
+    let mut value = value
+        .parse::<Value>()
+        .unwrap_or_else(|_| Value::from(value));
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, parents) = parts
+        .split_last()
+        .filter(|(last, parents)| !last.is_empty() && parents.iter().all(|p| !p.is_empty()))
+        .ok_or_else(|| anyhow!("Invalid key '{}'", key))?;
+    let mut table = document.as_table_mut();
+    for parent in parents {
+        let item = table
+            .entry(parent)
+            .or_insert_with(|| Item::Table(Table::new()));
+        table = item
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("'{}' in '{}' is not a table", parent, key))?;
+    }
+    // Keeps comments after the old value, like `port = 3000 # dev port`.
+    if let Some(old) = table.get(last).and_then(Item::as_value) {
+        *value.decor_mut() = old.decor().clone();
+    }
+    table[last] = Item::Value(value);
+
+    let updated = document.to_string();
+    toml::from_str::<Config>(&updated)
+        .map_err(|e| anyhow!("Invalid value for '{}': {}", key, e))?;
+    fs::write(path, updated).map_err(|e| anyhow!("Failed to write config file {}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_values_keeping_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lime.toml");
+        let path = path.to_str().unwrap();
+        fs::write(path, "# Local preview\nport = 3000 # dev port\n").unwrap();
+
+        set(path, "port", "8080").unwrap();
+        set(path, "host", "0.0.0.0").unwrap();
+        set(path, "search.enabled", "true").unwrap();
+        assert!(set(path, "port", "high").is_err());
+
+        let content = fs::read_to_string(path).unwrap();
+        assert!(content.starts_with("# Local preview\nport = 8080 # dev port\n"));
+        let config = crate::config::load_config(path).unwrap();
+        assert_eq!(get(&config, "port").unwrap(), "8080");
+        assert_eq!(get(&config, "host").unwrap(), "0.0.0.0");
+        assert_eq!(get(&config, "search.enabled").unwrap(), "true");
+        assert!(get(&config, "search.missing").is_err());
+    }
+}
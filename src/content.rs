@@ -0,0 +1,322 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{Method, StatusCode, header},
+    response::Response,
+};
+use tokio::fs;
+use tracing::{error, info};
+
+use crate::{admin::AdminState, paths::normalize_request_path};
+
+/// Publishes, creates and deletes files in the content directory:
+/// `PUT` writes a file, `MKCOL` creates a directory and `DELETE` removes either.
+pub async fn handle_content(State(state): State<Arc<AdminState>>, request: Request) -> Response {
+    let Some(content_dir) = &state.content_dir else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    // Nesting under the admin prefix leaves `/content/<path>` in the URI.
+    let raw = request
+        .uri()
+        .path()
+        .strip_prefix("/content")
+        .unwrap_or_default();
+    let Ok(relative) = normalize_request_path(raw) else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+    let relative = relative.trim_end_matches('/');
+    if relative.is_empty() {
+        return status(StatusCode::FORBIDDEN);
+    }
+    let Some(target) = resolve(content_dir, relative).await else {
+        return status(StatusCode::FORBIDDEN);
+    };
+
+    let method = request.method().clone();
+    let result = match method.as_str() {
+        "PUT" => {
+            let limit = state.max_file_size.map_or(usize::MAX, |s| s as usize);
+            let Ok(body) = to_bytes(request.into_body(), limit).await else {
+                return status(StatusCode::PAYLOAD_TOO_LARGE);
+            };
+            put(&target, &body).await
+        }
+        "MKCOL" => fs::create_dir(&target).await.map(|_| StatusCode::CREATED),
+        "DELETE" => delete(&target).await,
+        _ => {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "PUT, MKCOL, DELETE")
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+
+    match result {
+        Ok(code) => {
+            info!(method = %method, path = %relative, "Content updated");
+            if let Some(notifier) = &state.notifier {
+                notifier.content_changed();
+            }
+            status(code)
+        }
+        Err(e) => {
+            let code = match e.kind() {
+                io::ErrorKind::NotFound if method == Method::DELETE => StatusCode::NOT_FOUND,
+                // A missing parent directory, as WebDAV specifies for MKCOL.
+                io::ErrorKind::NotFound => StatusCode::CONFLICT,
+                io::ErrorKind::AlreadyExists => StatusCode::METHOD_NOT_ALLOWED,
+                io::ErrorKind::DirectoryNotEmpty | io::ErrorKind::IsADirectory => {
+                    StatusCode::CONFLICT
+                }
+                _ => {
+                    error!("failed to {} {}: {}", method, target.display(), e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            status(code)
+        }
+    }
+}
+
+/// Writes through a temporary file, so readers never see a partially written page.
+async fn put(target: &Path, body: &[u8]) -> io::Result<StatusCode> {
+    let existed = fs::try_exists(target).await?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let temp = target.with_file_name(format!(
+        ".{}.lime-{:08x}",
+        target.file_name().unwrap_or_default().to_string_lossy(),
+        rand::random::<u32>()
+    ));
+    fs::write(&temp, body).await?;
+    if let Err(e) = fs::rename(&temp, target).await {
+        let _ = fs::remove_file(&temp).await;
+        return Err(e);
+    }
+    Ok(if existed {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CREATED
+    })
+}
+
+async fn delete(target: &Path) -> io::Result<StatusCode> {
+    let metadata = fs::symlink_metadata(target).await?;
+    if metadata.is_dir() {
+        fs::remove_dir(target).await?;
+    } else {
+        fs::remove_file(target).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Joins `relative` onto `base`, refusing paths whose closest existing ancestor
+/// lies outside `base`, e.g. through a symlink.
+async fn resolve(base: &Path, relative: &str) -> Option<PathBuf> {
+    let base = fs::canonicalize(base).await.ok()?;
+    let target = base.join(relative);
+    let mut ancestor = target.parent()?;
+    let existing = loop {
+        match fs::canonicalize(ancestor).await {
+            Ok(path) => break path,
+            Err(_) => ancestor = ancestor.parent()?,
+        }
+    };
+    existing.starts_with(&base).then_some(target)
+}
+
+fn status(code: StatusCode) -> Response {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{Router, http::Request};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::{AdminConfig, ByteSize, Config},
+        test_router,
+    };
+
+    struct Site {
+        root: TempDir,
+        router: Router,
+    }
+
+    impl Site {
+        fn content(&self) -> PathBuf {
+            self.root.path().join("content")
+        }
+    }
+
+    /// A site whose content API writes to `content/`, next to an `outside/` directory.
+    fn site() -> Site {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("content/docs")).unwrap();
+        fs::create_dir_all(root.path().join("outside")).unwrap();
+        fs::write(root.path().join("content/docs/index.html"), "docs").unwrap();
+        fs::write(root.path().join("outside/secret.txt"), "secret").unwrap();
+        let config = Config {
+            pages_dir: root.path().join("content").to_string_lossy().into_owned(),
+            max_file_size: Some(ByteSize(16)),
+            admin: AdminConfig {
+                token: Some("t0ken".to_string()),
+                content_dir: Some(root.path().join("content").to_string_lossy().into_owned()),
+            },
+            ..Config::default()
+        };
+        let router = test_router(&config).unwrap();
+        Site { root, router }
+    }
+
+    async fn send(site: &Site, method: &str, path: &str, body: &str) -> StatusCode {
+        send_with(
+            site,
+            method,
+            path,
+            body,
+            &[("authorization", "Bearer t0ken")],
+        )
+        .await
+    }
+
+    async fn send_with(
+        site: &Site,
+        method: &str,
+        path: &str,
+        body: &str,
+        headers: &[(&str, &str)],
+    ) -> StatusCode {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("/__lime/content/{}", path));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        site.router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn writes_and_removes_content() {
+        let site = site();
+        assert_eq!(
+            send(&site, "PUT", "blog/post.html", "v1").await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            send(&site, "PUT", "blog/post.html", "v2").await,
+            StatusCode::NO_CONTENT
+        );
+        let written = fs::read_to_string(site.content().join("blog/post.html")).unwrap();
+        assert_eq!(written, "v2");
+
+        assert_eq!(
+            send(&site, "MKCOL", "drafts", "").await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            send(&site, "MKCOL", "drafts", "").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(send(&site, "MKCOL", "a/b", "").await, StatusCode::CONFLICT);
+
+        assert_eq!(
+            send(&site, "DELETE", "docs", "").await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            send(&site, "DELETE", "blog/post.html", "").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            send(&site, "DELETE", "blog/post.html", "").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&site, "DELETE", "missing.html", "").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_uploads_to_the_max_file_size() {
+        let site = site();
+        let status = send(&site, "PUT", "big.html", "seventeen bytes!!").await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!site.content().join("big.html").exists());
+        let status = send(&site, "PUT", "small.html", "sixteen bytes!!!").await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn stays_inside_the_content_dir() {
+        let site = site();
+        for path in [
+            "../outside/secret.txt",
+            "docs/../../outside/secret.txt",
+            "%2e%2e/outside/secret.txt",
+            "%2e%2e%2foutside%2fsecret.txt",
+        ] {
+            for method in ["PUT", "DELETE"] {
+                let status = send(&site, method, path, "pwned").await;
+                assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", method, path);
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                site.root.path().join("outside"),
+                site.content().join("escape"),
+            )
+            .unwrap();
+            for method in ["PUT", "DELETE", "MKCOL"] {
+                let status = send(&site, method, "escape/secret.txt", "pwned").await;
+                assert_eq!(status, StatusCode::FORBIDDEN, "{}", method);
+            }
+        }
+        let secret = site.root.path().join("outside/secret.txt");
+        assert_eq!(fs::read_to_string(secret).unwrap(), "secret");
+    }
+
+    #[tokio::test]
+    async fn needs_the_token_and_the_same_site() {
+        let site = site();
+        for headers in [
+            &[][..],
+            &[("authorization", "Bearer wrong")],
+            &[("authorization", "Basic dDBrZW4=")],
+        ] {
+            let status = send_with(&site, "PUT", "page.html", "hi", headers).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", headers);
+        }
+        let headers = [
+            ("authorization", "Bearer t0ken"),
+            ("sec-fetch-site", "cross-site"),
+        ];
+        let status = send_with(&site, "PUT", "page.html", "hi", &headers).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let status = send_with(&site, "DELETE", "docs/index.html", "", &headers).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        assert!(!site.content().join("page.html").exists());
+        assert!(site.content().join("docs/index.html").exists());
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::CorsRule;
+
+struct Policy {
+    prefix: String,
+    origins: Vec<String>,
+    any_origin: bool,
+    methods: HeaderValue,
+    headers: Option<HeaderValue>,
+    expose: Option<HeaderValue>,
+    credentials: bool,
+    max_age: Option<HeaderValue>,
+}
+
+/// Cross-origin policies, selected by the longest matching path prefix.
+pub struct Cors {
+    policies: Vec<Policy>,
+}
+
+impl Cors {
+    pub fn new(rules: &[CorsRule]) -> Result<Self> {
+        let mut policies = rules
+            .iter()
+            .map(|rule| {
+                if !rule.prefix.starts_with('/') {
+                    return Err(anyhow!("CORS prefix '{}' must start with '/'", rule.prefix));
+                }
+                let any_origin = rule.allow_origins.iter().any(|o| o == "*");
+                if any_origin && rule.allow_credentials {
+                    return Err(anyhow!(
+                        "CORS rule for '{}' cannot allow credentials from any origin",
+                        rule.prefix
+                    ));
+                }
+                let methods = rule
+                    .allow_methods
+                    .iter()
+                    .map(|m| {
+                        Method::from_bytes(m.to_uppercase().as_bytes())
+                            .map_err(|e| anyhow!("Invalid CORS method '{}': {}", m, e))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Policy {
+                    prefix: rule.prefix.trim_end_matches('/').to_string(),
+                    origins: rule.allow_origins.clone(),
+                    any_origin,
+                    methods: list_value(methods.iter().map(Method::as_str))?,
+                    headers: optional_list_value(&rule.allow_headers)?,
+                    expose: optional_list_value(&rule.expose_headers)?,
+                    credentials: rule.allow_credentials,
+                    max_age: rule.max_age.map(HeaderValue::from),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        policies.sort_by_key(|p| std::cmp::Reverse(p.prefix.len()));
+        Ok(Self { policies })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    fn policy(&self, path: &str) -> Option<&Policy> {
+        self.policies.iter().find(|p| {
+            path.strip_prefix(&p.prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+impl Policy {
+    fn allows(&self, origin: &str) -> bool {
+        self.any_origin || self.origins.iter().any(|o| o == origin)
+    }
+
+    fn set_origin(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        if self.any_origin {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+        } else {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    fn preflight(&self, origin: &HeaderValue, requested_headers: Option<&HeaderValue>) -> Response {
+        let mut response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, self.methods.clone())
+            .body(Body::empty())
+            .unwrap();
+        let headers = response.headers_mut();
+        self.set_origin(headers, origin);
+        // Without a configured list, echo the requested headers back.
+        if let Some(allowed) = self.headers.as_ref().or(requested_headers) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed.clone());
+        }
+        if let Some(max_age) = &self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.clone());
+        }
+        response
+    }
+}
+
+/// Answers preflight requests and adds CORS headers to responses for allowed origins.
+pub async fn apply_cors(State(cors): State<Arc<Cors>>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+    let Some(policy) = cors.policy(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    if !origin.to_str().is_ok_and(|o| policy.allows(o)) {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    if request.method() == Method::OPTIONS
+        && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        return policy.preflight(&origin, headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS));
+    }
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    policy.set_origin(headers, &origin);
+    if let Some(expose) = &policy.expose {
+        headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose.clone());
+    }
+    response
+}
+
+fn list_value<'a>(items: impl Iterator<Item = &'a str>) -> Result<HeaderValue> {
+    let list = items.collect::<Vec<_>>().join(", ");
+    HeaderValue::try_from(&list).map_err(|e| anyhow!("Invalid CORS header list '{}': {}", list, e))
+}
+
+fn optional_list_value(items: &[String]) -> Result<Option<HeaderValue>> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+    list_value(items.iter().map(String::as_str)).map(Some)
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use tracing::error;
+
+use crate::html;
+
+const NONCE_PLACEHOLDER: &str = "{{nonce}}";
+
+/// A `Content-Security-Policy` sent with HTML responses, with a fresh nonce per response.
+pub struct Csp {
+    policy: String,
+}
+
+impl Csp {
+    pub fn new(policy: &str) -> Result<Self> {
+        // Make sure the policy is a valid header value once the nonce is filled in.
+        HeaderValue::try_from(policy.replace(NONCE_PLACEHOLDER, "nonce"))
+            .map_err(|e| anyhow!("Invalid content security policy: {}", e))?;
+        Ok(Self {
+            policy: policy.to_string(),
+        })
+    }
+
+    fn uses_nonce(&self) -> bool {
+        self.policy.contains(NONCE_PLACEHOLDER)
+    }
+}
+
+/// Sets the policy on HTML responses. When it contains `{{nonce}}`, the same nonce is
+/// substituted into the page and added to inline `<script>` and `<style>` elements.
+pub async fn apply_csp(State(csp): State<Arc<Csp>>, request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if !is_html {
+        return response;
+    }
+    if !csp.uses_nonce() {
+        let mut response = response;
+        response.headers_mut().insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::try_from(&csp.policy).unwrap(),
+        );
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(b) => b,
+        Err(e) => {
+            error!("failed to read page for nonce injection: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let nonce = STANDARD.encode(rand::random::<[u8; 16]>());
+    let page = String::from_utf8_lossy(&bytes).replace(NONCE_PLACEHOLDER, &nonce);
+    let page = html::add_attributes(&page, |tag| {
+        let inline = html::is_tag(tag, "style")
+            || (html::is_tag(tag, "script") && html::attribute(tag, "src").is_none());
+        (inline && html::attribute(tag, "nonce").is_none()).then(|| format!(" nonce=\"{}\"", nonce))
+    });
+
+    parts.headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::try_from(csp.policy.replace(NONCE_PLACEHOLDER, &nonce)).unwrap(),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    // The page is different every time, so it can't be revalidated.
+    parts.headers.remove(header::ETAG);
+    Response::from_parts(parts, Body::from(page))
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    io::{self, Write as _},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Local};
+use colored::Colorize;
+
+use crate::{
+    analytics::Analytics,
+    traffic::{Traffic, format_bytes, request_size, response_size},
+};
+
+/// Requests and errors kept for the "recent" lists.
+const RECENT: usize = 12;
+const ERRORS: usize = 6;
+/// Pages and referrers listed for today, and routes and responses by bytes sent.
+const TOP: usize = 3;
+/// Routes whose bytes sent are counted separately.
+const TRAFFIC_ROUTES: usize = 100;
+const REFRESH: Duration = Duration::from_secs(1);
+const MAX_PATH_WIDTH: usize = 60;
+
+#[derive(Clone)]
+struct Entry {
+    time: DateTime<Local>,
+    method: String,
+    path: String,
+    status: u16,
+    duration: Duration,
+}
+
+#[derive(Default)]
+struct Stats {
+    total: u64,
+    /// Responses by status class, from 1xx to 5xx.
+    classes: [u64; 5],
+    recent: VecDeque<Entry>,
+    errors: VecDeque<Entry>,
+}
+
+/// Live statistics shown by `lime serve --tui`.
+pub struct Dashboard {
+    address: String,
+    started: Instant,
+    stats: Mutex<Stats>,
+    traffic: Traffic,
+    analytics: Option<Arc<Analytics>>,
+}
+
+impl Dashboard {
+    pub fn new(address: String, analytics: Option<Arc<Analytics>>) -> Self {
+        Self {
+            address,
+            started: Instant::now(),
+            stats: Mutex::new(Stats::default()),
+            traffic: Traffic::new(TRAFFIC_ROUTES),
+            analytics,
+        }
+    }
+
+    fn record(&self, entry: Entry) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.total += 1;
+        if let Some(count) = stats
+            .classes
+            .get_mut(usize::from(entry.status / 100).wrapping_sub(1))
+        {
+            *count += 1;
+        }
+        if entry.status >= 400 {
+            if stats.errors.len() == ERRORS {
+                stats.errors.pop_back();
+            }
+            stats.errors.push_front(entry.clone());
+        }
+        if stats.recent.len() == RECENT {
+            stats.recent.pop_back();
+        }
+        stats.recent.push_front(entry);
+    }
+
+    /// Redraws the dashboard every second on the terminal's alternate screen until the
+    /// task is dropped. Call [`Dashboard::close`] to restore the terminal afterwards.
+    pub async fn run(self: Arc<Self>) {
+        print!("\x1b[?1049h\x1b[?25l");
+        let mut interval = tokio::time::interval(REFRESH);
+        let mut last_total = 0;
+        loop {
+            interval.tick().await;
+            let total = self.stats.lock().unwrap().total;
+            let per_second = (total - last_total) as f64 / REFRESH.as_secs_f64();
+            last_total = total;
+            let mut stdout = io::stdout().lock();
+            let _ = write!(stdout, "\x1b[H\x1b[2J{}", self.render(per_second));
+            let _ = stdout.flush();
+        }
+    }
+
+    /// Leaves the alternate screen and shows the cursor again.
+    pub fn close() {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+
+    fn render(&self, per_second: f64) -> String {
+        let stats = self.stats.lock().unwrap();
+        let uptime = self.started.elapsed().as_secs();
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            " {} {}    up {:02}:{:02}:{:02}\n",
+            "Lime".bright_green().bold(),
+            self.address.bold(),
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60
+        );
+        let _ = writeln!(
+            output,
+            " Requests  {} total, {:.1}/s",
+            stats.total.to_string().bold(),
+            per_second
+        );
+        let _ = write!(output, " Status   ");
+        for (index, count) in stats.classes.iter().enumerate() {
+            let class = format!("{}xx", index + 1);
+            let _ = write!(
+                output,
+                " {} {}",
+                color_status(&class, index as u16 + 1),
+                count
+            );
+        }
+        let traffic = self.traffic.report();
+        let _ = write!(
+            output,
+            "\n Traffic   {} sent, {} received",
+            format_bytes(traffic.sent).bold(),
+            format_bytes(traffic.received).bold()
+        );
+        for (title, ranked) in [("Routes", &traffic.routes), ("Largest", &traffic.largest)] {
+            let ranked: Vec<String> = ranked
+                .iter()
+                .take(TOP)
+                .map(|(name, bytes)| format!("{} {}", truncate(name), format_bytes(*bytes)))
+                .collect();
+            let _ = write!(output, "\n {:<9} {}", title, ranked.join(", "));
+        }
+        if let Some(analytics) = &self.analytics {
+            let report = analytics.report(1, TOP);
+            let _ = write!(
+                output,
+                "\n Today     {} page views, {} visitors",
+                report.views.to_string().bold(),
+                report.visitors.to_string().bold()
+            );
+            for (title, ranked) in [("Pages", &report.pages), ("Referrers", &report.referrers)] {
+                let ranked: Vec<String> = ranked
+                    .iter()
+                    .map(|entry| format!("{} {}", truncate(&entry.name), entry.views))
+                    .collect();
+                let _ = write!(output, "\n {:<9} {}", title, ranked.join(", "));
+            }
+        }
+        let _ = writeln!(output, "\n\n {}", "Recent requests".bold());
+        for entry in &stats.recent {
+            let _ = writeln!(
+                output,
+                " {}  {:<7} {}  {:>6} ms  {}",
+                entry.time.format("%H:%M:%S"),
+                entry.method,
+                color_status(&entry.status.to_string(), entry.status / 100),
+                entry.duration.as_millis(),
+                truncate(&entry.path)
+            );
+        }
+        let _ = writeln!(output, "\n {}", "Recent errors".bold());
+        if stats.errors.is_empty() {
+            let _ = writeln!(output, " {}", "None".dimmed());
+        }
+        for entry in &stats.errors {
+            let _ = writeln!(
+                output,
+                " {}  {:<7} {}  {}",
+                entry.time.format("%H:%M:%S"),
+                entry.method,
+                color_status(&entry.status.to_string(), entry.status / 100),
+                truncate(&entry.path)
+            );
+        }
+        let _ = writeln!(output, "\n {}", "Press Ctrl+C to stop.".dimmed());
+        output
+    }
+}
+
+fn color_status(text: &str, class: u16) -> colored::ColoredString {
+    match class {
+        2 => text.green(),
+        3 => text.cyan(),
+        4 => text.yellow(),
+        5 => text.red(),
+        _ => text.normal(),
+    }
+}
+
+fn truncate(path: &str) -> String {
+    if path.chars().count() > MAX_PATH_WIDTH {
+        let kept: String = path.chars().take(MAX_PATH_WIDTH - 1).collect();
+        format!("{}…", kept)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Records every request and its outcome on the dashboard.
+pub async fn record_requests(
+    State(dashboard): State<Arc<Dashboard>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let received = request_size(&request);
+    let start = Instant::now();
+    let response = next.run(request).await;
+    dashboard.traffic.record(
+        &path,
+        received,
+        response_size(&response).unwrap_or_default(),
+    );
+    dashboard.record(Entry {
+        time: Local::now(),
+        method,
+        path,
+        status: response.status().as_u16(),
+        duration: start.elapsed(),
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_requests_by_status_class() {
+        colored::control::set_override(false);
+        let dashboard = Dashboard::new("http://127.0.0.1:3000".to_string(), None);
+        for (path, status) in [("/", 200), ("/old", 301), ("/missing", 404)] {
+            dashboard.record(Entry {
+                time: Local::now(),
+                method: "GET".to_string(),
+                path: path.to_string(),
+                status,
+                duration: Duration::from_millis(2),
+            });
+        }
+
+        let output = dashboard.render(3.0);
+        assert!(output.contains("Requests  3 total, 3.0/s"));
+        assert!(output.contains("1xx 0 2xx 1 3xx 1 4xx 1 5xx 0"));
+        let errors = output.split("Recent errors").nth(1).unwrap();
+        assert!(errors.contains("/missing") && !errors.contains("/old"));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::{Result, anyhow};
+use toml::{Table, Value};
+
+use crate::config::Config;
+
+/// Keys whose values are never printed.
+const SECRET_KEYS: [&str; 4] = ["token", "secret", "client_secret", "credentials"];
+
+/// Top-level keys that command-line flags can override.
+const CLI_KEYS: [&str; 5] = ["quiet", "banner", "color", "drafts", "log_level"];
+
+/// Renders the effective configuration as TOML, noting whether each value is a default,
+/// comes from the file at `path`, or was overridden on the command line.
+pub fn render(config: &Config, path: &str) -> Result<String> {
+    let mut effective = to_table(config)?;
+    redact(&mut effective);
+    let defaults = to_table(&Config::default())?;
+    let file = if Path::new(path).exists() {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read config file {}: {}", path, e))?;
+        content
+            .parse::<Table>()
+            .map_err(|e| anyhow!("Failed to parse config file {}: {}", path, e))?
+    } else {
+        Table::new()
+    };
+
+    let source = |keys: &[&str]| -> &'static str {
+        let file_value = lookup(&file, keys);
+        // Only flags are compared, since the file may spell other values differently.
+        let overridden = keys.len() == 1
+            && CLI_KEYS.contains(&keys[0])
+            && lookup(&effective, keys) != file_value.or_else(|| lookup(&defaults, keys));
+        if overridden {
+            "cli"
+        } else if file_value.is_some() {
+            "file"
+        } else {
+            "default"
+        }
+    };
+
+    let mut output = String::new();
+    // Plain values come first, since everything after a table header belongs to it.
+    for (key, value) in &effective {
+        if !is_table(value) && !is_table_array(value) {
+            write_value(&mut output, key, value, source(&[key]));
+        }
+    }
+    for (key, value) in &effective {
+        if let Value::Table(table) = value {
+            let _ = writeln!(output, "\n[{}] # {}", key, source(&[key]));
+            for (inner, value) in table {
+                write_value(&mut output, inner, value, source(&[key, inner]));
+            }
+        }
+    }
+    for (key, value) in &effective {
+        if let Value::Array(tables) = value
+            && is_table_array(value)
+        {
+            for table in tables.iter().filter_map(Value::as_table) {
+                let _ = writeln!(output, "\n[[{}]] # file", key);
+                for (inner, value) in table {
+                    write_value(&mut output, inner, value, "file");
+                }
+            }
+        }
+    }
+    Ok(output.trim_start().to_string())
+}
+
+fn to_table(config: &Config) -> Result<Table> {
+    Table::try_from(config).map_err(|e| anyhow!("Failed to serialize configuration: {}", e))
+}
+
+fn lookup<'a>(table: &'a Table, keys: &[&str]) -> Option<&'a Value> {
+    let (last, parents) = keys.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(*key)?.as_table()?;
+    }
+    table.get(*last)
+}
+
+fn is_table(value: &Value) -> bool {
+    matches!(value, Value::Table(_))
+}
+
+fn is_table_array(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if !items.is_empty() && items.iter().all(is_table))
+}
+
+/// Replaces secrets anywhere in `table`, including inside arrays of tables.
+fn redact(table: &mut Table) {
+    for (key, value) in table.iter_mut() {
+        if SECRET_KEYS.contains(&key.as_str()) {
+            *value = Value::String("<redacted>".to_string());
+            continue;
+        }
+        match value {
+            Value::Table(table) => redact(table),
+            Value::Array(items) => items
+                .iter_mut()
+                .filter_map(Value::as_table_mut)
+                .for_each(redact),
+            _ => {}
+        }
+    }
+}
+
+fn write_value(output: &mut String, key: &str, value: &Value, source: &str) {
+    let _ = writeln!(output, "{} = {} # {}", key, value, source);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_where_values_come_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lime.toml");
+        fs::write(
+            &path,
+            "port = 8080\nbanner = true\n[admin]\ntoken = \"hunter2\"\n\
+             [[auth]]\npattern = \"/api/**\"\nmethod = \"jwt\"\njwt = { secret = \"hunter3\" }\n",
+        )
+        .unwrap();
+        let mut config = crate::config::load_config(path.to_str().unwrap()).unwrap();
+        config.banner = false;
+
+        let output = render(&config, path.to_str().unwrap()).unwrap();
+        assert!(output.contains("port = 8080 # file\n"));
+        assert!(output.contains("host = \"127.0.0.1\" # default\n"));
+        assert!(output.contains("banner = false # cli\n"));
+        assert!(output.contains("token = \"<redacted>\" # file\n"));
+        assert!(!output.contains("hunter2"));
+        assert!(!output.contains("hunter3"));
+    }
+}
@@ -0,0 +1,67 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Failures the CLI exits with a code of their own for, so scripts wrapping Lime can tell
+/// them apart. Anything else exits with 1.
+#[derive(Debug, Error)]
+pub enum LimeError {
+    /// The configuration file can't be read or is invalid.
+    #[error("{0}")]
+    Config(String),
+    /// The server can't listen on its address, e.g. because the port is taken.
+    #[error("Failed to listen on {address}: {source}{help}")]
+    Bind {
+        address: String,
+        #[source]
+        source: io::Error,
+        /// Lines suggesting what to do about it.
+        help: String,
+    },
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// TLS is asked for, which Lime can't provide.
+    #[error("{0}")]
+    Tls(String),
+    /// A proxy rule can't be set up.
+    #[error("{0}")]
+    Proxy(String),
+}
+
+impl LimeError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Config(_) => 3,
+            Self::Bind { .. } => 4,
+            Self::Io { .. } => 5,
+            Self::Tls(_) => 6,
+            Self::Proxy(_) => 7,
+        }
+    }
+}
+
+/// The code to exit with after `error`.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<LimeError>()
+        .map_or(1, LimeError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn exit_codes_survive_context() {
+        let error = Err::<(), _>(LimeError::Proxy("Proxy route /api has no upstreams".into()))
+            .context("Failed to start")
+            .unwrap_err();
+        assert_eq!(exit_code(&error), 7);
+        assert_eq!(exit_code(&anyhow::anyhow!("Something else")), 1);
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use tracing::error;
+
+use crate::{html, request_id::RequestId, suggest::Suggester};
+
+/// Marks a response whose body is a custom error page with `{{...}}` placeholders.
+#[derive(Clone, Copy)]
+pub struct ErrorTemplate;
+
+/// Substitutes `{{status}}`, `{{status_text}}`, `{{path}}`, `{{request_id}}`, and
+/// `{{suggestions}}` in custom error pages.
+pub async fn render_error_templates(
+    State(suggester): State<Arc<Suggester>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+
+    let response = next.run(request).await;
+    if response.extensions().get::<ErrorTemplate>().is_none() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(b) => b,
+        Err(e) => {
+            error!("failed to read error page: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut page = String::from_utf8_lossy(&bytes).into_owned();
+    // Looking for similar pages walks the pages directory, so only do it when asked for.
+    if page.contains("{{suggestions}}") {
+        let suggestions = if parts.status == StatusCode::NOT_FOUND {
+            suggester.render(&path)
+        } else {
+            String::new()
+        };
+        page = page.replace("{{suggestions}}", &suggestions);
+    }
+    let page = page
+        .replace("{{status}}", parts.status.as_str())
+        .replace(
+            "{{status_text}}",
+            parts.status.canonical_reason().unwrap_or_default(),
+        )
+        .replace("{{path}}", &html::escape(&path))
+        .replace("{{request_id}}", &html::escape(&request_id));
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(page))
+}
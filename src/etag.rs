@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{config::EtagMode, store::StoredFile};
+
+/// Files whose hash is remembered; past this, the hashes are forgotten and recomputed.
+const MAX_HASHES: usize = 10_000;
+
+/// Computes the `ETag` of served files.
+pub struct Etags {
+    mode: EtagMode,
+    /// Content hashes of files served as they are.
+    hashes: Mutex<HashMap<PathBuf, Hashed>>,
+}
+
+/// The ETag of a file of the size and modification time it was computed for.
+struct Hashed {
+    len: u64,
+    modified: Option<SystemTime>,
+    tag: HeaderValue,
+}
+
+impl Etags {
+    /// Returns `None` when ETags are turned off.
+    pub fn new(mode: EtagMode) -> Option<Self> {
+        (mode != EtagMode::Off).then(|| Self {
+            mode,
+            hashes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The ETag of `content` read from `file`. `transformed` content differs from the
+    /// file, so its hash is never reused.
+    pub fn tag(&self, file: &StoredFile, content: &[u8], transformed: bool) -> HeaderValue {
+        if self.mode == EtagMode::Weak {
+            let modified = file
+                .modified
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos());
+            return HeaderValue::try_from(format!("W/\"{:x}-{:x}\"", file.len, modified)).unwrap();
+        }
+        if transformed {
+            return hash(content);
+        }
+        let mut hashes = self.hashes.lock().unwrap();
+        if let Some(hashed) = hashes.get(&file.path)
+            && hashed.len == file.len
+            && hashed.modified == file.modified
+        {
+            return hashed.tag.clone();
+        }
+        if hashes.len() >= MAX_HASHES {
+            hashes.clear();
+        }
+        let tag = hash(content);
+        hashes.insert(
+            file.path.clone(),
+            Hashed {
+                len: file.len,
+                modified: file.modified,
+                tag: tag.clone(),
+            },
+        );
+        tag
+    }
+}
+
+fn hash(content: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(content);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    HeaderValue::try_from(format!("\"{}\"", hex)).unwrap()
+}
+
+/// Answers `GET` and `HEAD` requests with `304 Not Modified` when the client already has
+/// the response, according to its `ETag`.
+pub async fn answer_not_modified(request: Request, next: Next) -> Response {
+    let if_none_match = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .filter(|_| matches!(*request.method(), Method::GET | Method::HEAD));
+    let response = next.run(request).await;
+    let Some(if_none_match) = if_none_match else {
+        return response;
+    };
+    let Some(etag) = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return response;
+    };
+    if response.status() != StatusCode::OK || !matches(&if_none_match, etag) {
+        return response;
+    }
+
+    let mut not_modified = Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .body(Body::empty())
+        .unwrap();
+    for name in [
+        header::ETAG,
+        header::CACHE_CONTROL,
+        header::VARY,
+        header::EXPIRES,
+    ] {
+        for value in response.headers().get_all(&name) {
+            not_modified.headers_mut().append(&name, value.clone());
+        }
+    }
+    not_modified
+}
+
+/// Whether `etag` is in the `If-None-Match` list, which compares ETags weakly.
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_etags_weakly() {
+        assert!(matches("\"abc\"", "\"abc\""));
+        assert!(matches("W/\"1-2\", \"abc\"", "W/\"1-2\""));
+        assert!(matches("W/\"abc\"", "\"abc\""));
+        assert!(matches("*", "\"abc\""));
+        assert!(!matches("\"abd\"", "\"abc\""));
+    }
+}
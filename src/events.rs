@@ -0,0 +1,121 @@
+use std::{convert::Infallible, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::catalog::fingerprint;
+
+/// Route of the event stream.
+pub const EVENTS_ROUTE: &str = "/__lime/events";
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+/// Changes kept for subscribers that fall behind.
+const CAPACITY: usize = 64;
+
+/// A change to the files of the site. `added` and `removed` list page URLs, and are
+/// empty when pages were only edited.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ContentChange {
+    /// `pages` or `static`.
+    pub dir: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+/// Passes content changes on to every client subscribed to the event stream.
+pub struct Events {
+    sender: broadcast::Sender<ContentChange>,
+    /// Whether a page URL may be announced.
+    include: Box<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl Events {
+    /// Announces changes to the pages whose URL passes `include`.
+    pub fn new(include: impl Fn(&str) -> bool + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self {
+            sender: broadcast::channel(CAPACITY).0,
+            include: Box::new(include),
+        })
+    }
+
+    pub fn publish(&self, mut change: ContentChange) {
+        change.added.retain(|url| (self.include)(url));
+        change.removed.retain(|url| (self.include)(url));
+        // Nobody may be listening.
+        let _ = self.sender.send(change);
+    }
+
+    /// Announces changes to the files in `dir`, the static directory.
+    pub fn watch_static(self: &Arc<Self>, dir: PathBuf) {
+        let events = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut last = fingerprint(&dir);
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            loop {
+                interval.tick().await;
+                let current = fingerprint(&dir);
+                if current != last {
+                    last = current;
+                    events.publish(ContentChange {
+                        dir: "static",
+                        added: Vec::new(),
+                        removed: Vec::new(),
+                    });
+                }
+            }
+        });
+    }
+}
+
+/// Streams every content change as a `change` event with the change as JSON.
+pub async fn handle_events(
+    State(events): State<Arc<Events>>,
+) -> Sse<ReceiverStream<Result<Event, Infallible>>> {
+    let mut changes = events.sender.subscribe();
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        loop {
+            let change = match changes.recv().await {
+                Ok(change) => change,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let Ok(event) = Event::default().event("change").json_data(&change) else {
+                continue;
+            };
+            if sender.send(Ok(event)).await.is_err() {
+                // The client went away.
+                return;
+            }
+        }
+    });
+    Sse::new(ReceiverStream::new(receiver)).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn leaves_out_excluded_pages() {
+        let events = Events::new(|url| !url.starts_with("/private"));
+        let mut changes = events.sender.subscribe();
+        events.publish(ContentChange {
+            dir: "pages",
+            added: vec!["/blog/new".to_string(), "/private/plans".to_string()],
+            removed: Vec::new(),
+        });
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.added, vec!["/blog/new".to_string()]);
+        assert_eq!(
+            serde_json::to_string(&change).unwrap(),
+            r#"{"dir":"pages","added":["/blog/new"]}"#
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::{io, path::Path, sync::Arc};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::header,
+    response::Response,
+};
+use flate2::{Compression, write::GzEncoder};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{error, info};
+
+use crate::admin::AdminState;
+
+/// Streams a `.tar.gz` of the pages and static directories.
+pub async fn handle_export(State(state): State<Arc<AdminState>>) -> Response {
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(16);
+    let pages_dir = state.pages_dir.clone();
+    let static_dir = state.static_dir.clone();
+
+    info!("Exporting site archive");
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter(tx.clone());
+        if let Err(e) = write_archive(writer, &pages_dir, &static_dir) {
+            error!("failed to export site archive: {}", e);
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+
+    let filename = format!(
+        "lime-export-{}.tar.gz",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/gzip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Body::from_stream(ReceiverStream::new(rx)))
+        .unwrap()
+}
+
+fn write_archive(writer: ChannelWriter, pages_dir: &Path, static_dir: &Path) -> io::Result<()> {
+    let encoder = GzEncoder::new(writer, Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    // Store symlinks as links, so the archive never includes files from outside the site.
+    archive.follow_symlinks(false);
+    for (name, dir) in [("pages", pages_dir), ("static", static_dir)] {
+        if dir.is_dir() {
+            archive.append_dir_all(name, dir)?;
+        }
+    }
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Forwards written bytes to the response body.
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+    use axum::{body::to_bytes, http::Request};
+    use flate2::read::GzDecoder;
+    use tower::ServiceExt;
+
+    use crate::{
+        config::{AdminConfig, Config},
+        test_router,
+    };
+
+    #[tokio::test]
+    async fn exports_the_site_without_following_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let pages = root.path().join("pages");
+        let assets = root.path().join("static");
+        let outside = root.path().join("outside");
+        fs::create_dir_all(pages.join("docs")).unwrap();
+        fs::create_dir_all(&assets).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(pages.join("index.html"), "home").unwrap();
+        fs::write(pages.join("docs/index.html"), "docs").unwrap();
+        fs::write(assets.join("style.css"), "css").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(outside.join("secret.txt"), pages.join("secret.txt")).unwrap();
+        symlink(&outside, assets.join("outside")).unwrap();
+
+        let config = Config {
+            pages_dir: pages.to_string_lossy().into_owned(),
+            static_dir: assets.to_string_lossy().into_owned(),
+            admin: AdminConfig {
+                token: Some("t0ken".to_string()),
+                content_dir: None,
+            },
+            ..Config::default()
+        };
+        let request = Request::get("/__lime/export.tar.gz")
+            .header("authorization", "Bearer t0ken")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = test_router(&config)
+            .unwrap()
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["content-type"], "application/gzip");
+        let archive = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let mut entries: Vec<(PathBuf, tar::EntryType)> =
+            tar::Archive::new(GzDecoder::new(&archive[..]))
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (
+                        entry.path().unwrap().into_owned(),
+                        entry.header().entry_type(),
+                    )
+                })
+                .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let files: Vec<_> = entries
+            .iter()
+            .filter(|(_, kind)| kind.is_file())
+            .map(|(path, _)| path.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "pages/docs/index.html",
+                "pages/index.html",
+                "static/style.css"
+            ]
+        );
+        let links: Vec<_> = entries
+            .iter()
+            .filter(|(_, kind)| kind.is_symlink())
+            .map(|(path, _)| path.to_str().unwrap())
+            .collect();
+        assert_eq!(links, ["pages/secret.txt", "static/outside"]);
+
+        let unpacked = tempfile::tempdir().unwrap();
+        tar::Archive::new(GzDecoder::new(&archive[..]))
+            .unpack(unpacked.path())
+            .unwrap();
+        let read = |path: &str| fs::read_to_string(unpacked.path().join(path)).unwrap();
+        assert_eq!(read("pages/index.html"), "home");
+        assert_eq!(read("pages/docs/index.html"), "docs");
+        assert_eq!(read("static/style.css"), "css");
+        assert!(
+            fs::symlink_metadata(unpacked.path().join("static/outside"))
+                .unwrap()
+                .is_symlink()
+        );
+    }
+}
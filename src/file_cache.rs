@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use axum::body::Bytes;
+use tokio::{fs, sync::OnceCell};
+use tracing::debug;
+
+use crate::config::ByteSize;
+
+/// A version of a file; a file whose version changed is read again.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Version {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+struct Entry {
+    version: Version,
+    content: OnceCell<Bytes>,
+    last_used: AtomicU64,
+    /// Whether the content counts towards the cache's size.
+    counted: AtomicBool,
+    /// When the file was first seen to have changed.
+    stale_since: OnceLock<Instant>,
+    refreshing: AtomicBool,
+}
+
+impl Entry {
+    fn new(version: Version, content: Option<Bytes>) -> Self {
+        Self {
+            version,
+            content: OnceCell::new_with(content),
+            last_used: AtomicU64::new(0),
+            counted: AtomicBool::new(false),
+            stale_since: OnceLock::new(),
+            refreshing: AtomicBool::new(false),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    files: HashMap<PathBuf, Arc<Entry>>,
+    size: u64,
+}
+
+impl Entries {
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.files.remove(path)
+            && entry.counted.load(Ordering::Relaxed)
+        {
+            self.size -= entry.version.len;
+        }
+    }
+}
+
+/// Keeps the content of recently served files in memory, up to a total size.
+pub struct FileCache {
+    max_size: u64,
+    stale_while_revalidate: Duration,
+    entries: Mutex<Entries>,
+    clock: AtomicU64,
+    #[cfg(test)]
+    reads: AtomicU64,
+}
+
+impl FileCache {
+    pub fn new(max_size: ByteSize, stale_while_revalidate: Duration) -> Self {
+        Self {
+            max_size: max_size.0,
+            stale_while_revalidate,
+            entries: Mutex::new(Entries::default()),
+            clock: AtomicU64::new(0),
+            #[cfg(test)]
+            reads: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the content of the file at `path`, reading it from disk only when it isn't
+    /// cached or has changed since, judging by its length and modification time. Concurrent requests for the same file share one read.
+    /// Within the `stale_while_revalidate` window, a changed file is served from the cache
+    /// while it is read again in the background.
+    pub async fn read(
+        self: &Arc<Self>,
+        path: &Path,
+        len: u64,
+        modified: Option<SystemTime>,
+    ) -> io::Result<Bytes> {
+        let version = Version { modified, len };
+        if version.len > self.max_size / 4 {
+            return fs::read(path).await.map(Bytes::from);
+        }
+
+        let entry = {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.files.get(path) {
+                Some(entry) if entry.version == version => entry.clone(),
+                Some(entry) if self.serves_stale(entry) => {
+                    let stale = entry.clone();
+                    drop(entries);
+                    if !stale.refreshing.swap(true, Ordering::Relaxed) {
+                        tokio::spawn(self.clone().refresh(
+                            path.to_path_buf(),
+                            version,
+                            stale.clone(),
+                        ));
+                    }
+                    return Ok(stale.content.get().cloned().unwrap_or_default());
+                }
+                _ => {
+                    entries.remove(path);
+                    let entry = Arc::new(Entry::new(version, None));
+                    entries.files.insert(path.to_path_buf(), entry.clone());
+                    entry
+                }
+            }
+        };
+        entry.last_used.store(
+            self.clock.fetch_add(1, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+
+        let mut loaded = false;
+        // Only one request reads the file, the others wait for its content. When the read
+        // fails, the next waiting request tries again.
+        let content = entry
+            .content
+            .get_or_try_init(|| async {
+                #[cfg(test)]
+                self.reads.fetch_add(1, Ordering::Relaxed);
+                loaded = true;
+                fs::read(path).await.map(Bytes::from)
+            })
+            .await?
+            .clone();
+        if loaded {
+            self.account(path, &entry);
+        }
+        Ok(content)
+    }
+
+    /// Evicts the files whose path matches, returning how many there were.
+    pub fn purge(&self, matches: impl Fn(&Path) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let purged: Vec<PathBuf> = entries
+            .files
+            .keys()
+            .filter(|path| matches(path))
+            .cloned()
+            .collect();
+        for path in &purged {
+            entries.remove(path);
+        }
+        purged.len()
+    }
+
+    fn serves_stale(&self, entry: &Entry) -> bool {
+        !self.stale_while_revalidate.is_zero()
+            && entry.content.initialized()
+            && entry.stale_since.get_or_init(Instant::now).elapsed() <= self.stale_while_revalidate
+    }
+
+    /// Reads a changed file and replaces the `stale` entry with it.
+    async fn refresh(self: Arc<Self>, path: PathBuf, version: Version, stale: Arc<Entry>) {
+        #[cfg(test)]
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let content = match fs::read(&path).await {
+            Ok(content) => Bytes::from(content),
+            Err(e) => {
+                debug!("Failed to refresh {:?}: {}", path, e);
+                stale.refreshing.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+        let entry = Arc::new(Entry::new(version, Some(content)));
+        entry
+            .last_used
+            .store(stale.last_used.load(Ordering::Relaxed), Ordering::Relaxed);
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if !entries
+                .files
+                .get(&path)
+                .is_some_and(|current| Arc::ptr_eq(current, &stale))
+            {
+                return;
+            }
+            entries.remove(&path);
+            entries.files.insert(path.clone(), entry.clone());
+        }
+        self.account(&path, &entry);
+    }
+
+    /// Counts a newly read entry towards the size, evicting the least recently used
+    /// entries when the cache grows too large.
+    fn account(&self, path: &Path, entry: &Arc<Entry>) {
+        let mut entries = self.entries.lock().unwrap();
+        // The file may have changed while it was being read.
+        if !entries
+            .files
+            .get(path)
+            .is_some_and(|current| Arc::ptr_eq(current, entry))
+        {
+            return;
+        }
+        entry.counted.store(true, Ordering::Relaxed);
+        entries.size += entry.version.len;
+        while entries.size > self.max_size {
+            let Some(oldest) = entries
+                .files
+                .iter()
+                .filter(|(_, e)| e.counted.load(Ordering::Relaxed) && !Arc::ptr_eq(e, entry))
+                .min_by_key(|(_, e)| e.last_used.load(Ordering::Relaxed))
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shares_one_read_between_concurrent_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        std::fs::write(&path, "console.log(1)").unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        let cache = Arc::new(FileCache::new(ByteSize(1 << 20), Duration::ZERO));
+
+        let mut reads = tokio::task::JoinSet::new();
+        for _ in 0..16 {
+            let (cache, path, metadata) = (cache.clone(), path.clone(), metadata.clone());
+            reads.spawn(async move {
+                cache
+                    .read(&path, metadata.len(), metadata.modified().ok())
+                    .await
+                    .unwrap()
+            });
+        }
+        while let Some(content) = reads.join_next().await {
+            assert_eq!(content.unwrap(), "console.log(1)");
+        }
+        assert_eq!(cache.reads.load(Ordering::Relaxed), 1);
+
+        std::fs::write(&path, "console.log(22)").unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            cache
+                .read(&path, metadata.len(), metadata.modified().ok())
+                .await
+                .unwrap(),
+            "console.log(22)"
+        );
+        assert_eq!(cache.reads.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn serves_stale_content_while_refreshing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        std::fs::write(&path, "old").unwrap();
+        let cache = Arc::new(FileCache::new(ByteSize(1 << 20), Duration::from_secs(10)));
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            cache
+                .read(&path, metadata.len(), metadata.modified().ok())
+                .await
+                .unwrap(),
+            "old"
+        );
+
+        std::fs::write(&path, "newer").unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            cache
+                .read(&path, metadata.len(), metadata.modified().ok())
+                .await
+                .unwrap(),
+            "old"
+        );
+        for _ in 0..100 {
+            if cache
+                .read(&path, metadata.len(), metadata.modified().ok())
+                .await
+                .unwrap()
+                == "newer"
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the changed file was never refreshed");
+    }
+}
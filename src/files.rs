@@ -0,0 +1,257 @@
+use std::{path::Path, sync::Arc, time::SystemTime};
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncReadExt};
+use tracing::error;
+
+use crate::{admin::AdminState, paths::normalize_request_path};
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    File,
+    Directory,
+}
+
+#[derive(Serialize)]
+struct FileInfo {
+    name: String,
+    kind: Kind,
+    size: u64,
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<Vec<FileInfo>>,
+}
+
+/// Returns metadata of a file, or of a directory and its entries, as JSON.
+/// Paths start with `pages/` or `static/`, like in the export archive.
+pub async fn handle_files(State(state): State<Arc<AdminState>>, request: Request) -> Response {
+    // Nesting under the admin prefix leaves `/files/<path>` in the URI.
+    let raw = request
+        .uri()
+        .path()
+        .strip_prefix("/files")
+        .unwrap_or_default();
+    let Ok(relative) = normalize_request_path(raw) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let relative = relative.trim_end_matches('/');
+    let (root, rest) = relative.split_once('/').unwrap_or((relative, ""));
+    let base = match root {
+        "pages" => &state.pages_dir,
+        "static" => &state.static_dir,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let (Ok(base), Ok(target)) = (
+        fs::canonicalize(base).await,
+        fs::canonicalize(base.join(rest)).await,
+    ) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !target.starts_with(&base) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match describe(&target, true, state.max_file_size).await {
+        Ok(info) => Json(info).into_response(),
+        Err(e) => {
+            error!("failed to read metadata of {}: {}", target.display(), e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        }
+    }
+}
+
+/// Describes `path`; directory entries are listed one level deep and files are hashed,
+/// unless they are larger than `max_hash_size`.
+async fn describe(path: &Path, top: bool, max_hash_size: Option<u64>) -> std::io::Result<FileInfo> {
+    let metadata = fs::metadata(path).await?;
+    let mut info = FileInfo {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        kind: Kind::File,
+        size: metadata.len(),
+        modified: metadata.modified().ok().map(format_time),
+        mime: None,
+        sha256: None,
+        entries: None,
+    };
+
+    if metadata.is_dir() {
+        info.kind = Kind::Directory;
+        if top {
+            let mut entries = Vec::new();
+            let mut dir = fs::read_dir(path).await?;
+            while let Some(entry) = dir.next_entry().await? {
+                // Broken symlinks and other unreadable entries are skipped.
+                if let Ok(entry) = Box::pin(describe(&entry.path(), false, max_hash_size)).await {
+                    entries.push(entry);
+                }
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            info.entries = Some(entries);
+        }
+    } else {
+        info.mime = Some(
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+        );
+        if top && max_hash_size.is_none_or(|max| info.size <= max) {
+            info.sha256 = Some(sha256(path).await?);
+        }
+    }
+    Ok(info)
+}
+
+/// Hashes a file without reading it into memory at once.
+async fn sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{Router, body::to_bytes, http::Request};
+    use serde_json::Value;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::{AdminConfig, ByteSize, Config},
+        test_router,
+    };
+
+    /// A site with `pages/` and `static/`, next to an `outside/` directory.
+    fn site() -> (TempDir, Router) {
+        let root = tempfile::tempdir().unwrap();
+        let path = |name: &str| root.path().join(name);
+        fs::create_dir_all(path("pages/docs")).unwrap();
+        fs::create_dir_all(path("static")).unwrap();
+        fs::create_dir_all(path("outside")).unwrap();
+        fs::write(path("pages/docs/index.html"), "<h1>Docs</h1>").unwrap();
+        fs::write(path("pages/docs/large.pdf"), vec![0; 64]).unwrap();
+        fs::write(path("outside/secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(path("outside"), path("pages/escape")).unwrap();
+            std::os::unix::fs::symlink(path("outside/secret.txt"), path("static/leak.txt"))
+                .unwrap();
+        }
+        let config = Config {
+            pages_dir: path("pages").to_string_lossy().into_owned(),
+            static_dir: path("static").to_string_lossy().into_owned(),
+            max_file_size: Some(ByteSize(32)),
+            admin: AdminConfig {
+                token: Some("t0ken".to_string()),
+                content_dir: None,
+            },
+            ..Config::default()
+        };
+        let router = test_router(&config).unwrap();
+        (root, router)
+    }
+
+    async fn files(router: &Router, path: &str) -> (StatusCode, Value) {
+        let request = Request::get(format!("/__lime/files/{}", path))
+            .header("authorization", "Bearer t0ken")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn describes_files_and_directories() {
+        let (_root, router) = site();
+        let (status, file) = files(&router, "pages/docs/index.html").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(file["name"], "index.html");
+        assert_eq!(file["kind"], "file");
+        assert_eq!(file["size"], 13);
+        assert_eq!(file["mime"], "text/html");
+        assert_eq!(
+            file["sha256"],
+            "9d25b6be52dbf649a926af0c331d9d98f0d2d0377b8d0173016ead8817c61023"
+        );
+        assert!(file["modified"].is_string());
+
+        // Files over the max file size aren't hashed.
+        let (_, file) = files(&router, "pages/docs/large.pdf").await;
+        assert_eq!(file["size"], 64);
+        assert!(file.get("sha256").is_none());
+
+        let (status, dir) = files(&router, "pages/docs/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(dir["kind"], "directory");
+        let entries = dir["entries"].as_array().unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e["name"].clone()).collect();
+        assert_eq!(names, ["index.html", "large.pdf"]);
+        assert!(entries[0].get("sha256").is_none());
+    }
+
+    #[tokio::test]
+    async fn stays_inside_the_site() {
+        let (_root, router) = site();
+        for path in [
+            "outside/secret.txt",
+            "pages/missing.html",
+            "escape/secret.txt",
+        ] {
+            let (status, _) = files(&router, path).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", path);
+        }
+        for path in [
+            "pages/../outside/secret.txt",
+            "pages/%2e%2e/outside/secret.txt",
+        ] {
+            let (status, _) = files(&router, path).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", path);
+        }
+        #[cfg(unix)]
+        for path in ["pages/escape/secret.txt", "pages/escape", "static/leak.txt"] {
+            let (status, _) = files(&router, path).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    Form, Router,
+    body::Body,
+    extract::{ConnectInfo, FromRequest, Path, Request, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use chrono::{Local, SecondsFormat};
+use reqwest::Client;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{FormConfig, FormEmailConfig};
+
+/// Route submissions are posted to.
+pub const FORMS_ROUTE: &str = "/__lime/form/{name}";
+/// Time to wait for a webhook or the SMTP server to answer.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Forms {
+    client: Client,
+    forms: HashMap<String, FormConfig>,
+    started: Instant,
+    /// The hour and the number of submissions in it, by client address.
+    submissions: Mutex<HashMap<(String, IpAddr), (u64, u32)>>,
+}
+
+impl Forms {
+    /// Counts a submission to `form` from `ip`, or returns the seconds until it may
+    /// submit again.
+    fn limit(&self, form: &FormConfig, ip: IpAddr) -> Option<u64> {
+        let elapsed = self.started.elapsed().as_secs();
+        let hour = elapsed / 3600;
+        let mut submissions = self.submissions.lock().unwrap();
+        if submissions.len() > 10_000 {
+            submissions.retain(|_, (h, _)| *h == hour);
+        }
+        let entry = submissions
+            .entry((form.name.clone(), ip))
+            .or_insert((hour, 0));
+        if entry.0 != hour {
+            *entry = (hour, 0);
+        }
+        if entry.1 >= form.rate_limit {
+            return Some(3600 - elapsed % 3600);
+        }
+        entry.1 += 1;
+        None
+    }
+}
+
+/// Accepts the submissions of the `[[forms]]` and forwards them to their webhook or
+/// mailbox.
+pub fn forms_router<S: Clone + Send + Sync + 'static>(configs: &[FormConfig]) -> Result<Router<S>> {
+    let mut forms = HashMap::new();
+    for form in configs {
+        if form.webhook.is_none() && form.email.is_none() {
+            return Err(anyhow!(
+                "Form '{}' has neither a webhook nor an email to deliver to",
+                form.name
+            ));
+        }
+        if forms.insert(form.name.clone(), form.clone()).is_some() {
+            return Err(anyhow!("Form '{}' is declared twice", form.name));
+        }
+    }
+    let client = Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .map_err(|e| anyhow!("Failed to create form webhook client: {}", e))?;
+    let forms = Arc::new(Forms {
+        client,
+        forms,
+        started: Instant::now(),
+        submissions: Mutex::new(HashMap::new()),
+    });
+    Ok(Router::new()
+        .route(FORMS_ROUTE, post(handle_form))
+        .with_state(forms))
+}
+
+async fn handle_form(
+    State(forms): State<Arc<Forms>>,
+    Path(name): Path<String>,
+    request: Request,
+) -> Response {
+    let Some(form) = forms.forms.get(&name) else {
+        return text(StatusCode::NOT_FOUND, "No such form.");
+    };
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |info| info.0.ip());
+    let fields = match Form::<Vec<(String, String)>>::from_request(request, &()).await {
+        Ok(Form(fields)) => fields,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    // Bots get the same answer as people, so they don't learn to skip the field.
+    if fields
+        .iter()
+        .any(|(field, value)| *field == form.honeypot && !value.is_empty())
+    {
+        debug!(form = name, %ip, "Dropped form submission that filled in the honeypot");
+        return sent(form);
+    }
+    if let Some(retry_after) = forms.limit(form, ip) {
+        warn!(form = name, %ip, "Too many form submissions");
+        return Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::RETRY_AFTER, retry_after)
+            .body(Body::from("Too many submissions, try again later."))
+            .unwrap();
+    }
+
+    let fields: Vec<_> = fields
+        .into_iter()
+        .filter(|(field, _)| *field != form.honeypot)
+        .collect();
+    if let Err(e) = deliver(&forms.client, form, &fields).await {
+        error!(form = name, "{}", e);
+        return text(
+            StatusCode::BAD_GATEWAY,
+            "Your message could not be sent, try again later.",
+        );
+    }
+    info!(form = name, %ip, "Forwarded form submission");
+    sent(form)
+}
+
+/// Sends a submission to the webhook and the mailbox of `form`.
+async fn deliver(client: &Client, form: &FormConfig, fields: &[(String, String)]) -> Result<()> {
+    if let Some(url) = &form.webhook {
+        let mut values = serde_json::Map::new();
+        for (field, value) in fields {
+            // Repeated fields, like a group of checkboxes, are joined.
+            match values.get_mut(field) {
+                Some(serde_json::Value::String(joined)) => {
+                    joined.push_str(", ");
+                    joined.push_str(value);
+                }
+                _ => {
+                    values.insert(field.clone(), value.clone().into());
+                }
+            }
+        }
+        let payload = json!({
+            "form": form.name,
+            "fields": values,
+            "submitted_at": Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        });
+        client
+            .post(url)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| anyhow!("Failed to post form '{}' to {}: {}", form.name, url, e))?;
+    }
+    if let Some(email) = &form.email {
+        tokio::time::timeout(DELIVERY_TIMEOUT, send_mail(email, fields))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("timed out")))
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to mail form '{}' through {}: {}",
+                    form.name,
+                    email.smtp,
+                    e
+                )
+            })?;
+    }
+    Ok(())
+}
+
+/// The message mailed for a submission, with CRLF line endings. A field named `email`
+/// becomes the `Reply-To`, so the sender can be answered directly.
+fn message(email: &FormEmailConfig, fields: &[(String, String)]) -> String {
+    let mut headers = vec![
+        format!("From: {}", email.from),
+        format!("To: {}", email.to.join(", ")),
+        format!("Subject: {}", email.subject),
+        format!("Date: {}", Local::now().to_rfc2822()),
+        "MIME-Version: 1.0".to_string(),
+        "Content-Type: text/plain; charset=utf-8".to_string(),
+    ];
+    if let Some((_, reply_to)) = fields.iter().find(|(field, value)| {
+        field == "email" && !value.is_empty() && !value.contains(['\r', '\n'])
+    }) {
+        headers.push(format!("Reply-To: {}", reply_to));
+    }
+    let body: Vec<_> = fields
+        .iter()
+        .map(|(field, value)| format!("{}: {}", field, value))
+        .collect();
+    let text = format!("{}\n\n{}\n", headers.join("\n"), body.join("\n"));
+    text.lines()
+        // Lines starting with a dot would end the message early.
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{}\r\n", line)
+            } else {
+                format!("{}\r\n", line)
+            }
+        })
+        .collect()
+}
+
+/// Mails a submission through an SMTP server that accepts mail without authentication.
+async fn send_mail(email: &FormEmailConfig, fields: &[(String, String)]) -> Result<()> {
+    let stream = TcpStream::connect(&email.smtp).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    reply(&mut reader, 2).await?;
+    command(&mut writer, &mut reader, "EHLO lime", 2).await?;
+    command(
+        &mut writer,
+        &mut reader,
+        &format!("MAIL FROM:<{}>", email.from),
+        2,
+    )
+    .await?;
+    for to in &email.to {
+        command(&mut writer, &mut reader, &format!("RCPT TO:<{}>", to), 2).await?;
+    }
+    command(&mut writer, &mut reader, "DATA", 3).await?;
+    writer.write_all(message(email, fields).as_bytes()).await?;
+    command(&mut writer, &mut reader, ".", 2).await?;
+    // The message is accepted, so a failure to say goodbye doesn't matter.
+    let _ = command(&mut writer, &mut reader, "QUIT", 2).await;
+    Ok(())
+}
+
+/// Sends an SMTP command and waits for a reply of the expected class, e.g. 2 for `250`.
+async fn command(
+    writer: &mut (impl AsyncWriteExt + Unpin),
+    reader: &mut (impl AsyncBufReadExt + Unpin),
+    line: &str,
+    class: u8,
+) -> Result<()> {
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    reply(reader, class).await.map_err(|e| {
+        anyhow!(
+            "{} was refused: {}",
+            line.split(':').next().unwrap_or(line),
+            e
+        )
+    })
+}
+
+/// Reads a reply, which may span several `250-` lines, and checks its class.
+async fn reply(reader: &mut (impl AsyncBufReadExt + Unpin), class: u8) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("the server closed the connection"));
+        }
+        let line = line.trim_end();
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        return match line.as_bytes().first() {
+            Some(digit) if *digit == b'0' + class => Ok(()),
+            _ => Err(anyhow!("{}", line)),
+        };
+    }
+}
+
+fn sent(form: &FormConfig) -> Response {
+    match &form.redirect {
+        Some(redirect) => Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(header::LOCATION, redirect)
+            .body(Body::empty())
+            .unwrap(),
+        None => text(StatusCode::OK, "Thanks, your message was sent."),
+    }
+}
+
+fn text(status: StatusCode, message: &'static str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(message))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn mails_through_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let email = FormEmailConfig {
+            smtp: listener.local_addr().unwrap().to_string(),
+            from: "site@example.com".to_string(),
+            to: vec!["me@example.com".to_string()],
+            subject: "Contact".to_string(),
+        };
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 ready\r\n").await.unwrap();
+            let mut received = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = match line.as_str() {
+                    "EHLO lime" => b"250-hello\r\n250 OK\r\n",
+                    "DATA" => b"354 go on\r\n",
+                    "QUIT" => b"221 bye\r\n",
+                    _ if line.starts_with("MAIL") || line.starts_with("RCPT") || line == "." => {
+                        b"250 OK\r\n"
+                    }
+                    _ => b"",
+                };
+                writer.write_all(reply).await.unwrap();
+                received.push(line);
+            }
+            received
+        });
+
+        let fields = vec![
+            ("email".to_string(), "ada@example.com".to_string()),
+            ("message".to_string(), "Hi\n.\nBye".to_string()),
+        ];
+        send_mail(&email, &fields).await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(received[1], "MAIL FROM:<site@example.com>");
+        assert_eq!(received[2], "RCPT TO:<me@example.com>");
+        assert!(received.contains(&"Reply-To: ada@example.com".to_string()));
+        assert!(received.contains(&"..".to_string()));
+        assert_eq!(received[received.len() - 2..], [".", "QUIT"]);
+    }
+}
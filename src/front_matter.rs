@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, de::Error};
+use tracing::warn;
+
+const DELIMITER: &str = "+++";
+
+/// Page metadata from a TOML block between `+++` lines at the top of a page.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub collections: Vec<String>,
+    /// Publication date, as a TOML date or datetime. Times without an offset are local.
+    #[serde(deserialize_with = "deserialize_date")]
+    pub date: Option<DateTime<Utc>>,
+    /// Work in progress, only served when drafts are enabled.
+    pub draft: bool,
+    /// The page is hidden until this time, like a draft.
+    #[serde(deserialize_with = "deserialize_date")]
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+impl FrontMatter {
+    /// Whether the page at `relative` is a draft, by front matter or by living in a `drafts` directory.
+    pub fn is_draft(&self, relative: &Path) -> bool {
+        self.draft || relative.components().any(|c| c.as_os_str() == "drafts")
+    }
+
+    /// Whether a scheduled page is due, or the page isn't scheduled at all.
+    pub fn is_published(&self) -> bool {
+        self.publish_at.is_none_or(|at| at <= Utc::now())
+    }
+
+    /// Whether the page is hidden, unless drafts are shown.
+    pub fn is_hidden(&self, relative: &Path) -> bool {
+        self.is_draft(relative) || !self.is_published()
+    }
+}
+
+/// Splits `content` into its front matter and the rest of the page.
+/// Pages without front matter get the default, and so do pages whose front matter is
+/// invalid, though the block is still removed so it never reaches the browser.
+pub fn split(content: &str) -> (FrontMatter, &str) {
+    let Some(rest) = content
+        .strip_prefix(DELIMITER)
+        .and_then(|r| r.strip_prefix("\r\n").or_else(|| r.strip_prefix('\n')))
+    else {
+        return (FrontMatter::default(), content);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == DELIMITER {
+            let front_matter = toml::from_str(&rest[..offset]).unwrap_or_else(|e| {
+                warn!("Invalid front matter: {}", e);
+                FrontMatter::default()
+            });
+            return (front_matter, &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    // No closing delimiter, so this is content that happens to start with `+++`.
+    (FrontMatter::default(), content)
+}
+
+fn deserialize_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let value = toml::Value::deserialize(deserializer)?;
+    let text = match &value {
+        toml::Value::Datetime(datetime) => datetime.to_string(),
+        toml::Value::String(text) => text.clone(),
+        _ => return Err(D::Error::custom("expected a date")),
+    };
+    parse_date(&text)
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("invalid date '{}'", text)))
+}
+
+fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    let local = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .ok()?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_removes_front_matter() {
+        let (front_matter, body) =
+            split("+++\ntitle = \"Hello\"\ntags = [\"rust\"]\n+++\n<h1>Hello</h1>");
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(front_matter.tags, ["rust"]);
+        assert_eq!(body, "<h1>Hello</h1>");
+    }
+
+    #[test]
+    fn leaves_pages_without_front_matter_alone() {
+        for page in [
+            "<h1>Hello</h1>",
+            "+++ not front matter",
+            "+++\nunterminated",
+        ] {
+            assert_eq!(split(page).1, page);
+        }
+    }
+
+    #[test]
+    fn parses_dates() {
+        let (front_matter, _) = split("+++\ndate = 2024-05-01T10:00:00Z\n+++\n");
+        assert_eq!(
+            front_matter.date.map(|d| d.to_rfc3339()).as_deref(),
+            Some("2024-05-01T10:00:00+00:00")
+        );
+        let (front_matter, _) = split("+++\ndate = \"2024-05-01\"\n+++\n");
+        assert!(front_matter.date.is_some());
+        let (front_matter, _) = split("+++\ndate = 2024-05-01\n+++\n");
+        assert!(front_matter.date.is_some());
+    }
+
+    #[test]
+    fn detects_drafts() {
+        let (front_matter, _) = split("+++\ndraft = true\n+++\n");
+        assert!(front_matter.is_draft(Path::new("post.html")));
+        let front_matter = FrontMatter::default();
+        assert!(front_matter.is_draft(Path::new("blog/drafts/post.html")));
+        assert!(!front_matter.is_draft(Path::new("blog/post.html")));
+    }
+
+    #[test]
+    fn hides_scheduled_pages_until_due() {
+        let (front_matter, _) = split("+++\npublish_at = 2000-01-01T00:00:00Z\n+++\n");
+        assert!(front_matter.is_published());
+        let (front_matter, _) = split("+++\npublish_at = 9999-01-01T00:00:00Z\n+++\n");
+        assert!(!front_matter.is_published());
+        assert!(front_matter.is_hidden(Path::new("post.html")));
+    }
+
+    #[test]
+    fn removes_invalid_front_matter() {
+        let (front_matter, body) = split("+++\r\ntitle = \n+++\r\n<p>x</p>");
+        assert!(front_matter.title.is_none());
+        assert_eq!(body, "<p>x</p>");
+    }
+}
@@ -0,0 +1,261 @@
+use std::{fs, io, net::SocketAddr, path::PathBuf, process, sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use axum::Router;
+use tokio::{
+    net::{TcpListener, TcpSocket, lookup_host},
+    sync::Notify,
+};
+use tracing::{info, warn};
+
+use crate::error::LimeError;
+
+/// Time in-flight requests get to finish once the server is asked to stop.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Listens on `address`. With `reuse_port`, other processes may listen on the same port,
+/// and the kernel spreads new connections between them.
+pub async fn bind(address: &str, reuse_port: bool) -> Result<TcpListener> {
+    #[cfg(not(unix))]
+    if reuse_port {
+        return Err(LimeError::Config("'reuse_port' is only supported on Unix".to_string()).into());
+    }
+    if !reuse_port {
+        return TcpListener::bind(address)
+            .await
+            .map_err(|source| bind_error(address, source));
+    }
+    let addr = lookup_host(address)
+        .await
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| {
+            bind_error(
+                address,
+                io::Error::new(io::ErrorKind::NotFound, "no such address"),
+            )
+        })?;
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()
+    } else {
+        TcpSocket::new_v6()
+    }
+    .map_err(|source| bind_error(address, source))?;
+    #[cfg(unix)]
+    socket
+        .set_reuseport(true)
+        .map_err(|source| bind_error(address, source))?;
+    socket
+        .bind(addr)
+        .map_err(|source| bind_error(address, source))?;
+    socket
+        .listen(1024)
+        .map_err(|source| bind_error(address, source))
+}
+
+fn bind_error(address: &str, source: io::Error) -> anyhow::Error {
+    let port = address
+        .rsplit_once(':')
+        .and_then(|(_, port)| port.parse::<u16>().ok());
+    LimeError::Bind {
+        address: address.to_string(),
+        help: port
+            .map(|port| bind_help(port, &source))
+            .unwrap_or_default(),
+        source,
+    }
+    .into()
+}
+
+/// What to do about a port that can't be listened on, as lines to print after the error.
+fn bind_help(port: u16, error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::AddrInUse => {
+            let owner = match port_owner(port) {
+                Some((pid, name)) => {
+                    format!("\n  Port {} is used by {} (PID {}).", port, name, pid)
+                }
+                None => format!("\n  Another process is using port {}.", port),
+            };
+            format!(
+                "{}\n  Stop it, or set another `port` in the configuration.",
+                owner
+            )
+        }
+        io::ErrorKind::PermissionDenied if port < 1024 => "\n  Ports below 1024 need \
+            privileges: run Lime as root, grant it CAP_NET_BIND_SERVICE, or set a `port` of \
+            1024 or above."
+            .to_string(),
+        io::ErrorKind::PermissionDenied => {
+            "\n  The system refused the port, e.g. because of a firewall or SELinux.".to_string()
+        }
+        _ => String::new(),
+    }
+}
+
+/// The ID and name of the process listening on TCP `port`, when this user may see it.
+#[cfg(target_os = "linux")]
+fn port_owner(port: u16) -> Option<(u32, String)> {
+    const LISTEN: &str = "0A";
+    let tables: String = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| fs::read_to_string(table).ok())
+        .collect();
+    // Each socket is a line like `0: 0100007F:0F9F 00000000:0000 0A ... <inode> ...`.
+    let inode = tables.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let local_port = fields.get(1)?.rsplit_once(':')?.1;
+        if u16::from_str_radix(local_port, 16).ok()? != port || *fields.get(3)? != LISTEN {
+            return None;
+        }
+        fields.get(9).map(|inode| inode.to_string())
+    })?;
+    let socket = PathBuf::from(format!("socket:[{}]", inode));
+    fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+        let holds = fs::read_dir(entry.path().join("fd"))
+            .ok()?
+            .flatten()
+            .any(|fd| fs::read_link(fd.path()).is_ok_and(|target| target == socket));
+        let name = fs::read_to_string(entry.path().join("comm")).ok()?;
+        holds.then(|| (pid, name.trim().to_string()))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn port_owner(_port: u16) -> Option<(u32, String)> {
+    None
+}
+
+/// Records the server's process ID for as long as it runs.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes this process's ID to `path`. With `take_over`, the process recorded there
+    /// before is asked to finish its requests and exit, handing the port over to this one.
+    pub fn create(path: &str, take_over: bool) -> Result<Self> {
+        let previous = fs::read_to_string(path)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok())
+            .filter(|pid| *pid != process::id());
+        fs::write(path, process::id().to_string())
+            .map_err(|e| anyhow!("Failed to write pid file {}: {}", path, e))?;
+        if take_over && let Some(pid) = previous {
+            stop(pid)?;
+        }
+        Ok(Self {
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // A newer process may have recorded itself already.
+        let current = fs::read_to_string(&self.path).unwrap_or_default();
+        if current.trim() == process::id().to_string() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn stop(pid: u32) -> Result<()> {
+    use nix::{
+        errno::Errno,
+        sys::signal::{Signal, kill},
+        unistd::Pid,
+    };
+
+    let raw = i32::try_from(pid).map_err(|_| anyhow!("Invalid process ID {}", pid))?;
+    match kill(Pid::from_raw(raw), Signal::SIGTERM) {
+        Ok(()) => {
+            info!("Asked process {} to hand over", pid);
+            Ok(())
+        }
+        // The previous server is already gone.
+        Err(Errno::ESRCH) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to stop process {}: {}", pid, e)),
+    }
+}
+
+#[cfg(not(unix))]
+fn stop(_pid: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Resolves on Ctrl+C, or on `SIGTERM` from a process taking over.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Serves `router` until the process is asked to stop, then stops accepting connections
+/// and gives in-flight requests up to [`DRAIN_TIMEOUT`] to finish.
+pub async fn serve(listener: TcpListener, router: Router) -> Result<()> {
+    let stopping = Arc::new(Notify::new());
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let stopping = stopping.clone();
+        async move {
+            shutdown_signal().await;
+            stopping.notify_one();
+        }
+    });
+    tokio::select! {
+        result = server => result.map_err(|source| {
+            LimeError::Io {
+                context: "Server failed".to_string(),
+                source,
+            }
+            .into()
+        }),
+        _ = async {
+            stopping.notified().await;
+            tokio::time::sleep(DRAIN_TIMEOUT).await;
+        } => {
+            warn!("Stopped before all requests finished");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn names_the_process_holding_a_port() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let error = io::Error::from(io::ErrorKind::AddrInUse);
+        let help = bind_help(port, &error);
+        assert!(
+            help.contains(&format!("(PID {})", process::id())),
+            "{}",
+            help
+        );
+    }
+}
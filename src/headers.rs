@@ -0,0 +1,108 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use globset::{Glob, GlobMatcher};
+
+use crate::config::HeaderRule;
+
+struct CompiledRule {
+    matcher: Option<GlobMatcher>,
+    extensions: Vec<String>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Custom response headers, selected by path pattern or file extension.
+pub struct Headers {
+    rules: Vec<CompiledRule>,
+}
+
+impl Headers {
+    pub fn new(rules: &[HeaderRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                if rule.pattern.is_none() && rule.extensions.is_empty() {
+                    return Err(anyhow!("Header rules need a pattern or extensions"));
+                }
+                let matcher = rule
+                    .pattern
+                    .as_deref()
+                    .map(|pattern| {
+                        Glob::new(pattern)
+                            .map(|g| g.compile_matcher())
+                            .map_err(|e| anyhow!("Invalid header pattern '{}': {}", pattern, e))
+                    })
+                    .transpose()?;
+                let headers = rule
+                    .set
+                    .iter()
+                    .map(|(name, value)| {
+                        let name = HeaderName::try_from(name)
+                            .map_err(|e| anyhow!("Invalid header name '{}': {}", name, e))?;
+                        let value = HeaderValue::try_from(value)
+                            .map_err(|e| anyhow!("Invalid value for header {}: {}", name, e))?;
+                        Ok((name, value))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(CompiledRule {
+                    matcher,
+                    extensions: rule
+                        .extensions
+                        .iter()
+                        .map(|e| e.trim_start_matches('.').to_lowercase())
+                        .collect(),
+                    headers,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl CompiledRule {
+    /// A rule with both a pattern and extensions needs both to match.
+    fn matches(&self, path: &str, extension: Option<&str>) -> bool {
+        let pattern_matches = self.matcher.as_ref().is_none_or(|m| m.is_match(path));
+        let extension_matches = self.extensions.is_empty()
+            || extension.is_some_and(|ext| self.extensions.iter().any(|e| *e == ext));
+        pattern_matches && extension_matches
+    }
+}
+
+/// Sets the headers of every matching rule on successful and redirect responses.
+/// Later rules override headers set by earlier ones.
+pub async fn apply_headers(
+    State(headers): State<Arc<Headers>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    let extension = Path::new(&path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+
+    let mut response = next.run(request).await;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return response;
+    }
+    for rule in headers
+        .rules
+        .iter()
+        .filter(|r| r.matches(&path, extension.as_deref()))
+    {
+        for (name, value) in &rule.headers {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+    response
+}
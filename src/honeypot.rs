@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tracing::warn;
+
+use crate::config::{HoneypotAction, HoneypotConfig};
+
+/// Responses held back at once with `tarpit`; more are answered right away, so a
+/// scanner can't use up the server's connections.
+const MAX_TARPITTED: usize = 100;
+
+/// Bans or slows down addresses that request paths no visitor of the site would.
+pub struct Honeypot {
+    paths: GlobSet,
+    action: HoneypotAction,
+    duration: Duration,
+    tarpit_delay: Duration,
+    /// Trapped addresses, until when.
+    trapped: Mutex<HashMap<IpAddr, Instant>>,
+    tarpitted: AtomicUsize,
+}
+
+impl Honeypot {
+    /// Builds the honeypot, or `None` when no paths are configured.
+    pub fn new(config: &HoneypotConfig) -> Result<Option<Self>> {
+        if config.paths.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &config.paths {
+            builder.add(
+                Glob::new(pattern)
+                    .map_err(|e| anyhow!("Invalid honeypot path '{}': {}", pattern, e))?,
+            );
+        }
+        let paths = builder
+            .build()
+            .map_err(|e| anyhow!("Failed to build honeypot paths: {}", e))?;
+        Ok(Some(Self {
+            paths,
+            action: config.action,
+            duration: Duration::from_secs(config.duration),
+            tarpit_delay: Duration::from_secs(config.tarpit_delay),
+            trapped: Mutex::new(HashMap::new()),
+            tarpitted: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Whether `ip` is trapped, trapping it first if it requested a honeypot `path`.
+    fn check(&self, ip: IpAddr, path: &str) -> bool {
+        let now = Instant::now();
+        let mut trapped = self.trapped.lock().unwrap();
+        if self.paths.is_match(path) {
+            if trapped.insert(ip, now + self.duration).is_none() {
+                warn!(%ip, path, "Trapped a scanner requesting a honeypot path");
+            }
+            return true;
+        }
+        match trapped.get(&ip) {
+            Some(until) if *until > now => true,
+            Some(_) => {
+                trapped.retain(|_, until| *until > now);
+                false
+            }
+            None => false,
+        }
+    }
+
+    async fn tarpit(&self) {
+        if self.tarpitted.fetch_add(1, Ordering::Relaxed) < MAX_TARPITTED {
+            tokio::time::sleep(self.tarpit_delay).await;
+        }
+        self.tarpitted.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub async fn trap_scanners(
+    State(honeypot): State<Arc<Honeypot>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip());
+    if !ip.is_some_and(|ip| honeypot.check(ip, request.uri().path())) {
+        return next.run(request).await;
+    }
+    if honeypot.action == HoneypotAction::Tarpit {
+        honeypot.tarpit().await;
+    }
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bans_addresses_that_hit_a_honeypot() {
+        let config = HoneypotConfig {
+            paths: vec!["/wp-login.php".to_string(), "/.env".to_string()],
+            ..HoneypotConfig::default()
+        };
+        let honeypot = Honeypot::new(&config).unwrap().unwrap();
+        let scanner = IpAddr::from([203, 0, 113, 7]);
+        let visitor = IpAddr::from([198, 51, 100, 1]);
+
+        assert!(!honeypot.check(scanner, "/"));
+        assert!(honeypot.check(scanner, "/.env"));
+        assert!(honeypot.check(scanner, "/"));
+        assert!(!honeypot.check(visitor, "/"));
+    }
+}
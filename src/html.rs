@@ -0,0 +1,198 @@
+/// Escapes text for safe use inside HTML content and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the contents of the `<title>` element, if any.
+pub fn title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    Some(decode_entities(html[start..end].trim()))
+}
+
+/// Extracts the visible text of an HTML document, dropping tags, scripts and styles.
+pub fn to_text(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut text = String::with_capacity(html.len() / 2);
+    let mut i = 0;
+    while let Some(offset) = html[i..].find('<') {
+        text.push_str(&html[i..i + offset]);
+        text.push(' ');
+        let tag_start = i + offset;
+        let Some(tag_len) = html[tag_start..].find('>') else {
+            i = html.len();
+            break;
+        };
+        i = tag_start + tag_len + 1;
+        for skipped in ["script", "style", "title"] {
+            if lower[tag_start + 1..].starts_with(skipped) {
+                let close = format!("</{}", skipped);
+                i = lower[i..].find(&close).map_or(html.len(), |end| i + end);
+                break;
+            }
+        }
+    }
+    text.push_str(&html[i..]);
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Iterates over the tags of `html`, each without its closing `>`. The contents of
+/// `<script>` and `<style>` elements are skipped, since they may contain `<`.
+pub fn tags(html: &str) -> impl Iterator<Item = &str> {
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        let start = i + html[i..].find('<')?;
+        let len = html[start..].find('>')?;
+        let tag = &html[start..start + len];
+        i = start + len;
+        for raw in ["script", "style"] {
+            if is_tag(tag, raw) && !tag.ends_with('/') {
+                let close = format!("</{}", raw);
+                let lower = html[i..].to_ascii_lowercase();
+                i = lower.find(&close).map_or(html.len(), |end| i + end);
+            }
+        }
+        Some(tag)
+    })
+}
+
+/// Calls `attributes` with every tag of `html`, without its closing `>`, and inserts
+/// the attributes it returns at the end of the tag.
+pub fn add_attributes(html: &str, mut attributes: impl FnMut(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut i = 0;
+    for tag in tags(html) {
+        let Some(extra) = attributes(tag) else {
+            continue;
+        };
+        let start = tag.as_ptr() as usize - html.as_ptr() as usize;
+        // Keep a self-closing slash at the end of the tag.
+        let end = start + tag.trim_end_matches(['/', ' ']).len();
+        output.push_str(&html[i..end]);
+        output.push_str(&extra);
+        i = end;
+    }
+    output.push_str(&html[i..]);
+    output
+}
+
+/// Whether the opening tag `tag` (e.g. `<script src="/a.js"`) is a `name` element.
+pub fn is_tag(tag: &str, name: &str) -> bool {
+    tag.strip_prefix('<')
+        .and_then(|rest| rest.get(..name.len()).map(|n| (n, &rest[name.len()..])))
+        .is_some_and(|(n, rest)| {
+            n.eq_ignore_ascii_case(name)
+                && (rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || c == '/'))
+        })
+}
+
+/// Returns the value of attribute `name` in the opening tag `tag`.
+pub fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag.trim_end_matches('>').split_once(char::is_whitespace)?.1;
+    loop {
+        rest = rest.trim_start();
+        let name_end = rest.find(|c: char| c == '=' || c == '/' || c.is_whitespace());
+        let (attr, after) = rest.split_at(name_end.unwrap_or(rest.len()));
+        if attr.is_empty() && after.is_empty() {
+            return None;
+        }
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('=') {
+            Some(value) => {
+                let value = value.trim_start();
+                match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let close = value[1..].find(quote).map_or(value.len(), |i| i + 1);
+                        (&value[1..close], value.get(close + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                        (&value[..end], &value[end..])
+                    }
+                }
+            }
+            None => ("", after.strip_prefix('/').unwrap_or(after)),
+        };
+        if attr.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        if attr.is_empty() && remaining.len() == rest.len() {
+            return None;
+        }
+        rest = remaining;
+    }
+}
+
+/// Returns the charset a page declares with `<meta charset>` or `<meta http-equiv>` in
+/// its first kilobyte, where browsers look for it.
+pub fn declared_charset(html: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&html[..html.len().min(1024)]);
+    tags(&head)
+        .filter(|tag| is_tag(tag, "meta"))
+        .find_map(|tag| {
+            let charset = attribute(tag, "charset").or_else(|| {
+                attribute(tag, "http-equiv")
+                    .filter(|name| name.eq_ignore_ascii_case("content-type"))
+                    .and(attribute(tag, "content"))
+                    .and_then(|content| {
+                        let start = content.to_ascii_lowercase().find("charset=")?;
+                        Some(&content[start + "charset=".len()..])
+                    })
+            })?;
+            let charset = charset.trim().trim_matches(['"', '\'']);
+            (!charset.is_empty()).then(|| charset.to_string())
+        })
+}
+
+/// Returns the 1-based line of the byte at `offset` in `text`.
+pub fn line_at(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_attributes() {
+        let tag = "<link rel=stylesheet href='/a.css' data-x disabled>";
+        assert_eq!(attribute(tag, "rel"), Some("stylesheet"));
+        assert_eq!(attribute(tag, "HREF"), Some("/a.css"));
+        assert_eq!(attribute(tag, "disabled"), Some(""));
+        assert_eq!(attribute(tag, "integrity"), None);
+    }
+
+    #[test]
+    fn finds_the_declared_charset() {
+        let page = b"<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">";
+        assert_eq!(declared_charset(page).as_deref(), Some("Shift_JIS"));
+        let page = b"<meta name=viewport content=width=device-width><meta charset=iso-8859-1>";
+        assert_eq!(declared_charset(page).as_deref(), Some("iso-8859-1"));
+        assert_eq!(declared_charset(b"<p>caf\xe9</p>"), None);
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::{Result, anyhow};
+use globset::{Glob, GlobMatcher};
+use tracing::debug;
+
+use crate::config::{InjectPosition, InjectRule};
+
+struct CompiledRule {
+    matcher: GlobMatcher,
+    position: InjectPosition,
+    html: String,
+    respect_privacy: bool,
+}
+
+/// Inserts configured HTML snippets into served pages.
+#[derive(Default)]
+pub struct Injector {
+    rules: Vec<CompiledRule>,
+}
+
+impl Injector {
+    pub fn new(rules: &[InjectRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let matcher = Glob::new(&rule.pattern)
+                    .map_err(|e| anyhow!("Invalid inject pattern '{}': {}", rule.pattern, e))?
+                    .compile_matcher();
+                Ok(CompiledRule {
+                    matcher,
+                    position: rule.position,
+                    html: rule.html.clone(),
+                    respect_privacy: rule.respect_privacy,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether pages may differ depending on the visitor's privacy preferences.
+    pub fn respects_privacy(&self) -> bool {
+        self.rules.iter().any(|r| r.respect_privacy)
+    }
+
+    /// Applies every rule matching `request_path` to `html`. Rules that respect privacy are
+    /// skipped when the visitor `opted_out` of tracking.
+    pub fn apply(&self, request_path: &str, mut html: Vec<u8>, opted_out: bool) -> Vec<u8> {
+        for rule in self
+            .rules
+            .iter()
+            .filter(|r| r.matcher.is_match(request_path))
+            .filter(|r| !(opted_out && r.respect_privacy))
+        {
+            let index = match rule.position {
+                InjectPosition::Head => find_tag(&html, b"</head>"),
+                InjectPosition::Body => rfind_tag(&html, b"</body>"),
+            };
+            match index {
+                Some(i) => {
+                    html.splice(i..i, rule.html.bytes());
+                }
+                None => debug!(path = %request_path, "No closing tag found for inject rule"),
+            }
+        }
+        html
+    }
+}
+
+fn find_tag(haystack: &[u8], tag: &[u8]) -> Option<usize> {
+    haystack
+        .windows(tag.len())
+        .position(|w| w.eq_ignore_ascii_case(tag))
+}
+
+fn rfind_tag(haystack: &[u8], tag: &[u8]) -> Option<usize> {
+    haystack
+        .windows(tag.len())
+        .rposition(|w| w.eq_ignore_ascii_case(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, position: InjectPosition, html: &str) -> InjectRule {
+        InjectRule {
+            pattern: pattern.to_string(),
+            position,
+            html: html.to_string(),
+            respect_privacy: false,
+        }
+    }
+
+    #[test]
+    fn injects_into_matching_pages() {
+        let mut analytics = rule("/**", InjectPosition::Body, "<script>track()</script>");
+        analytics.respect_privacy = true;
+        let injector = Injector::new(&[
+            rule("/blog/*", InjectPosition::Head, "<link rel=\"alternate\">"),
+            analytics,
+        ])
+        .unwrap();
+        let page = b"<HTML><HEAD></HEAD><body><p></body></p></body></html>".to_vec();
+
+        let html = injector.apply("/blog/post", page.clone(), false);
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            "<HTML><HEAD><link rel=\"alternate\"></HEAD><body><p></body></p>\
+             <script>track()</script></body></html>"
+        );
+        let html = injector.apply("/about", page.clone(), true);
+        assert_eq!(html, page);
+        assert!(Injector::new(&[rule("[", InjectPosition::Head, "")]).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    fs,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::config::JwtConfig;
+
+/// JWKS are refetched on unknown key IDs, but not more often than this.
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+enum Keys {
+    Static(DecodingKey, Algorithm),
+    Jwks {
+        url: String,
+        client: reqwest::Client,
+        set: RwLock<Option<JwkSet>>,
+        fetched_at: RwLock<Option<Instant>>,
+    },
+}
+
+/// Validates HS256 and RS256 tokens, including their `exp`, `iss`, and `aud` claims.
+pub struct JwtValidator {
+    keys: Keys,
+    issuer: Option<String>,
+    audience: Option<String>,
+    claim_headers: Vec<(String, HeaderName)>,
+}
+
+impl JwtValidator {
+    pub fn new(config: &JwtConfig) -> Result<Self> {
+        let keys = match (&config.secret, &config.public_key_file, &config.jwks_url) {
+            (Some(secret), None, None) => Keys::Static(
+                DecodingKey::from_secret(secret.as_bytes()),
+                Algorithm::HS256,
+            ),
+            (None, Some(path), None) => {
+                let pem = fs::read(path)
+                    .map_err(|e| anyhow!("Failed to read public key {}: {}", path, e))?;
+                let key = DecodingKey::from_rsa_pem(&pem)
+                    .map_err(|e| anyhow!("Invalid RSA public key {}: {}", path, e))?;
+                Keys::Static(key, Algorithm::RS256)
+            }
+            (None, None, Some(url)) => Keys::Jwks {
+                url: url.clone(),
+                client: reqwest::Client::new(),
+                set: RwLock::new(None),
+                fetched_at: RwLock::new(None),
+            },
+            _ => {
+                return Err(anyhow!(
+                    "Exactly one of 'secret', 'public_key_file', or 'jwks_url' must be set"
+                ));
+            }
+        };
+        let claim_headers = config
+            .claim_headers
+            .iter()
+            .map(|(claim, name)| {
+                HeaderName::try_from(name)
+                    .map(|name| (claim.clone(), name))
+                    .map_err(|_| anyhow!("Invalid header name '{}' for claim '{}'", name, claim))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            keys,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            claim_headers,
+        })
+    }
+
+    /// Names of the headers claims are forwarded in.
+    pub fn claim_header_names(&self) -> impl Iterator<Item = &HeaderName> {
+        self.claim_headers.iter().map(|(_, name)| name)
+    }
+
+    /// The configured claims of a validated token as headers. Lists are joined with
+    /// commas, and claims that are missing or can't be a header value are left out.
+    pub fn claim_headers(&self, claims: &Value) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (claim, name) in &self.claim_headers {
+            let value = match claims.get(claim) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Array(items)) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Some(Value::Null) | Some(Value::Object(_)) | None => continue,
+                Some(other) => other.to_string(),
+            };
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name.clone(), value);
+            }
+        }
+        headers
+    }
+
+    /// Returns the token's claims if it is valid.
+    pub async fn validate(&self, token: &str) -> Result<Value> {
+        let (key, algorithm) = match &self.keys {
+            Keys::Static(key, algorithm) => (key.clone(), *algorithm),
+            Keys::Jwks { .. } => (self.jwks_key(token).await?, Algorithm::RS256),
+        };
+
+        let mut validation = Validation::new(algorithm);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        let data = decode::<Value>(token, &key, &validation)?;
+        Ok(data.claims)
+    }
+
+    async fn jwks_key(&self, token: &str) -> Result<DecodingKey> {
+        let Keys::Jwks { set, .. } = &self.keys else {
+            unreachable!()
+        };
+        let kid = decode_header(token)?
+            .kid
+            .ok_or_else(|| anyhow!("Token has no key ID"))?;
+
+        if let Some(key) = find_key(set, &kid)? {
+            return Ok(key);
+        }
+        self.refresh_jwks().await?;
+        find_key(set, &kid)?.ok_or_else(|| anyhow!("Unknown key ID '{}'", kid))
+    }
+
+    async fn refresh_jwks(&self) -> Result<()> {
+        let Keys::Jwks {
+            url,
+            client,
+            set,
+            fetched_at,
+        } = &self.keys
+        else {
+            return Ok(());
+        };
+        if fetched_at
+            .read()
+            .unwrap()
+            .is_some_and(|t| t.elapsed() < JWKS_REFRESH_INTERVAL)
+        {
+            debug!("Skipping JWKS refresh, fetched recently");
+            return Ok(());
+        }
+        *fetched_at.write().unwrap() = Some(Instant::now());
+
+        let jwks = client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow!("Failed to fetch JWKS from {}: {}", url, e))?
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| anyhow!("Invalid JWKS from {}: {}", url, e))?;
+        info!(keys = jwks.keys.len(), "Fetched JWKS from {}", url);
+        *set.write().unwrap() = Some(jwks);
+        Ok(())
+    }
+}
+
+fn find_key(set: &RwLock<Option<JwkSet>>, kid: &str) -> Result<Option<DecodingKey>> {
+    let set = set.read().unwrap();
+    match set.as_ref().and_then(|s| s.find(kid)) {
+        Some(jwk) => Ok(Some(DecodingKey::from_jwk(jwk)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "hs256-secret";
+
+    fn validator() -> JwtValidator {
+        JwtValidator::new(&JwtConfig {
+            secret: Some(SECRET.to_string()),
+            public_key_file: None,
+            jwks_url: None,
+            issuer: Some("https://idp.example.com".to_string()),
+            audience: Some("lime".to_string()),
+            claim_headers: [
+                ("sub".to_string(), "X-User".to_string()),
+                ("groups".to_string(), "X-Groups".to_string()),
+                ("missing".to_string(), "X-Missing".to_string()),
+            ]
+            .into(),
+        })
+        .unwrap()
+    }
+
+    fn claims() -> Value {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        json!({
+            "sub": "ada",
+            "groups": ["docs", "ops"],
+            "iss": "https://idp.example.com",
+            "aud": "lime",
+            "exp": now + 3600,
+        })
+    }
+
+    fn token(algorithm: Algorithm, secret: &str, claims: &Value) -> String {
+        encode(
+            &Header::new(algorithm),
+            claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn with(claim: &str, value: Value) -> Value {
+        let mut claims = claims();
+        claims[claim] = value;
+        claims
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_tokens() {
+        let claims = validator()
+            .validate(&token(Algorithm::HS256, SECRET, &claims()))
+            .await
+            .unwrap();
+        assert_eq!(claims["sub"], "ada");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_tokens() {
+        let validator = validator();
+        let mut expired = claims();
+        expired["exp"] = json!(expired["exp"].as_u64().unwrap() - 7200);
+        let mut without_exp = claims();
+        without_exp.as_object_mut().unwrap().remove("exp");
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(claims().to_string())
+        );
+        let tokens = [
+            (
+                "signature",
+                token(Algorithm::HS256, "other-secret", &claims()),
+            ),
+            ("algorithm", token(Algorithm::HS384, SECRET, &claims())),
+            ("unsigned", unsigned),
+            ("expired", token(Algorithm::HS256, SECRET, &expired)),
+            ("no expiry", token(Algorithm::HS256, SECRET, &without_exp)),
+            (
+                "issuer",
+                token(
+                    Algorithm::HS256,
+                    SECRET,
+                    &with("iss", json!("https://evil.example.com")),
+                ),
+            ),
+            (
+                "audience",
+                token(Algorithm::HS256, SECRET, &with("aud", json!("other"))),
+            ),
+        ];
+        for (case, token) in tokens {
+            assert!(validator.validate(&token).await.is_err(), "{}", case);
+        }
+    }
+
+    #[test]
+    fn maps_claims_to_headers() {
+        let headers = validator().claim_headers(&claims());
+        assert_eq!(headers["x-user"], "ada");
+        assert_eq!(headers["x-groups"], "docs, ops");
+        assert!(!headers.contains_key("x-missing"));
+
+        let headers = validator().claim_headers(&with("sub", json!("ada\r\nX-Admin: yes")));
+        assert!(!headers.contains_key("x-user"));
+    }
+}
@@ -0,0 +1,87 @@
+//! The Lime web server. The `lime` binary is built on this library, which also lets a
+//! configuration be tested in-process with [`test_router`], and applications serve a few
+//! routes of their own next to a site with [`serve`].
+
+mod access_log;
+mod admin;
+mod alerts;
+mod analytics;
+mod auth;
+mod blog;
+mod bots;
+mod cache_check;
+mod catalog;
+mod chaos;
+pub mod cli;
+pub mod cluster;
+pub mod commands;
+mod compress;
+pub mod config;
+mod config_edit;
+mod content;
+mod cors;
+mod csp;
+mod csrf;
+mod dashboard;
+mod effective_config;
+pub mod error;
+mod error_template;
+mod etag;
+mod events;
+mod export;
+mod file_cache;
+mod files;
+mod forms;
+mod front_matter;
+mod handoff;
+mod headers;
+mod honeypot;
+mod html;
+mod inject;
+mod jwt;
+mod links;
+mod lint;
+mod listing;
+mod log_format;
+mod logging;
+mod metrics;
+mod mock_api;
+mod notify;
+mod oidc;
+pub mod pack;
+mod paths;
+mod precompressed;
+mod preload;
+mod priority;
+mod privacy;
+mod probes;
+mod profile;
+mod proxy;
+mod proxy_cache;
+mod purge;
+mod redirects;
+mod request_id;
+mod routes;
+mod search;
+mod server;
+mod session;
+mod sites;
+mod sri;
+mod status;
+mod storage;
+mod store;
+mod suggest;
+mod taxonomy;
+mod throttle;
+mod traffic;
+mod update;
+mod version;
+
+pub use server::test_router;
+
+/// Serves the site described by `config` as `lime serve` does. The routes in
+/// `config.handlers` take precedence over files and Lime's own routes, and go through the
+/// same middleware.
+pub async fn serve(mut config: config::Config) -> anyhow::Result<()> {
+    commands::handle_serve(&mut config, false).await
+}
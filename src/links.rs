@@ -0,0 +1,304 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use reqwest::{Client, StatusCode};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    config::Config,
+    front_matter, html,
+    paths::{self, normalize_request_path, resolve_case_insensitive},
+    redirects::Redirects,
+};
+
+/// Attributes that refer to other pages or resources, by element.
+const REFERENCES: [(&str, &str); 8] = [
+    ("a", "href"),
+    ("link", "href"),
+    ("img", "src"),
+    ("script", "src"),
+    ("source", "src"),
+    ("iframe", "src"),
+    ("video", "src"),
+    ("audio", "src"),
+];
+
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A link or resource reference in a page, at a 1-based line.
+pub struct Reference<'a> {
+    pub line: usize,
+    pub url: &'a str,
+}
+
+/// Lists the links and resource references in `html`. Placeholders like `{{prev_url}}`,
+/// which are filled in when the page is served, are left out.
+pub fn references(html: &str) -> Vec<Reference<'_>> {
+    html::tags(html)
+        .filter_map(|tag| {
+            let url = REFERENCES
+                .iter()
+                .find(|(element, _)| html::is_tag(tag, element))
+                .and_then(|(_, attribute)| html::attribute(tag, attribute))?
+                .trim();
+            let offset = tag.as_ptr() as usize - html.as_ptr() as usize;
+            (!url.is_empty() && !url.contains("{{")).then(|| Reference {
+                line: html::line_at(html, offset),
+                url,
+            })
+        })
+        .collect()
+}
+
+/// Resolves site URLs the way the server routes them.
+pub struct Resolver<'a> {
+    config: &'a Config,
+    redirects: Option<Arc<Redirects>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(config: &'a Config) -> Result<Self> {
+        let redirects = config
+            .redirects_file
+            .as_deref()
+            .map(|path| Redirects::load(path, config.case_insensitive))
+            .transpose()?;
+        Ok(Self { config, redirects })
+    }
+
+    /// Whether the server answers the absolute URL `url` with something other than a 404.
+    pub fn exists(&self, url: &str) -> bool {
+        let path = url.split('?').next().unwrap_or(url);
+        if self.redirects.as_ref().is_some_and(|r| r.contains(path)) || self.is_generated(path) {
+            return true;
+        }
+        let Ok(relative) = normalize_request_path(path) else {
+            return false;
+        };
+        for mount in &self.config.mounts {
+            let route = mount.route.trim_end_matches('/');
+            if let Some(rest) = path.strip_prefix(route)
+                && (rest.is_empty() || rest.starts_with('/'))
+            {
+                return self.file_exists(Path::new(&mount.dir), rest.trim_matches('/'));
+            }
+        }
+        let pages_dir = Path::new(&self.config.pages_dir);
+        self.file_exists(Path::new(&self.config.static_dir), &relative)
+            || self.file_exists(pages_dir, &relative)
+            || self.file_exists(
+                pages_dir,
+                &format!("{}.html", relative.trim_end_matches('/')),
+            )
+    }
+
+    /// Whether `relative` inside `base` is a file, or a directory with an index file.
+    fn file_exists(&self, base: &Path, relative: &str) -> bool {
+        let mut path = base.join(relative);
+        if self.config.case_insensitive && !path.exists() {
+            match resolve_case_insensitive(base, Path::new(relative)) {
+                Some(resolved) => path = resolved,
+                None => return false,
+            }
+        }
+        path.is_file()
+            || (path.is_dir()
+                && self
+                    .config
+                    .index_files
+                    .iter()
+                    .any(|index| path.join(index).is_file()))
+    }
+
+    /// Routes the server generates rather than serving from a file.
+    fn is_generated(&self, path: &str) -> bool {
+        let config = self.config;
+        let under = |prefix: &str| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '.']))
+        };
+        let is_permalink = || {
+            let mut segments = path.trim_start_matches('/').split('/');
+            let is_number = |s: Option<&str>, len| {
+                s.is_some_and(|s| s.len() == len && s.bytes().all(|b| b.is_ascii_digit()))
+            };
+            is_number(segments.next(), 4) && is_number(segments.next(), 2)
+        };
+        (config.search.enabled && path == config.search.route)
+            || (config.taxonomies && (under("/tags") || under("/collections")))
+            || config.blog.as_ref().is_some_and(|blog| {
+                under(&format!("/{}", blog.dir.trim_matches('/')))
+                    || (blog.permalinks && is_permalink())
+            })
+            || under("/__lime")
+            || (config.favicon && path == "/favicon.ico")
+    }
+}
+
+/// A page of the site as read from disk.
+pub struct SourcePage {
+    pub path: PathBuf,
+    pub url: String,
+    pub content: String,
+    /// Lines taken up by the front matter, to report lines of the file.
+    pub line_offset: usize,
+}
+
+impl SourcePage {
+    /// The page's HTML, without front matter.
+    pub fn html(&self) -> &str {
+        front_matter::split(&self.content).1
+    }
+}
+
+/// Reads every content page of the site.
+pub fn source_pages(config: &Config) -> Result<Vec<SourcePage>> {
+    let pages_dir = Path::new(&config.pages_dir);
+    paths::content_pages(pages_dir)
+        .iter()
+        .map(|relative| {
+            let path = pages_dir.join(relative);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+            let html = front_matter::split(&content).1;
+            let line_offset = content[..content.len() - html.len()].lines().count();
+            Ok(SourcePage {
+                path,
+                url: paths::page_url(relative, &config.index_files),
+                line_offset,
+                content,
+            })
+        })
+        .collect()
+}
+
+/// Reports links to local pages and files that don't exist, and with `external`, links to
+/// other sites that fail to load, checking at most `concurrency` of them at once.
+pub async fn check_links(config: &Config, external: bool, concurrency: usize) -> Result<()> {
+    let resolver = Resolver::new(config)?;
+    let pages = source_pages(config)?;
+
+    let mut dead = 0;
+    let mut checked = 0;
+    // External URLs with every place they are linked from.
+    let mut external_links: BTreeMap<String, Vec<(usize, usize)>> = BTreeMap::new();
+    for (index, page) in pages.iter().enumerate() {
+        for reference in references(page.html()) {
+            let line = reference.line + page.line_offset;
+            if reference.url.starts_with("http://") || reference.url.starts_with("https://") {
+                let url = reference.url.split('#').next().unwrap_or(reference.url);
+                external_links
+                    .entry(url.to_string())
+                    .or_default()
+                    .push((index, line));
+                continue;
+            }
+            let Some(target) = paths::join_url(&page.url, reference.url) else {
+                continue;
+            };
+            checked += 1;
+            if !resolver.exists(&target) {
+                report(&page.path, line, &format!("dead link to {}", reference.url));
+                dead += 1;
+            }
+        }
+    }
+
+    if external && !external_links.is_empty() {
+        let client = Client::builder()
+            .timeout(EXTERNAL_TIMEOUT)
+            .user_agent(concat!("lime/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+        for url in external_links.keys().cloned() {
+            let client = client.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let result = check_external(&client, &url).await;
+                (url, result)
+            });
+        }
+        while let Some(joined) = tasks.join_next().await {
+            let (url, result) = joined.map_err(|e| anyhow!("Link check failed: {}", e))?;
+            checked += external_links[&url].len();
+            if let Err(problem) = result {
+                for (index, line) in &external_links[&url] {
+                    report(&pages[*index].path, *line, &format!("{} {}", url, problem));
+                    dead += 1;
+                }
+            }
+        }
+    }
+
+    if dead == 0 {
+        println!(
+            "{} Checked {} links in {} pages",
+            "".green(),
+            checked,
+            pages.len()
+        );
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Found {} dead links out of {} in {} pages",
+            dead,
+            checked,
+            pages.len()
+        ))
+    }
+}
+
+/// Requests an external URL, falling back to `GET` for servers that don't support `HEAD`.
+async fn check_external(client: &Client, url: &str) -> Result<(), String> {
+    let mut response = client
+        .head(url)
+        .send()
+        .await
+        .map_err(|e| format!("failed: {}", e))?;
+    if matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| format!("failed: {}", e))?;
+    }
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return Err(format!("returned {}", response.status()));
+    }
+    Ok(())
+}
+
+fn report(path: &Path, line: usize, message: &str) {
+    println!(
+        "{}:{}: {}",
+        path.display().to_string().bold(),
+        line,
+        message
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_references_with_lines() {
+        let html = "<a href=\"/a\">a</a>\n<img src=\"b.png\" alt=\"\">\n\
+                    <a href=\"{{next_url}}\">next</a><a name=\"top\">";
+        let found: Vec<_> = references(html).iter().map(|r| (r.line, r.url)).collect();
+        assert_eq!(found, [(1, "/a"), (2, "b.png")]);
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+
+use crate::{
+    config::Config,
+    html,
+    links::{self, Resolver, source_pages},
+    paths,
+};
+
+/// Elements that never have a closing tag.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose closing tag may be left out.
+const OPTIONAL_CLOSE: [&str; 17] = [
+    "html", "head", "body", "p", "li", "dt", "dd", "option", "optgroup", "thead", "tbody", "tfoot",
+    "tr", "td", "th", "colgroup", "rt",
+];
+
+/// A problem found in a page, at a 1-based line.
+#[derive(Debug, PartialEq, Eq)]
+struct Problem {
+    line: usize,
+    message: String,
+}
+
+/// Checks every page for unclosed tags, a missing `<title>`, images without `alt`, and links
+/// to local files that do not exist.
+pub fn lint(config: &Config) -> Result<()> {
+    let resolver = Resolver::new(config)?;
+    let pages = source_pages(config)?;
+
+    let mut count = 0;
+    for page in &pages {
+        let html = page.html();
+        let mut problems = check_html(html);
+        problems.extend(check_references(html, &page.url, |target| {
+            resolver.exists(target)
+        }));
+        problems.sort_by_key(|p| p.line);
+        for problem in problems {
+            println!(
+                "{}:{}: {}",
+                page.path.display().to_string().bold(),
+                problem.line + page.line_offset,
+                problem.message
+            );
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        println!("{} No problems found in {} pages", "".green(), pages.len());
+        Ok(())
+    } else {
+        Err(anyhow!("Found {} problems in {} pages", count, pages.len()))
+    }
+}
+
+/// Checks the structure of a page: balanced tags, a title and image descriptions.
+fn check_html(html: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut open: Vec<(String, usize)> = Vec::new();
+    let mut has_title = false;
+
+    for tag in html::tags(html) {
+        let start = tag.as_ptr() as usize - html.as_ptr() as usize;
+        let line = html::line_at(html, start);
+        if tag.starts_with("<!") || tag.starts_with("<?") {
+            continue;
+        }
+
+        if let Some(closing) = tag.strip_prefix("</") {
+            let name = closing.trim().to_ascii_lowercase();
+            match open.iter().rposition(|(open_name, _)| *open_name == name) {
+                Some(index) => {
+                    for (unclosed, opened_at) in open.drain(index..).skip(1) {
+                        if !OPTIONAL_CLOSE.contains(&unclosed.as_str()) {
+                            problems.push(Problem {
+                                line: opened_at,
+                                message: format!("<{}> is never closed", unclosed),
+                            });
+                        }
+                    }
+                }
+                None if OPTIONAL_CLOSE.contains(&name.as_str()) => {}
+                None => problems.push(Problem {
+                    line,
+                    message: format!("</{}> has no matching opening tag", name),
+                }),
+            }
+            continue;
+        }
+
+        let name = tag[1..]
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+        match name.as_str() {
+            "title" => has_title = true,
+            "img" if html::attribute(tag, "alt").is_none() => problems.push(Problem {
+                line,
+                message: "<img> has no alt attribute".to_string(),
+            }),
+            _ => {}
+        }
+        if !VOID_ELEMENTS.contains(&name.as_str()) && !tag.ends_with('/') {
+            open.push((name, line));
+        }
+    }
+
+    for (unclosed, opened_at) in open {
+        if !OPTIONAL_CLOSE.contains(&unclosed.as_str()) {
+            problems.push(Problem {
+                line: opened_at,
+                message: format!("<{}> is never closed", unclosed),
+            });
+        }
+    }
+    if !has_title {
+        problems.push(Problem {
+            line: 1,
+            message: "page has no <title>".to_string(),
+        });
+    }
+    problems
+}
+
+/// Checks that local links and resources of the page at `url` resolve.
+fn check_references(html: &str, url: &str, exists: impl Fn(&str) -> bool) -> Vec<Problem> {
+    links::references(html)
+        .into_iter()
+        .filter(|reference| {
+            paths::join_url(url, reference.url).is_some_and(|target| !exists(&target))
+        })
+        .map(|reference| Problem {
+            line: reference.line,
+            message: format!("broken link to {}", reference.url),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_structural_problems() {
+        let html = "<html><head><title>Hi</title></head>\n\
+                    <body><div><p>text\n\
+                    <img src=\"a.png\"><br>\n\
+                    <script>if (a < b) {}</script>\n\
+                    <section></div></span></body></html>";
+        let messages: Vec<_> = check_html(html)
+            .into_iter()
+            .map(|p| (p.line, p.message))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (3, "<img> has no alt attribute".to_string()),
+                (5, "<section> is never closed".to_string()),
+                (5, "</span> has no matching opening tag".to_string()),
+            ]
+        );
+        assert_eq!(
+            check_html("<p>untitled</p>")[0].message,
+            "page has no <title>"
+        );
+    }
+
+    #[test]
+    fn finds_broken_references() {
+        let html = "<a href=\"/ok\">ok</a>\n<a href=\"missing\">x</a><a href=\"https://a.b\">\
+                    <a href=\"#top\">";
+        let problems = check_references(html, "/docs/", |target| target == "/ok");
+        assert_eq!(
+            problems,
+            [Problem {
+                line: 2,
+                message: "broken link to missing".to_string()
+            }]
+        );
+    }
+}
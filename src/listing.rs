@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use axum::{
+    body::Body,
+    http::{StatusCode, header},
+    response::Response,
+};
+use tokio::fs;
+
+use crate::html;
+
+const HTML_LISTING: &str = include_str!("../assets/listing.html");
+
+/// Renders a generated page through `listing.html` in the pages directory, or the built-in
+/// template. `{{title}}` is replaced with the escaped title and `{{content}}` with `content`.
+pub async fn render(pages_dir: &Path, title: &str, content: &str) -> Response {
+    let template = fs::read_to_string(pages_dir.join("listing.html"))
+        .await
+        .unwrap_or_else(|_| HTML_LISTING.to_string());
+    let page = template
+        .replace("{{title}}", &html::escape(title))
+        .replace("{{content}}", content);
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html")
+        .body(Body::from(page))
+        .unwrap()
+}
+
+/// Renders links to `pages` as an HTML list.
+pub fn page_list<'a>(pages: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let mut list = String::from("<ul>\n");
+    for (url, title) in pages {
+        list.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            html::escape(url),
+            html::escape(title)
+        ));
+    }
+    list.push_str("</ul>");
+    list
+}
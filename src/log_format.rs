@@ -0,0 +1,199 @@
+use std::{fmt::Write, net::SocketAddr, time::Duration};
+
+use anyhow::{Result, anyhow};
+use axum::http::{HeaderMap, Method, StatusCode, Uri, Version};
+use chrono::Local;
+
+use crate::privacy::Anonymizer;
+
+/// A parsed nginx-style access log template such as
+/// `$remote_addr - [$time_local] "$request" $status $body_bytes_sent`.
+pub struct LogFormat {
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+enum Variable {
+    RemoteAddr,
+    RequestMethod,
+    RequestUri,
+    Uri,
+    Args,
+    ServerProtocol,
+    Request,
+    Status,
+    BodyBytesSent,
+    RequestTime,
+    TimeLocal,
+    TimeIso8601,
+    RequestId,
+    Header(String),
+}
+
+/// Everything a template can refer to about a finished request.
+pub struct LogEntry<'a> {
+    pub remote_addr: Option<SocketAddr>,
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub version: Version,
+    pub headers: &'a HeaderMap,
+    pub request_id: &'a str,
+    pub status: StatusCode,
+    pub bytes: Option<u64>,
+    pub duration: Duration,
+    pub anonymizer: &'a Anonymizer,
+}
+
+impl LogFormat {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            if name.is_empty() {
+                literal.push('$');
+                continue;
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Variable(Variable::parse(&name)?));
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn render(&self, entry: &LogEntry) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => line.push_str(s),
+                Segment::Variable(v) => v.render(entry, &mut line),
+            }
+        }
+        line
+    }
+}
+
+impl Variable {
+    fn parse(name: &str) -> Result<Self> {
+        let variable = match name {
+            "remote_addr" => Self::RemoteAddr,
+            "request_method" => Self::RequestMethod,
+            "request_uri" => Self::RequestUri,
+            "uri" => Self::Uri,
+            "args" | "query_string" => Self::Args,
+            "server_protocol" => Self::ServerProtocol,
+            "request" => Self::Request,
+            "status" => Self::Status,
+            "body_bytes_sent" | "bytes_sent" => Self::BodyBytesSent,
+            "request_time" => Self::RequestTime,
+            "time_local" => Self::TimeLocal,
+            "time_iso8601" => Self::TimeIso8601,
+            "request_id" => Self::RequestId,
+            _ => match name.strip_prefix("http_") {
+                Some(header) => Self::Header(header.replace('_', "-")),
+                None => return Err(anyhow!("Unknown log format variable '${}'", name)),
+            },
+        };
+        Ok(variable)
+    }
+
+    fn render(&self, entry: &LogEntry, out: &mut String) {
+        let _ = match self {
+            Self::RemoteAddr => match entry.remote_addr {
+                Some(addr) => write!(out, "{}", entry.anonymizer.ip(addr.ip())),
+                None => write!(out, "-"),
+            },
+            Self::RequestMethod => write!(out, "{}", entry.method),
+            Self::RequestUri => write!(out, "{}", request_uri(entry.uri)),
+            Self::Uri => write!(out, "{}", entry.uri.path()),
+            Self::Args => write!(out, "{}", entry.uri.query().unwrap_or_default()),
+            Self::ServerProtocol => write!(out, "{:?}", entry.version),
+            Self::Request => write!(
+                out,
+                "{} {} {:?}",
+                entry.method,
+                request_uri(entry.uri),
+                entry.version
+            ),
+            Self::Status => write!(out, "{}", entry.status.as_u16()),
+            Self::BodyBytesSent => match entry.bytes {
+                Some(bytes) => write!(out, "{}", bytes),
+                None => write!(out, "-"),
+            },
+            Self::RequestTime => write!(out, "{:.3}", entry.duration.as_secs_f64()),
+            Self::TimeLocal => write!(out, "{}", Local::now().format("%d/%b/%Y:%H:%M:%S %z")),
+            Self::TimeIso8601 => write!(out, "{}", Local::now().format("%Y-%m-%dT%H:%M:%S%:z")),
+            Self::RequestId => write!(out, "{}", entry.request_id),
+            Self::Header(name) => match entry
+                .headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+            {
+                Some(value) => write!(out, "{}", entry.anonymizer.header(name, value)),
+                None => write!(out, "-"),
+            },
+        };
+    }
+}
+
+fn request_uri(uri: &Uri) -> &str {
+    uri.path_and_query().map(|p| p.as_str()).unwrap_or("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn renders_nginx_variables() {
+        let format = LogFormat::parse(
+            r#"$remote_addr "$request" $status $body_bytes_sent "$http_user_agent" $http_referer $"#,
+        )
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, "curl/8.0".parse().unwrap());
+        let uri: Uri = "/search?q=lime".parse().unwrap();
+        let entry = LogEntry {
+            remote_addr: Some("192.0.2.7:51000".parse().unwrap()),
+            method: &Method::GET,
+            uri: &uri,
+            version: Version::HTTP_11,
+            headers: &headers,
+            request_id: "abc",
+            status: StatusCode::NOT_FOUND,
+            bytes: None,
+            duration: Duration::from_millis(12),
+            anonymizer: &Anonymizer::new(&Config::default()),
+        };
+        assert_eq!(
+            format.render(&entry),
+            r#"192.0.2.7 "GET /search?q=lime HTTP/1.1" 404 - "curl/8.0" - $"#
+        );
+        assert!(LogFormat::parse("$remote_user").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::{fmt, fs::OpenOptions, sync::Mutex, time::Duration};
+
+use anyhow::{Result, anyhow};
+use tracing::{Event, Subscriber, field::Field};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    field::Visit,
+    fmt::{FmtContext, FormatEvent, FormatFields, format::Writer},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+use crate::{
+    config::{Config, LogOutput},
+    profile::Profiler,
+};
+
+/// Events with this target are printed verbatim, without timestamps or levels.
+pub const ACCESS_LOG_TARGET: &str = "lime::access";
+
+pub fn init_logging(config: &Config) -> Result<()> {
+    // RUST_LOG always takes precedence over the configured level.
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.log_level)
+            .map_err(|e| anyhow!("Invalid log level '{}': {}", config.log_level, e))?,
+    };
+    let profiler = config
+        .trace_file
+        .as_deref()
+        .map(|path| Profiler::start(path, Duration::from_secs(config.trace_duration)))
+        .transpose()?;
+    // The filter only applies to the log, so the profiler sees every span.
+    let registry = tracing_subscriber::registry().with(profiler);
+
+    match config.log_output {
+        LogOutput::Stdout => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(config.color)
+                    .event_format(AccessLogFormat(tracing_subscriber::fmt::format()))
+                    .with_filter(filter),
+            )
+            .init(),
+        LogOutput::File => {
+            let path = config
+                .log_file
+                .as_deref()
+                .ok_or_else(|| anyhow!("'log_file' must be set when log_output is 'file'"))?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| anyhow!("Failed to open log file {}: {}", path, e))?;
+            registry
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .with_ansi(false)
+                        .event_format(AccessLogFormat(tracing_subscriber::fmt::format()))
+                        .with_writer(Mutex::new(file))
+                        .with_filter(filter),
+                )
+                .init()
+        }
+        #[cfg(unix)]
+        LogOutput::Syslog => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .event_format(AccessLogFormat(
+                        tracing_subscriber::fmt::format().without_time(),
+                    ))
+                    .with_writer(syslog::Syslog::connect()?)
+                    .with_filter(filter),
+            )
+            .init(),
+        #[cfg(unix)]
+        LogOutput::Journald => {
+            let layer = tracing_journald::layer()
+                .map_err(|e| anyhow!("Failed to connect to journald: {}", e))?;
+            registry.with(layer.with_filter(filter)).init()
+        }
+        #[cfg(not(unix))]
+        LogOutput::Syslog | LogOutput::Journald => {
+            return Err(anyhow!(
+                "Log output '{:?}' is not supported on this platform",
+                config.log_output
+            ));
+        }
+    }
+    Ok(())
+}
+
+struct AccessLogFormat<F>(F);
+
+impl<S, N, F> FormatEvent<S, N> for AccessLogFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if event.metadata().target() != ACCESS_LOG_TARGET {
+            return self.0.format_event(ctx, writer, event);
+        }
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        writeln!(writer, "{}", message.0)
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod syslog {
+    use std::{io, os::unix::net::UnixDatagram, process, sync::Arc};
+
+    use anyhow::{Result, anyhow};
+    use tracing::{Level, Metadata};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    const SOCKET_PATHS: [&str; 2] = ["/dev/log", "/var/run/syslog"];
+    const FACILITY_DAEMON: u8 = 3;
+
+    /// Sends every log line as a single datagram to the local syslog socket.
+    pub struct Syslog {
+        pub(super) socket: Arc<UnixDatagram>,
+    }
+
+    impl Syslog {
+        pub fn connect() -> Result<Self> {
+            let socket =
+                UnixDatagram::unbound().map_err(|e| anyhow!("Failed to create socket: {}", e))?;
+            SOCKET_PATHS
+                .iter()
+                .find(|path| socket.connect(path).is_ok())
+                .ok_or_else(|| anyhow!("Failed to connect to syslog: no socket found"))?;
+            Ok(Self {
+                socket: Arc::new(socket),
+            })
+        }
+
+        fn line(&self, severity: u8) -> SyslogLine {
+            SyslogLine {
+                socket: self.socket.clone(),
+                severity,
+                buf: Vec::new(),
+            }
+        }
+    }
+
+    pub struct SyslogLine {
+        socket: Arc<UnixDatagram>,
+        severity: u8,
+        buf: Vec<u8>,
+    }
+
+    impl io::Write for SyslogLine {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for SyslogLine {
+        fn drop(&mut self) {
+            let line = String::from_utf8_lossy(&self.buf);
+            let message = format!(
+                "<{}>lime[{}]: {}",
+                FACILITY_DAEMON * 8 + self.severity,
+                process::id(),
+                line.trim_end()
+            );
+            let _ = self.socket.send(message.as_bytes());
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Syslog {
+        type Writer = SyslogLine;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.line(6)
+        }
+
+        fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+            let severity = match *meta.level() {
+                Level::ERROR => 3,
+                Level::WARN => 4,
+                Level::INFO => 6,
+                Level::DEBUG | Level::TRACE => 7,
+            };
+            self.line(severity)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_output_needs_a_path() {
+        let config = Config {
+            log_output: LogOutput::File,
+            log_file: None,
+            ..Config::default()
+        };
+        let error = init_logging(&config).unwrap_err();
+        assert!(error.to_string().contains("log_file"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_syslog_lines_as_datagrams() {
+        use std::{io::Write, os::unix::net::UnixDatagram, process, sync::Arc};
+
+        use tracing_subscriber::fmt::MakeWriter;
+
+        let (socket, server) = UnixDatagram::pair().unwrap();
+        let syslog = syslog::Syslog {
+            socket: Arc::new(socket),
+        };
+        let mut line = syslog.make_writer();
+        line.write_all(b"Server started\n").unwrap();
+        drop(line);
+
+        let mut buf = [0; 256];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&buf[..len]),
+            format!("<30>lime[{}]: Server started", process::id())
+        );
+    }
+}
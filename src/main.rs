@@ -1,26 +1,92 @@
-use std::process::exit;
+use std::{env, fs, process::exit};
 
-use crate::{cli::Cli, commands::handle_serve, config::load_config};
 use clap::Parser;
-
-mod cli;
-mod commands;
-mod config;
-mod server;
+use lime::{
+    cli::{self, Cli},
+    cluster,
+    commands::{
+        handle_check_cache, handle_compress, handle_config, handle_index, handle_links,
+        handle_lint, handle_pack, handle_routes, handle_self_update, handle_serve, handle_stats,
+    },
+    config::{Config, load_config},
+    error, pack,
+};
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    // A packed executable runs in the directory its site was extracted to, and serves it
+    // when run without arguments.
+    let packed = pack::unpack().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1);
+    });
+    let cli = match &packed {
+        Some(dir) => {
+            if let Err(e) = env::set_current_dir(dir) {
+                eprintln!("Failed to enter {}: {}", dir.display(), e);
+                exit(1);
+            }
+            if env::args().len() == 1 {
+                Cli::parse_from(env::args().chain(["serve".to_string()]))
+            } else {
+                Cli::parse()
+            }
+        }
+        None => Cli::parse(),
+    };
 
     // Loading configuration
-    let config = load_config(&cli.config.unwrap()).unwrap_or_default();
+    let config_path = cli.config.clone().unwrap();
+    let mut config = if fs::exists(&config_path).unwrap_or(true) {
+        load_config(&config_path).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(error::exit_code(&e));
+        })
+    } else {
+        Config::default()
+    };
+    cli.apply(&mut config);
+
+    if cluster::is_worker() {
+        cluster::configure_worker(&mut config);
+    }
+
+    if !config.color {
+        colored::control::set_override(false);
+    }
 
     let result = match cli.command {
-        cli::Commands::Serve => handle_serve(&config).await,
+        cli::Commands::Serve {
+            tui,
+            trace,
+            trace_duration,
+            chaos,
+        } => {
+            config.trace_file = trace;
+            config.trace_duration = trace_duration;
+            config.chaos_enabled = chaos;
+            handle_serve(&mut config, tui).await
+        }
+        cli::Commands::Index => handle_index(&config).await,
+        cli::Commands::Compress { dirs } => handle_compress(&config, &dirs).await,
+        cli::Commands::Lint => handle_lint(&config).await,
+        cli::Commands::Routes => handle_routes(&config).await,
+        cli::Commands::Links {
+            external,
+            concurrency,
+        } => handle_links(&config, external, concurrency).await,
+        cli::Commands::CheckCache { url } => handle_check_cache(&config, url).await,
+        cli::Commands::Pack { output } => handle_pack(&config, &config_path, &output).await,
+        cli::Commands::Stats { days, top } => handle_stats(&config, days, top).await,
+        cli::Commands::SelfUpdate { check } => handle_self_update(check).await,
+        cli::Commands::Config { command } => handle_config(&config, &config_path, command).await,
     };
 
+    if let Some(dir) = packed {
+        let _ = fs::remove_dir_all(dir);
+    }
     if let Err(e) = result {
         eprintln!("{e}");
-        exit(1);
+        exit(error::exit_code(&e));
     }
 }
@@ -0,0 +1,327 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    admin::ADMIN_PREFIX,
+    config::Config,
+    probes::is_health_probe,
+    traffic::{Traffic, request_size, response_size},
+};
+
+/// Upper bounds of the request duration buckets, in seconds.
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds of the response size buckets, in bytes.
+const SIZE_BUCKETS: [f64; 9] = [
+    256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+];
+
+/// Label of the routes past a group's limit.
+const OTHER_ROUTE: &str = "other";
+
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = self.buckets.iter().position(|le| value <= *le) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (le, count) in self.buckets.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, le, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+struct Series {
+    duration: Histogram,
+    size: Histogram,
+}
+
+/// Request duration and response size histograms, labelled by route group (`index`,
+/// `pages`, `static`, `proxy` or `errors`) and by route within the group.
+pub struct Metrics {
+    route: String,
+    max_routes: usize,
+    proxy_routes: Vec<String>,
+    mount_routes: Vec<String>,
+    series: Mutex<BTreeMap<(&'static str, String), Series>>,
+    traffic: Traffic,
+}
+
+impl Metrics {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            route: config.metrics.route.clone(),
+            max_routes: config.metrics.max_routes,
+            proxy_routes: config
+                .proxy_rules
+                .iter()
+                .map(|r| r.route.trim_end_matches('/').to_string())
+                .collect(),
+            mount_routes: config
+                .mounts
+                .iter()
+                .map(|m| m.route.trim_end_matches('/').to_string())
+                .collect(),
+            series: Mutex::new(BTreeMap::new()),
+            traffic: Traffic::new(config.metrics.max_routes),
+        }
+    }
+
+    /// The group and route a response is counted under. Routes are the proxy rule for
+    /// proxied requests, and the first path segment otherwise, so `/blog/a` and `/blog/b`
+    /// share one series.
+    fn classify(&self, path: &str, status: StatusCode) -> (&'static str, String) {
+        let first_segment = || {
+            let segment = path.split('/').nth(1).unwrap_or_default();
+            format!("/{}", segment)
+        };
+        if status.is_client_error() || status.is_server_error() {
+            return ("errors", status.as_str().to_string());
+        }
+        if let Some(route) = matching_route(&self.proxy_routes, path) {
+            return ("proxy", route.to_string());
+        }
+        if path == "/" {
+            return ("index", "/".to_string());
+        }
+        if let Some(route) = matching_route(&self.mount_routes, path) {
+            return ("static", route.to_string());
+        }
+        let last_segment = path.rsplit('/').next().unwrap_or_default();
+        match last_segment.rsplit_once('.') {
+            Some((_, extension)) if !extension.eq_ignore_ascii_case("html") => {
+                ("static", first_segment())
+            }
+            _ => ("pages", first_segment()),
+        }
+    }
+
+    fn record(&self, path: &str, status: StatusCode, seconds: f64, size: Option<u64>) {
+        let (group, route) = self.classify(path, status);
+        let mut series = self.series.lock().unwrap();
+        let mut key = (group, route);
+        if !series.contains_key(&key) {
+            let routes = series
+                .keys()
+                .filter(|(g, r)| *g == group && r != OTHER_ROUTE)
+                .count();
+            if routes >= self.max_routes {
+                key.1 = OTHER_ROUTE.to_string();
+            }
+        }
+        let series = series.entry(key).or_insert_with(|| Series {
+            duration: Histogram::new(&DURATION_BUCKETS),
+            size: Histogram::new(&SIZE_BUCKETS),
+        });
+        series.duration.observe(seconds);
+        if let Some(size) = size {
+            series.size.observe(size as f64);
+        }
+    }
+
+    /// The histograms in the Prometheus text format.
+    fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+        out.push_str(
+            "# HELP lime_request_duration_seconds Time until the response headers were sent.\n",
+        );
+        out.push_str("# TYPE lime_request_duration_seconds histogram\n");
+        for ((group, route), s) in series.iter() {
+            s.duration.render(
+                &mut out,
+                "lime_request_duration_seconds",
+                &labels(group, route),
+            );
+        }
+        out.push_str("# HELP lime_response_size_bytes Size of the response bodies.\n");
+        out.push_str("# TYPE lime_response_size_bytes histogram\n");
+        for ((group, route), s) in series.iter() {
+            s.size
+                .render(&mut out, "lime_response_size_bytes", &labels(group, route));
+        }
+        let traffic = self.traffic.report();
+        out.push_str("# HELP lime_received_bytes_total Bytes of request bodies received.\n");
+        out.push_str("# TYPE lime_received_bytes_total counter\n");
+        let _ = writeln!(out, "lime_received_bytes_total {}", traffic.received);
+        out.push_str("# HELP lime_sent_bytes_total Bytes of response bodies sent, by route.\n");
+        out.push_str("# TYPE lime_sent_bytes_total counter\n");
+        for (route, sent) in &traffic.routes {
+            let _ = writeln!(
+                out,
+                "lime_sent_bytes_total{{route=\"{}\"}} {}",
+                escape_label(route),
+                sent
+            );
+        }
+        out.push_str("# HELP lime_largest_response_bytes The largest responses sent, by path.\n");
+        out.push_str("# TYPE lime_largest_response_bytes gauge\n");
+        for (path, size) in &traffic.largest {
+            let _ = writeln!(
+                out,
+                "lime_largest_response_bytes{{path=\"{}\"}} {}",
+                escape_label(path),
+                size
+            );
+        }
+        out
+    }
+}
+
+/// Returns the longest of `routes` that `path` is under.
+fn matching_route<'a>(routes: &'a [String], path: &str) -> Option<&'a str> {
+    routes
+        .iter()
+        .filter(|route| {
+            path.strip_prefix(route.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|route| route.len())
+        .map(String::as_str)
+}
+
+fn labels(group: &str, route: &str) -> String {
+    format!("group=\"{}\",route=\"{}\"", group, escape_label(route))
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+pub async fn handle_metrics(State(metrics): State<Arc<Metrics>>) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(metrics.render()))
+        .unwrap()
+}
+
+pub async fn record_metrics(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if path == metrics.route || path.starts_with(ADMIN_PREFIX) || is_health_probe(&request) {
+        return next.run(request).await;
+    }
+    let received = request_size(&request);
+    let start = Instant::now();
+    let response = next.run(request).await;
+    // Streamed bodies of unknown length are only counted towards the duration.
+    let size = response_size(&response);
+    metrics
+        .traffic
+        .record(&path, received, size.unwrap_or_default());
+    metrics.record(
+        &path,
+        response.status(),
+        start.elapsed().as_secs_f64(),
+        size,
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+
+    #[test]
+    fn groups_routes_and_limits_cardinality() {
+        let config = Config {
+            metrics: MetricsConfig {
+                max_routes: 2,
+                ..MetricsConfig::default()
+            },
+            proxy_rules: vec![toml::from_str("route = \"/api\"\nupstreams = []").unwrap()],
+            ..Config::default()
+        };
+        let metrics = Metrics::new(&config);
+        assert_eq!(metrics.classify("/", StatusCode::OK), ("index", "/".into()));
+        assert_eq!(
+            metrics.classify("/api/users/1", StatusCode::OK),
+            ("proxy", "/api".into())
+        );
+        assert_eq!(
+            metrics.classify("/css/site.css", StatusCode::OK),
+            ("static", "/css".into())
+        );
+        assert_eq!(
+            metrics.classify("/missing", StatusCode::NOT_FOUND),
+            ("errors", "404".into())
+        );
+
+        for path in ["/blog/a", "/blog/b", "/docs/", "/about", "/contact"] {
+            metrics.record(path, StatusCode::OK, 0.002, Some(2048));
+        }
+        let output = metrics.render();
+        assert!(
+            output
+                .contains("lime_request_duration_seconds_count{group=\"pages\",route=\"/blog\"} 2")
+        );
+        assert!(
+            output
+                .contains("lime_request_duration_seconds_count{group=\"pages\",route=\"other\"} 2")
+        );
+        assert!(output.contains(
+            "lime_response_size_bytes_bucket{group=\"pages\",route=\"/docs\",le=\"4096\"} 1"
+        ));
+        metrics.traffic.record("/blog/a", 0, 2048);
+        assert!(
+            metrics
+                .render()
+                .contains("lime_sent_bytes_total{route=\"/blog\"} 2048")
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::State,
+    http::{StatusCode, Uri, header},
+    response::Response,
+    routing::get,
+};
+use serde_json::Value;
+use tokio::fs;
+use tracing::{debug, error};
+
+use crate::{config::MockApiConfig, paths::normalize_request_path};
+
+struct MockApi {
+    dir: PathBuf,
+    route: String,
+    latency: Duration,
+}
+
+/// Serves the JSON files in the mock API directory as read-only REST endpoints.
+pub fn mock_api_router<S: Clone + Send + Sync + 'static>(config: &MockApiConfig) -> Router<S> {
+    let route = config.route.trim_end_matches('/').to_string();
+    let api = Arc::new(MockApi {
+        dir: PathBuf::from(&config.dir),
+        route: route.clone(),
+        latency: Duration::from_millis(config.latency),
+    });
+    Router::new()
+        .route(&format!("{}/{{*path}}", route), get(handle_mock_api))
+        .with_state(api)
+}
+
+async fn handle_mock_api(State(api): State<Arc<MockApi>>, uri: Uri) -> Response {
+    if !api.latency.is_zero() {
+        tokio::time::sleep(api.latency).await;
+    }
+    let raw = uri
+        .path()
+        .strip_prefix(api.route.as_str())
+        .unwrap_or_default();
+    let Ok(path) = normalize_request_path(raw) else {
+        return json(StatusCode::BAD_REQUEST, r#"{"error":"Bad request"}"#.into());
+    };
+    let path = path.trim_end_matches('/');
+    match resolve(&api, path).await {
+        Some(body) => json(StatusCode::OK, body),
+        None => json(StatusCode::NOT_FOUND, r#"{"error":"Not found"}"#.into()),
+    }
+}
+
+/// Finds the JSON served at `path`: `users/42` is `users/42.json`, `users/42/index.json`,
+/// or the item with `"id": 42` in the array in `users.json`.
+async fn resolve(api: &MockApi, path: &str) -> Option<String> {
+    for file in [format!("{}.json", path), format!("{}/index.json", path)] {
+        if let Ok(content) = fs::read_to_string(api.dir.join(&file)).await {
+            debug!(file, "Serving mock API response");
+            return Some(content);
+        }
+    }
+    let (collection, id) = path.rsplit_once('/')?;
+    let file = api.dir.join(format!("{}.json", collection));
+    let content = fs::read_to_string(&file).await.ok()?;
+    let items: Value = match serde_json::from_str(&content) {
+        Ok(items) => items,
+        Err(e) => {
+            error!("Invalid JSON in {}: {}", file.display(), e);
+            return None;
+        }
+    };
+    find_item(&items, id).map(Value::to_string)
+}
+
+/// The item of the `items` array whose `id` is `id`, as a string or a number.
+fn find_item<'a>(items: &'a Value, id: &str) -> Option<&'a Value> {
+    items.as_array()?.iter().find(|item| match item.get("id") {
+        Some(Value::String(s)) => s == id,
+        Some(Value::Number(n)) => n.to_string() == id,
+        _ => false,
+    })
+}
+
+fn json(status: StatusCode, body: String) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_items_by_id() {
+        let users = serde_json::json!([
+            {"id": 1, "name": "Ada"},
+            {"id": "b2", "name": "Grace"},
+        ]);
+        assert_eq!(find_item(&users, "1").unwrap()["name"], "Ada");
+        assert_eq!(find_item(&users, "b2").unwrap()["name"], "Grace");
+        assert!(find_item(&users, "3").is_none());
+        assert!(find_item(&serde_json::json!({"id": 1}), "1").is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use axum::http::header;
+use chrono::{Local, SecondsFormat};
+use reqwest::Client;
+use tracing::warn;
+
+use crate::config::{Config, NotifyEvent, NotifyRule};
+
+/// Time to wait for a webhook to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Content changes are reported once no more arrived for this long, so publishing a
+/// whole site is a single deploy.
+const DEPLOY_QUIET: Duration = Duration::from_secs(5);
+
+struct Hook {
+    url: String,
+    events: Vec<NotifyEvent>,
+    template: String,
+}
+
+impl Hook {
+    fn new(rule: &NotifyRule, server: &str) -> Result<Self> {
+        if rule.events.contains(&NotifyEvent::Certificate) {
+            return Err(anyhow!(
+                "Webhook {}: certificate events need TLS, which Lime does not support yet",
+                rule.url
+            ));
+        }
+        // Catch a broken template at startup rather than when something goes wrong.
+        let sample = render(&rule.template, NotifyEvent::Startup, "\"test\"\n", server);
+        serde_json::from_str::<serde_json::Value>(&sample)
+            .map_err(|e| anyhow!("Webhook {}: template is not valid JSON: {}", rule.url, e))?;
+        Ok(Self {
+            url: rule.url.clone(),
+            events: rule.events.clone(),
+            template: rule.template.clone(),
+        })
+    }
+
+    fn payload(&self, event: NotifyEvent, message: &str, server: &str) -> String {
+        render(&self.template, event, message, server)
+    }
+}
+
+#[derive(Default)]
+struct Deploy {
+    changes: usize,
+    generation: u64,
+}
+
+/// Calls the `[[notify]]` webhooks when the server starts or stops, when error rates
+/// raise an alert, and when content is published.
+pub struct Notifier {
+    client: Client,
+    hooks: Vec<Hook>,
+    server: String,
+    deploy: Mutex<Deploy>,
+}
+
+impl Notifier {
+    /// Builds the notifier, or `None` when no webhooks are configured.
+    pub fn new(config: &Config) -> Result<Option<Arc<Self>>> {
+        if config.notify_rules.is_empty() {
+            return Ok(None);
+        }
+        let server = format!("{}:{}", config.host, config.port);
+        let hooks = config
+            .notify_rules
+            .iter()
+            .map(|rule| Hook::new(rule, &server))
+            .collect::<Result<Vec<_>>>()?;
+        let client = Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        Ok(Some(Arc::new(Self {
+            client,
+            hooks,
+            server,
+            deploy: Mutex::new(Deploy::default()),
+        })))
+    }
+
+    pub async fn started(&self) {
+        let message = format!(
+            "Lime {} started on {}",
+            env!("CARGO_PKG_VERSION"),
+            self.server
+        );
+        self.send(NotifyEvent::Startup, &message).await;
+    }
+
+    pub async fn stopped(&self) {
+        let message = format!("Lime on {} stopped", self.server);
+        self.send(NotifyEvent::Shutdown, &message).await;
+    }
+
+    /// Reports an error rate alert raised or cleared by [`ErrorAlerts`](crate::alerts::ErrorAlerts).
+    pub fn alert(self: &Arc<Self>, message: String) {
+        let notifier = self.clone();
+        tokio::spawn(async move { notifier.send(NotifyEvent::Errors, &message).await });
+    }
+
+    /// Counts a change made through the content API, reported once changes stop arriving.
+    pub fn content_changed(self: &Arc<Self>) {
+        let generation = {
+            let mut deploy = self.deploy.lock().unwrap();
+            deploy.changes += 1;
+            deploy.generation += 1;
+            deploy.generation
+        };
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(DEPLOY_QUIET).await;
+            let changes = {
+                let mut deploy = notifier.deploy.lock().unwrap();
+                if deploy.generation != generation {
+                    return;
+                }
+                std::mem::take(&mut deploy.changes)
+            };
+            let message = format!(
+                "{} {} published on {}",
+                changes,
+                if changes == 1 { "change" } else { "changes" },
+                notifier.server
+            );
+            notifier.send(NotifyEvent::Deploy, &message).await;
+        });
+    }
+
+    async fn send(&self, event: NotifyEvent, message: &str) {
+        for hook in self
+            .hooks
+            .iter()
+            .filter(|hook| hook.events.contains(&event))
+        {
+            self.post(hook, hook.payload(event, message, &self.server))
+                .await;
+        }
+    }
+
+    async fn post(&self, hook: &Hook, payload: String) {
+        let result = self
+            .client
+            .post(&hook.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(payload)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            warn!("Failed to call webhook {}: {}", hook.url, e);
+        }
+    }
+}
+
+/// Fills in the placeholders of a JSON template, escaped to stay valid inside strings.
+fn render(template: &str, event: NotifyEvent, message: &str, server: &str) -> String {
+    let escape = |value: &str| {
+        let quoted = serde_json::to_string(value).unwrap_or_default();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let event = serde_json::to_value(event)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default();
+    template
+        .replace("{{event}}", &event)
+        .replace("{{message}}", &escape(message))
+        .replace("{{server}}", &escape(server))
+        .replace(
+            "{{time}}",
+            &Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_template_values() {
+        let payload = render(
+            r#"{"content": "[{{event}}] {{message}}"}"#,
+            NotifyEvent::Deploy,
+            "2 \"changes\" published",
+            "127.0.0.1:3000",
+        );
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(json["content"], "[deploy] 2 \"changes\" published");
+    }
+}
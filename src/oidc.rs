@@ -0,0 +1,450 @@
+use anyhow::{Result, anyhow};
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{HeaderMap, StatusCode, Uri, header},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    auth::constant_time_eq,
+    config::{JwtConfig, OidcConfig},
+    jwt::JwtValidator,
+    session::{Sessions, cookie, set_cookie},
+};
+
+const STATE_COOKIE: &str = "lime_oidc_state";
+const STATE_LIFETIME: i64 = 10 * 60;
+
+#[derive(Deserialize)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Provider {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    validator: JwtValidator,
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// OpenID Connect authorization code flow for protected routes.
+pub struct Oidc {
+    config: OidcConfig,
+    callback_path: String,
+    secure: bool,
+    client: reqwest::Client,
+    provider: OnceCell<Provider>,
+}
+
+impl Oidc {
+    pub fn new(config: &OidcConfig) -> Result<Self> {
+        let redirect_url = reqwest::Url::parse(&config.redirect_url)
+            .map_err(|e| anyhow!("Invalid redirect_url '{}': {}", config.redirect_url, e))?;
+        Ok(Self {
+            callback_path: redirect_url.path().to_string(),
+            secure: redirect_url.scheme() == "https",
+            config: config.clone(),
+            client: reqwest::Client::new(),
+            provider: OnceCell::new(),
+        })
+    }
+
+    pub fn callback_path(&self) -> &str {
+        &self.callback_path
+    }
+
+    /// Sessions issued by this provider.
+    pub fn source(&self) -> &str {
+        &self.config.issuer
+    }
+
+    /// Redirects the browser to the identity provider, remembering where to return.
+    pub async fn login(&self, sessions: &Sessions, uri: &Uri) -> Response {
+        let provider = match self.provider().await {
+            Ok(p) => p,
+            Err(e) => return provider_error(e),
+        };
+
+        let nonce = format!("{:032x}", rand::random::<u128>());
+        let return_to = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let state = sessions
+            .key()
+            .sign(format!("{}|{}", nonce, return_to).as_bytes());
+
+        let mut location = match reqwest::Url::parse(&provider.authorization_endpoint) {
+            Ok(url) => url,
+            Err(e) => return provider_error(anyhow!("Invalid authorization endpoint: {}", e)),
+        };
+        location
+            .query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &nonce)
+            .append_pair("nonce", &nonce);
+
+        debug!(return_to = %return_to, "Redirecting to identity provider");
+        Response::builder()
+            .status(StatusCode::FOUND)
+            .header(header::LOCATION, location.as_str())
+            .header(
+                header::SET_COOKIE,
+                set_cookie(STATE_COOKIE, &state, STATE_LIFETIME, self.secure),
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Handles the redirect back from the identity provider and starts a session.
+    pub async fn callback(&self, sessions: &Sessions, uri: &Uri, headers: &HeaderMap) -> Response {
+        let Ok(Query(params)) = Query::<CallbackParams>::try_from_uri(uri) else {
+            return plain(StatusCode::BAD_REQUEST, "Invalid callback parameters.");
+        };
+        if let Some(error) = params.error {
+            warn!("Identity provider returned an error: {}", error);
+            return plain(StatusCode::FORBIDDEN, "Sign-in was not completed.");
+        }
+        let (Some(code), Some(state)) = (params.code, params.state) else {
+            return plain(StatusCode::BAD_REQUEST, "Invalid callback parameters.");
+        };
+
+        // The state cookie ties the callback to a login started in this browser.
+        let Some(stored) = cookie(headers, STATE_COOKIE)
+            .and_then(|c| sessions.key().verify(c))
+            .and_then(|p| String::from_utf8(p).ok())
+        else {
+            return plain(
+                StatusCode::BAD_REQUEST,
+                "Sign-in session expired, try again.",
+            );
+        };
+        let Some((nonce, return_to)) = stored.split_once('|') else {
+            return plain(StatusCode::BAD_REQUEST, "Invalid sign-in state.");
+        };
+        if !constant_time_eq(nonce, &state) {
+            warn!("OIDC state mismatch");
+            return plain(StatusCode::BAD_REQUEST, "Invalid sign-in state.");
+        }
+
+        let claims = match self.exchange(&code, nonce).await {
+            Ok(claims) => claims,
+            Err(e) => return provider_error(e),
+        };
+        let email = claims["email"].as_str().map(str::to_owned);
+        if !self.is_allowed(&claims) {
+            warn!(email = ?email, "Signed-in user is not allowed");
+            return plain(
+                StatusCode::FORBIDDEN,
+                "You are not allowed to access this site.",
+            );
+        }
+
+        let sub = claims["sub"].as_str().unwrap_or_default();
+        info!(sub = %sub, "User signed in");
+
+        Response::builder()
+            .status(StatusCode::FOUND)
+            .header(header::LOCATION, local_path(return_to))
+            .header(
+                header::SET_COOKIE,
+                sessions.start(sub, email, self.source(), self.secure),
+            )
+            .header(
+                header::SET_COOKIE,
+                set_cookie(STATE_COOKIE, "", 0, self.secure),
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Exchanges the authorization code for an ID token and returns its validated claims.
+    async fn exchange(&self, code: &str, nonce: &str) -> Result<Value> {
+        let provider = self.provider().await?;
+        let response = self
+            .client
+            .post(&provider.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.config.redirect_url),
+                ("client_id", &self.config.client_id),
+                ("client_secret", &self.config.client_secret),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow!("Token request failed: {}", e))?
+            .json::<TokenResponse>()
+            .await
+            .map_err(|e| anyhow!("Invalid token response: {}", e))?;
+
+        let claims = provider.validator.validate(&response.id_token).await?;
+        if claims["nonce"].as_str() != Some(nonce) {
+            return Err(anyhow!("ID token nonce mismatch"));
+        }
+        Ok(claims)
+    }
+
+    fn is_allowed(&self, claims: &Value) -> bool {
+        let email = claims["email"].as_str().unwrap_or_default();
+        // Providers may let users claim addresses they haven't confirmed.
+        let email_allowed = self.config.allowed_emails.is_empty()
+            || (claims["email_verified"] == true
+                && self.config.allowed_emails.iter().any(|allowed| {
+                    match allowed.strip_prefix('@') {
+                        Some(domain) => email
+                            .rsplit_once('@')
+                            .is_some_and(|(_, d)| d.eq_ignore_ascii_case(domain)),
+                        None => allowed.eq_ignore_ascii_case(email),
+                    }
+                }));
+
+        let groups: Vec<&str> = claims[&self.config.groups_claim]
+            .as_array()
+            .map(|g| g.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let group_allowed = self.config.allowed_groups.is_empty()
+            || self
+                .config
+                .allowed_groups
+                .iter()
+                .any(|g| groups.contains(&g.as_str()));
+
+        email_allowed && group_allowed
+    }
+
+    async fn provider(&self) -> Result<&Provider> {
+        self.provider
+            .get_or_try_init(|| async {
+                let issuer = self.config.issuer.trim_end_matches('/');
+                let url = format!("{}/.well-known/openid-configuration", issuer);
+                let discovery = self
+                    .client
+                    .get(&url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| anyhow!("Failed to fetch {}: {}", url, e))?
+                    .json::<Discovery>()
+                    .await
+                    .map_err(|e| anyhow!("Invalid OpenID configuration at {}: {}", url, e))?;
+                info!("Loaded OpenID configuration from {}", url);
+
+                let validator = JwtValidator::new(&JwtConfig {
+                    secret: None,
+                    public_key_file: None,
+                    jwks_url: Some(discovery.jwks_uri),
+                    issuer: Some(self.config.issuer.clone()),
+                    audience: Some(self.config.client_id.clone()),
+                    claim_headers: Default::default(),
+                })?;
+                Ok(Provider {
+                    authorization_endpoint: discovery.authorization_endpoint,
+                    token_endpoint: discovery.token_endpoint,
+                    validator,
+                })
+            })
+            .await
+    }
+}
+
+/// `return_to` if it is a path on this site, so sign-in never redirects elsewhere.
+/// Browsers read `/\` like `//`, as the start of another host.
+fn local_path(return_to: &str) -> &str {
+    let is_local =
+        return_to.starts_with('/') && !return_to.starts_with("//") && !return_to.starts_with("/\\");
+    if is_local { return_to } else { "/" }
+}
+
+fn provider_error(e: anyhow::Error) -> Response {
+    error!("OIDC error: {}", e);
+    plain(
+        StatusCode::BAD_GATEWAY,
+        "The identity provider could not be reached.",
+    )
+}
+
+fn plain(status: StatusCode, message: &'static str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(message))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, routing::post};
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::SessionConfig;
+
+    const SECRET: &str = "idp-secret";
+
+    fn sessions() -> Sessions {
+        Sessions::new(&SessionConfig {
+            secret: Some("session-secret".to_string()),
+            ..SessionConfig::default()
+        })
+    }
+
+    /// A client whose provider answers every code with an ID token for the usual claims,
+    /// overridden by `claims`.
+    async fn oidc(claims: Value) -> Oidc {
+        let mut id_token = json!({
+            "sub": "ada",
+            "email": "ada@example.com",
+            "email_verified": true,
+            "iss": "https://idp.example.com",
+            "aud": "lime",
+            "exp": chrono::Utc::now().timestamp() + 600,
+            "nonce": "n0nce",
+        });
+        for (claim, value) in claims.as_object().unwrap() {
+            id_token[claim] = value.clone();
+        }
+        let id_token = encode(
+            &Header::default(),
+            &id_token,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let token_endpoint = format!("http://{}/token", listener.local_addr().unwrap());
+        let app = Router::new().route(
+            "/token",
+            post(move || async move { Json(json!({ "id_token": id_token })) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let oidc = Oidc::new(&OidcConfig {
+            issuer: "https://idp.example.com".to_string(),
+            client_id: "lime".to_string(),
+            client_secret: "client-secret".to_string(),
+            redirect_url: "http://localhost/auth/callback".to_string(),
+            scopes: vec!["openid".to_string()],
+            allowed_emails: vec!["@example.com".to_string()],
+            allowed_groups: Vec::new(),
+            groups_claim: "groups".to_string(),
+        })
+        .unwrap();
+        let validator = JwtValidator::new(&JwtConfig {
+            secret: Some(SECRET.to_string()),
+            public_key_file: None,
+            jwks_url: None,
+            issuer: Some("https://idp.example.com".to_string()),
+            audience: Some("lime".to_string()),
+            claim_headers: Default::default(),
+        })
+        .unwrap();
+        let _ = oidc.provider.set(Provider {
+            authorization_endpoint: "https://idp.example.com/authorize".to_string(),
+            token_endpoint,
+            validator,
+        });
+        oidc
+    }
+
+    /// Returns from the provider with `state` to a login that stored `stored`.
+    async fn callback(oidc: &Oidc, stored: &str, state: &str) -> Response {
+        let sessions = sessions();
+        let mut headers = HeaderMap::new();
+        let cookie = format!(
+            "{}={}",
+            STATE_COOKIE,
+            sessions.key().sign(stored.as_bytes())
+        );
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+        let uri = format!("/auth/callback?code=c0de&state={}", state)
+            .parse()
+            .unwrap();
+        oidc.callback(&sessions, &uri, &headers).await
+    }
+
+    fn location(response: &Response) -> &str {
+        response.headers()[header::LOCATION].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn signs_in_and_returns_to_the_page() {
+        let oidc = oidc(json!({})).await;
+        let response = callback(&oidc, "n0nce|/docs/?page=2", "n0nce").await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(location(&response), "/docs/?page=2");
+        let cookies: Vec<_> = response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .collect();
+        assert!(cookies[0].to_str().unwrap().starts_with("lime_session="));
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_state_and_nonce() {
+        let client = oidc(json!({})).await;
+        let response = callback(&client, "n0nce|/", "other").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // A state cookie signed with another key is no state at all.
+        let mut headers = HeaderMap::new();
+        let forged = format!("{}=bjBuY2V8Lw.c2ln", STATE_COOKIE);
+        headers.insert(header::COOKIE, forged.parse().unwrap());
+        let uri = "/auth/callback?code=c0de&state=n0nce".parse().unwrap();
+        let response = client.callback(&sessions(), &uri, &headers).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let replayed = oidc(json!({ "nonce": "replayed" })).await;
+        let response = callback(&replayed, "n0nce|/", "n0nce").await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn only_returns_to_paths_on_the_site() {
+        let oidc = oidc(json!({})).await;
+        for return_to in [
+            "//evil.example.com/",
+            "/\\evil.example.com/",
+            "https://evil.example.com/",
+            "evil",
+        ] {
+            let stored = format!("n0nce|{}", return_to);
+            let response = callback(&oidc, &stored, "n0nce").await;
+            assert_eq!(location(&response), "/", "{}", return_to);
+        }
+    }
+
+    #[tokio::test]
+    async fn requires_verified_allowed_emails() {
+        for claims in [
+            json!({ "email": "ada@evil.example.com" }),
+            json!({ "email_verified": false }),
+            json!({ "email_verified": null }),
+        ] {
+            let oidc = oidc(claims.clone()).await;
+            let response = callback(&oidc, "n0nce|/", "n0nce").await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", claims);
+        }
+    }
+}
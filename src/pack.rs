@@ -0,0 +1,172 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use toml_edit::{DocumentMut, value};
+
+use crate::config::Config;
+
+/// Ends a packed executable, after the length of the archive before it.
+const MAGIC: &[u8; 8] = b"LIMEPACK";
+const TRAILER_LEN: u64 = 16;
+
+/// Writes a copy of this executable to `output` with the site and its configuration
+/// appended, so it serves the site without any files alongside it.
+pub fn pack(config: &Config, config_path: &str, output: &str) -> Result<()> {
+    let exe = std::env::current_exe()
+        .map_err(|e| anyhow!("Failed to locate the lime executable: {}", e))?;
+    let mut binary = Vec::new();
+    File::open(&exe)
+        .and_then(|mut file| {
+            // Repacking from a packed executable replaces the site it carries.
+            let len = binary_len(&mut file)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.take(len).read_to_end(&mut binary)
+        })
+        .map_err(|e| anyhow!("Failed to read {}: {}", exe.display(), e))?;
+
+    let archive =
+        build_archive(config, config_path).map_err(|e| anyhow!("Failed to pack site: {}", e))?;
+    let archive_len = archive.len() as u64;
+    binary.extend(archive);
+    binary.extend(archive_len.to_le_bytes());
+    binary.extend(MAGIC);
+    fs::write(output, &binary).map_err(|e| anyhow!("Failed to write {}: {}", output, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))
+            .map_err(|e| anyhow!("Failed to make {} executable: {}", output, e))?;
+    }
+    println!(
+        "Packed the site into {} ({:.1} MB, {:.1} MB of site)",
+        output,
+        binary.len() as f64 / 1_000_000.0,
+        archive_len as f64 / 1_000_000.0
+    );
+    Ok(())
+}
+
+/// A `.tar.gz` of the configuration and every directory and file it points to, with the
+/// paths in the configuration rewritten to where they are in the archive.
+fn build_archive(config: &Config, config_path: &str) -> Result<Vec<u8>> {
+    let content = if Path::new(config_path).exists() {
+        fs::read_to_string(config_path)?
+    } else {
+        String::new()
+    };
+    let mut document = content.parse::<DocumentMut>()?;
+
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best()));
+    // Symlinks are stored as the files they point to, since their targets are not packed.
+    archive.follow_symlinks(true);
+    for (name, dir) in [("pages", &config.pages_dir), ("static", &config.static_dir)] {
+        if Path::new(dir).is_dir() {
+            archive.append_dir_all(name, dir)?;
+        }
+        document[format!("{}_dir", name).as_str()] = value(name);
+    }
+    for (index, mount) in config.mounts.iter().enumerate() {
+        let name = format!("mounts/{}", index);
+        archive.append_dir_all(&name, &mount.dir)?;
+        document["mount"][index]["dir"] = value(name);
+    }
+    if let Some(file) = &config.redirects_file {
+        let extension = Path::new(file)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("toml");
+        let name = format!("redirects.{}", extension);
+        archive.append_path_with_name(file, &name)?;
+        document["redirects_file"] = value(name);
+    }
+
+    let content = document.to_string();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    archive.append_data(&mut header, "lime.toml", content.as_bytes())?;
+    Ok(archive.into_inner()?.finish()?)
+}
+
+/// Extracts the site packed into this executable, returning the directory it was
+/// extracted to, or `None` when the executable carries no site.
+pub fn unpack() -> Result<Option<PathBuf>> {
+    let exe = std::env::current_exe()
+        .map_err(|e| anyhow!("Failed to locate the lime executable: {}", e))?;
+    let archive = File::open(&exe)
+        .and_then(|mut file| read_archive(&mut file))
+        .map_err(|e| anyhow!("Failed to read {}: {}", exe.display(), e))?;
+    let Some(archive) = archive else {
+        return Ok(None);
+    };
+    let dir = std::env::temp_dir().join(format!("lime-pack-{}", std::process::id()));
+    tar::Archive::new(GzDecoder::new(archive.as_slice()))
+        .unpack(&dir)
+        .map_err(|e| anyhow!("Failed to extract the site to {}: {}", dir.display(), e))?;
+    Ok(Some(dir))
+}
+
+/// Length of the executable without a packed site.
+fn binary_len(file: &mut File) -> io::Result<u64> {
+    let len = file.metadata()?.len();
+    Ok(match archive_len(file)? {
+        Some(archive_len) => len - TRAILER_LEN - archive_len,
+        None => len,
+    })
+}
+
+fn read_archive(file: &mut File) -> io::Result<Option<Vec<u8>>> {
+    let Some(archive_len) = archive_len(file)? else {
+        return Ok(None);
+    };
+    file.seek(SeekFrom::End(-((TRAILER_LEN + archive_len) as i64)))?;
+    let mut archive = Vec::new();
+    file.take(archive_len).read_to_end(&mut archive)?;
+    Ok(Some(archive))
+}
+
+fn archive_len(file: &mut File) -> io::Result<Option<u64>> {
+    let len = file.metadata()?.len();
+    if len < TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut trailer = [0; TRAILER_LEN as usize];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    file.read_exact(&mut trailer)?;
+    if &trailer[8..] != MAGIC {
+        return Ok(None);
+    }
+    let archive_len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    Ok((archive_len <= len - TRAILER_LEN).then_some(archive_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn finds_the_appended_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lime");
+        fs::write(&path, b"\x7fELF binary").unwrap();
+        assert!(
+            read_archive(&mut File::open(&path).unwrap())
+                .unwrap()
+                .is_none()
+        );
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"archive").unwrap();
+        file.write_all(&7u64.to_le_bytes()).unwrap();
+        file.write_all(MAGIC).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(read_archive(&mut file).unwrap().unwrap(), b"archive");
+        assert_eq!(binary_len(&mut file).unwrap(), 11);
+    }
+}
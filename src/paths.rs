@@ -0,0 +1,360 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use tracing::debug;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PathError {
+    /// Malformed percent-encoding, invalid UTF-8 (including overlong sequences), or NUL bytes.
+    InvalidEncoding,
+    /// `..` segments or Windows path separators.
+    Traversal,
+}
+
+/// Turns the raw request path into a relative path that is safe to join onto a directory.
+///
+/// The path is percent-decoded exactly once and normalized to Unicode NFC. Empty and `.`
+/// segments are dropped, while a trailing slash is kept since it marks a directory request.
+pub fn normalize_request_path(raw: &str) -> Result<String, PathError> {
+    let bytes = percent_decode(raw)?;
+    let decoded = String::from_utf8(bytes).map_err(|_| PathError::InvalidEncoding)?;
+    if decoded.contains('\0') {
+        return Err(PathError::InvalidEncoding);
+    }
+    if decoded.contains('\\') {
+        return Err(PathError::Traversal);
+    }
+
+    let normalized: String = decoded.nfc().collect();
+    let mut segments = Vec::new();
+    for segment in normalized.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return Err(PathError::Traversal),
+            // Drive prefixes like `C:` would replace the base directory on Windows.
+            s if cfg!(windows) && s.contains(':') => return Err(PathError::Traversal),
+            s => segments.push(s),
+        }
+    }
+
+    let mut path = segments.join("/");
+    if normalized.ends_with('/') && !path.is_empty() {
+        path.push('/');
+    }
+    Ok(path)
+}
+
+fn percent_decode(raw: &str) -> Result<Vec<u8>, PathError> {
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).ok_or(PathError::InvalidEncoding)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| PathError::InvalidEncoding)?;
+            let byte = u8::from_str_radix(hex, 16).map_err(|_| PathError::InvalidEncoding)?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+/// Finds `relative` inside `base`, ignoring the case of every path component.
+/// Components matching exactly are preferred over case-insensitive matches.
+pub fn resolve_case_insensitive(base: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut resolved = base.to_path_buf();
+    for component in relative.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            Component::CurDir => continue,
+            _ => return None,
+        };
+        let exact = resolved.join(name);
+        if exact.exists() {
+            resolved = exact;
+            continue;
+        }
+        let wanted = name.to_string_lossy().to_lowercase();
+        let entry = fs::read_dir(&resolved)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .find(|e| e.file_name().to_string_lossy().to_lowercase() == wanted)?;
+        resolved = entry.path();
+    }
+    Some(resolved)
+}
+
+/// Resolves `url`, referenced from the page at `base`, to an absolute path on this site.
+/// The query string is kept, while fragments, other origins and paths climbing above
+/// the root yield `None`.
+pub fn join_url(base: &str, url: &str) -> Option<String> {
+    let url = url.split('#').next()?;
+    let (path, query) = url
+        .split_once('?')
+        .map_or((url, None), |(p, q)| (p, Some(q)));
+    if path.is_empty() || path.starts_with("//") || path.contains(':') {
+        return None;
+    }
+    let absolute = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        let dir = base.rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{}/{}", dir, path)
+    };
+    // Relative URLs may climb out of the page's directory, but never above the root.
+    let mut segments = Vec::new();
+    for segment in absolute.split('/') {
+        match segment {
+            ".." if segments.len() <= 1 => return None,
+            ".." => {
+                segments.pop();
+            }
+            "." => {}
+            segment => segments.push(segment),
+        }
+    }
+    let mut joined = segments.join("/");
+    if let Some(query) = query {
+        joined.push('?');
+        joined.push_str(query);
+    }
+    Some(joined)
+}
+
+/// Finds the file in `static_dir` served for the absolute URL `url`.
+pub fn static_file(static_dir: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next()?;
+    let relative = normalize_request_path(path).ok()?;
+    let static_canonical = fs::canonicalize(static_dir).ok()?;
+    let file = fs::canonicalize(static_dir.join(relative)).ok()?;
+    (file.starts_with(&static_canonical) && file.is_file()).then_some(file)
+}
+
+/// Whether `path` leads to or through a file or directory whose name starts with a dot,
+/// like `.env` or `.git/config`. `.well-known` is public by design.
+pub fn is_dotfile(path: &str) -> bool {
+    path.split('/')
+        .any(|segment| segment.starts_with('.') && segment != ".well-known")
+}
+
+/// Pages with a special meaning to Lime rather than content of their own.
+const SPECIAL_PAGES: [&str; 4] = [
+    "not-found.html",
+    "forbidden.html",
+    "internal-error.html",
+    "listing.html",
+];
+
+pub fn is_special_page(relative: &Path) -> bool {
+    SPECIAL_PAGES.iter().any(|page| relative == Path::new(page))
+}
+
+/// Lists the content pages in `pages_dir`, as paths relative to it.
+pub fn content_pages(pages_dir: &Path) -> Vec<PathBuf> {
+    html_files(pages_dir)
+        .into_iter()
+        .filter_map(|path| path.strip_prefix(pages_dir).ok().map(Path::to_path_buf))
+        .filter(|relative| !is_special_page(relative))
+        .collect()
+}
+
+/// Maps a page file to the URL Lime serves it under, e.g. `docs/intro.html` → `/docs/intro`.
+pub fn page_url(relative: &Path, index_files: &[String]) -> String {
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    let (dir, file) = relative.rsplit_once('/').unwrap_or(("", &relative));
+    let dir = if dir.is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{}/", dir)
+    };
+    if index_files.iter().any(|index| index == file) {
+        dir
+    } else {
+        format!("{}{}", dir, file.trim_end_matches(".html"))
+    }
+}
+
+fn html_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk(dir, &mut |path, metadata| {
+        if metadata.is_file() && path.extension().is_some_and(|e| e == "html") {
+            files.push(path.to_path_buf());
+        }
+    });
+    files
+}
+
+/// Calls `visit` with every file and directory below `dir`, parents before their
+/// contents. Symlinks are followed, except to a directory that is already being walked,
+/// so a link cycle can't recurse forever. Broken links and unreadable entries are skipped.
+pub fn walk(dir: &Path, visit: &mut impl FnMut(&Path, &fs::Metadata)) {
+    let mut ancestors: Vec<PathBuf> = fs::canonicalize(dir).into_iter().collect();
+    walk_dir(dir, &mut ancestors, visit);
+}
+
+fn walk_dir(
+    dir: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    visit: &mut impl FnMut(&Path, &fs::Metadata),
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        if !metadata.is_dir() {
+            visit(&path, &metadata);
+            continue;
+        }
+        let Ok(canonical) = fs::canonicalize(&path) else {
+            continue;
+        };
+        if ancestors.contains(&canonical) {
+            debug!("Skipping symlink cycle at {}", path.display());
+            continue;
+        }
+        visit(&path, &metadata);
+        ancestors.push(canonical);
+        walk_dir(&path, ancestors, visit);
+        ancestors.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_once() {
+        assert_eq!(normalize_request_path("/a%20b.html"), Ok("a b.html".into()));
+        assert_eq!(normalize_request_path("/%2541"), Ok("%41".into()));
+    }
+
+    #[test]
+    fn keeps_trailing_slash() {
+        assert_eq!(normalize_request_path("/docs/"), Ok("docs/".into()));
+        assert_eq!(
+            normalize_request_path("//docs//./guide"),
+            Ok("docs/guide".into())
+        );
+        assert_eq!(normalize_request_path("/"), Ok("".into()));
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        for raw in [
+            "/../etc/passwd",
+            "/a/../../etc/passwd",
+            "/%2e%2e/etc/passwd",
+            "/%2e%2e%2fetc%2fpasswd",
+            "/%2E%2E%2F%2E%2E%2Fetc",
+            "/..%2fetc",
+            "/a/%2e%2e",
+            "/..%5cwindows",
+            "/a\\..\\b",
+        ] {
+            assert_eq!(
+                normalize_request_path(raw),
+                Err(PathError::Traversal),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_encoding() {
+        for raw in [
+            "/%c0%ae%c0%ae/etc/passwd",
+            "/%e0%80%ae",
+            "/%ff",
+            "/a%2",
+            "/a%zz",
+            "/index.html%00.jpg",
+        ] {
+            assert_eq!(
+                normalize_request_path(raw),
+                Err(PathError::InvalidEncoding),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        // "e" followed by a combining acute accent becomes a single "é".
+        assert_eq!(
+            normalize_request_path("/cafe%CC%81"),
+            Ok("caf\u{e9}".into())
+        );
+        assert_eq!(normalize_request_path("/caf%C3%A9"), Ok("caf\u{e9}".into()));
+    }
+
+    #[test]
+    fn joins_urls_relative_to_the_page() {
+        assert_eq!(
+            join_url("/docs/page", "app.js?v=2"),
+            Some("/docs/app.js?v=2".into())
+        );
+        assert_eq!(
+            join_url("/docs/page", "../style.css"),
+            Some("/style.css".into())
+        );
+        assert_eq!(join_url("/docs/", "/a.css#x"), Some("/a.css".into()));
+        assert_eq!(join_url("/", "../a.css"), None);
+        assert_eq!(join_url("/", "https://cdn.example/a.js"), None);
+        assert_eq!(join_url("/", "//cdn.example/a.js"), None);
+    }
+
+    #[test]
+    fn resolves_paths_ignoring_case() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Docs")).unwrap();
+        fs::write(dir.path().join("Docs/Guide.html"), "").unwrap();
+        assert_eq!(
+            resolve_case_insensitive(dir.path(), Path::new("docs/GUIDE.HTML")),
+            Some(dir.path().join("Docs/Guide.html"))
+        );
+        assert_eq!(
+            resolve_case_insensitive(dir.path(), Path::new("docs/missing.html")),
+            None
+        );
+        assert_eq!(
+            resolve_case_insensitive(dir.path(), Path::new("../docs")),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walks_symlinked_dirs_without_cycles() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("docs/guide.html"), "").unwrap();
+        symlink(dir.path(), dir.path().join("docs/root")).unwrap();
+        symlink(dir.path().join("docs"), dir.path().join("docs/itself")).unwrap();
+        symlink(dir.path().join("docs"), dir.path().join("manual")).unwrap();
+        symlink(dir.path().join("missing"), dir.path().join("broken")).unwrap();
+
+        let mut visited = Vec::new();
+        walk(dir.path(), &mut |path, _| {
+            visited.push(path.strip_prefix(dir.path()).unwrap().to_path_buf());
+        });
+        visited.sort();
+        assert_eq!(
+            visited,
+            ["docs", "docs/guide.html", "manual", "manual/guide.html"].map(PathBuf::from)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::{
+    compress::sidecar_path,
+    store::{ContentStore, StoredFile},
+};
+
+/// Content codings and the extension of their sidecar, the smallest first.
+const ENCODINGS: [(&str, &str); 3] = [("br", "br"), ("zstd", "zst"), ("gzip", "gz")];
+
+/// Serves the compressed copies `lime compress` writes next to static files, without ever
+/// compressing at runtime.
+pub struct Precompressed {
+    paths: GlobSet,
+}
+
+impl Precompressed {
+    /// Returns `None` when no paths are served precompressed.
+    pub fn new(patterns: &[String]) -> Result<Option<Self>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(
+                Glob::new(pattern)
+                    .map_err(|e| anyhow!("Invalid static_gzip_only path '{}': {}", pattern, e))?,
+            );
+        }
+        let paths = builder
+            .build()
+            .map_err(|e| anyhow!("Failed to build static_gzip_only paths: {}", e))?;
+        Ok(Some(Self { paths }))
+    }
+
+    /// Whether responses to `path` depend on the `Accept-Encoding` header.
+    pub fn applies(&self, path: &str) -> bool {
+        self.paths.is_match(path)
+    }
+
+    /// The best copy of `file` the client accepts, with its content coding. Copies older
+    /// than the file are stale and never served.
+    pub async fn sidecar(
+        &self,
+        store: &dyn ContentStore,
+        base: &Path,
+        file: &StoredFile,
+        accept_encoding: &str,
+    ) -> Option<(&'static str, StoredFile)> {
+        for (encoding, extension) in ENCODINGS {
+            if !accepts(accept_encoding, encoding) {
+                continue;
+            }
+            let Ok(sidecar) = store.open(base, &sidecar_path(&file.path, extension)).await else {
+                continue;
+            };
+            if sidecar.modified >= file.modified {
+                return Some((encoding, sidecar));
+            }
+        }
+        None
+    }
+}
+
+/// Whether an `Accept-Encoding` header allows `encoding`, by name or through `*`.
+fn accepts(accept_encoding: &str, encoding: &str) -> bool {
+    let mut wildcard = false;
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(encoding) {
+            return quality > 0.0;
+        }
+        if name == "*" {
+            wildcard = quality > 0.0;
+        }
+    }
+    wildcard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_accept_encoding() {
+        assert!(accepts("gzip, deflate, br", "br"));
+        assert!(accepts("GZIP;q=0.5", "gzip"));
+        assert!(!accepts("gzip, br;q=0", "br"));
+        assert!(accepts("*", "zstd"));
+        assert!(!accepts("*, zstd;q=0", "zstd"));
+        assert!(!accepts("identity", "gzip"));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use axum::http::HeaderValue;
+use tracing::debug;
+
+use crate::{html, paths};
+
+/// Font formats worth preloading when a stylesheet refers to them.
+const FONT_EXTENSIONS: [&str; 4] = ["woff2", "woff", "ttf", "otf"];
+
+/// Builds `Link: rel=preload` headers from the stylesheets, scripts and fonts a page needs
+/// before it can render.
+pub struct Preloader {
+    static_dir: PathBuf,
+    /// Headers by page file, rebuilt when the page changes.
+    links: Mutex<HashMap<PathBuf, (SystemTime, Option<HeaderValue>)>>,
+}
+
+impl Preloader {
+    pub fn new(static_dir: PathBuf) -> Self {
+        Self {
+            static_dir,
+            links: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the `Link` header for the page at `file`, served as `request_path`.
+    pub fn link(
+        &self,
+        file: &Path,
+        modified: SystemTime,
+        request_path: &str,
+        html: &[u8],
+    ) -> Option<HeaderValue> {
+        let mut links = self.links.lock().unwrap();
+        if let Some((cached_at, link)) = links.get(file)
+            && *cached_at == modified
+        {
+            return link.clone();
+        }
+        let entries = self.scan(request_path, &String::from_utf8_lossy(html));
+        debug!(page = %request_path, count = entries.len(), "Scanned page for preloads");
+        let link = (!entries.is_empty())
+            .then(|| HeaderValue::try_from(entries.join(", ")).ok())
+            .flatten();
+        links.insert(file.to_path_buf(), (modified, link.clone()));
+        link
+    }
+
+    /// Collects preload entries for the resources referenced in the `<head>` of `html`.
+    fn scan(&self, request_path: &str, html: &str) -> Vec<String> {
+        let head_end = html
+            .to_ascii_lowercase()
+            .find("</head>")
+            .unwrap_or(html.len());
+        let mut entries = Vec::new();
+        let mut push = |entry: String| {
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        };
+        for tag in html::tags(&html[..head_end]) {
+            if html::is_tag(tag, "link")
+                && html::attribute(tag, "rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("stylesheet"))
+                })
+                && let Some(url) =
+                    html::attribute(tag, "href").and_then(|url| paths::join_url(request_path, url))
+            {
+                for (font, format) in self.fonts(&url) {
+                    push(format!(
+                        "<{}>; rel=preload; as=font; type=\"font/{}\"; crossorigin",
+                        font, format
+                    ));
+                }
+                push(format!("<{}>; rel=preload; as=style", url));
+            } else if html::is_tag(tag, "script")
+                && html::attribute(tag, "async").is_none()
+                && let Some(url) =
+                    html::attribute(tag, "src").and_then(|url| paths::join_url(request_path, url))
+            {
+                let is_module =
+                    html::attribute(tag, "type").is_some_and(|t| t.eq_ignore_ascii_case("module"));
+                if is_module {
+                    push(format!("<{}>; rel=modulepreload", url));
+                } else {
+                    push(format!("<{}>; rel=preload; as=script", url));
+                }
+            }
+        }
+        entries
+    }
+
+    /// Lists the local fonts referenced by `url(...)` in the stylesheet at `url`,
+    /// with their format.
+    fn fonts(&self, url: &str) -> Vec<(String, String)> {
+        let Some(css) = paths::static_file(&self.static_dir, url)
+            .and_then(|file| fs::read_to_string(file).ok())
+        else {
+            return Vec::new();
+        };
+        css.split("url(")
+            .skip(1)
+            .filter_map(|rest| {
+                let reference = rest.split(')').next()?.trim().trim_matches(['"', '\'']);
+                let extension = reference
+                    .split(['?', '#'])
+                    .next()?
+                    .rsplit_once('.')?
+                    .1
+                    .to_lowercase();
+                if !FONT_EXTENSIONS.contains(&extension.as_str()) {
+                    return None;
+                }
+                Some((paths::join_url(url, reference)?, extension))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preloads_head_resources_and_fonts() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("css")).unwrap();
+        fs::write(
+            dir.path().join("css/site.css"),
+            "@font-face { src: url('../fonts/inter.woff2') format('woff2'); }\
+             body { background: url(bg.png); }",
+        )
+        .unwrap();
+        let preloader = Preloader::new(dir.path().to_path_buf());
+
+        let html = concat!(
+            "<head><link rel=\"stylesheet\" href=\"/css/site.css\">",
+            "<script src=\"/app.js\"></script>",
+            "<script async src=\"/analytics.js\"></script>",
+            "<script type=\"module\" src=\"main.js\"></script></head>",
+            "<body><script src=\"/late.js\"></script></body>",
+        );
+        assert_eq!(
+            preloader.scan("/docs/", html),
+            [
+                "</fonts/inter.woff2>; rel=preload; as=font; type=\"font/woff2\"; crossorigin",
+                "</css/site.css>; rel=preload; as=style",
+                "</app.js>; rel=preload; as=script",
+                "</docs/main.js>; rel=modulepreload",
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::body::{Body, Bytes};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_stream::StreamExt;
+
+use crate::config::PriorityConfig;
+
+/// Pieces large responses are streamed in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Limits how many large files are sent at once, so pages and small assets never wait
+/// behind big downloads.
+pub struct Priority {
+    large_size: u64,
+    large: Arc<Semaphore>,
+}
+
+impl Priority {
+    /// Returns `None` when large responses aren't limited.
+    pub fn new(config: &PriorityConfig) -> Option<Self> {
+        let max = config.max_large_responses.filter(|max| *max > 0)?;
+        Some(Self {
+            large_size: config.large_response.0,
+            large: Arc::new(Semaphore::new(max)),
+        })
+    }
+
+    /// Waits for the turn of a response of `len` bytes. Large responses get a permit to
+    /// hold until they are sent, small ones go right away.
+    pub async fn turn(&self, len: u64) -> Option<OwnedSemaphorePermit> {
+        if len < self.large_size {
+            return None;
+        }
+        // The semaphore is never closed.
+        self.large.clone().acquire_owned().await.ok()
+    }
+}
+
+/// Streams `content`, giving up `permit` once it's sent or the client goes away.
+pub fn body_holding(content: Bytes, permit: OwnedSemaphorePermit) -> Body {
+    let chunks: Vec<Bytes> = (0..content.len())
+        .step_by(CHUNK_SIZE)
+        .map(|start| content.slice(start..content.len().min(start + CHUNK_SIZE)))
+        .collect();
+    Body::from_stream(tokio_stream::iter(chunks).map(move |chunk| {
+        let _ = &permit;
+        Ok::<_, Infallible>(chunk)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ByteSize;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn small_responses_never_wait() {
+        let priority = Priority::new(&PriorityConfig {
+            large_response: ByteSize(1000),
+            max_large_responses: Some(1),
+        })
+        .unwrap();
+        let permit = priority.turn(5000).await;
+        assert!(permit.is_some());
+        assert!(priority.turn(10).await.is_none());
+        let waiting = tokio::time::timeout(Duration::from_millis(50), priority.turn(5000));
+        assert!(waiting.await.is_err());
+        drop(permit);
+        assert!(priority.turn(5000).await.is_some());
+    }
+}
@@ -0,0 +1,124 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use axum::http::HeaderMap;
+use sha2::{Digest, Sha256};
+
+use crate::config::{Config, LogIp};
+
+/// Headers that carry client addresses added by proxies in front of Lime.
+const FORWARDED_HEADERS: [&str; 2] = ["x-forwarded-for", "x-real-ip"];
+
+/// Hides client addresses and user agents in the access log, as configured.
+pub struct Anonymizer {
+    ip: LogIp,
+    user_agent: bool,
+    salt: [u8; 16],
+}
+
+impl Anonymizer {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            ip: config.log_ip,
+            user_agent: config.log_user_agent,
+            salt: rand::random(),
+        }
+    }
+
+    pub fn ip(&self, ip: IpAddr) -> String {
+        match self.ip {
+            LogIp::Full => ip.to_string(),
+            LogIp::Truncate => truncate(ip).to_string(),
+            LogIp::Hash => {
+                let mut hasher = Sha256::new();
+                hasher.update(self.salt);
+                hasher.update(ip.to_string());
+                let digest = hasher.finalize();
+                digest[..6].iter().map(|b| format!("{:02x}", b)).collect()
+            }
+            LogIp::Omit => "-".to_string(),
+        }
+    }
+
+    /// The value of a request header as it should be logged.
+    pub fn header(&self, name: &str, value: &str) -> String {
+        if name.eq_ignore_ascii_case("user-agent") && !self.user_agent {
+            return "-".to_string();
+        }
+        if self.ip != LogIp::Full
+            && FORWARDED_HEADERS
+                .iter()
+                .any(|h| name.eq_ignore_ascii_case(h))
+        {
+            return value
+                .split(',')
+                .map(|part| match part.trim().parse::<IpAddr>() {
+                    Ok(ip) => self.ip(ip),
+                    Err(_) => "-".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+        value.to_string()
+    }
+}
+
+/// Whether the visitor asked not to be tracked, with `DNT: 1` or `Sec-GPC: 1`.
+pub fn opted_out(headers: &HeaderMap) -> bool {
+    ["dnt", "sec-gpc"].iter().any(|name| {
+        headers
+            .get(*name)
+            .is_some_and(|v| v.as_bytes().trim_ascii() == b"1")
+    })
+}
+
+/// Zeroes the host part of an address, keeping the network it belongs to.
+fn truncate(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let bits = u128::from(ip) & !((1u128 << 80) - 1);
+            IpAddr::V6(Ipv6Addr::from(bits))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_addresses() {
+        let config = Config {
+            log_ip: LogIp::Truncate,
+            log_user_agent: false,
+            ..Config::default()
+        };
+        let anonymizer = Anonymizer::new(&config);
+        assert_eq!(
+            anonymizer.ip("203.0.113.57".parse().unwrap()),
+            "203.0.113.0"
+        );
+        assert_eq!(
+            anonymizer.ip("2001:db8:85a3:8d3:1319:8a2e:370:7348".parse().unwrap()),
+            "2001:db8:85a3::"
+        );
+        assert_eq!(
+            anonymizer.header("X-Forwarded-For", "198.51.100.7, 10.0.0.1"),
+            "198.51.100.0, 10.0.0.0"
+        );
+        assert_eq!(anonymizer.header("User-Agent", "curl/8.0"), "-");
+    }
+
+    #[test]
+    fn honours_dnt_and_gpc() {
+        let mut headers = HeaderMap::new();
+        assert!(!opted_out(&headers));
+        headers.insert("DNT", "0".parse().unwrap());
+        assert!(!opted_out(&headers));
+        headers.insert("Sec-GPC", "1".parse().unwrap());
+        assert!(opted_out(&headers));
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::config::HealthProbeConfig;
+
+/// Marks requests from load balancers and orchestrators checking that the server is up,
+/// which are left out of logs, metrics and rate limits.
+#[derive(Clone, Copy)]
+pub struct HealthProbe;
+
+pub struct HealthProbes {
+    /// Lowercase user agent fragments.
+    user_agents: Vec<String>,
+    paths: GlobSet,
+}
+
+impl HealthProbes {
+    /// Builds the matcher, or `None` when no user agents or paths are configured.
+    pub fn new(config: &HealthProbeConfig) -> Result<Option<Self>> {
+        if config.user_agents.is_empty() && config.paths.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &config.paths {
+            builder.add(
+                Glob::new(pattern)
+                    .map_err(|e| anyhow!("Invalid health probe path '{}': {}", pattern, e))?,
+            );
+        }
+        let paths = builder
+            .build()
+            .map_err(|e| anyhow!("Failed to build health probe paths: {}", e))?;
+        Ok(Some(Self {
+            user_agents: config
+                .user_agents
+                .iter()
+                .map(|user_agent| user_agent.to_lowercase())
+                .collect(),
+            paths,
+        }))
+    }
+
+    fn is_probe(&self, path: &str, user_agent: &str) -> bool {
+        let user_agent = user_agent.to_lowercase();
+        self.paths.is_match(path)
+            || self
+                .user_agents
+                .iter()
+                .any(|fragment| user_agent.contains(fragment.as_str()))
+    }
+}
+
+pub async fn mark_health_probes(
+    State(probes): State<Arc<HealthProbes>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if probes.is_probe(request.uri().path(), user_agent) {
+        request.extensions_mut().insert(HealthProbe);
+    }
+    next.run(request).await
+}
+
+/// Whether `request` was marked as a health probe.
+pub fn is_health_probe(request: &Request) -> bool {
+    request.extensions().get::<HealthProbe>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_user_agents_and_paths() {
+        let probes = HealthProbes::new(&HealthProbeConfig {
+            user_agents: vec!["kube-probe".to_string()],
+            paths: vec!["/healthz".to_string()],
+        })
+        .unwrap()
+        .unwrap();
+        assert!(probes.is_probe("/", "kube-probe/1.29"));
+        assert!(probes.is_probe("/healthz", "curl/8.5.0"));
+        assert!(!probes.is_probe("/", "Mozilla/5.0 Firefox/130.0"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value, json};
+use tracing::{
+    Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+#[derive(Clone, Copy)]
+enum Format {
+    /// A JSON array of events for `chrome://tracing` and Perfetto.
+    Chrome,
+    /// One `root;child;leaf microseconds` line per span, for flame graph tools.
+    Folded,
+}
+
+struct Output {
+    format: Format,
+    writer: BufWriter<File>,
+    /// Whether a Chrome event was written yet, so the next one needs a comma.
+    written: bool,
+}
+
+/// Timing of a span, kept in its extensions.
+struct Timing {
+    start: Instant,
+    /// Time spent in child spans, which folded stacks subtract from this span's own time.
+    children: AtomicU64,
+    fields: Map<String, Value>,
+}
+
+/// Records how long spans take for a limited time and writes them to a file: a Chrome
+/// timeline, or folded stacks when the file name ends in `.folded`.
+pub struct Profiler {
+    start: Instant,
+    output: Arc<Mutex<Option<Output>>>,
+}
+
+impl Profiler {
+    /// Starts recording into `path`, and stops after `duration`.
+    pub fn start(path: &str, duration: Duration) -> Result<Self> {
+        let format = if Path::new(path).extension().is_some_and(|e| e == "folded") {
+            Format::Folded
+        } else {
+            Format::Chrome
+        };
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Failed to create trace file {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        if let Format::Chrome = format {
+            let _ = writer.write_all(b"[\n");
+        }
+        let output = Arc::new(Mutex::new(Some(Output {
+            format,
+            writer,
+            written: false,
+        })));
+
+        let finished = output.clone();
+        let path = path.to_string();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            if let Some(mut output) = finished.lock().unwrap().take() {
+                if let Format::Chrome = output.format {
+                    let _ = output.writer.write_all(b"\n]\n");
+                }
+                let _ = output.writer.flush();
+                eprintln!("Wrote the trace to {}", path);
+            }
+        });
+        Ok(Self {
+            start: Instant::now(),
+            output,
+        })
+    }
+}
+
+impl<S> Layer<S> for Profiler
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldVisitor(Map::new());
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(Timing {
+            start: Instant::now(),
+            children: AtomicU64::new(0),
+            fields: fields.0,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+            let mut fields = FieldVisitor(std::mem::take(&mut timing.fields));
+            values.record(&mut fields);
+            timing.fields = fields.0;
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let mut output = self.output.lock().unwrap();
+        let Some(output) = output.as_mut() else {
+            return;
+        };
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(timing) = extensions.get::<Timing>() else {
+            return;
+        };
+        let duration = timing.start.elapsed().as_micros() as u64;
+        if let Some(parent) = span.parent()
+            && let Some(parent_timing) = parent.extensions().get::<Timing>()
+        {
+            parent_timing
+                .children
+                .fetch_add(duration, Ordering::Relaxed);
+        }
+
+        let line = match output.format {
+            Format::Chrome => {
+                // Concurrent requests get a lane each.
+                let lane = span
+                    .scope()
+                    .from_root()
+                    .next()
+                    .map_or(0, |root| root.id().into_u64());
+                let event = json!({
+                    "name": span.name(),
+                    "cat": span.metadata().target(),
+                    "ph": "X",
+                    "ts": timing.start.saturating_duration_since(self.start).as_micros() as u64,
+                    "dur": duration,
+                    "pid": 1,
+                    "tid": lane,
+                    "args": timing.fields,
+                });
+                let separator = if output.written { ",\n" } else { "" };
+                output.written = true;
+                format!("{}{}", separator, event)
+            }
+            Format::Folded => {
+                let stack: Vec<&str> = span.scope().from_root().map(|s| s.name()).collect();
+                let own = duration.saturating_sub(timing.children.load(Ordering::Relaxed));
+                format!("{} {}\n", stack.join(";"), own)
+            }
+        };
+        // Flushed right away, so stopping the server early still leaves a usable file.
+        let _ = output.writer.write_all(line.as_bytes());
+        let _ = output.writer.flush();
+    }
+}
+
+struct FieldVisitor(Map<String, Value>);
+
+impl Visit for FieldVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info_span;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn writes_folded_stacks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.folded");
+        let profiler = Profiler::start(path.to_str().unwrap(), Duration::from_secs(60)).unwrap();
+        let subscriber = tracing_subscriber::registry().with(profiler);
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = info_span!("request", path = "/").entered();
+            let _file = info_span!("serve_file").entered();
+        });
+
+        let folded = std::fs::read_to_string(&path).unwrap();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(stacks, ["request;serve_file", "request"]);
+    }
+}
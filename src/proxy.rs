@@ -0,0 +1,679 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{
+    Router,
+    body::{Body, Bytes, to_bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header, request::Parts},
+    response::Response,
+    routing::any,
+};
+use reqwest::Client;
+use tracing::{instrument, warn};
+
+use crate::{config::ProxyRule, error::LimeError, proxy_cache::ResponseCache};
+
+/// Largest request body that is forwarded. Bodies are read in full before forwarding.
+const MAX_BODY_SIZE: usize = 16 << 20;
+
+/// Headers that only apply to a single connection, and so are never forwarded.
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+/// Claims of the request's JWT, added to the request by the `jwt` auth method and
+/// forwarded to the upstream as headers.
+#[derive(Clone)]
+pub struct ClaimHeaders(pub HeaderMap);
+
+/// Stops sending requests to an upstream after consecutive failures, and lets a single
+/// request through once the cooldown is over to see whether it recovered.
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// When the request probing a recovery was sent.
+    probing: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match state.open_until {
+            None => true,
+            Some(until) if now < until => false,
+            // A probe that never finished, e.g. because the client went away, is replaced.
+            Some(_) if state.probing.is_some_and(|at| now - at < self.cooldown) => false,
+            Some(_) => {
+                state.probing = Some(now);
+                true
+            }
+        }
+    }
+
+    fn succeeded(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    fn failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        if state.probing.is_some() || state.failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+            state.probing = None;
+        }
+    }
+
+    /// Time until requests may be sent again, when none may be sent now.
+    fn retry_after(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state
+            .open_until
+            .map(|until| until.saturating_duration_since(Instant::now()))
+    }
+}
+
+struct Upstream {
+    url: String,
+    breaker: CircuitBreaker,
+}
+
+/// The upstreams behind one proxied route.
+struct ProxyRoute {
+    upstreams: Vec<Upstream>,
+    client: Client,
+    error_page: Option<PathBuf>,
+    retries: u32,
+    retry_backoff: Duration,
+    cache: Option<Arc<ResponseCache>>,
+    cache_query_params: Option<Vec<String>>,
+    /// Headers that only come from [`ClaimHeaders`], never from the client.
+    claim_headers: Vec<HeaderName>,
+}
+
+impl ProxyRoute {
+    fn new(rule: &ProxyRule, claim_headers: &[HeaderName]) -> Result<Self> {
+        if rule.upstreams.is_empty() {
+            return Err(
+                LimeError::Proxy(format!("Proxy route {} has no upstreams", rule.route)).into(),
+            );
+        }
+        let client = Client::builder()
+            .timeout(Duration::from_secs(rule.timeout))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| LimeError::Proxy(format!("Failed to create HTTP client: {}", e)))?;
+        let upstreams = rule
+            .upstreams
+            .iter()
+            .map(|url| Upstream {
+                url: url.trim_end_matches('/').to_string(),
+                breaker: CircuitBreaker {
+                    threshold: rule.failure_threshold.max(1),
+                    cooldown: Duration::from_secs(rule.cooldown),
+                    state: Mutex::new(BreakerState::default()),
+                },
+            })
+            .collect();
+        Ok(Self {
+            upstreams,
+            client,
+            error_page: rule.error_page.as_ref().map(PathBuf::from),
+            retries: rule.retries,
+            retry_backoff: Duration::from_millis(rule.retry_backoff),
+            cache: rule.cache.then(|| {
+                Arc::new(ResponseCache::new(
+                    rule.cache_max_size,
+                    rule.cache_ttl.map(Duration::from_secs),
+                ))
+            }),
+            cache_query_params: rule.cache_query_params.clone(),
+            claim_headers: claim_headers.to_vec(),
+        })
+    }
+
+    /// The next upstream to send a request to, preferring ones it wasn't sent to yet.
+    fn next_upstream(&self, tried: &[usize]) -> Option<usize> {
+        let untried = (0..self.upstreams.len()).filter(|i| !tried.contains(i));
+        let retried = tried.iter().copied();
+        untried
+            .chain(retried)
+            .find(|i| self.upstreams[*i].breaker.allow())
+    }
+
+    /// Answers with the error page while every upstream's circuit is open.
+    async fn unavailable(&self) -> Response {
+        let retry_after = self
+            .upstreams
+            .iter()
+            .filter_map(|u| u.breaker.retry_after())
+            .min()
+            .unwrap_or_default();
+        let page = match &self.error_page {
+            Some(path) => tokio::fs::read(path).await.ok(),
+            None => None,
+        };
+        let (content_type, body) = match page {
+            Some(page) => ("text/html", Body::from(page)),
+            None => ("text/plain", Body::from("Service Unavailable")),
+        };
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::RETRY_AFTER, retry_after.as_secs().max(1))
+            .body(body)
+            .unwrap()
+    }
+}
+
+/// Routes forwarding requests to the upstreams of each proxy rule, and the response caches
+/// of the rules that have one. `claim_headers` are the headers JWT claims are forwarded
+/// in, see [`ClaimHeaders`].
+pub fn proxy_router<S: Clone + Send + Sync + 'static>(
+    rules: &[ProxyRule],
+    claim_headers: &[HeaderName],
+) -> Result<(Router<S>, Vec<Arc<ResponseCache>>)> {
+    let mut router = Router::new();
+    let mut caches = Vec::new();
+    for rule in rules {
+        let route = rule.route.trim_end_matches('/');
+        if !route.starts_with('/') {
+            return Err(LimeError::Proxy(format!(
+                "Proxy route '{}' must start with '/' and can't be the root",
+                rule.route
+            ))
+            .into());
+        }
+        let proxy = Arc::new(ProxyRoute::new(rule, claim_headers)?);
+        caches.extend(proxy.cache.clone());
+        router = router
+            .route(route, any(forward).with_state(proxy.clone()))
+            .route(
+                &format!("{}/{{*rest}}", route),
+                any(forward).with_state(proxy),
+            );
+    }
+    Ok((router, caches))
+}
+
+async fn forward(State(proxy): State<Arc<ProxyRoute>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, MAX_BODY_SIZE).await else {
+        return status_response(StatusCode::PAYLOAD_TOO_LARGE);
+    };
+    // Requests that may have changed something upstream are never sent twice.
+    let attempts = if parts.method.is_idempotent() {
+        proxy.retries + 1
+    } else {
+        1
+    };
+
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    // Responses to credentialed requests may be meant for that user only.
+    let cache_key = proxy
+        .cache
+        .as_ref()
+        .filter(|_| matches!(parts.method, Method::GET | Method::HEAD))
+        .filter(|_| !parts.headers.contains_key(header::AUTHORIZATION))
+        .map(|_| {
+            cache_key(
+                &parts.method,
+                &parts.uri,
+                &parts.headers,
+                proxy.cache_query_params.as_deref(),
+            )
+        });
+    if let Some((cache, key)) = proxy.cache.as_ref().zip(cache_key.as_ref())
+        && let Some(cached) = cache.get(key)
+    {
+        let mut response = Response::builder()
+            .status(cached.status)
+            .body(Body::from(cached.body.clone()))
+            .unwrap();
+        *response.headers_mut() = cached.headers.clone();
+        response.headers_mut().insert(
+            header::AGE,
+            HeaderValue::from(cached.stored.elapsed().as_secs()),
+        );
+        response
+            .headers_mut()
+            .insert("x-cache", HeaderValue::from_static("HIT"));
+        return response;
+    }
+
+    let headers = forwarded_headers(&parts, &proxy.claim_headers);
+    let mut tried = Vec::new();
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            tokio::time::sleep(proxy.retry_backoff * 2u32.saturating_pow(attempt - 1)).await;
+        }
+        let Some(index) = proxy.next_upstream(&tried) else {
+            break;
+        };
+        tried.push(index);
+        let upstream = &proxy.upstreams[index];
+        let result = send(
+            &proxy.client,
+            parts.method.clone(),
+            format!("{}{}", upstream.url, path),
+            headers.clone(),
+            body.clone(),
+        )
+        .await;
+        let (status, headers, body) = match result {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Proxy request to {} failed: {}", upstream.url, e);
+                upstream.breaker.failed();
+                continue;
+            }
+        };
+        if matches!(
+            status,
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ) {
+            upstream.breaker.failed();
+        } else {
+            upstream.breaker.succeeded();
+        }
+        let headers = without_hop_by_hop(headers);
+        if let Some((cache, key)) = proxy.cache.as_ref().zip(cache_key) {
+            cache.store(key, status, &headers, &body);
+        }
+        let mut response = Response::builder()
+            .status(status)
+            .body(Body::from(body))
+            .unwrap();
+        *response.headers_mut() = headers;
+        if proxy.cache.is_some() {
+            response
+                .headers_mut()
+                .insert("x-cache", HeaderValue::from_static("MISS"));
+        }
+        return response;
+    }
+
+    if tried.is_empty() {
+        proxy.unavailable().await
+    } else {
+        status_response(StatusCode::BAD_GATEWAY)
+    }
+}
+
+/// Sends a request and reads the whole response.
+#[instrument(level = "debug", name = "upstream", skip_all, fields(%method, %url))]
+async fn send(
+    client: &Client,
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Bytes,
+) -> reqwest::Result<(StatusCode, HeaderMap, Bytes)> {
+    let response = client
+        .request(method, url)
+        .headers(headers)
+        .body(body)
+        .send()
+        .await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    Ok((status, headers, response.bytes().await?))
+}
+
+/// The client's headers, plus `X-Forwarded-*` headers describing the original request
+/// and the claims of its JWT. Client copies of the claim headers are dropped.
+fn forwarded_headers(parts: &Parts, claim_headers: &[HeaderName]) -> HeaderMap {
+    let mut headers = without_hop_by_hop(parts.headers.clone());
+    for name in claim_headers {
+        headers.remove(name);
+    }
+    if let Some(ClaimHeaders(claims)) = parts.extensions.get::<ClaimHeaders>() {
+        headers.extend(claims.clone());
+    }
+    if let Some(ConnectInfo(addr)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+        let forwarded_for = match parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(previous) => format!("{}, {}", previous, addr.ip()),
+            None => addr.ip().to_string(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&forwarded_for) {
+            headers.insert("x-forwarded-for", value);
+        }
+    }
+    if let Some(host) = parts.headers.get(header::HOST) {
+        headers.insert("x-forwarded-host", host.clone());
+    }
+    headers.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+    headers
+}
+
+fn without_hop_by_hop(mut headers: HeaderMap) -> HeaderMap {
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+    headers
+}
+
+fn status_response(status: StatusCode) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(status.canonical_reason().unwrap_or_default()))
+        .unwrap()
+}
+
+/// The key a response is cached under, like `GET /path?query br,gzip`. With `params`, only those
+/// query parameters are part of it, sorted, so links with tracking parameters share one
+/// cached response.
+fn cache_key(method: &Method, uri: &Uri, headers: &HeaderMap, params: Option<&[String]>) -> String {
+    let url = match params {
+        None => uri.path_and_query().map_or("/", |p| p.as_str()).to_string(),
+        Some(params) => {
+            let mut pairs: Vec<_> = uri
+                .query()
+                .unwrap_or_default()
+                .split('&')
+                .filter(|pair| {
+                    let name = pair.split('=').next().unwrap_or_default();
+                    params.iter().any(|param| param == name)
+                })
+                .collect();
+            pairs.sort_unstable();
+            if pairs.is_empty() {
+                uri.path().to_string()
+            } else {
+                format!("{}?{}", uri.path(), pairs.join("&"))
+            }
+        }
+    };
+    // The request's `Accept-Encoding` is sent upstream as is, so a compressed body must
+    // only be replayed to clients that accept the same encodings.
+    match accepted_encodings(headers) {
+        Some(encodings) => format!("{} {} {}", method, url, encodings),
+        None => format!("{} {}", method, url),
+    }
+}
+
+/// The encodings a client accepts, lowercased and sorted, e.g. `br,gzip`.
+fn accepted_encodings(headers: &HeaderMap) -> Option<String> {
+    let mut encodings: Vec<String> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|coding| {
+            let mut parts = coding.split(';');
+            let name = parts.next()?.trim().to_ascii_lowercase();
+            let refused = parts.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            (!name.is_empty() && !refused).then_some(name)
+        })
+        .collect();
+    encodings.sort_unstable();
+    encodings.dedup();
+    (!encodings.is_empty()).then(|| encodings.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ByteSize;
+    use tokio::{sync::oneshot, task::JoinHandle};
+    use tower::ServiceExt;
+
+    /// Starts an upstream answering `/api/ping` and `/api/text`, which stops when the
+    /// sender is used. `/api/text` is gzipped for clients that accept it.
+    async fn start_upstream() -> (String, oneshot::Sender<()>, JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let app = Router::new()
+                .route("/api/ping", any(|| async { "pong" }))
+                .route(
+                    "/api/text",
+                    any(|headers: HeaderMap| async move {
+                        let gzip = headers
+                            .get(header::ACCEPT_ENCODING)
+                            .is_some_and(|v| v.to_str().unwrap().contains("gzip"));
+                        let mut response = Response::builder()
+                            .header(header::CACHE_CONTROL, "max-age=60")
+                            .header(header::VARY, "Accept-Encoding");
+                        if gzip {
+                            response = response.header(header::CONTENT_ENCODING, "gzip");
+                        }
+                        let body = if gzip { "gzipped" } else { "plain" };
+                        response.body(Body::from(body)).unwrap()
+                    }),
+                );
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await
+                .unwrap();
+        });
+        (url, stop, server)
+    }
+
+    fn rule(upstreams: Vec<String>) -> ProxyRule {
+        ProxyRule {
+            route: "/api".to_string(),
+            upstreams,
+            timeout: 5,
+            failure_threshold: 1,
+            cooldown: 60,
+            retries: 0,
+            retry_backoff: 0,
+            error_page: None,
+            cache: false,
+            cache_ttl: None,
+            cache_max_size: ByteSize(1 << 20),
+            cache_query_params: None,
+        }
+    }
+
+    fn request(method: Method) -> Request {
+        Request::builder()
+            .method(method)
+            .uri("/api/ping")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn opens_the_circuit_after_failures() {
+        let (upstream, stop, server) = start_upstream().await;
+        let dir = tempfile::tempdir().unwrap();
+        let error_page = dir.path().join("down.html");
+        std::fs::write(&error_page, "<p>Back soon</p>").unwrap();
+        let (router, _): (Router, _) = proxy_router(
+            &[ProxyRule {
+                error_page: Some(error_page.to_string_lossy().into_owned()),
+                ..rule(vec![upstream])
+            }],
+            &[],
+        )
+        .unwrap();
+
+        let response = router.clone().oneshot(request(Method::GET)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(body, "pong");
+
+        let _ = stop.send(());
+        server.await.unwrap();
+        let response = router.clone().oneshot(request(Method::GET)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let response = router.oneshot(request(Method::GET)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(body, "<p>Back soon</p>");
+    }
+
+    #[tokio::test]
+    async fn retries_idempotent_requests_on_the_next_upstream() {
+        let (down, stop, server) = start_upstream().await;
+        let _ = stop.send(());
+        server.await.unwrap();
+        let (up, _stop, _server) = start_upstream().await;
+        let proxy_rule = ProxyRule {
+            retries: 1,
+            failure_threshold: 5,
+            ..rule(vec![down, up])
+        };
+
+        let (router, _): (Router, _) =
+            proxy_router(std::slice::from_ref(&proxy_rule), &[]).unwrap();
+        let response = router.oneshot(request(Method::GET)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (router, _): (Router, _) = proxy_router(&[proxy_rule], &[]).unwrap();
+        let response = router.oneshot(request(Method::POST)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn forwards_claims_in_place_of_client_headers() {
+        let names = [HeaderName::from_static("x-user")];
+        let request = |claims: Option<&str>| {
+            let mut request = Request::builder()
+                .uri("/api/ping")
+                .header("x-user", "mallory")
+                .header("accept", "text/html")
+                .body(())
+                .unwrap();
+            if let Some(user) = claims {
+                let mut headers = HeaderMap::new();
+                headers.insert("x-user", HeaderValue::from_str(user).unwrap());
+                request.extensions_mut().insert(ClaimHeaders(headers));
+            }
+            request.into_parts().0
+        };
+
+        let headers = forwarded_headers(&request(Some("ada")), &names);
+        assert_eq!(
+            headers.get_all("x-user").iter().collect::<Vec<_>>(),
+            ["ada"]
+        );
+        assert_eq!(headers["accept"], "text/html");
+        let headers = forwarded_headers(&request(None), &names);
+        assert!(!headers.contains_key("x-user"));
+    }
+
+    #[tokio::test]
+    async fn caches_each_accepted_encoding_apart() {
+        let (upstream, _stop, _server) = start_upstream().await;
+        let (router, _): (Router, _) = proxy_router(
+            &[ProxyRule {
+                cache: true,
+                ..rule(vec![upstream])
+            }],
+            &[],
+        )
+        .unwrap();
+        let get = |accept_encoding: Option<&str>| {
+            let mut request = Request::builder().uri("/api/text");
+            if let Some(value) = accept_encoding {
+                request = request.header(header::ACCEPT_ENCODING, value);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let header = |name| {
+                    let value = response.headers().get(name)?;
+                    Some(value.to_str().unwrap().to_string())
+                };
+                let (encoding, cache) = (header("content-encoding"), header("x-cache"));
+                let body = to_bytes(response.into_body(), 1024).await.unwrap();
+                (
+                    String::from_utf8(body.to_vec()).unwrap(),
+                    encoding,
+                    cache.unwrap(),
+                )
+            }
+        };
+
+        let (body, encoding, cache) = get(Some("gzip, deflate")).await;
+        assert_eq!((body.as_str(), cache.as_str()), ("gzipped", "MISS"));
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        let (body, encoding, cache) = get(None).await;
+        assert_eq!((body.as_str(), cache.as_str()), ("plain", "MISS"));
+        assert!(encoding.is_none());
+        let (body, _, cache) = get(Some("identity")).await;
+        assert_eq!((body.as_str(), cache.as_str()), ("plain", "MISS"));
+        let (body, _, cache) = get(Some("deflate,GZIP")).await;
+        assert_eq!((body.as_str(), cache.as_str()), ("gzipped", "HIT"));
+        let (body, _, cache) = get(None).await;
+        assert_eq!((body.as_str(), cache.as_str()), ("plain", "HIT"));
+    }
+
+    #[test]
+    fn keys_caches_on_selected_query_params() {
+        let key = |uri: &str, params: Option<&[String]>| {
+            cache_key(
+                &Method::GET,
+                &uri.parse().unwrap(),
+                &HeaderMap::new(),
+                params,
+            )
+        };
+        assert_eq!(
+            key("/api/posts?utm_source=x&page=2", None),
+            "GET /api/posts?utm_source=x&page=2"
+        );
+        let params = ["page".to_string(), "sort".to_string()];
+        assert_eq!(
+            key("/api/posts?utm_source=x&sort=new&page=2", Some(&params)),
+            "GET /api/posts?page=2&sort=new"
+        );
+        assert_eq!(
+            key("/api/posts?page=2&sort=new", Some(&params)),
+            key("/api/posts?sort=new&page=2&ref=home", Some(&params))
+        );
+        assert_eq!(
+            key("/api/posts?utm_source=x", Some(&params)),
+            "GET /api/posts"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            "gzip, BR;q=0.5, zstd;q=0".parse().unwrap(),
+        );
+        assert_eq!(
+            cache_key(&Method::GET, &"/api/posts".parse().unwrap(), &headers, None),
+            "GET /api/posts br,gzip"
+        );
+    }
+}
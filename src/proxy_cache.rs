@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode, header},
+};
+
+use crate::config::ByteSize;
+
+/// Statuses that are worth keeping, since they don't depend on the moment they were sent.
+const CACHEABLE_STATUSES: [StatusCode; 3] = [
+    StatusCode::OK,
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::NOT_FOUND,
+];
+
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub stored: Instant,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct Entries {
+    responses: HashMap<String, Arc<CachedResponse>>,
+    size: u64,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &str) {
+        if let Some(response) = self.responses.remove(key) {
+            self.size -= response.body.len() as u64;
+        }
+    }
+}
+
+/// Keeps upstream responses in memory for as long as their `Cache-Control` allows, or for
+/// a fixed time when one is configured.
+pub struct ResponseCache {
+    max_size: u64,
+    ttl: Option<Duration>,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    pub fn new(max_size: ByteSize, ttl: Option<Duration>) -> Self {
+        Self {
+            max_size: max_size.0,
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Returns the response stored under `key`, unless it expired.
+    pub fn get(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let mut entries = self.entries.lock().unwrap();
+        let response = entries.responses.get(key)?.clone();
+        if response.expires <= Instant::now() {
+            entries.remove(key);
+            return None;
+        }
+        Some(response)
+    }
+
+    /// Stores a response under `key` when it may be cached, evicting the oldest responses
+    /// when the cache grows too large.
+    pub fn store(&self, key: String, status: StatusCode, headers: &HeaderMap, body: &Bytes) {
+        let Some(lifetime) = self.lifetime(status, headers) else {
+            return;
+        };
+        if body.len() as u64 > self.max_size / 4 {
+            return;
+        }
+        let now = Instant::now();
+        let response = Arc::new(CachedResponse {
+            status,
+            headers: headers.clone(),
+            body: body.clone(),
+            stored: now,
+            expires: now + lifetime,
+        });
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&key);
+        entries.size += response.body.len() as u64;
+        entries.responses.insert(key, response);
+        while entries.size > self.max_size {
+            let Some(oldest) = entries
+                .responses
+                .iter()
+                .min_by_key(|(_, r)| r.stored)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+    }
+
+    /// Evicts the responses whose URL path matches, returning how many there were.
+    pub fn purge(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let purged: Vec<String> = entries
+            .responses
+            .keys()
+            .filter(|key| {
+                // Keys look like `GET /path?query gzip`.
+                let url = key.split(' ').nth(1).unwrap_or(key);
+                matches(url.split('?').next().unwrap_or(url))
+            })
+            .cloned()
+            .collect();
+        for key in &purged {
+            entries.remove(key);
+        }
+        purged.len()
+    }
+
+    /// How long a response may be kept, if at all.
+    fn lifetime(&self, status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        if !CACHEABLE_STATUSES.contains(&status) || headers.contains_key(header::SET_COOKIE) {
+            return None;
+        }
+        // Responses that differ by request headers would need one entry per variant.
+        if headers
+            .get_all(header::VARY)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|v| !v.trim().eq_ignore_ascii_case("accept-encoding"))
+        {
+            return None;
+        }
+        let directives: Vec<String> = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|d| d.trim().to_ascii_lowercase())
+            .collect();
+        if directives
+            .iter()
+            .any(|d| d == "no-store" || d == "no-cache" || d == "private")
+        {
+            return None;
+        }
+        if let Some(ttl) = self.ttl {
+            return Some(ttl);
+        }
+        let max_age = |name: &str| {
+            directives.iter().find_map(|d| {
+                d.strip_prefix(name)?
+                    .strip_prefix('=')?
+                    .trim_matches('"')
+                    .parse::<u64>()
+                    .ok()
+            })
+        };
+        max_age("s-maxage")
+            .or_else(|| max_age("max-age"))
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(cache_control: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CACHE_CONTROL, cache_control.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn follows_cache_control_unless_overridden() {
+        let body = Bytes::from("{}");
+        let cache = ResponseCache::new(ByteSize(1 << 20), None);
+        cache.store("a".into(), StatusCode::OK, &headers("max-age=60"), &body);
+        cache.store("b".into(), StatusCode::OK, &headers("no-store"), &body);
+        cache.store("c".into(), StatusCode::OK, &HeaderMap::new(), &body);
+        cache.store(
+            "d".into(),
+            StatusCode::BAD_GATEWAY,
+            &headers("max-age=60"),
+            &body,
+        );
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_none());
+        assert!(cache.get("d").is_none());
+
+        let cache = ResponseCache::new(ByteSize(1 << 20), Some(Duration::from_secs(5)));
+        cache.store("c".into(), StatusCode::OK, &HeaderMap::new(), &body);
+        cache.store(
+            "e".into(),
+            StatusCode::OK,
+            &headers("private, max-age=60"),
+            &body,
+        );
+        assert!(cache.get("c").is_some());
+        assert!(cache.get("e").is_none());
+    }
+}
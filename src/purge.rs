@@ -0,0 +1,167 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::AdminState, config::Config, file_cache::FileCache, paths::page_url,
+    proxy_cache::ResponseCache,
+};
+
+/// The caches `POST /__lime/purge` evicts from.
+pub struct Caches {
+    files: Option<Arc<FileCache>>,
+    responses: Vec<Arc<ResponseCache>>,
+    /// Directories files are served from, with the URL they are served under.
+    roots: Vec<(String, PathBuf)>,
+    pages_dir: Option<PathBuf>,
+    index_files: Vec<String>,
+}
+
+impl Caches {
+    pub fn new(
+        config: &Config,
+        files: Option<Arc<FileCache>>,
+        responses: Vec<Arc<ResponseCache>>,
+    ) -> Self {
+        // The file cache is keyed by canonical paths.
+        let canonical = |dir: &str| std::fs::canonicalize(dir).ok();
+        let mut roots: Vec<(String, PathBuf)> = config
+            .mounts
+            .iter()
+            .filter_map(|m| {
+                Some((
+                    m.route.trim_end_matches('/').to_string(),
+                    canonical(&m.dir)?,
+                ))
+            })
+            .collect();
+        roots.extend(canonical(&config.static_dir).map(|dir| (String::new(), dir)));
+        let pages_dir = canonical(&config.pages_dir);
+        roots.extend(pages_dir.clone().map(|dir| (String::new(), dir)));
+        Self {
+            files,
+            responses,
+            roots,
+            pages_dir,
+            index_files: config.index_files.clone(),
+        }
+    }
+
+    /// Evicts every cached file and response whose URL matches `patterns`.
+    fn purge(&self, patterns: &GlobSet) -> usize {
+        let mut purged = 0;
+        if let Some(files) = &self.files {
+            purged += files.purge(|path| {
+                self.urls(path)
+                    .iter()
+                    .any(|url| patterns.is_match(url.as_str()))
+            });
+        }
+        for responses in &self.responses {
+            purged += responses.purge(|url| patterns.is_match(url));
+        }
+        purged
+    }
+
+    /// URLs a file is served under, e.g. `/blog/post.html` and `/blog/post`.
+    fn urls(&self, path: &Path) -> Vec<String> {
+        let mut urls = Vec::new();
+        for (prefix, root) in &self.roots {
+            if let Ok(relative) = path.strip_prefix(root) {
+                let relative_url = relative.to_string_lossy().replace('\\', "/");
+                urls.push(format!("{}/{}", prefix, relative_url));
+                if self.pages_dir.as_ref() == Some(root) {
+                    urls.push(page_url(relative, &self.index_files));
+                }
+            }
+        }
+        urls
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PurgeRequest {
+    /// URL paths or glob patterns, e.g. `/index.html` or `/api/**`.
+    paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PurgeResponse {
+    purged: usize,
+}
+
+/// Evicts the cached files and proxied responses matching the given paths.
+pub async fn handle_purge(
+    State(state): State<Arc<AdminState>>,
+    Json(request): Json<PurgeRequest>,
+) -> Response {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &request.paths {
+        match Glob::new(pattern) {
+            Ok(glob) => builder.add(glob),
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid pattern '{}': {}", pattern, e),
+                )
+                    .into_response();
+            }
+        };
+    }
+    let patterns = match builder.build() {
+        Ok(patterns) => patterns,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let purged = state.caches.purge(&patterns);
+    Json(PurgeResponse { purged }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::ByteSize;
+
+    #[tokio::test]
+    async fn purges_files_by_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        std::fs::create_dir_all(pages.join("blog")).unwrap();
+        let files = Arc::new(FileCache::new(ByteSize(1 << 20), Duration::ZERO));
+        for name in ["index.html", "blog/post.html", "blog/other.html"] {
+            std::fs::write(pages.join(name), name).unwrap();
+            let path = pages.join(name).canonicalize().unwrap();
+            let metadata = std::fs::metadata(&path).unwrap();
+            files
+                .read(&path, metadata.len(), metadata.modified().ok())
+                .await
+                .unwrap();
+        }
+        let config = Config {
+            pages_dir: pages.to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        let caches = Caches::new(&config, Some(files), Vec::new());
+
+        let patterns = |p: &str| {
+            GlobSetBuilder::new()
+                .add(Glob::new(p).unwrap())
+                .build()
+                .unwrap()
+        };
+        assert_eq!(caches.purge(&patterns("/blog/post")), 1);
+        assert_eq!(caches.purge(&patterns("/blog/post")), 0);
+        assert_eq!(caches.purge(&patterns("/**")), 2);
+    }
+}
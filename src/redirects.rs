@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use tracing::{debug, error, info};
+
+use crate::paths::normalize_request_path;
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Deserialize)]
+struct Redirect {
+    from: String,
+    to: String,
+    #[serde(default = "default_status")]
+    status: u16,
+}
+
+#[derive(Deserialize)]
+struct RedirectFile {
+    #[serde(default, rename = "redirect")]
+    redirects: Vec<Redirect>,
+}
+
+fn default_status() -> u16 {
+    301
+}
+
+/// Old → new URL map loaded from a TOML or CSV file and reloaded when the file changes.
+///
+/// Sources are matched the way files are resolved: percent-decoded, without empty or `.`
+/// segments, and ignoring case when `case_insensitive` is set.
+pub struct Redirects {
+    path: PathBuf,
+    case_insensitive: bool,
+    map: RwLock<HashMap<String, (String, StatusCode)>>,
+    modified: RwLock<Option<SystemTime>>,
+}
+
+impl Redirects {
+    pub fn load(path: &str, case_insensitive: bool) -> Result<Arc<Self>> {
+        let path = PathBuf::from(path);
+        let map = read_redirects(&path, case_insensitive)?;
+        Ok(Arc::new(Self {
+            modified: RwLock::new(modified_time(&path)),
+            map: RwLock::new(map),
+            case_insensitive,
+            path,
+        }))
+    }
+
+    /// Polls the file for changes in the background and reloads it.
+    pub fn watch(self: &Arc<Self>) {
+        let redirects = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            loop {
+                interval.tick().await;
+                redirects.reload_if_changed();
+            }
+        });
+    }
+
+    fn reload_if_changed(&self) {
+        let modified = modified_time(&self.path);
+        if modified == *self.modified.read().unwrap() {
+            return;
+        }
+        *self.modified.write().unwrap() = modified;
+
+        match read_redirects(&self.path, self.case_insensitive) {
+            Ok(map) => {
+                info!(count = map.len(), "Reloaded redirects from {:?}", self.path);
+                *self.map.write().unwrap() = map;
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    fn find(&self, path: &str) -> Option<(String, StatusCode)> {
+        let key = source_key(path, self.case_insensitive)?;
+        self.map.read().unwrap().get(&key).cloned()
+    }
+}
+
+/// The form a request path or redirect source is looked up by, or `None` when it can't
+/// be decoded.
+fn source_key(path: &str, case_insensitive: bool) -> Option<String> {
+    let path = format!("/{}", normalize_request_path(path).ok()?);
+    Some(if case_insensitive {
+        path.to_lowercase()
+    } else {
+        path
+    })
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read_redirects(
+    path: &PathBuf,
+    case_insensitive: bool,
+) -> Result<HashMap<String, (String, StatusCode)>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read redirects file {:?}: {}", path, e))?;
+    let redirects = if path.extension().is_some_and(|e| e == "csv") {
+        parse_csv(&content)
+    } else {
+        toml::from_str::<RedirectFile>(&content)
+            .map(|f| f.redirects)
+            .map_err(|e| anyhow!("Failed to parse redirects file {:?}: {}", path, e))
+    }?;
+    redirect_map(redirects, case_insensitive)
+}
+
+fn redirect_map(
+    redirects: Vec<Redirect>,
+    case_insensitive: bool,
+) -> Result<HashMap<String, (String, StatusCode)>> {
+    let mut map = HashMap::new();
+    for redirect in redirects {
+        let status = StatusCode::from_u16(redirect.status)
+            .ok()
+            .filter(|s| s.is_redirection())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid redirect status {} for '{}'",
+                    redirect.status,
+                    redirect.from
+                )
+            })?;
+        let key = source_key(&redirect.from, case_insensitive)
+            .ok_or_else(|| anyhow!("Invalid redirect source '{}'", redirect.from))?;
+        if map.insert(key, (redirect.to, status)).is_some() {
+            return Err(anyhow!("Duplicate redirect for '{}'", redirect.from));
+        }
+    }
+    Ok(map)
+}
+
+/// Parses `from,to[,status]` lines. Fields containing commas can be wrapped in double
+/// quotes, with `""` for a quote. Empty lines and lines starting with `#` are skipped.
+fn parse_csv(content: &str) -> Result<Vec<Redirect>> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let fields =
+                csv_fields(line).ok_or_else(|| anyhow!("Unterminated quote on line {}", number))?;
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            let status = match fields.get(2) {
+                Some(s) => s
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid redirect status on line {}", number))?,
+                None => default_status(),
+            };
+            match fields[..] {
+                [from, to, ..] if fields.len() <= 3 => Ok(Redirect {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    status,
+                }),
+                _ => Err(anyhow!("Invalid redirect on line {}", number)),
+            }
+        })
+        .collect()
+}
+
+/// Splits a CSV line into trimmed fields, or returns `None` when a quote isn't closed.
+fn csv_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field.trim().to_string());
+    Some(fields)
+}
+
+pub async fn apply_redirects(
+    State(redirects): State<Arc<Redirects>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let uri = request.uri();
+    let Some((mut location, status)) = redirects.find(uri.path()) else {
+        return next.run(request).await;
+    };
+
+    if let Some(query) = uri.query().filter(|_| !location.contains('?')) {
+        location.push('?');
+        location.push_str(query);
+    }
+    debug!(from = %uri.path(), to = %location, "Redirecting");
+    Response::builder()
+        .status(status)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn redirects(csv: &str, case_insensitive: bool) -> Result<Arc<Redirects>> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects.csv");
+        fs::write(&path, csv).unwrap();
+        Redirects::load(&path.to_string_lossy(), case_insensitive)
+    }
+
+    #[test]
+    fn parses_csv() {
+        let parsed = parse_csv(
+            "# old blog\n\n/old-post, /blog/new-post\n\"/a,b\",\"/say \"\"hi\"\"\",302\n",
+        )
+        .unwrap();
+        let parsed: Vec<_> = parsed
+            .iter()
+            .map(|r| (r.from.as_str(), r.to.as_str(), r.status))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("/old-post", "/blog/new-post", 301),
+                ("/a,b", "/say \"hi\"", 302),
+            ]
+        );
+        assert!(parse_csv("/a,/b,temporary").is_err());
+        assert!(parse_csv("/a,/b,302,extra").is_err());
+        assert!(parse_csv("/a").is_err());
+        assert!(parse_csv("\"/a,/b").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_statuses_and_duplicate_sources() {
+        assert!(redirects("/a,/b,200", false).is_err());
+        assert!(redirects("/a,/b,999", false).is_err());
+        assert!(redirects("/a,/b\n/a,/c", false).is_err());
+        assert!(redirects("/a,/b\n/%61,/c", false).is_err());
+        assert!(redirects("/About,/b\n/about,/c", false).is_ok());
+        assert!(redirects("/About,/b\n/about,/c", true).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirects.toml");
+        fs::write(
+            &path,
+            "[[redirect]]\nfrom = \"/old\"\nto = \"/new\"\nstatus = 308\n",
+        )
+        .unwrap();
+        let redirects = Redirects::load(&path.to_string_lossy(), false).unwrap();
+        assert_eq!(
+            redirects.find("/old"),
+            Some(("/new".to_string(), StatusCode::PERMANENT_REDIRECT))
+        );
+        fs::write(&path, "[[redirect]]\nfrom = \"/old\"\n").unwrap();
+        assert!(Redirects::load(&path.to_string_lossy(), false).is_err());
+    }
+
+    #[test]
+    fn matches_normalized_paths() {
+        let map = redirects("/old/page,/new\n/Caf%C3%A9,/coffee", false).unwrap();
+        for path in ["/old/page", "/%6Fld/page", "//old/page", "/./old//page"] {
+            assert!(map.contains(path), "{}", path);
+        }
+        assert!(map.contains("/Café"));
+        assert!(!map.contains("/OLD/page"));
+        assert!(!map.contains("/old/page/"));
+        assert!(!map.contains("/old/%ZZ"));
+
+        let map = redirects("/Old/Page,/new", true).unwrap();
+        assert!(map.contains("/old/PAGE"));
+    }
+
+    #[tokio::test]
+    async fn redirects_with_the_query_string() {
+        let map = redirects("/old,/new\n/search,/find?q=all", false).unwrap();
+        let router: Router = Router::new()
+            .route("/{*path}", get(|| async { "page" }))
+            .layer(axum::middleware::from_fn_with_state(map, apply_redirects));
+        let location = |uri: &'static str| {
+            let router = router.clone();
+            async move {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                let response = router.oneshot(request).await.unwrap();
+                response
+                    .headers()
+                    .get(header::LOCATION)
+                    .map(|v| v.to_str().unwrap().to_owned())
+            }
+        };
+        assert_eq!(location("//old?ref=x").await.as_deref(), Some("/new?ref=x"));
+        assert_eq!(
+            location("/search?q=x").await.as_deref(),
+            Some("/find?q=all")
+        );
+        assert_eq!(location("/other").await, None);
+    }
+}
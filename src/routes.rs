@@ -0,0 +1,289 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use tracing::info;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    catalog::{Fingerprint, fingerprint},
+    config::Config,
+    events::{ContentChange, Events},
+    paths::{is_special_page, page_url, walk},
+};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Every file and directory in the pages directory, rescanned when it changes, so
+/// requests for paths that can't be pages are answered without touching the disk.
+pub struct Routes {
+    pages_dir: PathBuf,
+    index_files: Vec<String>,
+    case_insensitive: bool,
+    root: RwLock<Arc<Node>>,
+    fingerprint: RwLock<Fingerprint>,
+}
+
+#[derive(Default)]
+struct Node {
+    /// The file name as it is on disk.
+    name: String,
+    is_dir: bool,
+    /// When a directory was last modified as of the scan, which changes when an entry
+    /// is added to it.
+    modified: Option<SystemTime>,
+    /// Entries of a directory by [`Routes::key`].
+    children: BTreeMap<String, Node>,
+}
+
+impl Routes {
+    pub fn load(config: &Config) -> Arc<Self> {
+        let routes = Self {
+            pages_dir: PathBuf::from(&config.pages_dir),
+            index_files: config.index_files.clone(),
+            case_insensitive: config.case_insensitive,
+            root: RwLock::new(Arc::default()),
+            fingerprint: RwLock::new(Fingerprint::default()),
+        };
+        routes.reload_if_changed();
+        Arc::new(routes)
+    }
+
+    /// Rescans the pages directory when it changes, announcing the changes to `events`.
+    pub fn watch(self: &Arc<Self>, events: Option<Arc<Events>>) {
+        let routes = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            loop {
+                interval.tick().await;
+                let routes = Arc::clone(&routes);
+                let change = tokio::task::spawn_blocking(move || routes.reload_if_changed()).await;
+                if let (Some(events), Ok(Some(change))) = (&events, change) {
+                    events.publish(change);
+                }
+            }
+        });
+    }
+
+    fn reload_if_changed(&self) -> Option<ContentChange> {
+        let fingerprint = fingerprint(&self.pages_dir);
+        if fingerprint == *self.fingerprint.read().unwrap() {
+            return None;
+        }
+        let first = *self.fingerprint.read().unwrap() == Fingerprint::default();
+        *self.fingerprint.write().unwrap() = fingerprint;
+        let before: BTreeSet<_> = self.pages().into_iter().collect();
+        *self.root.write().unwrap() = Arc::new(self.scan(&self.pages_dir));
+        if first {
+            return None;
+        }
+        let after: BTreeSet<_> = self.pages().into_iter().collect();
+        let mut change = ContentChange {
+            dir: "pages",
+            added: Vec::new(),
+            removed: Vec::new(),
+        };
+        for (url, file) in after.difference(&before) {
+            info!(file = %file.display(), "Page added: {}", url);
+            change.added.push(url.clone());
+        }
+        for (url, file) in before.difference(&after) {
+            info!(file = %file.display(), "Page removed: {}", url);
+            change.removed.push(url.clone());
+        }
+        Some(change)
+    }
+
+    fn scan(&self, dir: &Path) -> Node {
+        let mut root = Node {
+            is_dir: true,
+            modified: modified(dir),
+            ..Node::default()
+        };
+        walk(dir, &mut |path, metadata| {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                return;
+            };
+            let names: Vec<_> = relative
+                .iter()
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            let Some((name, parents)) = names.split_last() else {
+                return;
+            };
+            // Directories are visited before their contents, so the parents are there.
+            let mut node = &mut root;
+            for parent in parents {
+                match node.children.get_mut(&self.key(parent)) {
+                    Some(child) => node = child,
+                    None => return,
+                }
+            }
+            node.children.insert(
+                self.key(name),
+                Node {
+                    name: name.clone(),
+                    is_dir: metadata.is_dir(),
+                    modified: metadata.modified().ok().filter(|_| metadata.is_dir()),
+                    children: BTreeMap::new(),
+                },
+            );
+        });
+        root
+    }
+
+    /// Request paths are NFC, and may be matched regardless of case.
+    fn key(&self, name: &str) -> String {
+        let name: String = name.nfc().collect();
+        if self.case_insensitive {
+            name.to_lowercase()
+        } else {
+            name
+        }
+    }
+
+    /// Whether a file or directory may exist at `relative`, a normalized request path.
+    ///
+    /// Entries added since the last scan change the modification time of their
+    /// directory, so a path that isn't known costs one look at the disk to tell.
+    pub fn contains(&self, relative: &str) -> bool {
+        let root = self.root.read().unwrap().clone();
+        let mut node = root.as_ref();
+        let mut dir = self.pages_dir.clone();
+        for segment in relative.split('/').filter(|s| !s.is_empty()) {
+            match node.children.get(&self.key(segment)) {
+                Some(child) => {
+                    dir.push(&child.name);
+                    node = child;
+                }
+                None => return node.is_dir && modified(&dir) != node.modified,
+            }
+        }
+        true
+    }
+
+    /// Content pages and the URLs they are served under, sorted by URL.
+    pub fn pages(&self) -> Vec<(String, PathBuf)> {
+        let mut files = Vec::new();
+        collect_pages(&self.root.read().unwrap(), PathBuf::new(), &mut files);
+        let mut pages: Vec<_> = files
+            .into_iter()
+            .filter(|relative| !is_special_page(relative))
+            .map(|relative| (page_url(&relative, &self.index_files), relative))
+            .collect();
+        pages.sort();
+        pages
+    }
+}
+
+fn collect_pages(node: &Node, path: PathBuf, files: &mut Vec<PathBuf>) {
+    for child in node.children.values() {
+        let path = path.join(&child.name);
+        if child.is_dir {
+            collect_pages(child, path, files);
+        } else if path.extension().is_some_and(|e| e == "html") {
+            files.push(path);
+        }
+    }
+}
+
+/// Prints every page of the site and the file it's served from.
+pub fn print_routes(config: &Config) -> Result<()> {
+    let routes = Routes::load(config);
+    let pages = routes.pages();
+    let width = pages.iter().map(|(url, _)| url.len()).max().unwrap_or(0);
+    for (url, file) in &pages {
+        println!("{:width$}  {}", url, file.display());
+    }
+    Ok(())
+}
+
+/// How many pages and static assets the site has, e.g. `42 pages, 310 static assets`.
+pub fn summary(config: &Config) -> String {
+    let pages = Routes::load(config).pages().len();
+    let assets = count_files(Path::new(&config.static_dir));
+    format!(
+        "{} page{}, {} static asset{}",
+        pages,
+        if pages == 1 { "" } else { "s" },
+        assets,
+        if assets == 1 { "" } else { "s" }
+    )
+}
+
+fn count_files(dir: &Path) -> usize {
+    let mut count = 0;
+    walk(dir, &mut |_, metadata| {
+        if !metadata.is_dir() {
+            count += 1;
+        }
+    });
+    count
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knows_which_paths_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Docs")).unwrap();
+        fs::write(dir.path().join("index.html"), "").unwrap();
+        fs::write(dir.path().join("Docs/intro.html"), "").unwrap();
+        fs::write(dir.path().join("not-found.html"), "").unwrap();
+        let config = Config {
+            pages_dir: dir.path().to_string_lossy().into_owned(),
+            static_dir: dir.path().join("Docs").to_string_lossy().into_owned(),
+            case_insensitive: true,
+            ..Config::default()
+        };
+        let routes = Routes::load(&config);
+
+        assert!(routes.contains("docs/"));
+        assert!(routes.contains("docs/INTRO.html"));
+        assert!(!routes.contains("docs/intro"));
+        assert!(!routes.contains("wp-admin/setup.php"));
+        assert_eq!(
+            routes.pages(),
+            vec![
+                ("/".to_string(), PathBuf::from("index.html")),
+                ("/Docs/intro".to_string(), PathBuf::from("Docs/intro.html")),
+            ]
+        );
+        assert_eq!(summary(&config), "2 pages, 1 static asset");
+    }
+
+    #[test]
+    fn finds_pages_added_since_the_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("docs/intro.html"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("docs/up")).unwrap();
+        let config = Config {
+            pages_dir: dir.path().to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        let routes = Routes::load(&config);
+        assert!(!routes.contains("docs/guide.html"));
+        // The link back up is left out rather than scanned forever.
+        #[cfg(unix)]
+        assert!(!routes.contains("docs/up/docs/up"));
+
+        fs::write(dir.path().join("docs/guide.html"), "").unwrap();
+        fs::create_dir_all(dir.path().join("blog")).unwrap();
+        assert!(routes.contains("docs/guide.html"));
+        assert!(routes.contains("blog/"));
+        assert!(!routes.contains("docs/intro.html/more"));
+    }
+}
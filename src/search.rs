@@ -0,0 +1,498 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    Index, IndexReader, IndexWriter, TantivyDocument, Term,
+    collector::{Count, TopDocs},
+    directory::MmapDirectory,
+    doc,
+    query::{AllQuery, QueryParser},
+    schema::{Field, STORED, STRING, Schema, TEXT, Value},
+    snippet::SnippetGenerator,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::Config,
+    front_matter, html,
+    paths::{content_pages, page_url},
+};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+const WRITER_MEMORY: usize = 50_000_000;
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Full-text index over the pages directory, kept in memory or in `index_dir`.
+pub struct Search {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    url: Field,
+    title: Field,
+    body: Field,
+    modified: Field,
+    pages_dir: PathBuf,
+    index_files: Vec<String>,
+    drafts: bool,
+    include: Box<dyn Fn(&str) -> bool + Send + Sync>,
+    /// Modification time of every indexed page, by URL.
+    indexed: Mutex<HashMap<String, u64>>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    q: String,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SearchResults {
+    query: String,
+    total: usize,
+    results: Vec<SearchResult>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    url: String,
+    title: String,
+    /// Matching excerpt as HTML, with matched terms wrapped in `<b>`.
+    snippet: String,
+}
+
+impl Search {
+    /// Opens the index without updating it. Only pages whose URL passes `include` get indexed.
+    pub fn open(
+        config: &Config,
+        include: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let mut schema = Schema::builder();
+        schema.add_text_field("url", STRING | STORED);
+        schema.add_text_field("title", TEXT | STORED);
+        schema.add_text_field("body", TEXT | STORED);
+        schema.add_u64_field("modified", STORED);
+        let schema = schema.build();
+
+        let index = match &config.search.index_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)
+                    .map_err(|e| anyhow!("Failed to create index directory {}: {}", dir, e))?;
+                let directory = MmapDirectory::open(dir)
+                    .map_err(|e| anyhow!("Failed to open index directory {}: {}", dir, e))?;
+                Index::open_or_create(directory, schema.clone())
+                    .map_err(|e| anyhow!("Failed to open search index in {}: {}", dir, e))?
+            }
+            None => Index::create_in_ram(schema.clone()),
+        };
+        let writer = index
+            .writer::<TantivyDocument>(WRITER_MEMORY)
+            .map_err(|e| anyhow!("Failed to lock search index: {}", e))?;
+        let reader = index
+            .reader()
+            .map_err(|e| anyhow!("Failed to open search index: {}", e))?;
+
+        let field = |name| schema.get_field(name).expect("field is in the schema");
+        let search = Self {
+            url: field("url"),
+            title: field("title"),
+            body: field("body"),
+            modified: field("modified"),
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            pages_dir: PathBuf::from(&config.pages_dir),
+            index_files: config.index_files.clone(),
+            drafts: config.drafts,
+            include: Box::new(include),
+            indexed: Mutex::new(HashMap::new()),
+        };
+        *search.indexed.lock().unwrap() = search
+            .stored_pages()
+            .map_err(|e| anyhow!("Failed to read search index: {}", e))?;
+        Ok(search)
+    }
+
+    /// Reindexes pages that changed since they were indexed and drops removed ones.
+    /// Returns the number of pages added, updated or removed.
+    pub fn update(&self) -> Result<usize> {
+        let mut indexed = self.indexed.lock().unwrap();
+        let mut writer = self.writer.lock().unwrap();
+        let mut seen = HashMap::new();
+        let mut changes = 0;
+
+        for relative in content_pages(&self.pages_dir) {
+            let path = self.pages_dir.join(&relative);
+            let url = page_url(&relative, &self.index_files);
+            if !(self.include)(&url) {
+                continue;
+            }
+            let modified = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos() as u64);
+            seen.insert(url.clone(), modified);
+            if indexed.get(&url) == Some(&modified) {
+                continue;
+            }
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("failed to read {} for indexing: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let (front_matter, content) = front_matter::split(&content);
+            // Scheduled pages are picked up by a later update once they are due.
+            if !self.drafts && front_matter.is_hidden(&relative) {
+                seen.remove(&url);
+                continue;
+            }
+            let title = front_matter
+                .title
+                .or_else(|| html::title(content))
+                .unwrap_or_else(|| url.clone());
+            writer.delete_term(Term::from_field_text(self.url, &url));
+            writer
+                .add_document(doc!(
+                    self.url => url,
+                    self.title => title,
+                    self.body => html::to_text(content),
+                    self.modified => modified,
+                ))
+                .map_err(|e| anyhow!("Failed to index {}: {}", path.display(), e))?;
+            changes += 1;
+        }
+        for url in indexed.keys().filter(|url| !seen.contains_key(*url)) {
+            writer.delete_term(Term::from_field_text(self.url, url));
+            changes += 1;
+        }
+
+        if changes > 0 {
+            writer
+                .commit()
+                .map_err(|e| anyhow!("Failed to commit search index: {}", e))?;
+            self.reader
+                .reload()
+                .map_err(|e| anyhow!("Failed to reload search index: {}", e))?;
+        }
+        *indexed = seen;
+        Ok(changes)
+    }
+
+    /// Drops everything and indexes all pages from scratch.
+    pub fn rebuild(&self) -> Result<usize> {
+        {
+            let mut writer = self.writer.lock().unwrap();
+            writer
+                .delete_all_documents()
+                .and_then(|_| writer.commit())
+                .map_err(|e| anyhow!("Failed to clear search index: {}", e))?;
+        }
+        self.indexed.lock().unwrap().clear();
+        self.update()
+    }
+
+    /// Keeps the index up to date as pages change, starting with an update right away.
+    pub fn watch(self: &Arc<Self>) {
+        let search = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            loop {
+                interval.tick().await;
+                Arc::clone(&search).update_in_background().await;
+            }
+        });
+    }
+
+    /// Runs [`Search::update`] on the blocking pool and logs the outcome.
+    pub async fn update_in_background(self: Arc<Self>) {
+        match tokio::task::spawn_blocking(move || self.update()).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(changes)) => info!(changes, "Updated search index"),
+            Ok(Err(e)) => error!("{}", e),
+            Err(e) => error!("search index update failed: {}", e),
+        }
+    }
+
+    /// Reads the URL and modification time of every stored page.
+    fn stored_pages(&self) -> tantivy::Result<HashMap<String, u64>> {
+        let searcher = self.reader.searcher();
+        let limit = usize::try_from(searcher.num_docs()).unwrap_or(usize::MAX);
+        let mut pages = HashMap::new();
+        if limit == 0 {
+            return Ok(pages);
+        }
+        for (_, address) in
+            searcher.search(&AllQuery, &TopDocs::with_limit(limit).order_by_score())?
+        {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let url = document.get_first(self.url).and_then(|v| v.as_str());
+            let modified = document.get_first(self.modified).and_then(|v| v.as_u64());
+            if let (Some(url), Some(modified)) = (url, modified) {
+                pages.insert(url.to_string(), modified);
+            }
+        }
+        Ok(pages)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> tantivy::Result<SearchResults> {
+        let searcher = self.reader.searcher();
+        let parser = QueryParser::for_index(&self.index, vec![self.title, self.body]);
+        // Typos in query syntax shouldn't fail the search, so parse leniently.
+        let (parsed, _) = parser.parse_query_lenient(query);
+        let (total, top) = searcher.search(
+            &parsed,
+            &(Count, TopDocs::with_limit(limit).order_by_score()),
+        )?;
+        let mut snippets = SnippetGenerator::create(&searcher, &*parsed, self.body)?;
+        snippets.set_max_num_chars(200);
+
+        let mut results = Vec::with_capacity(top.len());
+        for (_, address) in top {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let field = |f| {
+                document
+                    .get_first(f)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            results.push(SearchResult {
+                url: field(self.url),
+                title: field(self.title),
+                snippet: snippets.snippet_from_doc(&document).to_html(),
+            });
+        }
+        Ok(SearchResults {
+            query: query.to_string(),
+            total,
+            results,
+        })
+    }
+}
+
+/// Answers `GET /search?q=<query>[&limit=<n>]` with JSON results.
+pub async fn handle_search(
+    State(search): State<Arc<Search>>,
+    Query(params): Query<SearchParams>,
+) -> Response {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let result = tokio::task::spawn_blocking(move || search.search(params.q.trim(), limit)).await;
+    match result {
+        Ok(Ok(results)) => Json(results).into_response(),
+        Ok(Err(e)) => {
+            error!("search failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("search task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, body::to_bytes, http::Request, routing::get};
+    use serde_json::{Value, json};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{auth::Auth, config::SessionConfig};
+
+    /// A site with a few pages, one of them a draft and one behind `[[auth]]`.
+    fn site() -> (TempDir, Config) {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        fs::create_dir_all(pages.join("private")).unwrap();
+        fs::write(
+            pages.join("index.html"),
+            "<title>Home</title><p>Lime serves static sites quickly.</p>",
+        )
+        .unwrap();
+        fs::write(
+            pages.join("guide.html"),
+            "<title>Guide</title><p>Install lime with cargo, then run lime serve.</p>",
+        )
+        .unwrap();
+        fs::write(
+            pages.join("upcoming.html"),
+            "+++\ndraft = true\n+++\n<title>Upcoming</title><p>Lime draft notes.</p>",
+        )
+        .unwrap();
+        fs::write(
+            pages.join("private/plans.html"),
+            "<title>Plans</title><p>Secret lime plans.</p>",
+        )
+        .unwrap();
+        let mut config: Config = toml::from_str(
+            r#"
+            [[auth]]
+            pattern = "/private/**"
+            method = "bearer"
+            credentials = ["t0ken"]
+            "#,
+        )
+        .unwrap();
+        config.pages_dir = pages.to_string_lossy().into_owned();
+        (dir, config)
+    }
+
+    fn open(config: &Config) -> Arc<Search> {
+        let auth = Auth::new(&config.auth_rules, &SessionConfig::default(), false).unwrap();
+        let search = Search::open(config, move |url| !auth.is_protected(url)).unwrap();
+        search.update().unwrap();
+        Arc::new(search)
+    }
+
+    async fn query(search: &Arc<Search>, uri: &str) -> (StatusCode, Value) {
+        let router = Router::new().route("/search", get(handle_search).with_state(search.clone()));
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn answers_with_results_and_snippets() {
+        let (_dir, config) = site();
+        let search = open(&config);
+        let (status, results) = query(&search, "/search?q=cargo").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            results,
+            json!({
+                "query": "cargo",
+                "total": 1,
+                "results": [{
+                    "url": "/guide",
+                    "title": "Guide",
+                    "snippet": "Install lime with <b>cargo</b>, then run lime serve",
+                }],
+            })
+        );
+
+        let (_, results) = query(&search, "/search?q=lime&limit=1").await;
+        assert_eq!(results["total"], 2);
+        assert_eq!(results["results"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn needs_a_query() {
+        let (_dir, config) = site();
+        let search = open(&config);
+        for uri in ["/search?q=", "/search?q=%20%20"] {
+            let (status, results) = query(&search, uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(results["total"], 0, "{}", uri);
+            assert_eq!(results["results"], json!([]), "{}", uri);
+        }
+        let (status, _) = query(&search, "/search").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn leaves_out_drafts_and_protected_pages() {
+        let (_dir, mut config) = site();
+        let search = open(&config);
+        let (_, results) = query(&search, "/search?q=lime").await;
+        let urls: Vec<_> = results["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["url"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(urls.len(), 2);
+        assert!(!urls.contains(&"/upcoming".to_string()));
+        assert!(!urls.contains(&"/private/plans".to_string()));
+
+        config.drafts = true;
+        let search = open(&config);
+        let (_, results) = query(&search, "/search?q=draft").await;
+        assert_eq!(results["results"][0]["url"], "/upcoming");
+    }
+
+    fn urls(search: &Search, query: &str) -> Vec<String> {
+        let mut urls: Vec<_> = search
+            .search(query, 10)
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|result| result.url)
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    #[test]
+    fn reindexes_changed_pages() {
+        let (dir, config) = site();
+        let search = open(&config);
+        assert_eq!(search.update().unwrap(), 0);
+        assert_eq!(urls(&search, "tarball"), Vec::<String>::new());
+
+        let guide = dir.path().join("pages/guide.html");
+        fs::write(&guide, "<title>Guide</title><p>Download the tarball.</p>").unwrap();
+        assert_eq!(search.update().unwrap(), 1);
+        assert_eq!(urls(&search, "tarball"), ["/guide"]);
+        assert_eq!(urls(&search, "cargo"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn drops_deleted_pages() {
+        let (dir, config) = site();
+        let search = open(&config);
+        assert_eq!(urls(&search, "lime"), ["/", "/guide"]);
+
+        fs::remove_file(dir.path().join("pages/guide.html")).unwrap();
+        assert_eq!(search.update().unwrap(), 1);
+        assert_eq!(urls(&search, "lime"), ["/"]);
+        assert_eq!(urls(&search, "cargo"), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn index_command_rebuilds_from_scratch() {
+        let (dir, mut config) = site();
+        config.quiet = true;
+        config.search.index_dir = Some(dir.path().join("index").to_string_lossy().into_owned());
+        open(&config);
+
+        // An edit that keeps the modification time is invisible to `update`...
+        let guide = dir.path().join("pages/guide.html");
+        let modified = fs::metadata(&guide).unwrap().modified().unwrap();
+        fs::write(&guide, "<title>Guide</title><p>Download the tarball.</p>").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&guide)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let search = open(&config);
+        assert_eq!(urls(&search, "tarball"), Vec::<String>::new());
+        drop(search);
+
+        // ...but `lime index` reads every page again.
+        crate::commands::handle_index(&config).await.unwrap();
+        let search = open(&config);
+        assert_eq!(urls(&search, "tarball"), ["/guide"]);
+        assert_eq!(urls(&search, "cargo"), Vec::<String>::new());
+    }
+}
@@ -2,36 +2,185 @@ use anyhow::{Result, anyhow};
 use axum::{
     Router,
     body::Body,
-    extract::{Path, State},
-    http::{HeaderMap, HeaderValue, StatusCode, header},
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode, Uri, header},
+    middleware,
     response::Response,
     routing::get,
 };
 use colored::Colorize;
-use std::{path::PathBuf, sync::Arc};
-use tokio::{fs, net::TcpListener};
-use tracing::{debug, error, info, warn};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::fs;
+use tracing::{debug, error, instrument, warn};
 
-use crate::config::Config;
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use crate::{
+    access_log::{AccessLog, log_requests},
+    admin::{ADMIN_PREFIX, admin_router},
+    alerts::{ErrorAlerts, track_errors},
+    analytics::{Analytics, count_page_views},
+    auth::{Auth, authenticate},
+    blog::{Blog, blog_router},
+    bots::{BotFilter, filter_bots},
+    catalog::Catalog,
+    chaos::{Chaos, inject_chaos},
+    cluster,
+    config::{ByteSize, Config, EtagMode, LogOutput, TrailingSlash},
+    cors::{Cors, apply_cors},
+    csp::{Csp, apply_csp},
+    dashboard::{Dashboard, record_requests},
+    error_template::{ErrorTemplate, render_error_templates},
+    etag::{Etags, answer_not_modified},
+    events::{EVENTS_ROUTE, Events, handle_events},
+    file_cache::FileCache,
+    forms::forms_router,
+    front_matter,
+    handoff::{PidFile, bind, serve},
+    headers::{Headers, apply_headers},
+    honeypot::{Honeypot, trap_scanners},
+    html,
+    inject::Injector,
+    logging::init_logging,
+    metrics::{Metrics, handle_metrics, record_metrics},
+    mock_api::mock_api_router,
+    notify::Notifier,
+    paths::{PathError, is_dotfile, normalize_request_path, resolve_case_insensitive},
+    precompressed::Precompressed,
+    preload::Preloader,
+    priority::{self, Priority},
+    privacy,
+    probes::{HealthProbes, mark_health_probes},
+    proxy::proxy_router,
+    purge::Caches,
+    redirects::{Redirects, apply_redirects},
+    request_id::assign_request_id,
+    routes::{self, Routes},
+    search::{Search, handle_search},
+    sites::{Site, discover, landing_page},
+    sri::Integrity,
+    status::{ServerStatus, record_status},
+    store::{ContentStore, FsStore, StoreError},
+    suggest::Suggester,
+    taxonomy::taxonomy_router,
+    throttle::{Throttle, throttle_responses},
+    update,
+};
 
 const HTML_NOT_FOUND: &str = include_str!("../assets/not-found.html");
+const HTML_FORBIDDEN: &str = include_str!("../assets/forbidden.html");
 const HTML_INTERNAL_ERROR: &str = include_str!("../assets/internal-error.html");
 const HTML_DEFAULT_INDEX: &str = include_str!("../assets/index-page.html");
+const DEFAULT_FAVICON: &[u8] = include_bytes!("../assets/favicon.ico");
 
 #[derive(Clone)]
 pub struct AppState {
-    working_dir: PathBuf,
+    pages_dir: PathBuf,
+    static_dir: PathBuf,
+    mounts: Vec<Mount>,
+    /// The site folders listed at `/` in multi-site mode.
+    sites: Option<Arc<Vec<Site>>>,
+    trailing_slash: TrailingSlash,
+    case_insensitive: bool,
+    store: Arc<dyn ContentStore>,
+    max_file_size: Option<ByteSize>,
+    index_files: Vec<String>,
+    injector: Arc<Injector>,
+    integrity: Option<Arc<Integrity>>,
+    preloader: Option<Arc<Preloader>>,
+    favicon: bool,
+    serve_dotfiles: bool,
+    mask_forbidden: bool,
+    drafts: bool,
+    blog: Option<Arc<Blog>>,
+    routes: Arc<Routes>,
+    etags: Option<Arc<Etags>>,
+    priority: Option<Arc<Priority>>,
+    precompressed: Option<Arc<Precompressed>>,
+    legacy_charset: Option<String>,
+    cache_control: Option<HeaderValue>,
 }
 
-fn init_logging() {
-    tracing_subscriber::registry()
-        .with(EnvFilter::from_default_env())
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+/// Serves the site. With `tui`, a live dashboard takes the place of the banner and the
+/// console log.
+pub async fn start_server(config: &Config, tui: bool) -> Result<()> {
+    let listener = bind(
+        &format!("{}:{}", config.host, config.port),
+        config.reuse_port,
+    )
+    .await?;
+
+    let notifier = Notifier::new(config)?;
+    let analytics = Analytics::open(config)?;
+    let mut router = build_router(config, notifier.clone(), analytics.clone())?;
+    // Only taken over once this server is ready to answer requests in its place.
+    let _pid_file = config
+        .pid_file
+        .as_deref()
+        .map(|path| PidFile::create(path, config.reuse_port))
+        .transpose()?;
+
+    // Workers leave startup and shutdown notifications to the supervisor.
+    let lifecycle = notifier.filter(|_| !cluster::is_worker());
+    if let Some(analytics) = &analytics {
+        analytics.autosave();
+    }
+    let result = if tui {
+        let address = format!("http://{}:{}", config.host, config.port);
+        let dashboard = Arc::new(Dashboard::new(address, analytics.clone()));
+        router = router.layer(middleware::from_fn_with_state(
+            dashboard.clone(),
+            record_requests,
+        ));
+        // Logs printed to the console would scroll the dashboard away.
+        if !matches!(config.log_output, LogOutput::Stdout) {
+            init_logging(config)?;
+        }
+        notify_started(lifecycle.clone());
+        let drawing = tokio::spawn(dashboard.run());
+        let result = serve(listener, router).await;
+        drawing.abort();
+        Dashboard::close();
+        result
+    } else {
+        init_logging(config)?;
+        announce(config, "");
+        update::check_on_startup(config);
+        notify_started(lifecycle.clone());
+        serve(listener, router).await
+    };
+    if let Some(notifier) = lifecycle {
+        notifier.stopped().await;
+    }
+    if let Some(Err(e)) = analytics.map(|analytics| analytics.save()) {
+        error!("{}", e);
+    }
+    result
+}
+
+/// Tells the webhooks the server is up, without holding up requests.
+pub fn notify_started(notifier: Option<Arc<Notifier>>) {
+    if let Some(notifier) = notifier {
+        tokio::spawn(async move { notifier.started().await });
+    }
 }
 
-pub async fn start_server(config: &Config) -> Result<()> {
+/// Prints the banner and the address the site is available on, followed by `note`.
+pub fn announce(config: &Config, note: &str) {
+    if config.quiet {
+        return;
+    }
+    let address = format!("http://{}:{}{}", config.host, config.port, note);
+    if !config.banner {
+        println!("Available on: {}", address);
+        if config.route_summary {
+            println!("Serving {}", routes::summary(config));
+        }
+        return;
+    }
     println!(
         "\n {}{}",
         "\u{2F34B} Lime Web Server v".bright_green().bold(),
@@ -44,123 +193,677 @@ pub async fn start_server(config: &Config) -> Result<()> {
             "In order to configure Lime, create 'lime.toml' file in the current directory.".bold()
         );
     }
+    println!("    Available on: {}", address);
+    if config.chaos_enabled {
+        println!(
+            "    {} Responses are delayed, failed and dropped on purpose",
+            "".yellow().bold()
+        );
+    }
+    if config.route_summary {
+        println!("    Serving {}", routes::summary(config));
+    }
+    println!();
+}
 
-    let listener = TcpListener::bind(&format!("{}:{}", config.host, config.port))
-        .await
-        .map_err(|e| anyhow!(e.to_string()))?;
+/// Builds the router serving the site described by `config`, as `lime serve` would but
+/// without notifications or analytics, so requests can be sent to it in-process with
+/// `tower::ServiceExt::oneshot`.
+pub fn test_router(config: &Config) -> Result<Router> {
+    build_router(config, None, None)
+}
+
+/// Builds the router serving the site described by `config`.
+pub fn build_router(
+    config: &Config,
+    notifier: Option<Arc<Notifier>>,
+    analytics: Option<Arc<Analytics>>,
+) -> Result<Router> {
+    let mut mounts: Vec<Mount> = config
+        .mounts
+        .iter()
+        .map(|m| Mount {
+            route: m.route.trim_end_matches('/').to_string(),
+            dir: PathBuf::from(&m.dir),
+        })
+        .collect();
+    let sites = config
+        .multi_site
+        .then(|| discover(Path::new(&config.pages_dir)));
+    for site in sites.iter().flatten() {
+        let route = format!("/{}", site.name);
+        // A [[mount]] at the same route wins.
+        if !mounts.iter().any(|m| m.route == route) {
+            mounts.push(Mount {
+                route,
+                dir: Path::new(&config.pages_dir).join(&site.name),
+            });
+        }
+    }
+    // Longest routes first, so nested mounts take precedence.
+    mounts.sort_by_key(|m| std::cmp::Reverse(m.route.len()));
 
-    let working_dir = PathBuf::from(&config.working_dir);
-    let state = Arc::new(AppState { working_dir });
+    let symlink_targets = config
+        .symlink_targets
+        .iter()
+        .map(|dir| {
+            std::fs::canonicalize(dir)
+                .map_err(|e| anyhow!("Failed to resolve symlink target {}: {}", dir, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    let router = Router::new()
+    let auth = Arc::new(Auth::new(
+        &config.auth_rules,
+        &config.session,
+        config.case_insensitive,
+    )?);
+    let pages_dir = PathBuf::from(&config.pages_dir);
+    let catalog = (config.taxonomies || config.blog.is_some()).then(|| {
+        // Protected pages are never listed, so listings don't leak their titles.
+        let protected = auth.clone();
+        let catalog = Catalog::load(config, move |url| !protected.is_protected(url));
+        catalog.watch();
+        catalog
+    });
+    let blog = config
+        .blog
+        .as_ref()
+        .zip(catalog.clone())
+        .map(|(blog, catalog)| Blog::new(blog, catalog, pages_dir.clone()));
+
+    let events = config.events.then(|| {
+        // Protected pages are never announced, so the stream doesn't leak their URLs.
+        let protected = auth.clone();
+        let events = Events::new(move |url| !protected.is_protected(url));
+        events.watch_static(PathBuf::from(&config.static_dir));
+        events
+    });
+    let routes = Routes::load(config);
+    routes.watch(events.clone());
+
+    let file_cache = config.cache.enabled.then(|| {
+        Arc::new(FileCache::new(
+            config.cache.max_size,
+            Duration::from_secs(config.cache.stale_while_revalidate),
+        ))
+    });
+    let injector = Arc::new(Injector::new(&config.inject_rules)?);
+    let access_log = Arc::new(AccessLog::new(config)?);
+    let state = Arc::new(AppState {
+        pages_dir: pages_dir.clone(),
+        static_dir: PathBuf::from(&config.static_dir),
+        mounts,
+        sites: sites.map(Arc::new),
+        index_files: config.index_files.clone(),
+        trailing_slash: config.trailing_slash,
+        case_insensitive: config.case_insensitive,
+        store: Arc::new(FsStore::new(symlink_targets, file_cache.clone())),
+        max_file_size: config.max_file_size,
+        injector,
+        integrity: config
+            .sri
+            .then(|| Arc::new(Integrity::new(PathBuf::from(&config.static_dir)))),
+        preloader: config
+            .preload
+            .then(|| Arc::new(Preloader::new(PathBuf::from(&config.static_dir)))),
+        favicon: config.favicon,
+        serve_dotfiles: config.serve_dotfiles,
+        mask_forbidden: config.mask_forbidden,
+        drafts: config.drafts,
+        blog: blog.clone(),
+        routes,
+        etags: Etags::new(config.etag).map(Arc::new),
+        priority: Priority::new(&config.priority).map(Arc::new),
+        precompressed: Precompressed::new(&config.static_gzip_only)?.map(Arc::new),
+        legacy_charset: config.legacy_charset.clone(),
+        cache_control: (config.cache.enabled && config.cache.stale_while_revalidate > 0).then(
+            || {
+                HeaderValue::from_str(&format!(
+                    "max-age=0, stale-while-revalidate={}",
+                    config.cache.stale_while_revalidate
+                ))
+                .unwrap()
+            },
+        ),
+    });
+
+    let mut router = Router::new()
         .route("/", get(handle_index))
-        .route("/{*path}", get(handle_wildcard))
-        .with_state(state);
+        .route("/{*path}", get(handle_wildcard));
+    if config.search.enabled {
+        // Protected pages stay out of the index, so results never leak their content.
+        let protected = auth.clone();
+        let search = Arc::new(Search::open(config, move |url| {
+            !protected.is_protected(url)
+        })?);
+        // Indexing reads every page, so it runs in the background instead of holding up
+        // startup; until it finishes, searches answer from whatever the index already has.
+        if config.search.watch {
+            search.watch();
+        } else {
+            tokio::spawn(Arc::clone(&search).update_in_background());
+        }
+        router = router.route(&config.search.route, get(handle_search).with_state(search));
+    }
+    let metrics = config
+        .metrics
+        .enabled
+        .then(|| Arc::new(Metrics::new(config)));
+    if let Some(metrics) = &metrics {
+        router = router.route(
+            &config.metrics.route,
+            get(handle_metrics).with_state(metrics.clone()),
+        );
+    }
+    if let Some(catalog) = catalog.filter(|_| config.taxonomies) {
+        router = router.merge(taxonomy_router(catalog, pages_dir));
+    }
+    if let Some(blog) = blog {
+        router = router.merge(blog_router(blog));
+    }
+    if let Some(mock_api) = &config.mock_api {
+        router = router.merge(mock_api_router(mock_api));
+    }
+    if let Some(events) = events {
+        router = router.route(EVENTS_ROUTE, get(handle_events).with_state(events));
+    }
+    if !config.forms.is_empty() {
+        router = router.merge(forms_router(&config.forms)?);
+    }
+    let (proxy, response_caches) = proxy_router(&config.proxy_rules, &auth.claim_header_names())?;
+    router = router.merge(proxy);
+    let caches = Caches::new(config, file_cache, response_caches);
+    let status = Arc::new(ServerStatus::new(config));
+    let admin = admin_router(
+        config,
+        caches,
+        status.clone(),
+        notifier.clone(),
+        analytics.clone(),
+    );
+    // Requests are only counted for the status page when there is one.
+    let status = admin.is_some().then_some(status);
+    if let Some(admin) = admin {
+        router = router.nest(ADMIN_PREFIX, admin);
+    }
+    // The embedder's routes are tried first, and whatever they don't match is served by
+    // Lime, so they may take over any path, `/` included.
+    let mut router: Router = match &config.handlers {
+        Some(handlers) => handlers
+            .clone()
+            .fallback_service(router.with_state(state.clone())),
+        None => router.with_state(state.clone()),
+    };
+    if let Some(path) = &config.redirects_file {
+        let redirects = Redirects::load(path, config.case_insensitive)?;
+        redirects.watch();
+        router = router.layer(middleware::from_fn_with_state(redirects, apply_redirects));
+    }
+    let suggester = Arc::new(Suggester::new(
+        state.pages_dir.clone(),
+        config.index_files.clone(),
+    ));
+    let mut router = router.layer(middleware::from_fn_with_state(
+        suggester,
+        render_error_templates,
+    ));
+    if let Some(policy) = &config.csp {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(Csp::new(policy)?),
+            apply_csp,
+        ));
+    }
+    let headers = Headers::new(&config.header_rules)?;
+    if !headers.is_empty() {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(headers),
+            apply_headers,
+        ));
+    }
+    if !auth.is_empty() {
+        router = router.layer(middleware::from_fn_with_state(auth, authenticate));
+    }
+    // Preflight requests carry no credentials, so they are answered before authentication.
+    let cors = Cors::new(&config.cors_rules)?;
+    if !cors.is_empty() {
+        router = router.layer(middleware::from_fn_with_state(Arc::new(cors), apply_cors));
+    }
+    if let Some(metrics) = metrics {
+        router = router.layer(middleware::from_fn_with_state(metrics, record_metrics));
+    }
+    if let Some(analytics) = analytics {
+        router = router.layer(middleware::from_fn_with_state(analytics, count_page_views));
+    }
+    // Outside the counters, so pages served from the browser cache are still counted.
+    if config.etag != EtagMode::Off {
+        router = router.layer(middleware::from_fn(answer_not_modified));
+    }
+    if let Some(status) = status {
+        router = router.layer(middleware::from_fn_with_state(status, record_status));
+    }
+    if let Some(alerts) = ErrorAlerts::new(config, notifier) {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(alerts),
+            track_errors,
+        ));
+    }
+    // Outside the counters, so blocked bots don't show up in metrics or alerts.
+    if let Some(filter) = BotFilter::new(&config.bots) {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(filter),
+            filter_bots,
+        ));
+    }
+    if let Some(throttle) = Throttle::new(config)? {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(throttle),
+            throttle_responses,
+        ));
+    }
+    if let Some(honeypot) = Honeypot::new(&config.honeypot)? {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(honeypot),
+            trap_scanners,
+        ));
+    }
+    if let Some(chaos) = Chaos::new(config)? {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(chaos),
+            inject_chaos,
+        ));
+    }
+    let mut router = router.layer(middleware::from_fn_with_state(access_log, log_requests));
+    if let Some(probes) = HealthProbes::new(&config.health_probes)? {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(probes),
+            mark_health_probes,
+        ));
+    }
+    Ok(router.layer(middleware::from_fn(assign_request_id)))
+}
 
-    init_logging();
-    println!("    Available on: http://{}:{}\n", config.host, config.port);
-    axum::serve(listener, router)
-        .await
-        .map_err(|e| anyhow!(e.to_string()))?;
+/// A directory exposed under a URL prefix.
+#[derive(Clone)]
+struct Mount {
+    route: String,
+    dir: PathBuf,
+}
+
+impl AppState {
+    /// Finds the mount serving `path` and returns it with the remaining relative path.
+    fn find_mount<'a>(&self, path: &'a str) -> Option<(&Mount, &'a str)> {
+        self.mounts.iter().find_map(|mount| {
+            let rest = path.strip_prefix(mount.route.as_str())?;
+            if rest.is_empty() || rest.starts_with('/') {
+                Some((mount, rest.trim_start_matches('/')))
+            } else {
+                None
+            }
+        })
+    }
 
-    Ok(())
+    /// Joins `relative` onto `base`, falling back to a case-insensitive match when enabled.
+    fn resolve(&self, base: &std::path::Path, relative: &str) -> PathBuf {
+        let path = base.join(relative);
+        if !self.case_insensitive || path.exists() {
+            return path;
+        }
+        resolve_case_insensitive(base, std::path::Path::new(relative)).unwrap_or(path)
+    }
+
+    /// Returns the first configured index file that exists in `dir`.
+    fn find_index(&self, dir: &std::path::Path) -> Option<PathBuf> {
+        self.index_files
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
 }
 
-pub async fn handle_index(State(state): State<Arc<AppState>>) -> Response {
-    let path = &state.working_dir.join("index.html");
-    if !path.exists() {
-        Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "text/html")
-            .body(Body::from(HTML_DEFAULT_INDEX))
-            .unwrap()
-    } else {
-        serve_file(
-            &state.working_dir.join("index.html"),
-            &state.working_dir,
-            true,
-        )
-        .await
+pub async fn handle_index(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let opted_out = privacy::opted_out(&headers);
+    match state.find_index(&state.pages_dir) {
+        Some(path) => serve_file(&state, &state.pages_dir, &path, "/", true, opted_out, None).await,
+        None => match &state.sites {
+            Some(sites) => landing_page(sites, &state.pages_dir).await,
+            None => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/html")
+                .body(Body::from(HTML_DEFAULT_INDEX))
+                .unwrap(),
+        },
     }
 }
 
 pub async fn handle_wildcard(
-    Path(path): Path<String>,
     State(state): State<Arc<AppState>>,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> Response {
-    info!(requested_path = %path, "Handling request");
+    let opted_out = privacy::opted_out(&headers);
+    let accept_encoding = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok());
+    // Decode the raw path ourselves, so it is decoded exactly once and checked for traversal.
+    // The query string never takes part in file resolution: `/style.css?v=123` is `style.css`.
+    let path = match normalize_request_path(uri.path()) {
+        Ok(path) => path,
+        Err(PathError::Traversal) => {
+            warn!("Path traversal attempt: {}", uri.path());
+            return not_found(&state.pages_dir).await;
+        }
+        Err(PathError::InvalidEncoding) => {
+            debug!("Malformed request path: {}", uri.path());
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+
+    if !state.serve_dotfiles && is_dotfile(&path) {
+        debug!(path = %path, "Refusing to serve a dotfile");
+        return forbidden(&state).await;
+    }
+
     let extension = PathBuf::from(&path)
         .extension()
         .and_then(|e| e.to_str())
         .unwrap_or("html")
         .to_lowercase();
 
+    let request_path = format!("/{}", path);
+    if let Some((mount, rest)) = state.find_mount(&request_path) {
+        debug!(path = %path, mount = %mount.route, "Serving mounted file");
+        let mut file_path = state.resolve(&mount.dir, rest);
+        if file_path.is_dir() {
+            if !path.ends_with('/') && state.trailing_slash != TrailingSlash::Off {
+                return slash_redirect(&uri, true);
+            }
+            match state.find_index(&file_path) {
+                Some(index) => file_path = index,
+                None => return not_found(&state.pages_dir).await,
+            }
+        } else if path.ends_with('/')
+            && state.trailing_slash == TrailingSlash::Strict
+            && state
+                .resolve(&mount.dir, rest.trim_end_matches('/'))
+                .is_file()
+        {
+            return slash_redirect(&uri, false);
+        }
+        let is_text = mime_guess::from_path(&file_path)
+            .first()
+            .is_some_and(|m| m == mime_guess::mime::TEXT_HTML);
+        return serve_file(
+            &state,
+            &mount.dir,
+            &file_path,
+            &path,
+            is_text,
+            opted_out,
+            accept_encoding,
+        )
+        .await;
+    }
+
+    if path == "favicon.ico" && state.favicon && !state.resolve(&state.static_dir, &path).exists() {
+        debug!("Serving built-in favicon");
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "image/x-icon")
+            .body(Body::from(DEFAULT_FAVICON))
+            .unwrap();
+    }
+
+    if let Some(post) = state
+        .blog
+        .as_ref()
+        .and_then(|blog| blog.resolve_permalink(&request_path))
+    {
+        debug!(path = %path, post = ?post, "Serving post");
+        let file_path = state.pages_dir.join(post);
+        return serve_file(
+            &state,
+            &state.pages_dir,
+            &file_path,
+            &path,
+            true,
+            opted_out,
+            None,
+        )
+        .await;
+    }
+
     if extension.as_str() != "html" {
         debug!(path = %path, extension = %extension, "Serving static asset");
-        serve_file(&state.working_dir, &state.working_dir, false).await
+        serve_file(
+            &state,
+            &state.static_dir,
+            &state.resolve(&state.static_dir, &path),
+            &path,
+            false,
+            opted_out,
+            accept_encoding,
+        )
+        .await
     } else {
         debug!(path = %path, "Serving HTML file");
-        serve_html(&path, &state.working_dir).await
+        serve_html(&state, &path, &uri, opted_out).await
     }
 }
 
-async fn serve_html(path: &str, base_dir: &PathBuf) -> Response {
-    let mut html_path = base_dir.join(path);
-    if html_path.extension().is_none() {
-        html_path.set_extension("html");
+async fn serve_html(state: &AppState, path: &str, uri: &Uri, opted_out: bool) -> Response {
+    // Neither a page nor a directory of pages, so there is nothing to look for on disk.
+    let page = path.trim_end_matches('/');
+    if !state.routes.contains(page) && !state.routes.contains(&format!("{}.html", page)) {
+        return not_found(&state.pages_dir).await;
     }
-    serve_file(&html_path, base_dir, true).await
-}
+    let dir_path = state.resolve(&state.pages_dir, path);
+    let html_path = if std::path::Path::new(path).extension().is_none() {
+        let html_file = format!("{}.html", path.trim_end_matches('/'));
+        state.resolve(&state.pages_dir, &html_file)
+    } else {
+        dir_path.clone()
+    };
 
-async fn serve_file(file_path: &PathBuf, base_dir: &PathBuf, is_text: bool) -> Response {
-    let base_canonical = match fs::canonicalize(base_dir).await {
-        Ok(p) => p,
-        Err(e) => {
-            error!("Failed to canonicalize base dir: {}", e);
-            return internal_error(base_dir).await;
+    // `/docs/` always means the directory, `/docs` prefers `docs.html` if it exists.
+    let serve_dir = if path.ends_with('/') {
+        if !dir_path.is_dir()
+            && state.trailing_slash == TrailingSlash::Strict
+            && html_path.is_file()
+        {
+            return slash_redirect(uri, false);
+        }
+        dir_path.is_dir()
+    } else if !html_path.is_file() && dir_path.is_dir() {
+        if state.trailing_slash != TrailingSlash::Off {
+            return slash_redirect(uri, true);
         }
+        true
+    } else {
+        false
     };
 
-    let full_canonical = match fs::canonicalize(file_path).await {
-        Ok(p) => p,
-        Err(_) => return not_found(base_dir).await,
-    };
+    if serve_dir {
+        if let Some(index) = state.find_index(&dir_path) {
+            return serve_file(state, &state.pages_dir, &index, path, true, opted_out, None).await;
+        }
+        return not_found(&state.pages_dir).await;
+    }
+    // Posts with a permalink are only served there.
+    if let Some(permalink) = state.blog.as_ref().and_then(|blog| {
+        let relative = html_path.strip_prefix(&state.pages_dir).ok()?;
+        blog.permalink(relative)
+    }) {
+        return Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header(header::LOCATION, permalink)
+            .body(Body::empty())
+            .unwrap();
+    }
+    serve_file(
+        state,
+        &state.pages_dir,
+        &html_path,
+        path,
+        true,
+        opted_out,
+        None,
+    )
+    .await
+}
 
-    if !full_canonical.starts_with(&base_canonical) {
-        warn!("Path traversal attempt: {:?}", file_path);
-        return not_found(base_dir).await;
+/// Redirects to the canonical form of the requested URL, with or without a trailing slash.
+fn slash_redirect(uri: &Uri, add_slash: bool) -> Response {
+    let path = uri.path().trim_end_matches('/');
+    let mut location = if add_slash {
+        format!("{}/", path)
+    } else {
+        path.to_string()
+    };
+    if let Some(query) = uri.query() {
+        location.push('?');
+        location.push_str(query);
     }
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
 
-    let metadata = match fs::metadata(&full_canonical).await {
-        Ok(m) => m,
-        Err(_) => return not_found(base_dir).await,
+#[instrument(level = "debug", skip_all, fields(path = %request_path))]
+async fn serve_file(
+    state: &AppState,
+    base_dir: &Path,
+    file_path: &PathBuf,
+    request_path: &str,
+    is_text: bool,
+    opted_out: bool,
+    accept_encoding: Option<&str>,
+) -> Response {
+    let mut file = match state.store.open(base_dir, file_path).await {
+        Ok(file) => file,
+        Err(StoreError::NotFound) => return not_found(&state.pages_dir).await,
+        Err(StoreError::Outside) => {
+            warn!("Path traversal attempt: {:?}", file_path);
+            return forbidden(state).await;
+        }
+        Err(StoreError::Unavailable(e)) => {
+            error!("Failed to canonicalize base dir: {}", e);
+            return internal_error(&state.pages_dir).await;
+        }
     };
 
-    if metadata.is_dir() {
-        return not_found(base_dir).await;
+    // Decided by the file itself, not by the compressed copy it may be served from.
+    let mime_type = mime_guess::from_path(&file.path)
+        .first_or_octet_stream()
+        .to_string();
+    let varies_by_encoding = !is_text
+        && mime_type != "text/html"
+        && state.precompressed.as_ref().is_some_and(|precompressed| {
+            precompressed.applies(&format!("/{}", request_path.trim_start_matches('/')))
+        });
+    let mut encoding = None;
+    if varies_by_encoding
+        && let Some(precompressed) = &state.precompressed
+        && let Some(accept_encoding) = accept_encoding
+        && let Some((name, sidecar)) = precompressed
+            .sidecar(state.store.as_ref(), base_dir, &file, accept_encoding)
+            .await
+    {
+        debug!(file = ?sidecar.path, "Serving precompressed copy");
+        encoding = Some(name);
+        file = sidecar;
+    }
+
+    if let Some(max) = state.max_file_size
+        && file.len > max.0
+    {
+        warn!(
+            size = file.len,
+            limit = max.0,
+            "Refusing to serve {:?}: file exceeds max_file_size",
+            file.path
+        );
+        return forbidden(state).await;
     }
 
-    let content = if is_text {
-        match fs::read_to_string(&full_canonical).await {
-            Ok(s) => s.into_bytes(),
+    // Taken before reading, so large files don't fill the memory while they wait either.
+    let permit = match &state.priority {
+        Some(priority) => priority.turn(file.len).await,
+        None => None,
+    };
+    let content = state.store.read(&file).await;
+    let mut charset = None;
+    let mut content = if is_text {
+        let bytes = match content {
+            Ok(bytes) => bytes,
             Err(e) => {
                 error!("failed to read text file: {}", e);
-                return internal_error(base_dir).await;
+                return internal_error(&state.pages_dir).await;
+            }
+        };
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => {
+                let (front_matter, page) = front_matter::split(s);
+                let relative = file_path.strip_prefix(&state.pages_dir).ok();
+                if let Some(relative) = relative
+                    && !state.drafts
+                    && front_matter.is_hidden(relative)
+                {
+                    debug!("Hiding unpublished page {:?}", relative);
+                    return not_found(&state.pages_dir).await;
+                }
+                let page = page.to_string();
+                match (&state.blog, relative) {
+                    (Some(blog), Some(relative)) => blog.fill_navigation(relative, page),
+                    _ => page,
+                }
+                .into()
+            }
+            // Pages in legacy encodings such as Latin-1 or Shift-JIS are served as they are.
+            Err(_) => {
+                charset = html::declared_charset(&bytes).or_else(|| state.legacy_charset.clone());
+                debug!(charset = ?charset, "Serving {:?} as it is, it isn't UTF-8", file.path);
+                bytes
             }
         }
     } else {
-        match fs::read(&full_canonical).await {
+        match content {
             Ok(b) => b,
             Err(e) => {
                 error!("failed to read file: {}", e);
-                return internal_error(base_dir).await;
+                return internal_error(&state.pages_dir).await;
             }
         }
     };
 
-    let mime_type = mime_guess::from_path(&full_canonical)
-        .first_or_octet_stream()
-        .to_string();
+    let mut link = None;
+    if mime_type == "text/html" {
+        let request_path = format!("/{}", request_path.trim_start_matches('/'));
+        let mut html = Vec::from(content);
+        if !state.injector.is_empty() {
+            html = state.injector.apply(&request_path, html, opted_out);
+        }
+        if let Some(integrity) = &state.integrity {
+            html = match String::from_utf8(html) {
+                Ok(html) => integrity.apply(&request_path, &html).into_bytes(),
+                Err(e) => e.into_bytes(),
+            };
+        }
+        if let Some(preloader) = &state.preloader
+            && let Some(modified) = file.modified
+        {
+            link = preloader.link(&file.path, modified, &request_path, &html);
+        }
+        content = html.into();
+    }
+    let etag = state
+        .etags
+        .as_ref()
+        .map(|etags| etags.tag(&file, &content, is_text || mime_type == "text/html"));
 
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -169,14 +872,51 @@ async fn serve_file(file_path: &PathBuf, base_dir: &PathBuf, is_text: bool) -> R
             .unwrap_or(HeaderValue::from_static("application/octet-stream")),
     );
 
-    Response::builder()
+    let content_type = match &charset {
+        Some(charset) => format!("{}; charset={}", mime_type, charset),
+        None => mime_type.clone(),
+    };
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header("Content-Type", &mime_type)
-        .body(Body::from(content))
-        .unwrap()
+        .header("Content-Type", &content_type);
+    let body = match permit {
+        Some(permit) => {
+            response = response.header(header::CONTENT_LENGTH, content.len());
+            priority::body_holding(content, permit)
+        }
+        None => Body::from(content),
+    };
+    let mut response = response.body(body).unwrap();
+    if let Some(link) = link {
+        response.headers_mut().insert(header::LINK, link);
+    }
+    if let Some(etag) = etag {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    if let Some(encoding) = encoding {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+    if varies_by_encoding {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    if mime_type == "text/html" && state.injector.respects_privacy() {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("DNT, Sec-GPC"));
+    }
+    if let Some(cache_control) = &state.cache_control {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, cache_control.clone());
+    }
+    response
 }
 
-async fn not_found(base_dir: &PathBuf) -> Response {
+pub async fn not_found(base_dir: &PathBuf) -> Response {
     let not_found_html = base_dir.join("not-found.html");
 
     if !not_found_html.exists() {
@@ -198,19 +938,50 @@ async fn not_found(base_dir: &PathBuf) -> Response {
     Response::builder()
         .status(StatusCode::NOT_FOUND)
         .header("Content-Type", "text/html")
+        .extension(ErrorTemplate)
+        .body(Body::from(content))
+        .unwrap()
+}
+
+/// Answers a request that is denied with `forbidden.html` from the pages directory or the
+/// built-in page, or as not found with `mask_forbidden`.
+async fn forbidden(state: &AppState) -> Response {
+    if state.mask_forbidden {
+        return not_found(&state.pages_dir).await;
+    }
+    let forbidden_html = state.pages_dir.join("forbidden.html");
+    let content = match fs::read_to_string(&forbidden_html).await {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Response::builder()
+                .header("Content-Type", "text/html")
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from(HTML_FORBIDDEN))
+                .unwrap();
+        }
+        Err(e) => {
+            error!("failed to read text file: {}", e);
+            return internal_error(&state.pages_dir).await;
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", "text/html")
+        .status(StatusCode::FORBIDDEN)
+        .extension(ErrorTemplate)
         .body(Body::from(content))
         .unwrap()
 }
 
 #[allow(clippy::ptr_arg)]
 async fn internal_error(base_dir: &PathBuf) -> Response {
-    let not_found_html = base_dir.join("not-found.html");
+    let internal_error_html = base_dir.join("internal-error.html");
 
-    if !not_found_html.exists() {
+    if !internal_error_html.exists() {
         return default_internal_error().await;
     }
 
-    let content = match fs::read_to_string(&not_found_html).await {
+    let content = match fs::read_to_string(&internal_error_html).await {
         Ok(s) => s.into_bytes(),
         Err(e) => {
             error!("failed to read text file: {}", e);
@@ -220,7 +991,8 @@ async fn internal_error(base_dir: &PathBuf) -> Response {
 
     Response::builder()
         .header("Content-Type", "text/html")
-        .status(StatusCode::NOT_FOUND)
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .extension(ErrorTemplate)
         .body(Body::from(content))
         .unwrap()
 }
@@ -232,3 +1004,524 @@ async fn default_internal_error() -> Response {
         .body(Body::from(HTML_INTERNAL_ERROR))
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::to_bytes,
+        http::{Method, Request},
+    };
+    use std::fs;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::{CorsRule, HeaderRule, MountConfig};
+
+    struct Site {
+        root: tempfile::TempDir,
+        router: Router,
+    }
+
+    fn site() -> Site {
+        site_with(|_| {})
+    }
+
+    /// Builds the test site with `configure` applied to its configuration.
+    fn site_with(configure: impl FnOnce(&mut Config)) -> Site {
+        let root = tempfile::tempdir().unwrap();
+        let pages = root.path().join("pages");
+        let assets = root.path().join("static");
+        fs::create_dir_all(pages.join("docs")).unwrap();
+        fs::create_dir_all(&assets).unwrap();
+        fs::write(pages.join("about.html"), "about").unwrap();
+        fs::write(pages.join("caf\u{e9}.html"), "cafe").unwrap();
+        fs::write(pages.join("docs/index.html"), "docs").unwrap();
+        fs::write(assets.join("style.css"), "css").unwrap();
+        fs::write(root.path().join("secret.txt"), "secret").unwrap();
+        fs::write(root.path().join("secret.html"), "secret").unwrap();
+
+        let mut config = Config {
+            pages_dir: pages.to_string_lossy().into_owned(),
+            static_dir: assets.to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        configure(&mut config);
+        let router = build_router(&config, None, None).unwrap();
+        Site { root, router }
+    }
+
+    async fn get(site: &Site, uri: &str) -> (StatusCode, String) {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = site.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn serves_files_inside_the_root() {
+        let site = site();
+        assert_eq!(get(&site, "/about").await, (StatusCode::OK, "about".into()));
+        assert_eq!(
+            get(&site, "/style.css").await,
+            (StatusCode::OK, "css".into())
+        );
+        assert_eq!(get(&site, "/docs/").await, (StatusCode::OK, "docs".into()));
+        assert_eq!(
+            get(&site, "/%61bout").await,
+            (StatusCode::OK, "about".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_query_strings() {
+        let site = site();
+        assert_eq!(
+            get(&site, "/style.css?v=123").await,
+            (StatusCode::OK, "css".into())
+        );
+        assert_eq!(
+            get(&site, "/about?ref=home").await,
+            (StatusCode::OK, "about".into())
+        );
+        assert_eq!(
+            get(&site, "/about?../../secret").await,
+            (StatusCode::OK, "about".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_files_over_max_file_size() {
+        let mut site = site();
+        let config = Config {
+            pages_dir: site
+                .root
+                .path()
+                .join("pages")
+                .to_string_lossy()
+                .into_owned(),
+            static_dir: site
+                .root
+                .path()
+                .join("static")
+                .to_string_lossy()
+                .into_owned(),
+            max_file_size: Some(ByteSize(4)),
+            ..Config::default()
+        };
+        site.router = build_router(&config, None, None).unwrap();
+        assert_eq!(get(&site, "/style.css").await.0, StatusCode::OK);
+        assert_eq!(get(&site, "/about").await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn rejects_traversal() {
+        let site = site();
+        for uri in [
+            "/../secret.txt",
+            "/../secret",
+            "/%2e%2e/secret.txt",
+            "/%2e%2e%2fsecret.txt",
+            "/..%2fsecret",
+            "/docs/%2e%2e/%2e%2e/secret.html",
+            "/..%5csecret.txt",
+        ] {
+            let (status, body) = get(&site, uri).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            assert_ne!(body, "secret", "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_encoding() {
+        let site = site();
+        for uri in ["/%c0%ae%c0%ae/secret.txt", "/about%00.css", "/%ff", "/a%2"] {
+            let (status, _) = get(&site, uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn double_encoding_is_not_decoded_twice() {
+        let site = site();
+        let (status, body) = get(&site, "/%252e%252e/secret.txt").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_ne!(body, "secret");
+    }
+
+    #[tokio::test]
+    async fn normalizes_unicode() {
+        let site = site();
+        assert_eq!(
+            get(&site, "/cafe%CC%81").await,
+            (StatusCode::OK, "cafe".into())
+        );
+        assert_eq!(
+            get(&site, "/caf%C3%A9").await,
+            (StatusCode::OK, "cafe".into())
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn follows_symlinks_into_allowed_targets() {
+        let root = tempfile::tempdir().unwrap();
+        let pages = root.path().join("pages");
+        let shared = root.path().join("shared");
+        fs::create_dir_all(&pages).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(shared.join("logo.svg"), "logo").unwrap();
+        fs::write(root.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&shared, pages.join("shared")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("secret.txt"), pages.join("leak.txt")).unwrap();
+
+        let config = Config {
+            pages_dir: pages.to_string_lossy().into_owned(),
+            static_dir: pages.to_string_lossy().into_owned(),
+            symlink_targets: vec![shared.to_string_lossy().into_owned()],
+            ..Config::default()
+        };
+        let site = Site {
+            router: build_router(&config, None, None).unwrap(),
+            root,
+        };
+        assert_eq!(
+            get(&site, "/shared/logo.svg").await,
+            (StatusCode::OK, "logo".into())
+        );
+        assert_eq!(get(&site, "/leak.txt").await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_symlinks_escaping_the_root() {
+        let site = site();
+        let secret = site.root.path().join("secret.txt");
+        std::os::unix::fs::symlink(secret, site.root.path().join("static/leak.txt")).unwrap();
+        let (status, body) = get(&site, "/leak.txt").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_ne!(body, "secret");
+    }
+
+    #[tokio::test]
+    async fn denies_dotfiles_unless_masked() {
+        let site = site_with(|config| {
+            let assets = Path::new(&config.static_dir);
+            fs::create_dir_all(assets.join(".well-known")).unwrap();
+            fs::write(assets.join(".env"), "TOKEN=1").unwrap();
+            fs::write(assets.join(".well-known/security.txt"), "contact").unwrap();
+        });
+        assert_eq!(get(&site, "/.env").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(get(&site, "/.git/config").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(
+            get(&site, "/.well-known/security.txt").await,
+            (StatusCode::OK, "contact".into())
+        );
+
+        let masked = site_with(|config| config.mask_forbidden = true);
+        assert_eq!(get(&masked, "/.env").await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn applies_header_rules_by_pattern_and_extension() {
+        let site = site_with(|config| {
+            config.header_rules = vec![
+                HeaderRule {
+                    pattern: None,
+                    extensions: vec!["CSS".into()],
+                    set: [("x-kind".into(), "stylesheet".into())].into(),
+                },
+                HeaderRule {
+                    pattern: Some("/docs/**".into()),
+                    extensions: Vec::new(),
+                    set: [("x-kind".into(), "docs".into())].into(),
+                },
+            ];
+        });
+        let header = |uri: &'static str| {
+            let router = site.router.clone();
+            async move {
+                let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+                let response = router.oneshot(request).await.unwrap();
+                response
+                    .headers()
+                    .get("x-kind")
+                    .map(|v| v.to_str().unwrap().to_owned())
+            }
+        };
+        assert_eq!(header("/style.css").await.as_deref(), Some("stylesheet"));
+        assert_eq!(header("/docs/").await.as_deref(), Some("docs"));
+        assert_eq!(header("/about").await, None);
+        assert_eq!(header("/missing.css").await, None);
+    }
+
+    #[tokio::test]
+    async fn answers_cors_preflight_with_longest_prefix_policy() {
+        let rule = |prefix: &str, max_age| CorsRule {
+            prefix: prefix.into(),
+            allow_origins: vec!["https://app.example".into()],
+            allow_methods: vec!["get".into(), "post".into()],
+            allow_headers: Vec::new(),
+            expose_headers: Vec::new(),
+            allow_credentials: false,
+            max_age,
+        };
+        let site = site_with(|config| {
+            config.cors_rules = vec![rule("/", None), rule("/docs/", Some(600))];
+        });
+        let preflight = |uri: &'static str, origin: &'static str| {
+            let router = site.router.clone();
+            async move {
+                let request = Request::builder()
+                    .method(Method::OPTIONS)
+                    .uri(uri)
+                    .header(header::ORIGIN, origin)
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                    .body(Body::empty())
+                    .unwrap();
+                router.oneshot(request).await.unwrap()
+            }
+        };
+
+        let response = preflight("/docs/install", "https://app.example").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let response = preflight("/about", "https://app.example").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_MAX_AGE)
+        );
+
+        let response = preflight("/about", "https://evil.example").await;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[tokio::test]
+    async fn adds_csp_nonce_to_inline_scripts() {
+        let site = site_with(|config| {
+            config.csp = Some("script-src 'nonce-{{nonce}}'".into());
+        });
+        fs::write(
+            site.root.path().join("pages/app.html"),
+            "<script>run()</script><script src=\"/app.js\"></script>",
+        )
+        .unwrap();
+
+        let request = Request::builder().uri("/app").body(Body::empty()).unwrap();
+        let response = site.router.clone().oneshot(request).await.unwrap();
+        let policy = response.headers()[header::CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let nonce = policy
+            .strip_prefix("script-src 'nonce-")
+            .and_then(|rest| rest.strip_suffix('\''))
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            format!(
+                "<script nonce=\"{}\">run()</script><script src=\"/app.js\"></script>",
+                nonce
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn fills_in_custom_error_pages() {
+        let site = site_with(|config| {
+            fs::write(
+                Path::new(&config.pages_dir).join("not-found.html"),
+                "{{status}} {{status_text}} {{path}} {{request_id}}",
+            )
+            .unwrap();
+        });
+        let request = Request::builder()
+            .uri("/missing/%3Cb%3E")
+            .body(Body::empty())
+            .unwrap();
+        let response = site.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let request_id = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            format!("404 Not Found /missing/%3Cb%3E {}", request_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_a_builtin_favicon_unless_the_site_has_one() {
+        let favicon = |site: &Site| {
+            let router = site.router.clone();
+            async move {
+                let request = Request::builder()
+                    .uri("/favicon.ico")
+                    .body(Body::empty())
+                    .unwrap();
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, body.to_vec())
+            }
+        };
+        assert_eq!(
+            favicon(&site()).await,
+            (StatusCode::OK, DEFAULT_FAVICON.to_vec())
+        );
+        let own = site_with(|config| {
+            fs::write(Path::new(&config.static_dir).join("favicon.ico"), "mine").unwrap();
+        });
+        assert_eq!(favicon(&own).await, (StatusCode::OK, b"mine".to_vec()));
+        let off = site_with(|config| config.favicon = false);
+        assert_eq!(favicon(&off).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn serves_mounts_under_their_route() {
+        let site = site_with(|config| {
+            let media = Path::new(&config.pages_dir).join("../media");
+            fs::create_dir_all(media.join("raw")).unwrap();
+            fs::write(media.join("photo.jpg"), "photo").unwrap();
+            fs::write(media.join("raw/photo.jpg"), "raw photo").unwrap();
+            config.mounts = vec![
+                MountConfig {
+                    route: "/media/".into(),
+                    dir: media.to_string_lossy().into_owned(),
+                },
+                MountConfig {
+                    route: "/media/raw".into(),
+                    dir: media.join("raw").to_string_lossy().into_owned(),
+                },
+            ];
+        });
+        assert_eq!(
+            get(&site, "/media/photo.jpg").await,
+            (StatusCode::OK, "photo".into())
+        );
+        // Nested mounts take precedence over the mount they're in.
+        assert_eq!(
+            get(&site, "/media/raw/photo.jpg").await,
+            (StatusCode::OK, "raw photo".into())
+        );
+        assert_eq!(get(&site, "/mediaphoto.jpg").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            get(&site, "/media/../secret.txt").await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn tries_index_files_in_order() {
+        let site = site_with(|config| {
+            let docs = Path::new(&config.pages_dir).join("docs");
+            fs::write(docs.join("default.html"), "default").unwrap();
+            fs::create_dir_all(docs.join("old")).unwrap();
+            fs::write(docs.join("old/index.htm"), "old docs").unwrap();
+            config.index_files = vec!["index.htm".into(), "default.html".into()];
+        });
+        assert_eq!(
+            get(&site, "/docs/").await,
+            (StatusCode::OK, "default".into())
+        );
+        assert_eq!(
+            get(&site, "/docs/old/").await,
+            (StatusCode::OK, "old docs".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_directories_to_their_index() {
+        let site = site_with(|config| {
+            let pages = Path::new(&config.pages_dir);
+            fs::create_dir_all(pages.join("guide")).unwrap();
+            fs::create_dir_all(pages.join("empty")).unwrap();
+            fs::write(pages.join("guide.html"), "guide page").unwrap();
+            fs::write(pages.join("guide/index.html"), "guide index").unwrap();
+            config.mounts = vec![MountConfig {
+                route: "/manual".into(),
+                dir: pages.join("docs").to_string_lossy().into_owned(),
+            }];
+        });
+        // `/guide/` always means the directory, `/guide` prefers `guide.html`.
+        assert_eq!(
+            get(&site, "/guide/").await,
+            (StatusCode::OK, "guide index".into())
+        );
+        assert_eq!(
+            get(&site, "/guide").await,
+            (StatusCode::OK, "guide page".into())
+        );
+        assert_eq!(get(&site, "/empty/").await.0, StatusCode::NOT_FOUND);
+
+        let request = Request::builder()
+            .uri("/manual/")
+            .body(Body::empty())
+            .unwrap();
+        let response = site.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "docs");
+    }
+
+    #[tokio::test]
+    async fn redirects_to_canonical_trailing_slashes() {
+        let location = |site: &Site, uri: &'static str| {
+            let router = site.router.clone();
+            async move {
+                let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+                let response = router.oneshot(request).await.unwrap();
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .map(|v| v.to_str().unwrap().to_owned());
+                (response.status(), location)
+            }
+        };
+        let moved = |to: &str| (StatusCode::MOVED_PERMANENTLY, Some(to.to_string()));
+
+        let site = site();
+        assert_eq!(
+            location(&site, "/docs?page=2").await,
+            moved("/docs/?page=2")
+        );
+        assert_eq!(location(&site, "/about/").await, (StatusCode::OK, None));
+
+        let strict = site_with(|config| config.trailing_slash = TrailingSlash::Strict);
+        assert_eq!(location(&strict, "/about/").await, moved("/about"));
+        assert_eq!(location(&strict, "/docs").await, moved("/docs/"));
+
+        let off = site_with(|config| config.trailing_slash = TrailingSlash::Off);
+        assert_eq!(location(&off, "/docs").await, (StatusCode::OK, None));
+    }
+
+    #[tokio::test]
+    async fn matches_paths_ignoring_case_when_enabled() {
+        let site = site();
+        assert_eq!(get(&site, "/ABOUT").await.0, StatusCode::NOT_FOUND);
+
+        let site = site_with(|config| config.case_insensitive = true);
+        assert_eq!(get(&site, "/ABOUT").await, (StatusCode::OK, "about".into()));
+        assert_eq!(
+            get(&site, "/Style.CSS").await,
+            (StatusCode::OK, "css".into())
+        );
+        assert_eq!(get(&site, "/DOCS/").await, (StatusCode::OK, "docs".into()));
+    }
+}
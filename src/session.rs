@@ -0,0 +1,207 @@
+use axum::http::{HeaderMap, header};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::SessionConfig;
+
+pub const SESSION_COOKIE: &str = "lime_session";
+
+/// A signed-in user, stored in an HMAC-signed cookie.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub sub: String,
+    pub email: Option<String>,
+    /// What issued the session, e.g. `basic:<realm>` or the OIDC issuer.
+    pub source: String,
+    /// Expiry as a Unix timestamp.
+    pub exp: i64,
+}
+
+/// Issues and checks session cookies so credentials aren't re-checked on every request.
+pub struct Sessions {
+    key: SessionKey,
+    lifetime: i64,
+}
+
+impl Sessions {
+    pub fn new(config: &SessionConfig) -> Self {
+        let key = match &config.secret {
+            Some(secret) => SessionKey::from_secret(secret),
+            None => SessionKey::random(),
+        };
+        Self {
+            key,
+            lifetime: i64::try_from(config.lifetime).unwrap_or(i64::MAX),
+        }
+    }
+
+    pub fn key(&self) -> &SessionKey {
+        &self.key
+    }
+
+    /// Returns the session from the request cookies if it is authentic, unexpired and from `source`.
+    pub fn get(&self, headers: &HeaderMap, source: &str) -> Option<Session> {
+        let payload = self.key.verify(cookie(headers, SESSION_COOKIE)?)?;
+        let session: Session = serde_json::from_slice(&payload).ok()?;
+        (session.exp > chrono::Utc::now().timestamp() && session.source == source)
+            .then_some(session)
+    }
+
+    /// Returns a `Set-Cookie` value starting a new session.
+    pub fn start(&self, sub: &str, email: Option<String>, source: &str, secure: bool) -> String {
+        let session = Session {
+            sub: sub.to_string(),
+            email,
+            source: source.to_string(),
+            exp: chrono::Utc::now().timestamp().saturating_add(self.lifetime),
+        };
+        let value = self
+            .key
+            .sign(&serde_json::to_vec(&session).unwrap_or_default());
+        set_cookie(SESSION_COOKIE, &value, self.lifetime, secure)
+    }
+}
+
+/// Key used to sign and verify cookie values.
+pub struct SessionKey([u8; 32]);
+
+impl SessionKey {
+    /// A key that only lives as long as the process, so sessions end on restart.
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn from_secret(secret: &str) -> Self {
+        Self(Sha256::digest(secret.as_bytes()).into())
+    }
+
+    /// Returns `payload` and its signature as `<payload>.<signature>`, both base64url-encoded.
+    pub fn sign(&self, payload: &[u8]) -> String {
+        let signature = self.mac().chain_update(payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Returns the payload of a value created by [`SessionKey::sign`] if the signature matches.
+    pub fn verify(&self, value: &str) -> Option<Vec<u8>> {
+        let (payload, signature) = value.split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.mac()
+            .chain_update(&payload)
+            .verify_slice(&signature)
+            .ok()?;
+        Some(payload)
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        <Hmac<Sha256> as KeyInit>::new_from_slice(&self.0).expect("HMAC accepts any key length")
+    }
+}
+
+/// Returns the value of the cookie called `name`.
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Builds a `Set-Cookie` value for an HTTP-only cookie on the whole site.
+pub fn set_cookie(name: &str, value: &str, max_age: i64, secure: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+        name, value, max_age
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn with_lifetime(lifetime: u64) -> Sessions {
+        Sessions::new(&SessionConfig {
+            secret: Some("session-secret".to_string()),
+            lifetime,
+        })
+    }
+
+    /// The request headers carrying the cookie from a `Set-Cookie` value.
+    fn cookie_headers(set_cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let cookie = set_cookie.split(';').next().unwrap();
+        headers.insert(header::COOKIE, HeaderValue::from_str(cookie).unwrap());
+        headers
+    }
+
+    #[test]
+    fn verifies_signed_values() {
+        let key = SessionKey::from_secret("session-secret");
+        let value = key.sign(b"ada");
+        assert_eq!(key.verify(&value).as_deref(), Some(&b"ada"[..]));
+
+        let (payload, signature) = value.split_once('.').unwrap();
+        let forged = format!("{}.{}", URL_SAFE_NO_PAD.encode("bob"), signature);
+        assert_eq!(key.verify(&forged), None);
+        let truncated = format!("{}.{}", payload, &signature[1..]);
+        assert_eq!(key.verify(&truncated), None);
+        assert_eq!(key.verify(payload), None);
+        assert_eq!(SessionKey::from_secret("other").verify(&value), None);
+        assert_eq!(SessionKey::random().verify(&value), None);
+    }
+
+    #[test]
+    fn accepts_sessions_from_the_same_source() {
+        let sessions = with_lifetime(3600);
+        let headers = cookie_headers(&sessions.start("ada", None, "basic:Lime", false));
+        let session = sessions.get(&headers, "basic:Lime").unwrap();
+        assert_eq!(session.sub, "ada");
+        assert!(sessions.get(&headers, "https://idp.example.com").is_none());
+        // Another server with the same secret accepts it too.
+        assert!(with_lifetime(60).get(&headers, "basic:Lime").is_some());
+    }
+
+    #[test]
+    fn rejects_tampered_and_expired_sessions() {
+        let sessions = with_lifetime(3600);
+        let set_cookie = sessions.start("ada", None, "basic:Lime", false);
+        let value = cookie(&cookie_headers(&set_cookie), SESSION_COOKIE)
+            .unwrap()
+            .to_string();
+        let (payload, signature) = value.split_once('.').unwrap();
+        let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        let tampered = format!(
+            "{}={}.{}",
+            SESSION_COOKIE,
+            URL_SAFE_NO_PAD.encode(payload.replace("ada", "bob")),
+            signature
+        );
+        assert!(
+            sessions
+                .get(&cookie_headers(&tampered), "basic:Lime")
+                .is_none()
+        );
+
+        let expired = with_lifetime(0).start("ada", None, "basic:Lime", false);
+        assert!(
+            sessions
+                .get(&cookie_headers(&expired), "basic:Lime")
+                .is_none()
+        );
+    }
+}
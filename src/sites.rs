@@ -0,0 +1,92 @@
+use std::{fs, path::Path};
+
+use axum::response::Response;
+
+use crate::{html, listing};
+
+/// A site folder in the pages directory, served under `/<name>/`.
+#[derive(Debug, PartialEq)]
+pub struct Site {
+    pub name: String,
+    /// The `<title>` of its `index.html`, or its name.
+    pub title: String,
+}
+
+/// Lists the site folders in `dir` by name, leaving out hidden ones.
+pub fn discover(dir: &Path) -> Vec<Site> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sites: Vec<_> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.'))
+        .map(|name| {
+            let title = fs::read_to_string(dir.join(&name).join("index.html"))
+                .ok()
+                .and_then(|index| html::title(&index))
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| name.clone());
+            Site { name, title }
+        })
+        .collect();
+    sites.sort_by(|a, b| a.name.cmp(&b.name));
+    sites
+}
+
+/// The landing page linking to each site, rendered through the listing template.
+pub async fn landing_page(sites: &[Site], pages_dir: &Path) -> Response {
+    if sites.is_empty() {
+        let content = format!(
+            "<p>No sites yet. Add a folder to {}.</p>",
+            html::escape(&pages_dir.display().to_string())
+        );
+        return listing::render(pages_dir, "Sites", &content).await;
+    }
+    let mut content = String::from("<ul>\n");
+    for site in sites {
+        content.push_str(&format!(
+            "<li><a href=\"/{}/\">{}</a>",
+            html::escape(&site.name),
+            html::escape(&site.title)
+        ));
+        if site.title != site.name {
+            content.push_str(&format!(
+                " <span class=\"count\">{}</span>",
+                html::escape(&site.name)
+            ));
+        }
+        content.push_str("</li>\n");
+    }
+    content.push_str("</ul>");
+    listing::render(pages_dir, "Sites", &content).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_site_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("portfolio")).unwrap();
+        fs::create_dir_all(dir.path().join("blog")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        fs::write(dir.path().join("blog/index.html"), "<title>My Blog</title>").unwrap();
+        assert_eq!(
+            discover(dir.path()),
+            vec![
+                Site {
+                    name: "blog".to_string(),
+                    title: "My Blog".to_string()
+                },
+                Site {
+                    name: "portfolio".to_string(),
+                    title: "portfolio".to_string()
+                },
+            ]
+        );
+    }
+}
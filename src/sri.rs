@@ -0,0 +1,96 @@
+use std::{collections::HashMap, fs, path::PathBuf, sync::Mutex, time::SystemTime};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha2::{Digest, Sha384};
+use tracing::debug;
+
+use crate::{html, paths};
+
+/// Adds Subresource Integrity attributes to local scripts and stylesheets in served pages.
+pub struct Integrity {
+    static_dir: PathBuf,
+    /// Hashes by file, recomputed when the file changes.
+    hashes: Mutex<HashMap<PathBuf, (SystemTime, String)>>,
+}
+
+impl Integrity {
+    pub fn new(static_dir: PathBuf) -> Self {
+        Self {
+            static_dir,
+            hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds `integrity` and `crossorigin` to `<script src>` and `<link rel="stylesheet">`
+    /// tags of `html` that point into the static directory and have no `integrity` yet.
+    pub fn apply(&self, request_path: &str, html: &str) -> String {
+        html::add_attributes(html, |tag| {
+            let url = if html::is_tag(tag, "script") {
+                html::attribute(tag, "src")
+            } else if html::is_tag(tag, "link")
+                && html::attribute(tag, "rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("stylesheet"))
+                })
+            {
+                html::attribute(tag, "href")
+            } else {
+                None
+            };
+            let hash = url
+                .filter(|_| html::attribute(tag, "integrity").is_none())
+                .and_then(|url| self.hash(request_path, url))?;
+            let mut attributes = format!(" integrity=\"{}\"", hash);
+            if html::attribute(tag, "crossorigin").is_none() {
+                attributes.push_str(" crossorigin=\"anonymous\"");
+            }
+            Some(attributes)
+        })
+    }
+
+    fn hash(&self, request_path: &str, url: &str) -> Option<String> {
+        let path = paths::static_file(&self.static_dir, &paths::join_url(request_path, url)?)?;
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let mut hashes = self.hashes.lock().unwrap();
+        if let Some((cached_at, hash)) = hashes.get(&path)
+            && *cached_at == modified
+        {
+            return Some(hash.clone());
+        }
+        let content = fs::read(&path).ok()?;
+        let hash = format!("sha384-{}", STANDARD.encode(Sha384::digest(&content)));
+        debug!("Computed integrity hash for {:?}", path);
+        hashes.insert(path, (modified, hash.clone()));
+        Some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_integrity_to_local_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("js")).unwrap();
+        fs::write(dir.path().join("js/app.js"), "alert(1);").unwrap();
+        fs::write(dir.path().join("style.css"), "body {}").unwrap();
+        let integrity = Integrity::new(dir.path().to_path_buf());
+
+        let html = concat!(
+            "<script src=\"/js/app.js?v=2\"></script>",
+            "<link rel=\"stylesheet\" href=\"../style.css\" />",
+            "<script src=\"https://cdn.example/lib.js\"></script>",
+            "<link rel=\"icon\" href=\"/style.css\">",
+        );
+        let output = integrity.apply("/docs/page", html);
+        let expected = format!("sha384-{}", STANDARD.encode(Sha384::digest(b"alert(1);")));
+        assert!(output.starts_with(&format!(
+            "<script src=\"/js/app.js?v=2\" integrity=\"{}\" crossorigin=\"anonymous\"></script>",
+            expected
+        )));
+        assert!(output.contains("crossorigin=\"anonymous\" />"));
+        assert!(output.contains("<script src=\"https://cdn.example/lib.js\"></script>"));
+        assert!(output.ends_with("<link rel=\"icon\" href=\"/style.css\">"));
+    }
+}
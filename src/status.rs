@@ -0,0 +1,188 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Local};
+
+use crate::{admin::AdminState, config::Config, probes::is_health_probe};
+
+/// Server errors listed on the status page.
+const RECENT_ERRORS: usize = 20;
+
+struct ServerError {
+    time: DateTime<Local>,
+    method: String,
+    path: String,
+    status: u16,
+}
+
+/// What `GET /__lime/status` reports: uptime, a summary of the configuration and the
+/// latest requests that failed with a server error.
+pub struct ServerStatus {
+    started: Instant,
+    started_at: DateTime<Local>,
+    summary: String,
+    total: Mutex<[u64; 5]>,
+    errors: Mutex<VecDeque<ServerError>>,
+}
+
+impl ServerStatus {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: Local::now(),
+            summary: summarize(config),
+            total: Mutex::new([0; 5]),
+            errors: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn record(&self, method: &str, path: &str, status: StatusCode) {
+        if let Some(count) = self
+            .total
+            .lock()
+            .unwrap()
+            .get_mut(usize::from(status.as_u16() / 100).saturating_sub(1))
+        {
+            *count += 1;
+        }
+        if !status.is_server_error() {
+            return;
+        }
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == RECENT_ERRORS {
+            errors.pop_back();
+        }
+        errors.push_front(ServerError {
+            time: Local::now(),
+            method: method.to_string(),
+            path: path.to_string(),
+            status: status.as_u16(),
+        });
+    }
+
+    fn render(&self) -> String {
+        let uptime = self.uptime().as_secs();
+        let mut output = String::new();
+        let _ = writeln!(output, "Lime {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(output, "PID:      {}", std::process::id());
+        let _ = writeln!(
+            output,
+            "Started:  {}",
+            self.started_at.format("%Y-%m-%d %H:%M:%S %z")
+        );
+        let _ = writeln!(
+            output,
+            "Uptime:   {}d {:02}:{:02}:{:02}",
+            uptime / 86400,
+            uptime / 3600 % 24,
+            uptime / 60 % 60,
+            uptime % 60
+        );
+        let total = self.total.lock().unwrap();
+        let _ = write!(output, "Requests: {}", total.iter().sum::<u64>());
+        for (index, count) in total.iter().enumerate() {
+            let _ = write!(output, ", {}xx {}", index + 1, count);
+        }
+        let _ = writeln!(output, "\n\n{}", self.summary);
+
+        let errors = self.errors.lock().unwrap();
+        let _ = writeln!(output, "Recent server errors:");
+        if errors.is_empty() {
+            let _ = writeln!(output, "  none");
+        }
+        for error in errors.iter() {
+            let _ = writeln!(
+                output,
+                "  {} {} {} {}",
+                error.time.format("%Y-%m-%d %H:%M:%S"),
+                error.status,
+                error.method,
+                error.path
+            );
+        }
+        output
+    }
+}
+
+/// The parts of the configuration worth checking on a running server.
+fn summarize(config: &Config) -> String {
+    let enabled = |on: bool| if on { "on" } else { "off" };
+    let mut output = String::new();
+    let _ = writeln!(output, "Listening on: {}:{}", config.host, config.port);
+    let _ = writeln!(output, "Workers:      {}", config.workers);
+    let _ = writeln!(output, "Pages:        {}", config.pages_dir);
+    let _ = writeln!(output, "Static:       {}", config.static_dir);
+    for mount in &config.mounts {
+        let _ = writeln!(output, "Mount:        {} -> {}", mount.route, mount.dir);
+    }
+    for rule in &config.proxy_rules {
+        let _ = writeln!(
+            output,
+            "Proxy:        {} -> {}",
+            rule.route,
+            rule.upstreams.join(", ")
+        );
+    }
+    let _ = writeln!(output, "Memory cache: {}", enabled(config.cache.enabled));
+    let _ = writeln!(output, "Search:       {}", enabled(config.search.enabled));
+    let _ = writeln!(output, "Metrics:      {}", enabled(config.metrics.enabled));
+    let _ = writeln!(output, "Auth rules:   {}", config.auth_rules.len());
+    output
+}
+
+pub async fn handle_status(State(state): State<Arc<AdminState>>) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(state.status.render()))
+        .unwrap()
+}
+
+pub async fn record_status(
+    State(status): State<Arc<ServerStatus>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_health_probe(&request) {
+        return next.run(request).await;
+    }
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    status.record(&method, &path, response.status());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_recent_server_errors() {
+        let status = ServerStatus::new(&Config::default());
+        status.record("GET", "/", StatusCode::OK);
+        status.record("GET", "/missing", StatusCode::NOT_FOUND);
+        status.record("POST", "/api/orders", StatusCode::BAD_GATEWAY);
+
+        let output = status.render();
+        assert!(output.contains("Requests: 3, 1xx 0, 2xx 1, 3xx 0, 4xx 1, 5xx 1"));
+        assert!(output.contains("502 POST /api/orders"));
+        assert!(!output.contains("/missing"));
+    }
+}
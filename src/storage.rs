@@ -0,0 +1,474 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{error, info};
+
+use crate::{
+    cluster,
+    config::{Config, StorageConfig},
+};
+
+/// Time to wait for the bucket to answer a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// SHA-256 of the empty body of `GET` requests.
+const EMPTY_PAYLOAD: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Objects downloaded at once.
+const CONCURRENCY: usize = 8;
+
+/// Replaces the `s3://` directories of `config` with local copies of the buckets, and
+/// keeps those up to date in the background.
+///
+/// Workers only use the copies, which the supervisor keeps up to date.
+pub async fn mirror_buckets(config: &mut Config) -> Result<()> {
+    let mut dirs: Vec<&mut String> = vec![&mut config.pages_dir, &mut config.static_dir];
+    dirs.extend(config.mounts.iter_mut().map(|mount| &mut mount.dir));
+    let mut mirrors = Vec::new();
+    for dir in dirs {
+        let Some((bucket, prefix)) = parse_url(dir) else {
+            continue;
+        };
+        let mirror = Mirror::new(&config.storage, bucket, prefix)?;
+        *dir = mirror.dir.to_string_lossy().into_owned();
+        mirrors.push(Arc::new(mirror));
+    }
+    if mirrors.is_empty() || cluster::is_worker() {
+        return Ok(());
+    }
+
+    for mirror in &mirrors {
+        let changes = mirror.sync().await?;
+        if !config.quiet {
+            println!("Synced {} files from {}", changes, mirror.url());
+        }
+    }
+    if config.storage.refresh > 0 {
+        let refresh = Duration::from_secs(config.storage.refresh);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                for mirror in &mirrors {
+                    match mirror.sync().await {
+                        Ok(0) => {}
+                        Ok(changes) => info!("Synced {} files from {}", changes, mirror.url()),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Splits `s3://bucket/prefix` into the bucket and the key prefix, ending in `/`.
+fn parse_url(dir: &str) -> Option<(String, String)> {
+    let path = dir.strip_prefix("s3://")?;
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    let prefix = prefix.trim_matches('/');
+    let prefix = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", prefix)
+    };
+    Some((bucket.to_string(), prefix))
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    /// Reads the standard AWS variables; without them, requests are anonymous.
+    fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key: env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_key: env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+struct Object {
+    key: String,
+    etag: String,
+}
+
+/// A bucket of an S3-compatible service, addressed path-style.
+struct Bucket {
+    client: Client,
+    endpoint: Url,
+    region: String,
+    name: String,
+    credentials: Option<Credentials>,
+}
+
+impl Bucket {
+    /// Lists the objects under `prefix`.
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2".to_string()),
+                ("prefix", prefix.to_string()),
+            ];
+            if let Some(token) = token.take() {
+                query.push(("continuation-token", token));
+            }
+            let body = self.get("", &query).await?;
+            let body = String::from_utf8_lossy(&body);
+            for contents in elements(&body, "Contents") {
+                let key = elements(contents, "Key").first().map(|k| unescape(k));
+                let etag = elements(contents, "ETag").first().map(|e| unescape(e));
+                if let (Some(key), Some(etag)) = (key, etag) {
+                    objects.push(Object { key, etag });
+                }
+            }
+            let truncated = elements(&body, "IsTruncated").first() == Some(&"true");
+            token = elements(&body, "NextContinuationToken")
+                .first()
+                .map(|t| unescape(t));
+            if !truncated || token.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+
+    /// Fetches `key`, or lists the bucket when it is empty.
+    async fn get(&self, key: &str, query: &[(&str, String)]) -> Result<Vec<u8>> {
+        let path = format!(
+            "/{}/{}",
+            uri_encode(&self.name, true),
+            uri_encode(key, false)
+        );
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+
+        let mut request = self.client.get(url);
+        for (name, value) in self.sign(&path, &query, Utc::now()) {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow!("Failed to fetch s3://{}/{}: {}", self.name, key, e))?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| anyhow!("Failed to fetch s3://{}/{}: {}", self.name, key, e))?;
+        Ok(body.to_vec())
+    }
+
+    /// Headers signing a `GET` request with AWS Signature Version 4.
+    fn sign(&self, path: &str, query: &str, now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+        let Some(credentials) = &self.credentials else {
+            return Vec::new();
+        };
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or_default(), port),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", EMPTY_PAYLOAD.to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "GET\n{}\n{}\n{}\n{}\n{}",
+            path, query, canonical_headers, signed_headers, EMPTY_PAYLOAD
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = format!("AWS4{}", credentials.secret_key).into_bytes();
+        for part in [date.as_str(), &self.region, "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        // The host header is set by the client.
+        headers.remove(0);
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key, scope, signed_headers, signature
+            ),
+        ));
+        headers
+    }
+}
+
+/// A local copy of the objects under a prefix of a bucket.
+struct Mirror {
+    bucket: Arc<Bucket>,
+    prefix: String,
+    dir: PathBuf,
+    /// ETags of the copied objects by key, kept next to `dir`.
+    manifest: PathBuf,
+}
+
+impl Mirror {
+    fn new(config: &StorageConfig, bucket: String, prefix: String) -> Result<Self> {
+        let endpoint = Url::parse(&config.endpoint)
+            .map_err(|e| anyhow!("Invalid storage endpoint {}: {}", config.endpoint, e))?;
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("lime/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        let dir = Path::new(&config.cache_dir)
+            .join(&bucket)
+            .join(prefix.trim_end_matches('/'));
+        let manifest = dir.with_extension("etags.json");
+        Ok(Self {
+            bucket: Arc::new(Bucket {
+                client,
+                endpoint,
+                region: config.region.clone(),
+                name: bucket,
+                credentials: Credentials::from_env(),
+            }),
+            prefix,
+            dir,
+            manifest,
+        })
+    }
+
+    fn url(&self) -> String {
+        format!("s3://{}/{}", self.bucket.name, self.prefix)
+    }
+
+    /// Downloads new and changed objects and deletes the copies of removed ones,
+    /// returning how many files changed.
+    async fn sync(&self) -> Result<usize> {
+        let mut etags: HashMap<String, String> = fs::read_to_string(&self.manifest)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let objects = self.bucket.list(&self.prefix).await?;
+        let mut changes = 0;
+
+        let mut listed = HashSet::new();
+        let semaphore = Arc::new(Semaphore::new(CONCURRENCY));
+        let mut downloads = JoinSet::new();
+        for object in objects {
+            let Some(path) = self.local_path(&object.key) else {
+                continue;
+            };
+            listed.insert(object.key.clone());
+            if etags.get(&object.key) == Some(&object.etag) && path.exists() {
+                continue;
+            }
+            let bucket = self.bucket.clone();
+            let semaphore = semaphore.clone();
+            downloads.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let body = bucket.get(&object.key, &[]).await?;
+                write_atomically(&path, &body)?;
+                Ok::<_, anyhow::Error>(object)
+            });
+        }
+        let mut failure = None;
+        while let Some(result) = downloads.join_next().await {
+            match result.map_err(|e| anyhow!("Download task failed: {}", e)) {
+                Ok(Ok(object)) => {
+                    etags.insert(object.key, object.etag);
+                    changes += 1;
+                }
+                Ok(Err(e)) | Err(e) => failure = Some(e),
+            }
+        }
+
+        let removed: Vec<String> = etags
+            .keys()
+            .filter(|key| !listed.contains(*key))
+            .cloned()
+            .collect();
+        for key in removed {
+            if let Some(path) = self.local_path(&key) {
+                let _ = fs::remove_file(path);
+            }
+            etags.remove(&key);
+            changes += 1;
+        }
+
+        if changes > 0 {
+            let content = serde_json::to_string(&etags)
+                .map_err(|e| anyhow!("Failed to serialize {}: {}", self.manifest.display(), e))?;
+            write_atomically(&self.manifest, content.as_bytes())?;
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(changes),
+        }
+    }
+
+    /// Where the copy of `key` goes, or `None` for folder markers and keys that would
+    /// escape the directory.
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        let relative = Path::new(key.strip_prefix(&self.prefix)?);
+        let safe = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        (safe && !key.ends_with('/') && !key.is_empty()).then(|| self.dir.join(relative))
+    }
+}
+
+/// Writes next to `path` first, so requests never see half a file.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let temporary = path.with_extension("lime-tmp");
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&temporary, content))
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Contents of the `<tag>` elements in `xml`, which must not nest.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Percent-encodes everything but unreserved characters, and `/` unless `slash`.
+fn uri_encode(text: &str, slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha256> as KeyInit>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_object_listings() {
+        let xml = "<ListBucketResult><IsTruncated>true</IsTruncated>\
+            <Contents><Key>site/a&amp;b.html</Key><ETag>&quot;1f&quot;</ETag></Contents>\
+            <Contents><Key>site/css/</Key><ETag>&quot;d4&quot;</ETag></Contents>\
+            <NextContinuationToken>next</NextContinuationToken></ListBucketResult>";
+        let keys: Vec<String> = elements(xml, "Contents")
+            .iter()
+            .map(|c| unescape(elements(c, "Key")[0]))
+            .collect();
+        assert_eq!(keys, ["site/a&b.html", "site/css/"]);
+        assert_eq!(elements(xml, "NextContinuationToken"), ["next"]);
+
+        let (bucket, prefix) = parse_url("s3://my-site/site").unwrap();
+        let mirror = Mirror::new(&Default::default(), bucket, prefix).unwrap();
+        assert!(mirror.local_path("site/a&b.html").is_some());
+        assert!(mirror.local_path("site/css/").is_none());
+        assert!(mirror.local_path("site/../secret").is_none());
+        assert_eq!(uri_encode("a b/c", false), "a%20b/c");
+    }
+
+    #[test]
+    fn signs_requests() {
+        let bucket = Bucket {
+            client: Client::new(),
+            endpoint: Url::parse("http://127.0.0.1:5056").unwrap(),
+            region: "us-east-1".to_string(),
+            name: "my-site".to_string(),
+            credentials: Some(Credentials {
+                access_key: "AKIDEXAMPLE".to_string(),
+                secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+                session_token: None,
+            }),
+        };
+        let now = "2026-10-15T12:00:00Z".parse().unwrap();
+        let headers = bucket.sign("/my-site/", "list-type=2&prefix=site%2Fpages%2F", now);
+        // Signed by botocore for the same request.
+        assert_eq!(
+            headers.last().unwrap().1,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20261015/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=e824fb23e22ac31f228db74e8d14b766f2c797e20c3e04432497b3aff833ae34"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use axum::body::Bytes;
+use tokio::fs;
+
+use crate::file_cache::FileCache;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A file found by a [`ContentStore`].
+pub struct StoredFile {
+    /// Where the file really is. Its extension decides the MIME type, and caches use it
+    /// to tell files apart.
+    pub path: PathBuf,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+pub enum StoreError {
+    NotFound,
+    /// The file is outside of the directory it was requested from, e.g. through a symlink.
+    Outside,
+    /// The directory itself can't be read.
+    Unavailable(io::Error),
+}
+
+/// Where served files come from. Requests are resolved to paths, and the store decides
+/// whether and how they can be read, so content can live elsewhere than the local file
+/// system without changing request handling.
+pub trait ContentStore: Send + Sync {
+    /// Finds the file at `path`, which must be inside `base`.
+    fn open<'a>(
+        &'a self,
+        base: &'a Path,
+        path: &'a Path,
+    ) -> StoreFuture<'a, Result<StoredFile, StoreError>>;
+
+    /// Reads the whole of a file returned by [`ContentStore::open`].
+    fn read<'a>(&'a self, file: &'a StoredFile) -> StoreFuture<'a, io::Result<Bytes>>;
+}
+
+/// Files on the local file system, optionally kept in memory.
+pub struct FsStore {
+    /// Directories outside of the served ones that symlinks may point into.
+    symlink_targets: Vec<PathBuf>,
+    file_cache: Option<Arc<FileCache>>,
+}
+
+impl FsStore {
+    pub fn new(symlink_targets: Vec<PathBuf>, file_cache: Option<Arc<FileCache>>) -> Self {
+        Self {
+            symlink_targets,
+            file_cache,
+        }
+    }
+}
+
+impl ContentStore for FsStore {
+    fn open<'a>(
+        &'a self,
+        base: &'a Path,
+        path: &'a Path,
+    ) -> StoreFuture<'a, Result<StoredFile, StoreError>> {
+        Box::pin(async move {
+            let base = fs::canonicalize(base)
+                .await
+                .map_err(StoreError::Unavailable)?;
+            let path = fs::canonicalize(path)
+                .await
+                .map_err(|_| StoreError::NotFound)?;
+            let allowed = path.starts_with(&base)
+                || self.symlink_targets.iter().any(|dir| path.starts_with(dir));
+            if !allowed {
+                return Err(StoreError::Outside);
+            }
+            let metadata = fs::metadata(&path)
+                .await
+                .map_err(|_| StoreError::NotFound)?;
+            if metadata.is_dir() {
+                return Err(StoreError::NotFound);
+            }
+            Ok(StoredFile {
+                path,
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            })
+        })
+    }
+
+    fn read<'a>(&'a self, file: &'a StoredFile) -> StoreFuture<'a, io::Result<Bytes>> {
+        Box::pin(async move {
+            match &self.file_cache {
+                Some(cache) => cache.read(&file.path, file.len, file.modified).await,
+                None => fs::read(&file.path).await.map(Bytes::from),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_files_inside_the_base_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        std::fs::create_dir_all(pages.join("docs")).unwrap();
+        std::fs::write(pages.join("index.html"), "home").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        let store = FsStore::new(Vec::new(), None);
+
+        let file = match store.open(&pages, &pages.join("index.html")).await {
+            Ok(file) => file,
+            Err(_) => panic!("index.html should be found"),
+        };
+        assert_eq!(file.len, 4);
+        assert_eq!(store.read(&file).await.unwrap(), "home");
+        assert!(matches!(
+            store.open(&pages, &pages.join("../secret.txt")).await,
+            Err(StoreError::Outside)
+        ));
+        assert!(matches!(
+            store.open(&pages, &pages.join("docs")).await,
+            Err(StoreError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use crate::{
+    html,
+    paths::{content_pages, page_url},
+};
+
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Finds pages with URLs close to a missing one, for "did you mean" hints on 404 pages.
+pub struct Suggester {
+    pages_dir: PathBuf,
+    index_files: Vec<String>,
+}
+
+impl Suggester {
+    pub fn new(pages_dir: PathBuf, index_files: Vec<String>) -> Self {
+        Self {
+            pages_dir,
+            index_files,
+        }
+    }
+
+    /// Returns up to three page URLs closest to `path`, best match first.
+    pub fn suggest(&self, path: &str) -> Vec<String> {
+        let wanted = path.trim_end_matches(".html").to_lowercase();
+        let wanted_name = last_segment(&wanted);
+        let mut candidates: Vec<(usize, String)> = content_pages(&self.pages_dir)
+            .into_iter()
+            .filter(|relative| !relative.components().any(|c| c.as_os_str() == "drafts"))
+            .map(|relative| page_url(&relative, &self.index_files))
+            .filter_map(|url| {
+                let lower = url.to_lowercase();
+                // A page that kept its name but moved to another directory is a good match too.
+                let distance =
+                    distance(&wanted, &lower).min(distance(wanted_name, last_segment(&lower)) + 1);
+                let threshold = (wanted.chars().count() / 3).max(2);
+                (distance <= threshold).then_some((distance, url))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, url)| url)
+            .collect()
+    }
+
+    /// Renders the suggestions for `path` as an HTML list, or nothing when there are none.
+    pub fn render(&self, path: &str) -> String {
+        let suggestions = self.suggest(path);
+        if suggestions.is_empty() {
+            return String::new();
+        }
+        let mut list = String::from("<ul class=\"suggestions\">");
+        for url in suggestions {
+            let url = html::escape(&url);
+            list.push_str(&format!("<li><a href=\"{}\">{}</a></li>", url, url));
+        }
+        list.push_str("</ul>");
+        list
+    }
+}
+
+fn last_segment(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("/docs/intro", "/docs/intro"), 0);
+        assert_eq!(distance("/docs/itnro", "/docs/intro"), 2);
+        assert_eq!(distance("/blog/caf\u{e9}", "/blog/cafe"), 1);
+    }
+
+    #[test]
+    fn suggests_close_and_moved_pages() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("guides")).unwrap();
+        for page in ["about.html", "guides/install.html", "contact.html"] {
+            std::fs::write(root.path().join(page), "").unwrap();
+        }
+        let suggester = Suggester::new(root.path().to_path_buf(), vec!["index.html".into()]);
+
+        assert_eq!(suggester.suggest("/abuot"), ["/about"]);
+        assert_eq!(suggester.suggest("/docs/install"), ["/guides/install"]);
+        assert!(suggester.suggest("/completely-different").is_empty());
+    }
+}
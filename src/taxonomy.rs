@@ -0,0 +1,364 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+
+use crate::{
+    catalog::{Catalog, Page},
+    html, listing,
+    server::not_found,
+};
+
+/// A front matter list that groups pages, like tags.
+#[derive(Clone, Copy)]
+enum Taxonomy {
+    Tags,
+    Collections,
+}
+
+impl Taxonomy {
+    fn route(self) -> &'static str {
+        match self {
+            Self::Tags => "/tags",
+            Self::Collections => "/collections",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Tags => "Tags",
+            Self::Collections => "Collections",
+        }
+    }
+
+    fn terms(self, page: &Page) -> &[String] {
+        match self {
+            Self::Tags => &page.front_matter.tags,
+            Self::Collections => &page.front_matter.collections,
+        }
+    }
+}
+
+struct TaxonomyState {
+    taxonomy: Taxonomy,
+    catalog: Arc<Catalog>,
+    pages_dir: PathBuf,
+}
+
+/// A tag or collection and the pages listing it.
+#[derive(Serialize)]
+struct Term {
+    name: String,
+    url: String,
+    pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+struct PageLink {
+    url: String,
+    title: String,
+}
+
+#[derive(Serialize)]
+struct TermSummary {
+    name: String,
+    url: String,
+    count: usize,
+}
+
+/// Builds listing pages and JSON endpoints for tags and collections.
+/// These routes shadow files at the same URLs in the pages directory, such as
+/// `pages/tags/index.html` or `pages/tags/rust.html`.
+pub fn taxonomy_router<S: Clone + Send + Sync + 'static>(
+    catalog: Arc<Catalog>,
+    pages_dir: PathBuf,
+) -> Router<S> {
+    let mut router = Router::new();
+    for taxonomy in [Taxonomy::Tags, Taxonomy::Collections] {
+        let state = Arc::new(TaxonomyState {
+            taxonomy,
+            catalog: catalog.clone(),
+            pages_dir: pages_dir.clone(),
+        });
+        let route = taxonomy.route();
+        router = router.merge(
+            Router::new()
+                .route(route, get(redirect_to_slash))
+                .route(&format!("{}/", route), get(handle_index))
+                .route(&format!("{}.json", route), get(handle_index_json))
+                .route(&format!("{}/{{name}}", route), get(handle_term_json))
+                .route(&format!("{}/{{name}}/", route), get(handle_term))
+                .with_state(state),
+        );
+    }
+    router
+}
+
+async fn handle_index(State(state): State<Arc<TaxonomyState>>) -> Response {
+    let summaries = summaries(&state);
+    let mut content = String::from("<ul>\n");
+    for term in &summaries {
+        content.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> <span class=\"count\">{}</span></li>\n",
+            html::escape(&term.url),
+            html::escape(&term.name),
+            term.count
+        ));
+    }
+    content.push_str("</ul>");
+    listing::render(&state.pages_dir, state.taxonomy.title(), &content).await
+}
+
+async fn handle_index_json(State(state): State<Arc<TaxonomyState>>) -> Response {
+    Json(summaries(&state)).into_response()
+}
+
+async fn handle_term(
+    State(state): State<Arc<TaxonomyState>>,
+    Path(name): Path<String>,
+) -> Response {
+    let Some(term) = find_term(&state, &name) else {
+        return not_found(&state.pages_dir).await;
+    };
+    let content = listing::page_list(
+        term.pages
+            .iter()
+            .map(|p| (p.url.as_str(), p.title.as_str())),
+    );
+    listing::render(&state.pages_dir, &term.name, &content).await
+}
+
+/// Serves `/<taxonomy>/<slug>.json`, and redirects `/<taxonomy>/<slug>` to its listing page.
+/// The router can't match a suffix after a parameter, so the `.json` is checked here.
+async fn handle_term_json(
+    State(state): State<Arc<TaxonomyState>>,
+    Path(name): Path<String>,
+) -> Response {
+    match name.strip_suffix(".json") {
+        Some(slug) => match find_term(&state, slug) {
+            Some(term) => Json(term).into_response(),
+            None => not_found(&state.pages_dir).await,
+        },
+        None => redirect(&format!("{}/{}/", state.taxonomy.route(), name)),
+    }
+}
+
+async fn redirect_to_slash(State(state): State<Arc<TaxonomyState>>) -> Response {
+    redirect(&format!("{}/", state.taxonomy.route()))
+}
+
+/// Groups pages by term slug, so `Rust` and `rust` end up together.
+fn terms(state: &TaxonomyState) -> BTreeMap<String, Term> {
+    let mut terms = BTreeMap::new();
+    for page in state.catalog.pages().iter() {
+        for name in state.taxonomy.terms(page) {
+            let slug = slugify(name);
+            if slug.is_empty() {
+                continue;
+            }
+            let term = terms.entry(slug.clone()).or_insert_with(|| Term {
+                name: name.clone(),
+                url: format!("{}/{}/", state.taxonomy.route(), slug),
+                pages: Vec::new(),
+            });
+            term.pages.push(PageLink {
+                url: page.url.clone(),
+                title: page.title.clone(),
+            });
+        }
+    }
+    terms
+}
+
+fn summaries(state: &TaxonomyState) -> Vec<TermSummary> {
+    terms(state)
+        .into_values()
+        .map(|term| TermSummary {
+            count: term.pages.len(),
+            name: term.name,
+            url: term.url,
+        })
+        .collect()
+}
+
+fn find_term(state: &TaxonomyState, slug: &str) -> Option<Term> {
+    terms(state).remove(slug)
+}
+
+/// Lowercases `name` and joins its words with dashes, e.g. `Web Servers` → `web-servers`.
+fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn redirect(location: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{body::to_bytes, http::Request};
+    use serde_json::{Value, json};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{config::Config, test_router};
+
+    fn site() -> (TempDir, Router) {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        fs::create_dir_all(pages.join("tags/rust")).unwrap();
+        fs::write(
+            pages.join("axum.html"),
+            "+++\ntitle = \"Axum\"\ntags = [\"Rust\", \"Web Servers\"]\ncollections = [\"guides\"]\n+++\n",
+        )
+        .unwrap();
+        fs::write(
+            pages.join("tokio.html"),
+            "+++\ntitle = \"Tokio\"\ntags = [\"rust\"]\n+++\n",
+        )
+        .unwrap();
+        // Real pages at URLs the taxonomy routes take over, and one below them.
+        fs::write(pages.join("tags/index.html"), "static tags").unwrap();
+        fs::write(pages.join("tags/rust.html"), "static rust").unwrap();
+        fs::write(pages.join("tags/rust/notes.html"), "notes").unwrap();
+        let config = Config {
+            pages_dir: pages.to_string_lossy().into_owned(),
+            taxonomies: true,
+            ..Config::default()
+        };
+        let router = test_router(&config).unwrap();
+        (dir, router)
+    }
+
+    async fn get(router: &Router, uri: &str) -> (StatusCode, Option<String>, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            location,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    async fn get_json(router: &Router, uri: &str) -> Value {
+        let (status, _, body) = get(router, uri).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_terms() {
+        let (_dir, router) = site();
+        let (status, _, index) = get(&router, "/tags/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            index.contains(
+                "<li><a href=\"/tags/rust/\">Rust</a> <span class=\"count\">2</span></li>"
+            ),
+            "{}",
+            index
+        );
+        assert!(index.contains("href=\"/tags/web-servers/\""), "{}", index);
+
+        let (status, _, term) = get(&router, "/tags/rust/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            term.contains(
+                "<li><a href=\"/axum\">Axum</a></li>\n<li><a href=\"/tokio\">Tokio</a></li>"
+            ),
+            "{}",
+            term
+        );
+        let (_, _, collection) = get(&router, "/collections/guides/").await;
+        assert!(collection.contains("href=\"/axum\""), "{}", collection);
+
+        assert_eq!(get(&router, "/tags/go/").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            get(&router, "/tags").await,
+            (
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/tags/".into()),
+                String::new()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_terms_as_json() {
+        let (_dir, router) = site();
+        assert_eq!(
+            get_json(&router, "/tags.json").await,
+            json!([
+                {"name": "Rust", "url": "/tags/rust/", "count": 2},
+                {"name": "Web Servers", "url": "/tags/web-servers/", "count": 1},
+            ])
+        );
+        assert_eq!(
+            get_json(&router, "/tags/rust.json").await,
+            json!({
+                "name": "Rust",
+                "url": "/tags/rust/",
+                "pages": [
+                    {"url": "/axum", "title": "Axum"},
+                    {"url": "/tokio", "title": "Tokio"},
+                ],
+            })
+        );
+        assert_eq!(
+            get_json(&router, "/collections.json").await,
+            json!([{"name": "guides", "url": "/collections/guides/", "count": 1}])
+        );
+        assert_eq!(get(&router, "/tags/go.json").await.0, StatusCode::NOT_FOUND);
+
+        // Without the suffix, a term redirects to its listing instead of answering JSON.
+        assert_eq!(
+            get(&router, "/tags/rust").await,
+            (
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/tags/rust/".into()),
+                String::new()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn shadows_pages_at_the_same_urls() {
+        let (_dir, router) = site();
+        let (_, _, index) = get(&router, "/tags/").await;
+        assert!(!index.contains("static tags"), "{}", index);
+        assert_eq!(
+            get(&router, "/tags/rust").await.0,
+            StatusCode::MOVED_PERMANENTLY
+        );
+        let (_, _, term) = get(&router, "/tags/rust/").await;
+        assert!(!term.contains("static rust"), "{}", term);
+        assert_eq!(
+            get(&router, "/tags/rust/notes").await,
+            (StatusCode::OK, None, "notes".into())
+        );
+    }
+}
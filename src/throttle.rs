@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use globset::{GlobBuilder, GlobMatcher};
+use tokio::{sync::mpsc, time::Instant};
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+
+use crate::{config::Config, paths::normalize_request_path};
+
+/// Connections tracked before the closed ones are forgotten.
+const CLEANUP_THRESHOLD: usize = 1024;
+/// Smallest piece responses are sent in.
+const MIN_CHUNK: usize = 1024;
+/// Largest piece responses are sent in, so connections sharing the server's rate take
+/// turns often.
+const MAX_CHUNK: usize = 64 * 1024;
+
+/// Keeps the server, each connection and responses to some paths under a number of bytes
+/// per second.
+pub struct Throttle {
+    /// The rate of each response to paths matching the pattern. The first match applies.
+    rules: Vec<(GlobMatcher, u64)>,
+    per_connection: Option<u64>,
+    /// Each connection's pace. Responses hold on to their connection's entry, so it's
+    /// gone once none are being sent.
+    connections: Mutex<HashMap<SocketAddr, Weak<Pace>>>,
+    /// Shared by every connection. Pieces reserve their time in the order they are ready,
+    /// so the connections take turns.
+    total: Option<Pace>,
+}
+
+/// When the next byte may be sent at a rate.
+struct Pace {
+    rate: u64,
+    next: Mutex<Instant>,
+}
+
+impl Pace {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Reserves the time to send `len` bytes, returning when sending may start.
+    fn reserve(&self, len: usize) -> Instant {
+        let mut next = self.next.lock().unwrap();
+        let start = (*next).max(Instant::now());
+        *next = start + Duration::from_secs_f64(len as f64 / self.rate as f64);
+        start
+    }
+}
+
+impl Throttle {
+    /// Returns `None` without any rate.
+    pub fn new(config: &Config) -> Result<Option<Self>> {
+        let rules = config
+            .throttle_rules
+            .iter()
+            .filter(|rule| rule.rate.0 > 0)
+            .map(|rule| {
+                GlobBuilder::new(&rule.pattern)
+                    .case_insensitive(config.case_insensitive)
+                    .build()
+                    .map(|glob| (glob.compile_matcher(), rule.rate.0))
+                    .map_err(|e| anyhow!("Invalid throttle pattern '{}': {}", rule.pattern, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let per_connection = config
+            .max_rate_per_connection
+            .map(|rate| rate.0)
+            .filter(|rate| *rate > 0);
+        let total = config.max_rate.map(|rate| rate.0).filter(|rate| *rate > 0);
+        if rules.is_empty() && per_connection.is_none() && total.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            rules,
+            per_connection,
+            connections: Mutex::new(HashMap::new()),
+            total: total.map(Pace::new),
+        }))
+    }
+
+    /// The pace of a response to `path`, when a rule slows it down. Rules are matched
+    /// against the path as files are resolved, so `/mirror/%2e/debian.iso` or
+    /// `//mirror/debian.iso` get the rate of `/mirror/**` too. Undecodable paths are
+    /// rejected before a body is sent, so they need none.
+    fn rule(&self, path: &str) -> Option<Pace> {
+        let path = format!("/{}", normalize_request_path(path).ok()?);
+        self.rules
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(&path))
+            .map(|(_, rate)| Pace::new(*rate))
+    }
+
+    fn pace(&self, address: SocketAddr) -> Option<Arc<Pace>> {
+        let rate = self.per_connection?;
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(pace) = connections.get(&address).and_then(Weak::upgrade) {
+            return Some(pace);
+        }
+        if connections.len() >= CLEANUP_THRESHOLD {
+            connections.retain(|_, pace| pace.strong_count() > 0);
+        }
+        let pace = Arc::new(Pace::new(rate));
+        connections.insert(address, Arc::downgrade(&pace));
+        Some(pace)
+    }
+
+    /// Pieces sent at once, about a tenth of a second's worth at the lowest rate.
+    fn chunk_size(&self, rule: Option<&Pace>) -> usize {
+        let rate = rule
+            .map(|pace| pace.rate)
+            .into_iter()
+            .chain(self.per_connection)
+            .chain(self.total.as_ref().map(|pace| pace.rate))
+            .min()
+            .unwrap_or_default();
+        usize::try_from(rate / 10)
+            .unwrap_or(usize::MAX)
+            .clamp(MIN_CHUNK, MAX_CHUNK)
+    }
+
+    /// Waits until `len` bytes of the response with `rule` may be sent on the connection
+    /// with `pace`.
+    async fn wait(&self, rule: Option<&Pace>, pace: Option<&Pace>, len: usize) {
+        if let Some(rule) = rule {
+            tokio::time::sleep_until(rule.reserve(len)).await;
+        }
+        if let Some(pace) = pace {
+            tokio::time::sleep_until(pace.reserve(len)).await;
+        }
+        if let Some(total) = &self.total {
+            tokio::time::sleep_until(total.reserve(len)).await;
+        }
+    }
+}
+
+/// Sends response bodies no faster than the rates of their path, the connection and the
+/// server allow.
+pub async fn throttle_responses(
+    State(throttle): State<Arc<Throttle>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let address = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0);
+    let rule = throttle.rule(request.uri().path());
+    let response = next.run(request).await;
+    if rule.is_none() && throttle.per_connection.is_none() && throttle.total.is_none() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let len = body.size_hint().exact();
+    if len == Some(0) {
+        return Response::from_parts(parts, body);
+    }
+    // Keep the length streaming would hide, so downloads still show their progress.
+    if let Some(len) = len {
+        parts
+            .headers
+            .entry(header::CONTENT_LENGTH)
+            .or_insert(HeaderValue::from(len));
+    }
+
+    let pace = address.and_then(|address| throttle.pace(address));
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut data = body.into_data_stream();
+        let chunk_size = throttle.chunk_size(rule.as_ref());
+        while let Some(chunk) = data.next().await {
+            let mut chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+            while !chunk.is_empty() {
+                let piece = chunk.split_to(chunk.len().min(chunk_size));
+                throttle
+                    .wait(rule.as_ref(), pace.as_deref(), piece.len())
+                    .await;
+                if sender.send(Ok(piece)).await.is_err() {
+                    // The client went away.
+                    return;
+                }
+            }
+        }
+    });
+    Response::from_parts(parts, Body::from_stream(ReceiverStream::new(receiver)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::to_bytes, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::{ByteRate, ThrottleRule};
+
+    #[test]
+    fn paces_paths_and_connections() {
+        let config = |case_insensitive| Config {
+            max_rate_per_connection: Some(ByteRate(1000)),
+            throttle_rules: vec![ThrottleRule {
+                pattern: "/mirror/**".to_string(),
+                rate: ByteRate(500),
+            }],
+            case_insensitive,
+            ..Config::default()
+        };
+        let throttle = Throttle::new(&config(false)).unwrap().unwrap();
+        assert_eq!(throttle.rule("/mirror/debian.iso").unwrap().rate, 500);
+        assert!(throttle.rule("/style.css").is_none());
+        for path in [
+            "/mirror/%2e/debian.iso",
+            "//mirror/debian.iso",
+            "/%6Dirror/debian.iso",
+            "/mirror/./debian.iso",
+        ] {
+            assert_eq!(
+                throttle.rule(path).map(|pace| pace.rate),
+                Some(500),
+                "{}",
+                path
+            );
+        }
+        assert!(throttle.rule("/MIRROR/debian.iso").is_none());
+        let insensitive = Throttle::new(&config(true)).unwrap().unwrap();
+        assert_eq!(insensitive.rule("/MIRROR/debian.iso").unwrap().rate, 500);
+
+        let address = SocketAddr::from(([203, 0, 113, 7], 50000));
+        let pace = throttle.pace(address).unwrap();
+        let start = pace.reserve(500);
+        let other = throttle.pace(address).unwrap();
+        assert_eq!(other.reserve(500), start + Duration::from_millis(500));
+        let elsewhere = throttle
+            .pace(SocketAddr::from(([203, 0, 113, 8], 50000)))
+            .unwrap();
+        assert!(elsewhere.reserve(500) < start + Duration::from_millis(500));
+    }
+
+    /// Fetches `/file` from each of `addresses` at once, returning when each body was done.
+    async fn download(throttle: Throttle, addresses: &[SocketAddr]) -> Vec<Duration> {
+        let router = Router::new()
+            .route("/file", get(|| async { vec![b'x'; 10_000] }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(throttle),
+                throttle_responses,
+            ));
+        let start = Instant::now();
+        let downloads: Vec<_> = addresses
+            .iter()
+            .map(|address| {
+                let mut request = Request::get("/file").body(Body::empty()).unwrap();
+                request.extensions_mut().insert(ConnectInfo(*address));
+                let router = router.clone();
+                tokio::spawn(async move {
+                    let response = router.oneshot(request).await.unwrap();
+                    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                    assert_eq!(body.len(), 10_000);
+                    start.elapsed()
+                })
+            })
+            .collect();
+        let mut elapsed = Vec::new();
+        for download in downloads {
+            elapsed.push(download.await.unwrap());
+        }
+        elapsed
+    }
+
+    #[tokio::test]
+    async fn shares_the_total_rate_between_connections() {
+        let addresses = [
+            SocketAddr::from(([203, 0, 113, 7], 50000)),
+            SocketAddr::from(([203, 0, 113, 8], 50000)),
+        ];
+        let config = |max_rate| Config {
+            max_rate_per_connection: Some(ByteRate(20_000)),
+            max_rate,
+            ..Config::default()
+        };
+
+        // Each connection alone sends its 10 kB in about half a second...
+        let alone = download(Throttle::new(&config(None)).unwrap().unwrap(), &addresses).await;
+        for elapsed in alone {
+            assert!(elapsed < Duration::from_millis(700), "{:?}", elapsed);
+        }
+        // ...but sharing 20 kB/s, both take about a second, finishing close together.
+        let shared = download(
+            Throttle::new(&config(Some(ByteRate(20_000))))
+                .unwrap()
+                .unwrap(),
+            &addresses,
+        )
+        .await;
+        for elapsed in shared {
+            assert!(elapsed >= Duration::from_millis(800), "{:?}", elapsed);
+            assert!(elapsed < Duration::from_millis(1500), "{:?}", elapsed);
+        }
+    }
+}
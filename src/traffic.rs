@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use axum::{body::HttpBody, extract::Request, http::header, response::Response};
+
+/// Largest responses remembered.
+const LARGEST: usize = 10;
+/// Label of the routes past the limit.
+const OTHER_ROUTE: &str = "other";
+
+/// Bytes received and sent, the bytes sent under each route, and the largest responses,
+/// to tell what uses the bandwidth.
+pub struct Traffic {
+    max_routes: usize,
+    totals: Mutex<Totals>,
+}
+
+#[derive(Default)]
+struct Totals {
+    received: u64,
+    sent: u64,
+    /// Bytes sent under each first path segment, e.g. `/photos`.
+    routes: BTreeMap<String, u64>,
+    /// The largest response of each path, largest first.
+    largest: Vec<(String, u64)>,
+}
+
+/// A snapshot of [`Traffic`], with routes sorted by bytes sent.
+pub struct TrafficReport {
+    pub received: u64,
+    pub sent: u64,
+    pub routes: Vec<(String, u64)>,
+    pub largest: Vec<(String, u64)>,
+}
+
+impl Traffic {
+    pub fn new(max_routes: usize) -> Self {
+        Self {
+            max_routes,
+            totals: Mutex::new(Totals::default()),
+        }
+    }
+
+    pub fn record(&self, path: &str, received: u64, sent: u64) {
+        let mut totals = self.totals.lock().unwrap();
+        totals.received += received;
+        totals.sent += sent;
+
+        let mut route = format!("/{}", path.split('/').nth(1).unwrap_or_default());
+        if !totals.routes.contains_key(&route)
+            && totals.routes.keys().filter(|r| *r != OTHER_ROUTE).count() >= self.max_routes
+        {
+            route = OTHER_ROUTE.to_string();
+        }
+        *totals.routes.entry(route).or_default() += sent;
+
+        let largest = &mut totals.largest;
+        if sent == 0
+            || (largest.len() == LARGEST && largest.last().is_some_and(|(_, size)| *size >= sent))
+        {
+            return;
+        }
+        match largest.iter().position(|(p, _)| p == path) {
+            Some(i) if largest[i].1 >= sent => return,
+            Some(i) => {
+                largest.remove(i);
+            }
+            None => {}
+        }
+        let at = largest.partition_point(|(_, size)| *size >= sent);
+        largest.insert(at, (path.to_string(), sent));
+        largest.truncate(LARGEST);
+    }
+
+    pub fn report(&self) -> TrafficReport {
+        let totals = self.totals.lock().unwrap();
+        let mut routes: Vec<_> = totals
+            .routes
+            .iter()
+            .map(|(route, sent)| (route.clone(), *sent))
+            .collect();
+        routes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        TrafficReport {
+            received: totals.received,
+            sent: totals.sent,
+            routes,
+            largest: totals.largest.clone(),
+        }
+    }
+}
+
+/// The size of a request's body, by its `Content-Length`.
+pub fn request_size(request: &Request) -> u64 {
+    request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .unwrap_or_default()
+}
+
+/// The size of a response's body, unless it's streamed without a known length.
+pub fn response_size(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .or_else(|| response.body().size_hint().exact())
+}
+
+/// Formats `bytes` for people, e.g. `1.5 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_routes_and_keeps_the_largest_responses() {
+        let traffic = Traffic::new(2);
+        traffic.record("/photos/a.jpg", 0, 5000);
+        traffic.record("/photos/b.jpg", 0, 3000);
+        traffic.record("/css/site.css", 0, 100);
+        traffic.record("/upload", 700, 20);
+        traffic.record("/photos/a.jpg", 0, 5000);
+
+        let report = traffic.report();
+        assert_eq!((report.received, report.sent), (700, 13120));
+        assert_eq!(
+            report.routes,
+            vec![
+                ("/photos".to_string(), 13000),
+                ("/css".to_string(), 100),
+                ("other".to_string(), 20),
+            ]
+        );
+        assert_eq!(report.largest[0], ("/photos/a.jpg".to_string(), 5000));
+        assert_eq!(report.largest.len(), 4);
+        assert_eq!(format_bytes(1536 * 1024), "1.5 MB");
+    }
+}
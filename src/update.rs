@@ -0,0 +1,297 @@
+use std::{env, fs, path::Path, time::Duration};
+
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::config::Config;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/kostya-zero/lime/releases/latest";
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How long the check on startup waits for an answer.
+const STARTUP_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// The release version without a leading `v`.
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    pub fn is_newer(&self) -> bool {
+        is_newer(self.version(), CURRENT_VERSION)
+    }
+
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|a| a.name == name)
+    }
+}
+
+fn client(timeout: Duration) -> Result<Client> {
+    Client::builder()
+        .timeout(timeout)
+        .user_agent(concat!("lime/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))
+}
+
+/// Fetches the latest published release.
+pub async fn latest_release(timeout: Duration) -> Result<Release> {
+    fetch_release(LATEST_RELEASE_URL, timeout).await
+}
+
+async fn fetch_release(url: &str, timeout: Duration) -> Result<Release> {
+    client(timeout)?
+        .get(url)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow!("Failed to check for the latest release: {}", e))?
+        .json()
+        .await
+        .map_err(|e| anyhow!("Failed to read the latest release: {}", e))
+}
+
+/// Checks for a newer release in the background, unless `update_check` is off, and prints
+/// a line when there is one. Startup doesn't wait for it.
+pub fn check_on_startup(config: &Config) -> Option<JoinHandle<()>> {
+    spawn_check(config, LATEST_RELEASE_URL, STARTUP_CHECK_TIMEOUT)
+}
+
+fn spawn_check(config: &Config, url: &str, timeout: Duration) -> Option<JoinHandle<()>> {
+    if !config.update_check || config.quiet {
+        return None;
+    }
+    let url = url.to_string();
+    Some(tokio::spawn(async move {
+        // Network errors are not worth mentioning.
+        match fetch_release(&url, timeout).await {
+            Ok(release) if release.is_newer() => println!(
+                "{} Lime {} is available, run `lime self-update` to install it",
+                "".cyan(),
+                release.version()
+            ),
+            Ok(_) => {}
+            Err(e) => debug!("{}", e),
+        }
+    }))
+}
+
+/// Replaces the running executable with the binary of the latest release, after checking
+/// it against the release's SHA-256 checksum. With `check`, only reports whether an update
+/// is available.
+pub async fn self_update(check: bool) -> Result<()> {
+    let release = latest_release(Duration::from_secs(30)).await?;
+    if !release.is_newer() {
+        println!("Lime {} is up to date", CURRENT_VERSION);
+        return Ok(());
+    }
+    if check {
+        println!(
+            "Lime {} is available (current: {})",
+            release.version(),
+            CURRENT_VERSION
+        );
+        return Ok(());
+    }
+
+    let name = asset_name()?;
+    let binary = release
+        .asset(&name)
+        .ok_or_else(|| anyhow!("Release {} has no binary for {}", release.tag_name, name))?;
+    let checksum = release
+        .asset(&format!("{}.sha256", name))
+        .ok_or_else(|| anyhow!("Release {} has no checksum for {}", release.tag_name, name))?;
+
+    println!("Downloading Lime {}...", release.version());
+    let client = client(Duration::from_secs(300))?;
+    let expected = download(&client, &checksum.browser_download_url).await?;
+    let expected = String::from_utf8_lossy(&expected)
+        .split_whitespace()
+        .next()
+        .map(str::to_lowercase)
+        .ok_or_else(|| anyhow!("Checksum file for {} is empty", name))?;
+    let bytes = download(&client, &binary.browser_download_url).await?;
+    let actual = hex(&Sha256::digest(&bytes));
+    if actual != expected {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            name,
+            expected,
+            actual
+        ));
+    }
+
+    let current = env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|e| anyhow!("Failed to locate the current executable: {}", e))?;
+    replace_executable(&current, &bytes)?;
+    println!(
+        "Updated Lime from {} to {}",
+        CURRENT_VERSION,
+        release.version()
+    );
+    Ok(())
+}
+
+async fn download(client: &Client, url: &str) -> Result<Vec<u8>> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow!("Failed to download {}: {}", url, e))?;
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| anyhow!("Failed to download {}: {}", url, e))?;
+    Ok(bytes.to_vec())
+}
+
+/// Writes the new binary next to the current one and moves it into place, so a failed
+/// write never leaves a broken executable behind.
+fn replace_executable(current: &Path, bytes: &[u8]) -> Result<()> {
+    let staged = current.with_extension("new");
+    fs::write(&staged, bytes).map_err(|e| anyhow!("Failed to write {:?}: {}", staged, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))
+            .map_err(|e| anyhow!("Failed to make {:?} executable: {}", staged, e))?;
+    }
+    // Windows can't overwrite a running executable, but it can rename it.
+    #[cfg(windows)]
+    {
+        let old = current.with_extension("old");
+        let _ = fs::remove_file(&old);
+        fs::rename(current, &old)
+            .map_err(|e| anyhow!("Failed to move {:?} aside: {}", current, e))?;
+    }
+    fs::rename(&staged, current).map_err(|e| anyhow!("Failed to replace {:?}: {}", current, e))
+}
+
+/// Name of the release asset built for this platform, e.g. `lime-x86_64-unknown-linux-gnu`.
+fn asset_name() -> Result<String> {
+    let platform = match env::consts::OS {
+        "linux" if cfg!(target_env = "musl") => "unknown-linux-musl",
+        "linux" => "unknown-linux-gnu",
+        "windows" => "pc-windows-msvc",
+        "macos" => "apple-darwin",
+        os => return Err(anyhow!("No release binaries are published for {}", os)),
+    };
+    Ok(format!(
+        "lime-{}-{}{}",
+        env::consts::ARCH,
+        platform,
+        env::consts::EXE_SUFFIX
+    ))
+}
+
+/// Compares dotted versions numerically, so `0.10.0` is newer than `0.9.1`.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(candidate) > parse(current)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(is_newer("0.10.0", "0.9.1"));
+        assert!(is_newer("1.0.0", "0.2.0"));
+        assert!(!is_newer("0.2.0", "0.2.0"));
+        assert!(!is_newer("0.2.0-rc.1", "0.2.0"));
+        assert!(!is_newer("0.1.9", "0.2.0"));
+    }
+
+    /// Accepts connections but never answers, like an endpoint that is down.
+    async fn unresponsive() -> (String, tokio::sync::mpsc::UnboundedReceiver<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/releases/latest", listener.local_addr().unwrap());
+        let (accepted, connections) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                open.push(stream);
+                let _ = accepted.send(());
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn checks_without_holding_up_startup() {
+        let (url, mut connections) = unresponsive().await;
+        let config = Config {
+            update_check: true,
+            quiet: false,
+            ..Config::default()
+        };
+        let started = Instant::now();
+        let check = spawn_check(&config, &url, Duration::from_millis(300)).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(!check.is_finished());
+
+        connections.recv().await.unwrap();
+        // The check gives up once the timeout is over.
+        tokio::time::timeout(Duration::from_secs(5), check)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn can_be_turned_off() {
+        let (url, mut connections) = unresponsive().await;
+        for config in [
+            Config {
+                update_check: false,
+                ..Config::default()
+            },
+            Config {
+                quiet: true,
+                ..Config::default()
+            },
+        ] {
+            assert!(spawn_check(&config, &url, Duration::from_millis(300)).is_none());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(connections.try_recv().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::{sync::Arc, thread};
+
+use axum::{Json, extract::State, http::header, response::IntoResponse};
+use chrono::{DateTime, SecondsFormat};
+use serde::Serialize;
+
+use crate::{admin::AdminState, config::Config};
+
+const GIT_COMMIT: &str = env!("LIME_GIT_COMMIT");
+const BUILD_TIMESTAMP: &str = env!("LIME_BUILD_TIMESTAMP");
+const RUSTC_VERSION: &str = env!("LIME_RUSTC_VERSION");
+
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    commit: &'static str,
+    build_date: Option<String>,
+    rustc: &'static str,
+    features: Vec<&'static str>,
+    runtime: RuntimeInfo,
+}
+
+#[derive(Serialize)]
+struct RuntimeInfo {
+    os: &'static str,
+    arch: &'static str,
+    pid: u32,
+    cpus: usize,
+    workers: usize,
+    uptime_seconds: u64,
+}
+
+/// Names of the optional features the configuration turns on.
+pub fn enabled_features(config: &Config) -> Vec<&'static str> {
+    [
+        ("cache", config.cache.enabled),
+        ("proxy", !config.proxy_rules.is_empty()),
+        ("search", config.search.enabled),
+        ("metrics", config.metrics.enabled),
+        ("analytics", config.analytics.enabled),
+        ("auth", !config.auth_rules.is_empty()),
+        (
+            "bots",
+            config.bots.default_rules || !config.bots.rules.is_empty(),
+        ),
+        ("cors", !config.cors_rules.is_empty()),
+        ("honeypot", !config.honeypot.paths.is_empty()),
+        ("csp", config.csp.is_some()),
+        ("sri", config.sri),
+        ("preload", config.preload),
+        ("redirects", config.redirects_file.is_some()),
+        ("taxonomies", config.taxonomies),
+        ("blog", config.blog.is_some()),
+        ("content_api", config.admin.content_dir.is_some()),
+        ("reuse_port", config.reuse_port),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+/// Build and runtime details, for inventory tools keeping track of many servers.
+pub async fn handle_version(State(state): State<Arc<AdminState>>) -> impl IntoResponse {
+    let build_date = BUILD_TIMESTAMP
+        .parse()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true));
+    let info = VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: GIT_COMMIT,
+        build_date,
+        rustc: RUSTC_VERSION,
+        features: state.features.clone(),
+        runtime: RuntimeInfo {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            pid: std::process::id(),
+            cpus: thread::available_parallelism().map_or(1, |n| n.get()),
+            workers: state.workers,
+            uptime_seconds: state.status.uptime().as_secs(),
+        },
+    };
+    ([(header::CACHE_CONTROL, "no-store")], Json(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+
+    #[test]
+    fn lists_enabled_features() {
+        assert!(enabled_features(&Config::default()).is_empty());
+        let config = Config {
+            sri: true,
+            metrics: MetricsConfig {
+                enabled: true,
+                ..MetricsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(enabled_features(&config), ["metrics", "sri"]);
+    }
+}
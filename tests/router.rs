@@ -0,0 +1,314 @@
+use std::fs;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{HeaderMap, Method, Request, StatusCode, header},
+};
+use lime::{config::Config, test_router};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+struct Site {
+    _root: TempDir,
+    router: Router,
+}
+
+/// A site with a few pages and static files, configured by `toml` on top of its
+/// directories.
+fn site(toml: &str) -> Site {
+    let root = tempfile::tempdir().unwrap();
+    let pages = root.path().join("pages");
+    let assets = root.path().join("static");
+    fs::create_dir_all(pages.join("docs")).unwrap();
+    fs::create_dir_all(pages.join("internal")).unwrap();
+    fs::create_dir_all(assets.join("internal")).unwrap();
+    fs::write(pages.join("index.html"), "<h1>Home</h1>").unwrap();
+    fs::write(pages.join("caf\u{e9}.html"), "<p>Caf\u{e9} \u{2615}</p>").unwrap();
+    fs::write(pages.join("latin1.html"), b"<p>caf\xe9</p>").unwrap();
+    fs::write(pages.join("docs/index.html"), "docs").unwrap();
+    fs::write(pages.join("internal/secret.html"), "secret").unwrap();
+    fs::write(assets.join("style.css"), "body { color: #333 }").unwrap();
+    fs::write(assets.join("internal/notes.txt"), "notes").unwrap();
+    fs::write(root.path().join("secret.txt"), "secret").unwrap();
+
+    let mut config: Config = toml::from_str(toml).unwrap();
+    config.pages_dir = pages.to_string_lossy().into_owned();
+    config.static_dir = assets.to_string_lossy().into_owned();
+    let router = test_router(&config).unwrap();
+    Site {
+        _root: root,
+        router,
+    }
+}
+
+async fn send(site: &Site, method: Method, uri: &str) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())
+        .unwrap();
+    let response = site.router.clone().oneshot(request).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX).await.unwrap();
+    (parts.status, parts.headers, body.to_vec())
+}
+
+#[tokio::test]
+async fn content_length_counts_bytes() {
+    let site = site("");
+    for uri in ["/", "/caf%C3%A9", "/latin1", "/style.css", "/missing"] {
+        let (_, headers, body) = send(&site, Method::GET, uri).await;
+        let length: usize = headers[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(length, body.len(), "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn head_matches_get() {
+    let site = site("");
+    for uri in ["/", "/caf%C3%A9", "/style.css", "/docs/", "/missing"] {
+        let (get_status, get_headers, _) = send(&site, Method::GET, uri).await;
+        let (status, headers, body) = send(&site, Method::HEAD, uri).await;
+        assert_eq!(status, get_status, "{}", uri);
+        assert!(body.is_empty(), "{}", uri);
+        for name in [header::CONTENT_TYPE, header::CONTENT_LENGTH, header::ETAG] {
+            assert_eq!(
+                headers.get(&name),
+                get_headers.get(&name),
+                "{} {}",
+                uri,
+                name
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn applies_header_rules() {
+    let site = site(
+        r#"
+        [[headers]]
+        extensions = ["css"]
+        [headers.set]
+        Cache-Control = "public, max-age=31536000, immutable"
+        "#,
+    );
+    let (_, headers, _) = send(&site, Method::GET, "/style.css").await;
+    assert_eq!(
+        headers[header::CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+    let (_, headers, _) = send(&site, Method::GET, "/").await;
+    assert!(headers.get(header::CACHE_CONTROL).is_none());
+}
+
+#[tokio::test]
+async fn answers_errors() {
+    let site = site("");
+    let (status, headers, _) = send(&site, Method::GET, "/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+    let (status, _, _) = send(&site, Method::GET, "/bad%ZZ").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = send(&site, Method::POST, "/").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn never_serves_files_outside_the_site() {
+    let site = site("");
+    for uri in [
+        "/../secret.txt",
+        "/%2e%2e/secret.txt",
+        "/%2E%2E%2Fsecret.txt",
+        "/docs/..%2f..%2fsecret.txt",
+        "/..%5csecret.txt",
+    ] {
+        let (status, _, body) = send(&site, Method::GET, uri).await;
+        assert_ne!(status, StatusCode::OK, "{}", uri);
+        assert!(
+            !String::from_utf8_lossy(&body).contains("secret"),
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn protects_every_spelling_of_a_path() {
+    let rule = r#"
+        [[auth]]
+        pattern = "/internal/**"
+        method = "basic"
+        credentials = ["ada:lovelace"]
+    "#;
+    let protected = site(rule);
+    for uri in [
+        "/internal/secret",
+        "/%69nternal/secret",
+        "//internal/secret",
+        "/./internal/secret",
+        "/internal/notes.txt",
+        "/internal//notes.txt",
+    ] {
+        let (status, _, body) = send(&protected, Method::GET, uri).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        assert!(body.is_empty(), "{}", uri);
+    }
+    let (status, _, _) = send(&protected, Method::GET, "/internal/%ff").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = send(&protected, Method::GET, "/docs/").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let insensitive = site(&format!("case_insensitive = true\n{}", rule));
+    for uri in ["/INTERNAL/secret", "/Internal/Notes.txt"] {
+        let (status, _, _) = send(&insensitive, Method::GET, uri).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn rejects_cross_site_admin_requests() {
+    let site = site("[admin]\ntoken = \"t0ken\"");
+    let purge = |headers: &[(&str, &str)]| {
+        let mut request = Request::post("/__lime/purge")
+            .header(header::AUTHORIZATION, "Bearer t0ken")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::HOST, "localhost:3000");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request
+            .body(Body::from(r#"{"paths": ["/index.html"]}"#))
+            .unwrap();
+        site.router.clone().oneshot(request)
+    };
+
+    for headers in [
+        [("sec-fetch-site", "cross-site")],
+        [("sec-fetch-site", "same-site")],
+        [("origin", "https://evil.example.com")],
+        [("origin", "null")],
+    ] {
+        let response = purge(&headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{:?}", headers);
+    }
+    for headers in [
+        &[][..],
+        &[("sec-fetch-site", "same-origin")],
+        &[("origin", "http://localhost:3000")],
+    ] {
+        let response = purge(headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{:?}", headers);
+    }
+}
+
+#[tokio::test]
+async fn handlers_take_over_lime_routes() {
+    let root = tempfile::tempdir().unwrap();
+    let pages = root.path().join("pages");
+    fs::create_dir_all(pages.join("docs")).unwrap();
+    fs::write(pages.join("index.html"), "home page").unwrap();
+    fs::write(pages.join("about.html"), "about page").unwrap();
+    fs::write(pages.join("docs/index.html"), "docs page").unwrap();
+    let config = Config {
+        pages_dir: pages.to_string_lossy().into_owned(),
+        handlers: Some(
+            Router::new()
+                .route("/", axum::routing::get(|| async { "dynamic home" }))
+                .route("/about", axum::routing::get(|| async { "dynamic about" })),
+        ),
+        ..Config::default()
+    };
+    let site = Site {
+        router: test_router(&config).unwrap(),
+        _root: root,
+    };
+    for (uri, expected) in [
+        ("/", "dynamic home"),
+        ("/about", "dynamic about"),
+        ("/docs/", "docs page"),
+    ] {
+        let (status, _, body) = send(&site, Method::GET, uri).await;
+        assert_eq!(
+            (status, body),
+            (StatusCode::OK, expected.as_bytes().to_vec())
+        );
+    }
+    let (status, _, _) = send(&site, Method::GET, "/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn handlers_take_precedence_over_files() {
+    let root = tempfile::tempdir().unwrap();
+    fs::write(root.path().join("contact.html"), "static form").unwrap();
+    let config = Config {
+        pages_dir: root.path().to_string_lossy().into_owned(),
+        handlers: Some(
+            Router::new().route(
+                "/contact",
+                axum::routing::get(|| async { "dynamic form" })
+                    .post(|body: String| async move { format!("Thanks, {}", body) }),
+            ),
+        ),
+        ..Config::default()
+    };
+    let site = Site {
+        router: test_router(&config).unwrap(),
+        _root: root,
+    };
+    let (status, _, body) = send(&site, Method::GET, "/contact").await;
+    assert_eq!((status, body), (StatusCode::OK, b"dynamic form".to_vec()));
+    let request = Request::post("/contact").body(Body::from("Ada")).unwrap();
+    let response = site.router.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "Thanks, Ada");
+    let (status, _, _) = send(&site, Method::GET, "/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn drops_form_submissions_that_fill_in_the_honeypot() {
+    let site = site(
+        r#"
+        [[forms]]
+        name = "contact"
+        webhook = "http://127.0.0.1:9/unreachable"
+        redirect = "/thanks"
+        "#,
+    );
+    let post = |uri: &str, body: &'static str| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let response = site
+        .router
+        .clone()
+        .oneshot(post("/__lime/form/contact", "message=Buy+now&_gotcha=x"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()[header::LOCATION], "/thanks");
+    let response = site
+        .router
+        .clone()
+        .oneshot(post("/__lime/form/contact", "message=Hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let response = site
+        .router
+        .clone()
+        .oneshot(post("/__lime/form/other", "message=Hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}